@@ -0,0 +1,32 @@
+#ifndef MVDIST_H
+#define MVDIST_H
+
+/* C interface to the mvdist crate (built with the `capi` feature).
+ *
+ * Matrices are row-major. `infin` uses the codes -1 (unbounded), 0 (bounded above),
+ * 1 (bounded below) and 2 (bounded on both sides). The evaluation functions return 0 on normal
+ * completion, 1 if the point limit was reached, 2 if the result was flagged as ill-conditioned,
+ * and -1 on error; the error message is then available from mvdist_last_error(). Output pointers
+ * may be NULL. */
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+int mvdist_eval(int n, const double *cov, int nu, int m, const double *lower,
+                const double *upper, const int *infin, const double *constraints,
+                const double *delta, int maxpts, double abseps, double releps,
+                double *value, double *error, int *nevals);
+
+int mvdist_crit(int n, const double *cov, int nu, int m, const double *lower,
+                const double *upper, const int *infin, const double *constraints,
+                double alpha, int maxpts, double abseps,
+                double *crit, double *error, int *nevals);
+
+const char *mvdist_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,135 @@
+//! Audit records of evaluations, for settings such as regulatory submissions that must show how
+//! each reported probability was produced. `MvProblem::mvdist_audited` evaluates a problem as
+//! `mvdist_report` does and records the crate version, the method and seed used, a fingerprint of
+//! the inputs, the settings and the times of the evaluation alongside its report. With the `json`
+//! feature, `Audit::to_json` writes the record for archiving with the results.
+
+use native::DEFAULT_SEED;
+use report::Report;
+use std::time::{SystemTime, UNIX_EPOCH};
+use {Backend, EvalConfig, MvDistError, MvProblem};
+
+/// The method that produced a result.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Algorithm {
+    /// One of the special-purpose methods of `exact`, without integration.
+    Exact,
+    /// Genz's separation of variables, integrated with randomized lattice rules by the `MVDIST`
+    /// routine of the Fortran backend.
+    FortranLattice,
+    /// Genz's separation of variables, integrated with randomized lattice rules by the native
+    /// backend.
+    NativeLattice,
+    /// Deterministic integration on dimension-adaptive sparse grids by `sparse_grid`, whose error
+    /// is not a statistical one.
+    SparseGrid,
+}
+
+/// How a result was produced.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Audit {
+    /// The version of `mvdist` that evaluated the problem.
+    pub crate_version: String,
+    /// `MvProblem::fingerprint` of the problem, as 16 hexadecimal digits.
+    pub input_hash: String,
+    pub algorithm: Algorithm,
+    /// The seed of the lattice shifts of the native backend, or `None` for the exact methods, the
+    /// sparse grids, which are not random, and the Fortran backend, which uses its own internal
+    /// generator.
+    pub seed: Option<u64>,
+    /// The settings of the problem, including its tolerances and budget.
+    pub config: EvalConfig,
+    /// The result, with its number of evaluations, conditioning, diagnostics and elapsed time.
+    pub report: Report,
+    /// When the evaluation started and finished, in milliseconds since the Unix epoch by the
+    /// system clock.
+    pub started: u64,
+    pub finished: u64,
+}
+
+impl Audit {
+    /// Write the record as pretty-printed JSON.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).expect("audit serialization cannot fail")
+    }
+
+    /// Read a record written by `to_json`.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Audit, String> {
+        ::serde_json::from_str(json).map_err(|e| format!("Invalid audit record: {}", e))
+    }
+}
+
+/// The milliseconds since the Unix epoch, or 0 for a clock set before it.
+fn unix_millis(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs().saturating_mul(1000).saturating_add(d.subsec_millis() as u64))
+        .unwrap_or(0)
+}
+
+impl MvProblem {
+    /// Evaluate the problem with `mvdist_report`, recording how the result was produced.
+    pub fn mvdist_audited(&self) -> Result<Audit, MvDistError> {
+        let started = SystemTime::now();
+        let report = self.mvdist_report()?;
+        let finished = SystemTime::now();
+        let algorithm = match report.diagnostics.and_then(|d| d.backend) {
+            None => Algorithm::Exact,
+            Some(Backend::Fortran) => Algorithm::FortranLattice,
+            Some(Backend::Native) => Algorithm::NativeLattice,
+            Some(Backend::SparseGrid) => Algorithm::SparseGrid,
+        };
+        Ok(Audit {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            input_hash: format!("{:016x}", self.fingerprint()),
+            algorithm: algorithm,
+            seed: if algorithm == Algorithm::NativeLattice {
+                Some(self.config.seed.unwrap_or(DEFAULT_SEED))
+            } else {
+                None
+            },
+            config: self.config.clone(),
+            report: report,
+            started: unix_millis(started),
+            finished: unix_millis(finished),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use BoundType;
+
+    #[test]
+    fn records_the_evaluation() {
+        let lb = Array::from_elem((3,), -1.0);
+        let ub = Array1::from_vec(vec![1.0, 0.5, 2.0]);
+        let mut problem = MvProblem::new(Array::eye(3), lb, ub, vec![BoundType::Both; 3]);
+        let independent = problem.fingerprint();
+        let audit = problem.mvdist_audited().unwrap();
+        assert_eq!((audit.algorithm, audit.seed), (Algorithm::Exact, None));
+        assert_eq!(audit.input_hash, format!("{:016x}", independent));
+        assert_eq!(audit.crate_version, env!("CARGO_PKG_VERSION"));
+        assert!(audit.started <= audit.finished && audit.started > 0);
+        assert_eq!(audit.report.result, problem.mvdist().unwrap());
+        problem.cov[[0, 1]] = 0.5;
+        problem.cov[[1, 0]] = 0.5;
+        problem.cov[[1, 2]] = 0.3;
+        problem.cov[[2, 1]] = 0.3;
+        problem.config.backend = Some(Backend::Native);
+        let audit = problem.mvdist_audited().unwrap();
+        assert_eq!(audit.algorithm, Algorithm::NativeLattice);
+        assert_eq!(audit.seed, Some(DEFAULT_SEED));
+        assert!(audit.input_hash != format!("{:016x}", independent));
+        problem.config.backend = Some(Backend::SparseGrid);
+        let audit = problem.mvdist_audited().unwrap();
+        assert_eq!((audit.algorithm, audit.seed), (Algorithm::SparseGrid, None));
+        #[cfg(feature = "json")]
+        assert_eq!(Audit::from_json(&audit.to_json()).unwrap(), audit);
+    }
+}
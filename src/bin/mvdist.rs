@@ -0,0 +1,192 @@
+//! Command-line interface for one-off evaluations. Reads a problem (in the JSON format documented
+//! in `mvdist::problem`, or the equivalent TOML) from a file or standard input.
+//!
+//! ```text
+//! mvdist [INPUT]             print the probability, error, number of evaluations and condition
+//!                            number of the covariance
+//! mvdist crit ALPHA [INPUT]  print the equicoordinate critical value at level ALPHA, with the
+//!                            level achieved at it and an interval bracketing the exact value
+//! mvdist batch [--timeout SECONDS] COV TABLE OUT
+//!                            evaluate the problems of an Arrow IPC or Parquet TABLE sharing the
+//!                            covariance CSV `COV`, writing the results to `OUT` (requires the
+//!                            `columnar` feature); the problems not started within SECONDS are
+//!                            written as cancelled
+//! mvdist worker              answer the requests of a `mvdist::pool::ProcessPool` on standard
+//!                            input and output (requires the `pool` feature)
+//! ```
+//!
+//! `INPUT` is either a problem `FILE` (`-` or omitted to read from standard input) or
+//! `--csv COV BOUNDS [CONSTRAINTS]` to build the problem from CSV files as described in
+//! `mvdist::csv`.
+
+extern crate mvdist;
+extern crate toml;
+
+use mvdist::{MVResult, MvCritResult, MvProblem};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &'static str = "usage: mvdist [INPUT]
+       mvdist crit ALPHA [INPUT]
+       mvdist batch [--timeout SECONDS] COV TABLE OUT
+       mvdist worker
+where INPUT is FILE (default: standard input) or --csv COV BOUNDS [CONSTRAINTS]";
+
+fn read_input(args: &[String]) -> Result<MvProblem, String> {
+    match args.first().map(|s| s.as_str()) {
+        Some("--csv") => {
+            match args.len() {
+                3 => MvProblem::from_csv(&args[1], &args[2], None),
+                4 => MvProblem::from_csv(&args[1], &args[2], Some(&args[3])),
+                _ => Err(USAGE.to_string()),
+            }
+        }
+        path if args.len() <= 1 => read_problem(path),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn read_problem(path: Option<&str>) -> Result<MvProblem, String> {
+    let mut input = String::new();
+    match path {
+        None | Some("-") => {
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|e| format!("Unable to read standard input: {}", e))?;
+        }
+        Some(path) => {
+            File::open(path)
+                .and_then(|mut f| f.read_to_string(&mut input))
+                .map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        }
+    }
+    if input.trim_start().starts_with('{') {
+        MvProblem::from_json(&input).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(&input).map_err(|e| format!("Invalid problem file: {}", e))
+    }
+}
+
+fn print_result(res: &MVResult) {
+    println!("value: {}", res.value);
+    println!("error: {}", res.error);
+    println!("nevals: {}", res.nevals);
+    println!("state: {:?}", res.state);
+}
+
+fn print_crit(res: &MvCritResult) {
+    println!("crit: {}", res.crit);
+    println!("achieved_alpha: {}", res.achieved_alpha);
+    println!("alpha_error: {}", res.alpha_error);
+    println!("bracket: {} {}", res.bracket.0, res.bracket.1);
+    println!("nevals: {}", res.nevals);
+    println!("state: {:?}", res.state);
+}
+
+fn crit(problem: &MvProblem, alpha: f64) -> Result<MvCritResult, String> {
+    problem.mvcrit(alpha).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "pool")]
+fn worker() -> Result<(), String> {
+    let (stdin, stdout) = (io::stdin(), io::stdout());
+    mvdist::pool::serve(stdin.lock(), stdout.lock(), &mvdist::CancellationToken::new())
+        .map_err(|e| format!("Worker failed: {}", e))
+}
+
+#[cfg(not(feature = "pool"))]
+fn worker() -> Result<(), String> {
+    Err(format!("Workers require the `pool` feature"))
+}
+
+/// Whether `path` names a Parquet file rather than an Arrow IPC file.
+#[cfg(feature = "columnar")]
+fn is_parquet(path: &str) -> bool {
+    path.ends_with(".parquet") || path.ends_with(".pq")
+}
+
+#[cfg(feature = "columnar")]
+fn batch(cov: &str, table: &str, out: &str, timeout: Option<f64>) -> Result<(), String> {
+    use mvdist::cache::CachedEvaluator;
+    use mvdist::{columnar, CancellationToken};
+    use std::thread;
+    use std::time::Duration;
+    let cov = File::open(cov)
+        .map_err(|e| format!("Unable to read {}: {}", cov, e))
+        .and_then(mvdist::csv::read_covariance)?;
+    let problems = if is_parquet(table) {
+        columnar::read_parquet(table, &cov)?
+    } else {
+        columnar::read_ipc(table, &cov)?
+    };
+    let cancel = CancellationToken::new();
+    if let Some(seconds) = timeout {
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs_f64(seconds));
+            cancel.cancel();
+        });
+    }
+    let results = CachedEvaluator::new().evaluate_all(&problems, &cancel);
+    if is_parquet(out) {
+        columnar::write_parquet(out, &results)
+    } else {
+        columnar::write_ipc(out, &results)
+    }
+}
+
+#[cfg(not(feature = "columnar"))]
+fn batch(_: &str, _: &str, _: &str, _: Option<f64>) -> Result<(), String> {
+    Err(format!("Batch tables require the `columnar` feature"))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    if args.first().map(|s| s.as_str()) == Some("batch") {
+        let (timeout, rest) = if args.get(1).map(|s| s.as_str()) == Some("--timeout") {
+            let seconds = args.get(2).and_then(|s| s.parse::<f64>().ok());
+            match seconds {
+                Some(s) if s >= 0.0 && s.is_finite() => (Some(s), &args[3..]),
+                _ => return Err(USAGE.to_string()),
+            }
+        } else {
+            (None, &args[1..])
+        };
+        if rest.len() != 3 {
+            return Err(USAGE.to_string());
+        }
+        batch(&rest[0], &rest[1], &rest[2], timeout)
+    } else if args.first().map(|s| s.as_str()) == Some("worker") {
+        if args.len() != 1 {
+            return Err(USAGE.to_string());
+        }
+        worker()
+    } else if args.first().map(|s| s.as_str()) == Some("crit") {
+        if args.len() < 2 {
+            return Err(USAGE.to_string());
+        }
+        let alpha = args[1].parse::<f64>().map_err(|_| format!("Invalid ALPHA: {}", args[1]))?;
+        let problem = read_input(&args[2..])?;
+        crit(&problem, alpha).map(|res| print_crit(&res))
+    } else {
+        let problem = read_input(args)?;
+        let report = problem.mvdist_report().map_err(|e| e.to_string())?;
+        print_result(&report.result);
+        if let Some(c) = report.conditioning {
+            println!("condition_number: {}", c.condition_number);
+        }
+        for w in &report.warnings {
+            writeln!(io::stderr(), "warning: {}", w).unwrap();
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Err(e) = run(&args) {
+        writeln!(io::stderr(), "{}", e).unwrap();
+        process::exit(1);
+    }
+}
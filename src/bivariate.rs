@@ -0,0 +1,214 @@
+//! Conditional distributions of one variable of a bivariate normal or t distribution given the
+//! other, for distributional regression with a fitted dependence structure: the conditional CDF
+//! and quantiles of `X_2` given `X_1 = x`, in closed form, and given `a < X_1 < b`, as ratios of
+//! bivariate probabilities. Quantile curves over a grid of `x` are the conditional quantiles at
+//! each point.
+//!
+//! Given `X_1 = x`, `X_2` is normal with mean `μ_2 + ρ σ_2 z` and standard deviation
+//! `σ_2 sqrt(1 - ρ²)`, where `z = (x - μ_1) / σ_1`; for the t distribution it is t with `nu + 1`
+//! degrees of freedom, the same location, and the scale multiplied by `sqrt((nu + z²) / (nu + 1))`.
+
+use exact::{bvn, bvt};
+use univariate::{norm_cdf, norm_inv, t_cdf, t_inv};
+use {Kernel, MvDistError};
+
+/// A bivariate normal or t distribution with location `mean`, standard deviations (or scales for
+/// the t distribution) `sd` and correlation `rho`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bivariate {
+    mean: [f64; 2],
+    sd: [f64; 2],
+    rho: f64,
+    /// Degrees of freedom, or 0 for the normal distribution.
+    nu: f64,
+}
+
+impl Bivariate {
+    /// The distribution with location `mean`, covariance (or scatter matrix) `cov` and
+    /// distribution `kernel`. The variances must be positive and finite and the correlation
+    /// strictly between -1 and 1.
+    pub fn new(mean: [f64; 2],
+               cov: [[f64; 2]; 2],
+               kernel: Kernel)
+               -> Result<Bivariate, MvDistError> {
+        let nu = kernel.nu()?;
+        if mean.iter().any(|m| !m.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Invalid mean {:?}", mean)));
+        }
+        let sd = [cov[0][0].sqrt(), cov[1][1].sqrt()];
+        if sd.iter().any(|&s| !(s > 0.0) || s.is_infinite()) || cov[0][1] != cov[1][0] {
+            return Err(MvDistError::InvalidInput(format!("Invalid covariance {:?}", cov)));
+        }
+        let rho = cov[0][1] / (sd[0] * sd[1]);
+        if !(rho.abs() < 1.0) {
+            return Err(MvDistError::InvalidInput(format!("The correlation {} is not strictly \
+                                                          between -1 and 1",
+                                                         rho)));
+        }
+        Ok(Bivariate {
+            mean: mean,
+            sd: sd,
+            rho: rho,
+            nu: nu,
+        })
+    }
+
+    /// The location and scale of `X_2` given `X_1 = x`.
+    fn given(&self, x: f64) -> (f64, f64) {
+        let z = (x - self.mean[0]) / self.sd[0];
+        let location = self.mean[1] + self.rho * self.sd[1] * z;
+        let scale = self.sd[1] * (1.0 - self.rho * self.rho).sqrt();
+        if self.nu > 0.0 {
+            (location, scale * ((self.nu + z * z) / (self.nu + 1.0)).sqrt())
+        } else {
+            (location, scale)
+        }
+    }
+
+    /// `P(X_2 <= y | X_1 = x)`.
+    pub fn conditional_cdf(&self, y: f64, x: f64) -> f64 {
+        let (location, scale) = self.given(x);
+        let u = (y - location) / scale;
+        if self.nu > 0.0 {
+            t_cdf(self.nu + 1.0, u)
+        } else {
+            norm_cdf(u)
+        }
+    }
+
+    /// The `p` quantile of `X_2` given `X_1 = x`, for `0 < p < 1`.
+    pub fn conditional_quantile(&self, p: f64, x: f64) -> Result<f64, MvDistError> {
+        check_probability(p)?;
+        let (location, scale) = self.given(x);
+        let u = if self.nu > 0.0 {
+            t_inv(self.nu + 1.0, p)
+        } else {
+            norm_inv(p)
+        };
+        Ok(location + scale * u)
+    }
+
+    /// `P(lower < X_1 < upper, X_2 <= y)` and `P(lower < X_1 < upper)`.
+    fn interval(&self, y: f64, lower: f64, upper: f64) -> Result<(f64, f64), MvDistError> {
+        if !(lower < upper) {
+            return Err(MvDistError::InvalidInput(format!("Empty interval ({}, {})", lower, upper)));
+        }
+        let a = (lower - self.mean[0]) / self.sd[0];
+        let b = (upper - self.mean[0]) / self.sd[0];
+        let w = (y - self.mean[1]) / self.sd[1];
+        let inf = ::std::f64::INFINITY;
+        if self.nu > 0.0 {
+            if self.nu != self.nu.trunc() || self.nu > ::std::u32::MAX as f64 {
+                return Err(MvDistError::InvalidInput(format!("Conditioning on an interval \
+                                                              requires integer degrees of \
+                                                              freedom, got {}",
+                                                             self.nu)));
+            }
+            let nu = self.nu as u32;
+            Ok((bvt([a, -inf], [b, w], self.rho, nu)?, t_cdf(self.nu, b) - t_cdf(self.nu, a)))
+        } else {
+            Ok((bvn([a, -inf], [b, w], self.rho), norm_cdf(b) - norm_cdf(a)))
+        }
+    }
+
+    /// `P(X_2 <= y | lower < X_1 < upper)`, for limits that may be infinite. The t distribution
+    /// needs integer degrees of freedom here, and the interval a positive probability.
+    pub fn interval_cdf(&self, y: f64, lower: f64, upper: f64) -> Result<f64, MvDistError> {
+        let (joint, marginal) = self.interval(y, lower, upper)?;
+        if !(marginal > 0.0) {
+            return Err(MvDistError::InvalidInput(format!("The interval ({}, {}) has probability \
+                                                          zero",
+                                                         lower,
+                                                         upper)));
+        }
+        Ok((joint / marginal).max(0.0).min(1.0))
+    }
+
+    /// The `p` quantile of `X_2` given `lower < X_1 < upper`, for `0 < p < 1`, by bisection on
+    /// `interval_cdf` to within about `1e-12` standard deviations.
+    pub fn interval_quantile(&self,
+                             p: f64,
+                             lower: f64,
+                             upper: f64)
+                             -> Result<f64, MvDistError> {
+        check_probability(p)?;
+        let cdf = |y: f64| self.interval_cdf(y, lower, upper);
+        let step = self.sd[1];
+        let (mut lo, mut hi) = (self.mean[1] - step, self.mean[1] + step);
+        while cdf(lo)? > p {
+            lo -= 2.0 * (hi - lo);
+        }
+        while cdf(hi)? < p {
+            hi += 2.0 * (hi - lo);
+        }
+        for _ in 0..200 {
+            let mid = 0.5 * (lo + hi);
+            if mid == lo || mid == hi || hi - lo <= 1e-12 * step {
+                break;
+            }
+            if cdf(mid)? > p {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        Ok(0.5 * (lo + hi))
+    }
+}
+
+fn check_probability(p: f64) -> Result<(), MvDistError> {
+    if !(p > 0.0 && p < 1.0) {
+        return Err(MvDistError::InvalidInput(format!("Expected a probability strictly between 0 \
+                                                      and 1, got {}",
+                                                     p)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use univariate::{norm_cdf, t_cdf};
+
+    #[test]
+    fn conditionals_of_the_normal() {
+        let inf = ::std::f64::INFINITY;
+        let d = Bivariate::new([1.0, -2.0], [[4.0, 1.2], [1.2, 1.0]], Kernel::Normal).unwrap();
+        // ρ = 0.6: given X_1 = 3, X_2 has mean -2 + 0.6 and standard deviation 0.8
+        assert!((d.conditional_cdf(-1.4, 3.0) - 0.5).abs() < 1e-15);
+        assert!((d.conditional_cdf(-0.6, 3.0) - norm_cdf(1.0)).abs() < 1e-15);
+        let q = d.conditional_quantile(0.9, 3.0).unwrap();
+        assert!((d.conditional_cdf(q, 3.0) - 0.9).abs() < 1e-12);
+        // given the whole line, the marginal
+        let c = d.interval_cdf(-1.0, -inf, inf).unwrap();
+        assert!((c - norm_cdf(1.0)).abs() < 1e-14);
+        // a narrow interval approaches the point
+        let c = d.interval_cdf(-0.6, 3.0 - 1e-4, 3.0 + 1e-4).unwrap();
+        assert!((c - norm_cdf(1.0)).abs() < 1e-7);
+        let q = d.interval_quantile(0.25, 0.0, inf).unwrap();
+        assert!((d.interval_cdf(q, 0.0, inf).unwrap() - 0.25).abs() < 1e-10);
+        assert!(d.interval_cdf(0.0, 1.0, 1.0).is_err());
+        assert!(d.conditional_quantile(1.0, 0.0).is_err());
+        assert!(Bivariate::new([0.0, 0.0], [[1.0, 1.0], [1.0, 1.0]], Kernel::Normal).is_err());
+    }
+
+    #[test]
+    fn conditionals_of_the_t() {
+        let inf = ::std::f64::INFINITY;
+        let t = Kernel::StudentT { nu: 3.0 };
+        let d = Bivariate::new([0.0, 0.0], [[1.0, 0.5], [0.5, 1.0]], t).unwrap();
+        // given X_1 = 2, X_2 is t with 4 degrees of freedom, location 1 and scale² 0.75 · 1.75
+        let scale = (0.75f64 * 1.75).sqrt();
+        assert!((d.conditional_cdf(1.5, 2.0) - t_cdf(4.0, 0.5 / scale)).abs() < 1e-14);
+        let q = d.conditional_quantile(0.05, 2.0).unwrap();
+        assert!((d.conditional_cdf(q, 2.0) - 0.05).abs() < 1e-12);
+        let c = d.interval_cdf(0.7, -inf, inf).unwrap();
+        assert!((c - t_cdf(3.0, 0.7)).abs() < 1e-12);
+        let q = d.interval_quantile(0.6, 1.0, 2.0).unwrap();
+        assert!((d.interval_cdf(q, 1.0, 2.0).unwrap() - 0.6).abs() < 1e-10);
+        let fractional = Kernel::StudentT { nu: 2.5 };
+        let d = Bivariate::new([0.0, 0.0], [[1.0, 0.5], [0.5, 1.0]], fractional).unwrap();
+        assert!(d.conditional_quantile(0.5, 1.0).is_ok() && d.interval_cdf(0.0, 0.0, 1.0).is_err());
+    }
+}
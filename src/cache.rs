@@ -0,0 +1,110 @@
+//! Memoization of repeated evaluations of identical problems.
+
+use std::collections::HashMap;
+use {CancellationToken, MVResult, MvDistError, MvProblem};
+
+/// Evaluates problems with `MvProblem::mvdist`, returning the stored result when a problem
+/// equal to an earlier one (see the `PartialEq` implementation of `MvProblem`) is seen again.
+/// Errors are not cached.
+#[derive(Clone, Debug, Default)]
+pub struct CachedEvaluator {
+    results: HashMap<MvProblem, MVResult>,
+    hits: u64,
+    misses: u64,
+}
+
+impl CachedEvaluator {
+    pub fn new() -> CachedEvaluator {
+        CachedEvaluator::default()
+    }
+
+    pub fn evaluate(&mut self, problem: &MvProblem) -> Result<MVResult, MvDistError> {
+        if let Some(&result) = self.results.get(problem) {
+            self.hits += 1;
+            return Ok(result);
+        }
+        self.misses += 1;
+        let result = problem.mvdist()?;
+        self.results.insert(problem.clone(), result);
+        Ok(result)
+    }
+
+    /// `evaluate` each of `problems` in turn until `cancel` is cancelled. The results are in the
+    /// order of `problems`, with `MvDistError::Cancelled` for those not evaluated, so the results
+    /// of the completed problems are kept.
+    pub fn evaluate_all(&mut self,
+                        problems: &[MvProblem],
+                        cancel: &CancellationToken)
+                        -> Vec<Result<MVResult, MvDistError>> {
+        problems.iter()
+            .map(|problem| if cancel.is_cancelled() {
+                Err(MvDistError::Cancelled)
+            } else {
+                self.evaluate(problem)
+            })
+            .collect()
+    }
+
+    /// The number of evaluations answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of evaluations that had to be computed.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The number of cached results.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use BoundType;
+
+    #[test]
+    fn repeats_hit_the_cache() {
+        let problem = MvProblem::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]),
+                                     Array1::from_vec(vec![0.0, 0.0]),
+                                     Array1::from_vec(vec![1.0, 1.0]),
+                                     vec![BoundType::Above; 2]);
+        let mut same = problem.clone();
+        same.lb[0] = 7.0; // unused by `Above`
+        let mut cache = CachedEvaluator::new();
+        let first = cache.evaluate(&problem).unwrap();
+        assert_eq!(cache.evaluate(&same).unwrap(), first);
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+        same.ub[1] = 2.0;
+        cache.evaluate(&same).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn cancelled_batches_keep_completed_results() {
+        let problem = MvProblem::new(Array::eye(1),
+                                     Array1::from_vec(vec![0.0]),
+                                     Array1::from_vec(vec![1.0]),
+                                     vec![BoundType::Above]);
+        let problems = vec![problem; 3];
+        let mut cache = CachedEvaluator::new();
+        let cancel = CancellationToken::new();
+        assert!(cache.evaluate_all(&problems, &cancel).iter().all(|r| r.is_ok()));
+        cancel.clone().cancel();
+        let results = cache.evaluate_all(&problems, &cancel);
+        assert!(results.iter().all(|r| *r == Err(MvDistError::Cancelled)));
+        assert_eq!(cache.hits(), 2);
+    }
+}
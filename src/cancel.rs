@@ -0,0 +1,34 @@
+//! Cooperative cancellation of long-running batches of evaluations.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between the code running a batch and whoever may stop it, such as a Ctrl-C
+/// handler or a supervisor with a deadline. Clones share the flag. Batches check it between
+/// evaluations, so the evaluation in progress when it is set still runs to completion.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Ask every batch checking this token, or a clone of it, to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    /// A token set whenever `flag` is, for callers that already have one.
+    fn from(flag: Arc<AtomicBool>) -> CancellationToken {
+        CancellationToken { cancelled: flag }
+    }
+}
@@ -0,0 +1,282 @@
+//! C interface, enabled by the `capi` feature. See `include/mvdist.h` for the declarations.
+//!
+//! Matrices are passed as row-major arrays. Bound types use the Fortran `INFIN` codes: `-1`
+//! unbounded, `0` bounded above, `1` bounded below, `2` bounded on both sides. Each evaluation
+//! function returns `0` on normal completion, `1` if the point limit was reached, `2` if the result
+//! was flagged as ill-conditioned, and `-1` on error, in which case `mvdist_last_error` describes
+//! the problem.
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_double, c_int};
+use std::ptr;
+use std::slice::from_raw_parts;
+use {slice, BoundType, MVInform, MVResult, MvDistError};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+unsafe fn array<'a, T>(p: *const T, len: usize, name: &str) -> Result<&'a [T], String> {
+    if p.is_null() {
+        Err(format!("`{}` is null", name))
+    } else {
+        Ok(from_raw_parts(p, len))
+    }
+}
+
+fn dim(x: c_int, name: &str) -> Result<usize, String> {
+    if x > 0 {
+        Ok(x as usize)
+    } else {
+        Err(format!("`{}` must be positive, got {}", name, x))
+    }
+}
+
+fn bound_type(code: c_int) -> Result<BoundType, String> {
+    match code {
+        -1 => Ok(BoundType::Unbounded),
+        0 => Ok(BoundType::Above),
+        1 => Ok(BoundType::Below),
+        2 => Ok(BoundType::Both),
+        x => Err(format!("Invalid bound type code {}", x)),
+    }
+}
+
+struct Inputs<'a> {
+    n: usize,
+    cov: &'a [f64],
+    lb: &'a [f64],
+    ub: &'a [f64],
+    types: Vec<BoundType>,
+    constraints: &'a [f64],
+}
+
+unsafe fn inputs<'a>(n: c_int,
+                     cov: *const c_double,
+                     m: c_int,
+                     lower: *const c_double,
+                     upper: *const c_double,
+                     infin: *const c_int,
+                     constraints: *const c_double)
+                     -> Result<Inputs<'a>, MvDistError> {
+    let (n, m) = (dim(n, "n")?, dim(m, "m")?);
+    Ok(Inputs {
+        n: n,
+        cov: array(cov, n * n, "cov")?,
+        lb: array(lower, m, "lower")?,
+        ub: array(upper, m, "upper")?,
+        types: array(infin, m, "infin")?
+            .iter()
+            .map(|&c| bound_type(c))
+            .collect::<Result<Vec<_>, _>>()?,
+        constraints: array(constraints, m * n, "constraints")?,
+    })
+}
+
+unsafe fn finish(res: Result<MVResult, MvDistError>,
+                 value: *mut c_double,
+                 error: *mut c_double,
+                 nevals: *mut c_int)
+                 -> c_int {
+    match res {
+        Ok(res) => {
+            if !value.is_null() {
+                *value = res.value;
+            }
+            if !error.is_null() {
+                *error = res.error;
+            }
+            if !nevals.is_null() {
+                *nevals = res.nevals.min(c_int::max_value() as u64) as c_int;
+            }
+            match res.state {
+                MVInform::Normal => 0,
+                MVInform::PtLimitReached => 1,
+                MVInform::IllConditioned => 2,
+            }
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            -1
+        }
+    }
+}
+
+/// C counterpart of `mvdist`. `cov` is `n x n`, `constraints` is `m x n`, and `lower`, `upper`,
+/// `infin` and `delta` have length `m`. Any of the output pointers may be null.
+#[no_mangle]
+pub unsafe extern "C" fn mvdist_eval(n: c_int,
+                                     cov: *const c_double,
+                                     nu: c_int,
+                                     m: c_int,
+                                     lower: *const c_double,
+                                     upper: *const c_double,
+                                     infin: *const c_int,
+                                     constraints: *const c_double,
+                                     delta: *const c_double,
+                                     maxpts: c_int,
+                                     abseps: c_double,
+                                     releps: c_double,
+                                     value: *mut c_double,
+                                     error: *mut c_double,
+                                     nevals: *mut c_int)
+                                     -> c_int {
+    let res = inputs(n, cov, m, lower, upper, infin, constraints).and_then(|i| {
+        let delta = array(delta, i.types.len(), "delta")?;
+        slice::mvdist(i.n,
+                      i.cov,
+                      nu,
+                      i.lb,
+                      i.ub,
+                      &i.types,
+                      i.constraints,
+                      delta,
+                      maxpts.max(0) as u64,
+                      abseps,
+                      releps)
+    });
+    finish(res, value, error, nevals)
+}
+
+/// C counterpart of `mvcrit`. The critical value is written to `crit` and the error of the level
+/// achieved at it to `error`.
+#[no_mangle]
+pub unsafe extern "C" fn mvdist_crit(n: c_int,
+                                     cov: *const c_double,
+                                     nu: c_int,
+                                     m: c_int,
+                                     lower: *const c_double,
+                                     upper: *const c_double,
+                                     infin: *const c_int,
+                                     constraints: *const c_double,
+                                     alpha: c_double,
+                                     maxpts: c_int,
+                                     abseps: c_double,
+                                     crit: *mut c_double,
+                                     error: *mut c_double,
+                                     nevals: *mut c_int)
+                                     -> c_int {
+    let res = inputs(n, cov, m, lower, upper, infin, constraints).and_then(|i| {
+        slice::mvcrit(i.n,
+                      i.cov,
+                      nu,
+                      i.lb,
+                      i.ub,
+                      &i.types,
+                      i.constraints,
+                      alpha,
+                      maxpts.max(0) as u64,
+                      abseps)
+    });
+    let res = res.map(|r| {
+        MVResult {
+            value: r.crit,
+            error: r.alpha_error,
+            nevals: r.nevals,
+            state: r.state,
+        }
+    });
+    finish(res, crit, error, nevals)
+}
+
+/// Message describing the most recent error on the calling thread, or null if there has been none.
+/// The string remains valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn mvdist_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::thread;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(mvdist_last_error()).to_string_lossy().into_owned() }
+    }
+
+    #[test]
+    fn evaluates_and_reports_errors() {
+        let (cov, eye, zeros) = ([1.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0], [0.0; 2]);
+        let (mut value, mut nevals) = (0.0, 0);
+        let eval = |cov: *const c_double,
+                    infin: &[c_int],
+                    value: *mut c_double,
+                    nevals: *mut c_int| unsafe {
+            mvdist_eval(2,
+                        cov,
+                        0,
+                        2,
+                        zeros.as_ptr(),
+                        zeros.as_ptr(),
+                        infin.as_ptr(),
+                        eye.as_ptr(),
+                        zeros.as_ptr(),
+                        10_000,
+                        1e-6,
+                        0.0,
+                        value,
+                        ptr::null_mut(),
+                        nevals)
+        };
+        assert!(thread::spawn(|| mvdist_last_error().is_null()).join().unwrap());
+        assert_eq!(eval(cov.as_ptr(), &[0, 0], &mut value, &mut nevals), 0);
+        assert!((value - 0.25).abs() < 1e-12 && nevals >= 0);
+        // the outputs may be null
+        assert_eq!(eval(cov.as_ptr(), &[0, 0], ptr::null_mut(), ptr::null_mut()), 0);
+        assert_eq!(eval(ptr::null(), &[0, 0], &mut value, &mut nevals), -1);
+        assert_eq!(last_error(), "`cov` is null");
+        assert_eq!(eval(cov.as_ptr(), &[0, 3], &mut value, &mut nevals), -1);
+        assert!(last_error().contains("Invalid bound type code 3"));
+    }
+
+    #[test]
+    fn critical_values() {
+        let (cov, zeros, infin) = ([1.0, 0.0, 0.0, 1.0], [0.0; 2], [2, 2]);
+        let (mut crit, mut error, mut nevals) = (0.0, 0.0, 0);
+        let status = unsafe {
+            mvdist_crit(2,
+                        cov.as_ptr(),
+                        0,
+                        2,
+                        zeros.as_ptr(),
+                        zeros.as_ptr(),
+                        infin.as_ptr(),
+                        cov.as_ptr(),
+                        0.05,
+                        100_000,
+                        1e-5,
+                        &mut crit,
+                        &mut error,
+                        &mut nevals)
+        };
+        assert_eq!(status, 0);
+        // (2 Φ(c) - 1)² = 0.95
+        assert!((crit - 2.2364766445577895).abs() < 1e-3 && error >= 0.0);
+        let status = unsafe {
+            mvdist_crit(0,
+                        cov.as_ptr(),
+                        0,
+                        2,
+                        zeros.as_ptr(),
+                        zeros.as_ptr(),
+                        infin.as_ptr(),
+                        cov.as_ptr(),
+                        0.05,
+                        100_000,
+                        1e-5,
+                        &mut crit,
+                        &mut error,
+                        &mut nevals)
+        };
+        assert_eq!(status, -1);
+        assert_eq!(last_error(), "`n` must be positive, got 0");
+    }
+}
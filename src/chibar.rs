@@ -0,0 +1,231 @@
+//! Chi-bar-square distributions for order-restricted inference. For `X` normal with mean zero
+//! and covariance `cov` in `k` dimensions, and the cone `{θ : R θ >= 0}` with `m` linearly
+//! independent constraints `R`, the squared length of the projection of `X` onto the cone in the
+//! metric of `cov⁻¹` is distributed as the mixture `Σ_i w_i χ²_i`. The weights are non-zero only
+//! for `i >= k - m`, and are those of the orthant cone for the covariance `W = R cov Rᵀ`.
+//!
+//! For the orthant, the projection has exactly the components `A` positive with probability
+//! `P(Y_A >= 0) P(Z_B >= 0)`, where `B` is the complement of `A`, `Y_A` has the conditional
+//! covariance `W_AA - W_AB W_BB⁻¹ W_BA` and `Z_B` the covariance `W_BB⁻¹`, so `w_{k - m + i}` is a
+//! sum of products of orthant probabilities over the `C(m, i)` subsets `A` of size `i`. Orthants of
+//! up to three dimensions have closed forms; larger ones are evaluated with `mvdist_kernel`.
+
+use linalg::{cholesky, solve_lower};
+use ndarray::prelude::*;
+use std::f64::consts::PI;
+use univariate::inc_gamma;
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+/// The most constraints accepted by `weights`, which evaluates `2^m` pairs of orthant
+/// probabilities.
+pub const MAX_CONSTRAINTS: usize = 20;
+
+/// The distribution `Σ_i weights[i] χ²_i`, for `i` from 0 to the dimension.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChiBarSquare {
+    pub weights: Vec<f64>,
+    /// A bound on the error of the weights in total, and so of `cdf` and `sf`.
+    pub error: f64,
+    /// The evaluations used by the orthant probabilities.
+    pub nevals: u64,
+    /// `MVInform::Normal` unless an orthant probability ended otherwise.
+    pub state: MVInform,
+}
+
+impl ChiBarSquare {
+    /// `P(χ̄² <= x)`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        1.0 - self.sf(x)
+    }
+
+    /// `P(χ̄² > x)`, the p-value of an observed statistic `x`.
+    pub fn sf(&self, x: f64) -> f64 {
+        let p = self.weights
+            .iter()
+            .enumerate()
+            .map(|(i, &w)| {
+                if i == 0 {
+                    if x < 0.0 { w } else { 0.0 }
+                } else {
+                    w * (1.0 - inc_gamma(i as f64 / 2.0, x / 2.0))
+                }
+            })
+            .sum::<f64>();
+        p.max(0.0).min(1.0)
+    }
+}
+
+/// `P(Y >= 0)` for `Y` normal with mean zero and covariance `cov`.
+fn orthant(cov: &Array2<f64>, maxpts: u64, abseps: f64) -> Result<MVResult, MvDistError> {
+    let n = cov.rows();
+    let r = |i: usize, j: usize| {
+        (cov[[i, j]] / (cov[[i, i]] * cov[[j, j]]).sqrt()).max(-1.0).min(1.0).asin()
+    };
+    let value = match n {
+        0 => 1.0,
+        1 => 0.5,
+        2 => 0.25 + r(0, 1) / (2.0 * PI),
+        3 => 0.125 + (r(0, 1) + r(0, 2) + r(1, 2)) / (4.0 * PI),
+        _ => {
+            return ::mvdist_kernel(cov,
+                                   Kernel::Normal,
+                                   &Array::zeros((n,)),
+                                   &Array::zeros((n,)),
+                                   &vec![BoundType::Below; n],
+                                   &Array::eye(n),
+                                   &Array::zeros((n,)),
+                                   maxpts,
+                                   abseps,
+                                   0.0)
+        }
+    };
+    Ok(MVResult {
+        value: value,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    })
+}
+
+/// The covariances `W_AA - W_AB W_BB⁻¹ W_BA` and `W_BB⁻¹` of the projection onto the face where
+/// the variables `a` are positive and `b` are zero.
+fn face(w: &Array2<f64>, a: &[usize], b: &[usize]) -> Option<(Array2<f64>, Array2<f64>)> {
+    let mut w_bb = Array2::zeros((b.len(), b.len()));
+    for (r, &i) in b.iter().enumerate() {
+        for (c, &j) in b.iter().enumerate() {
+            w_bb[[r, c]] = w[[i, j]];
+        }
+    }
+    let l = cholesky(&w_bb)?;
+    let dot = |u: &[f64], v: &[f64]| u.iter().zip(v.iter()).map(|(x, y)| x * y).sum::<f64>();
+    // the columns of L⁻¹ W_BA, and of L⁻¹, whose inner products are the entries of W_BB⁻¹
+    let cross = a.iter()
+        .map(|&j| solve_lower(&l, &b.iter().map(|&i| w[[i, j]]).collect::<Vec<f64>>()))
+        .collect::<Vec<Vec<f64>>>();
+    let unit = (0..b.len())
+        .map(|j| {
+            let e = (0..b.len()).map(|i| if i == j { 1.0 } else { 0.0 }).collect::<Vec<f64>>();
+            solve_lower(&l, &e)
+        })
+        .collect::<Vec<Vec<f64>>>();
+    let mut cond = Array2::zeros((a.len(), a.len()));
+    for r in 0..a.len() {
+        for c in 0..a.len() {
+            cond[[r, c]] = w[[a[r], a[c]]] - dot(&cross[r], &cross[c]);
+        }
+    }
+    let mut inverse = Array2::zeros((b.len(), b.len()));
+    for r in 0..b.len() {
+        for c in 0..b.len() {
+            inverse[[r, c]] = dot(&unit[r], &unit[c]);
+        }
+    }
+    Some((cond, inverse))
+}
+
+/// The chi-bar-square distribution of the projection of `X ~ N(0, cov)` onto the cone
+/// `{θ : constraints θ >= 0}`, as described in the module documentation. `constraints` is `m × k`
+/// with linearly independent rows, at most `MAX_CONSTRAINTS` of them; with `Array::eye(k)` the
+/// cone is the positive orthant. Each orthant probability of more than three dimensions is
+/// evaluated with `maxpts` and `abseps`.
+pub fn weights(cov: &Array2<f64>,
+               constraints: &Array2<f64>,
+               maxpts: u64,
+               abseps: f64)
+               -> Result<ChiBarSquare, MvDistError> {
+    let (k, m) = (cov.rows(), constraints.rows());
+    if cov.cols() != k || constraints.cols() != k {
+        return Err(MvDistError::InvalidInput(format!("Expected a square covariance and {} \
+                                                      constraint columns, got {}x{} and {}x{}",
+                                                     k,
+                                                     k,
+                                                     cov.cols(),
+                                                     m,
+                                                     constraints.cols())));
+    }
+    if m == 0 || m > k {
+        return Err(MvDistError::InvalidInput(format!("Expected 1 to {} constraints, got {}",
+                                                     k,
+                                                     m)));
+    }
+    if m > MAX_CONSTRAINTS {
+        return Err(MvDistError::DimensionTooLarge {
+            n: m,
+            max: MAX_CONSTRAINTS,
+        });
+    }
+    let w = constraints.dot(cov).dot(&constraints.t());
+    if cholesky(&w).is_none() {
+        return Err(MvDistError::NotPositiveSemidefinite);
+    }
+    let mut result = ChiBarSquare {
+        weights: vec![0.0; k + 1],
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    for subset in 0..(1usize << m) {
+        let (a, b): (Vec<usize>, Vec<usize>) = (0..m).partition(|&i| subset & (1 << i) != 0);
+        let (cond, inverse) = face(&w, &a, &b).ok_or(MvDistError::NotPositiveSemidefinite)?;
+        let mut p = 1.0;
+        let mut error = 0.0;
+        for cov in &[cond, inverse] {
+            let q = orthant(cov, maxpts, abseps)?;
+            error = error * q.value + p * q.error + error * q.error;
+            p *= q.value;
+            result.nevals += q.nevals;
+            if q.state != MVInform::Normal {
+                result.state = q.state;
+            }
+        }
+        result.weights[k - m + a.len()] += p;
+        result.error += error;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::ar1;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn orthant_weights() {
+        // independent components: binomial weights
+        let r = weights(&Array::eye(4), &Array::eye(4), 100_000, 1e-8).unwrap();
+        for (i, &c) in [1.0, 4.0, 6.0, 4.0, 1.0].iter().enumerate() {
+            assert!((r.weights[i] - c / 16.0).abs() < 1e-8);
+        }
+        // two correlated components
+        let rho: f64 = 0.5;
+        let r = weights(&arr2(&[[1.0, rho], [rho, 1.0]]), &Array::eye(2), 1000, 1e-8).unwrap();
+        assert!((r.weights[0] - (0.25 - rho.asin() / (2.0 * PI))).abs() < 1e-15);
+        assert!((r.weights[1] - 0.5).abs() < 1e-15);
+        assert!((r.weights[2] - (0.25 + rho.asin() / (2.0 * PI))).abs() < 1e-15);
+        assert_eq!((r.error, r.nevals), (0.0, 0));
+        assert!((r.sf(0.0) - r.weights[1] - r.weights[2]).abs() < 1e-15);
+        assert!(r.sf(40.0) < 1e-8 && r.cdf(40.0) > 1.0 - 1e-8);
+    }
+
+    #[test]
+    fn weights_of_a_cone() {
+        // five correlated components constrained to be increasing
+        let cov = ar1(5, 0.4);
+        let mut constraints = Array2::zeros((4, 5));
+        for i in 0..4 {
+            constraints[[i, i]] = -1.0;
+            constraints[[i, i + 1]] = 1.0;
+        }
+        let r = weights(&cov, &constraints, 200_000, 1e-6).unwrap();
+        assert_eq!(r.weights[0], 0.0);
+        // the weights sum to one, and the odd and even weights to one half each
+        let even = r.weights.iter().step_by(2).sum::<f64>();
+        let odd = r.weights.iter().skip(1).step_by(2).sum::<f64>();
+        assert!((even - 0.5).abs() < 1e-4 && (odd - 0.5).abs() < 1e-4);
+        assert!(r.weights[1..].iter().all(|&w| w > 0.0));
+        assert!(weights(&cov, &Array::zeros((2, 5)), 1000, 1e-6).is_err());
+        assert!(weights(&cov, &Array::eye(6), 1000, 1e-6).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Reading tables of problems and writing tables of results as Arrow IPC files or Parquet, enabled
+//! by the `columnar` feature, for problem sets produced by data pipelines.
+//!
+//! A problem table has one row per problem and `Float64` columns `lower_0`, `upper_0`, `lower_1`,
+//! `upper_1`, ... for the bounds of each variable; other columns are ignored. A null or infinite
+//! entry leaves the variable unbounded on that side, as in `csv::read_bounds`. Every problem has
+//! the same covariance and the `MvProblem::new` defaults for its other settings.
+//!
+//! A result table has one row per problem with the nullable columns `value`, `error` (`Float64`),
+//! `nevals` (`UInt64`), `state` and `message` (`Utf8`). `state` is the `Debug` form of the
+//! `MVInform`, and `message` the error of a problem that could not be evaluated, whose other
+//! columns are null.
+
+use arrow::array::{Array, ArrayRef, Float64Array, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use ndarray::prelude::*;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use {BoundType, MVResult, MvDistError, MvProblem};
+
+fn open<P: AsRef<Path>>(path: P) -> Result<File, String> {
+    let path = path.as_ref();
+    File::open(path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))
+}
+
+fn create<P: AsRef<Path>>(path: P) -> Result<File, String> {
+    let path = path.as_ref();
+    File::create(path).map_err(|e| format!("Unable to create {}: {}", path.display(), e))
+}
+
+fn bound_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a Float64Array, String> {
+    batch.column_by_name(name)
+        .ok_or_else(|| format!("Problem table has no `{}` column", name))?
+        .as_any()
+        .downcast_ref::<Float64Array>()
+        .ok_or_else(|| format!("Column `{}` is not Float64", name))
+}
+
+/// The problems in the rows of `batch`, each with covariance `cov`.
+pub fn problems_from_batch(batch: &RecordBatch,
+                           cov: &Array2<f64>)
+                           -> Result<Vec<MvProblem>, String> {
+    let n = cov.rows();
+    let mut columns = Vec::with_capacity(n);
+    for i in 0..n {
+        columns.push((bound_column(batch, &format!("lower_{}", i))?,
+                      bound_column(batch, &format!("upper_{}", i))?));
+    }
+    if batch.column_by_name(&format!("lower_{}", n)).is_some() {
+        return Err(format!("Problem table has more than the {} variables of the covariance", n));
+    }
+    let finite = |column: &Float64Array, row: usize| {
+        if column.is_null(row) {
+            None
+        } else {
+            Some(column.value(row)).filter(|x| x.is_finite())
+        }
+    };
+    let mut problems = Vec::with_capacity(batch.num_rows());
+    for row in 0..batch.num_rows() {
+        let (mut lb, mut ub) = (Array::zeros((n,)), Array::zeros((n,)));
+        let mut types = Vec::with_capacity(n);
+        for (i, &(lower, upper)) in columns.iter().enumerate() {
+            let (lo, hi) = (finite(lower, row), finite(upper, row));
+            types.push(match (lo, hi) {
+                (None, None) => BoundType::Unbounded,
+                (None, Some(_)) => BoundType::Above,
+                (Some(_), None) => BoundType::Below,
+                (Some(_), Some(_)) => BoundType::Both,
+            });
+            lb[i] = lo.unwrap_or(0.0);
+            ub[i] = hi.unwrap_or(0.0);
+        }
+        problems.push(MvProblem::new(cov.clone(), lb, ub, types));
+    }
+    Ok(problems)
+}
+
+fn collect_problems<I, E>(batches: I, cov: &Array2<f64>) -> Result<Vec<MvProblem>, String>
+    where I: Iterator<Item = Result<RecordBatch, E>>,
+          E: ::std::fmt::Display
+{
+    let mut problems = Vec::new();
+    for batch in batches {
+        let batch = batch.map_err(|e| format!("Unable to read problem table: {}", e))?;
+        problems.extend(problems_from_batch(&batch, cov)?);
+    }
+    Ok(problems)
+}
+
+/// Read the problems of an Arrow IPC file, each with covariance `cov`.
+pub fn read_ipc<P: AsRef<Path>>(path: P, cov: &Array2<f64>) -> Result<Vec<MvProblem>, String> {
+    let reader = FileReader::try_new(open(path)?, None)
+        .map_err(|e| format!("Invalid Arrow file: {}", e))?;
+    collect_problems(reader, cov)
+}
+
+/// Read the problems of a Parquet file, each with covariance `cov`.
+pub fn read_parquet<P: AsRef<Path>>(path: P, cov: &Array2<f64>) -> Result<Vec<MvProblem>, String> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(open(path)?)
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("Invalid Parquet file: {}", e))?;
+    collect_problems(reader, cov)
+}
+
+/// A field of each result, or `None` for an error.
+fn result_column<T, F>(results: &[Result<MVResult, MvDistError>], field: F) -> Vec<Option<T>>
+    where F: Fn(&MVResult) -> T
+{
+    results.iter().map(|r| r.as_ref().ok().map(&field)).collect()
+}
+
+/// The result table of `results`, in order.
+pub fn results_to_batch(results: &[Result<MVResult, MvDistError>]) -> Result<RecordBatch, String> {
+    let messages = results.iter()
+        .map(|r| r.as_ref().err().map(|e| e.to_string()))
+        .collect::<Vec<Option<String>>>();
+    let columns: Vec<ArrayRef> =
+        vec![Arc::new(Float64Array::from(result_column(results, |r| r.value))),
+             Arc::new(Float64Array::from(result_column(results, |r| r.error))),
+             Arc::new(UInt64Array::from(result_column(results, |r| r.nevals))),
+             Arc::new(StringArray::from(result_column(results, |r| format!("{:?}", r.state)))),
+             Arc::new(StringArray::from(messages))];
+    let schema = Schema::new(vec![Field::new("value", DataType::Float64, true),
+                                  Field::new("error", DataType::Float64, true),
+                                  Field::new("nevals", DataType::UInt64, true),
+                                  Field::new("state", DataType::Utf8, true),
+                                  Field::new("message", DataType::Utf8, true)]);
+    RecordBatch::try_new(Arc::new(schema), columns).map_err(|e| format!("{}", e))
+}
+
+/// Write the result table of `results` as an Arrow IPC file.
+pub fn write_ipc<P: AsRef<Path>>(path: P,
+                                 results: &[Result<MVResult, MvDistError>])
+                                 -> Result<(), String> {
+    let batch = results_to_batch(results)?;
+    let mut writer = FileWriter::try_new(create(path)?, &batch.schema())
+        .map_err(|e| format!("Unable to write Arrow file: {}", e))?;
+    writer.write(&batch)
+        .and_then(|()| writer.finish())
+        .map_err(|e| format!("Unable to write Arrow file: {}", e))
+}
+
+/// Write the result table of `results` as a Parquet file.
+pub fn write_parquet<P: AsRef<Path>>(path: P,
+                                     results: &[Result<MVResult, MvDistError>])
+                                     -> Result<(), String> {
+    let batch = results_to_batch(results)?;
+    let mut writer = ArrowWriter::try_new(create(path)?, batch.schema(), None)
+        .map_err(|e| format!("Unable to write Parquet file: {}", e))?;
+    writer.write(&batch)
+        .and_then(|()| writer.close().map(|_| ()))
+        .map_err(|e| format!("Unable to write Parquet file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use MVInform;
+
+    #[test]
+    fn problems_from_bound_columns() {
+        let inf = ::std::f64::INFINITY;
+        let schema = Schema::new(vec![Field::new("id", DataType::UInt64, false),
+                                      Field::new("lower_0", DataType::Float64, true),
+                                      Field::new("upper_0", DataType::Float64, true),
+                                      Field::new("lower_1", DataType::Float64, true),
+                                      Field::new("upper_1", DataType::Float64, true)]);
+        let columns: Vec<ArrayRef> =
+            vec![Arc::new(UInt64Array::from(vec![7, 8])),
+                 Arc::new(Float64Array::from(vec![None, Some(-1.0)])),
+                 Arc::new(Float64Array::from(vec![Some(0.5), Some(inf)])),
+                 Arc::new(Float64Array::from(vec![None, Some(-2.0)])),
+                 Arc::new(Float64Array::from(vec![None, Some(2.0)]))];
+        let batch = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+        let cov = arr2(&[[1.0, 0.5], [0.5, 1.0]]);
+        let problems = problems_from_batch(&batch, &cov).unwrap();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].ub[0], 0.5);
+        assert_eq!(problems[1].lb, Array1::from_vec(vec![-1.0, -2.0]));
+        match (problems[0].types[0], problems[0].types[1], problems[1].types[0]) {
+            (BoundType::Above, BoundType::Unbounded, BoundType::Below) => (),
+            t => panic!("unexpected bound types {:?}", t),
+        }
+        assert!(problems_from_batch(&batch, &Array::eye(3)).is_err());
+        assert!(problems_from_batch(&batch, &Array::eye(1)).is_err());
+    }
+
+    #[test]
+    fn results_keep_errors() {
+        let results = vec![Ok(MVResult {
+                               value: 0.25,
+                               error: 1e-6,
+                               nevals: 1000,
+                               state: MVInform::Normal,
+                           }),
+                           Err(MvDistError::NotPositiveSemidefinite)];
+        let batch = results_to_batch(&results).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let value = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(value.value(0), 0.25);
+        assert!(value.is_null(1));
+        let message = batch.column(4).as_any().downcast_ref::<StringArray>().unwrap();
+        assert!(message.is_null(0));
+        assert_eq!(message.value(1), MvDistError::NotPositiveSemidefinite.to_string());
+    }
+}
@@ -0,0 +1,154 @@
+//! Conditioning diagnostics for covariance matrices. A nearly singular covariance gives nearly
+//! degenerate conditional distributions in the integrand, which show up as large errors or wrong
+//! answers rather than as an error, so `conditioning` measures it and `Conditioning::flag` marks a
+//! result as `MVInform::IllConditioned`.
+//!
+//! Both measures are taken on the correlation matrix, since rescaling the variables does not change
+//! the problem.
+
+use linalg::{cholesky, solve_lower, solve_lower_transpose};
+use ndarray::prelude::*;
+use {MVInform, MVResult, MvDistError};
+
+/// The condition number above which results are flagged. Rounding errors in the factorization are
+/// of order `1e-16` times the condition number, which at this point approaches the default
+/// `abseps`.
+pub const CONDITION_THRESHOLD: f64 = 1e10;
+
+/// The most steps of power iteration taken for each eigenvalue.
+const ITERATIONS: usize = 100;
+
+/// The conditioning of a covariance matrix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Conditioning {
+    /// The ratio of the largest to the smallest eigenvalue of the correlation matrix, or infinity
+    /// if it is singular.
+    pub condition_number: f64,
+    /// The smallest diagonal entry of the Cholesky factor of the correlation matrix, or 0 if it is
+    /// singular: the conditional standard deviation of the variable best determined by those
+    /// before it, relative to its own.
+    pub min_pivot: f64,
+}
+
+impl Conditioning {
+    /// Whether the condition number is at most `CONDITION_THRESHOLD`.
+    pub fn is_well_conditioned(&self) -> bool {
+        self.condition_number <= CONDITION_THRESHOLD
+    }
+
+    /// `result` with its state set to `MVInform::IllConditioned` unless the covariance is well
+    /// conditioned. This replaces `MVInform::PtLimitReached`, since neither the value nor the
+    /// error of an ill-conditioned problem can be relied on.
+    pub fn flag(&self, mut result: MVResult) -> MVResult {
+        if !self.is_well_conditioned() {
+            result.state = MVInform::IllConditioned;
+        }
+        result
+    }
+}
+
+/// The largest eigenvalue of the symmetric positive definite operator `apply` on vectors of length
+/// `n`, by power iteration from a fixed start with unequal components.
+fn largest_eigenvalue<F>(n: usize, apply: F) -> f64
+    where F: Fn(&[f64]) -> Vec<f64>
+{
+    let mut x = (0..n).map(|i| 1.0 / (i as f64 + 1.0)).collect::<Vec<f64>>();
+    let mut lambda = 0.0;
+    for _ in 0..ITERATIONS {
+        let norm = x.iter().map(|v| v * v).sum::<f64>().sqrt();
+        for v in &mut x {
+            *v /= norm;
+        }
+        let y = apply(&x);
+        let next = x.iter().zip(y.iter()).map(|(a, b)| a * b).sum::<f64>();
+        x = y;
+        if (next - lambda).abs() <= 1e-6 * next {
+            return next;
+        }
+        lambda = next;
+    }
+    lambda
+}
+
+/// The conditioning of `cov`. A matrix with a variance that is not positive counts as singular.
+///
+/// The eigenvalues are estimated by power iteration with the correlation matrix and its inverse,
+/// so the condition number may be underestimated by a small factor when eigenvalues are clustered,
+/// which does not matter for comparing it with `CONDITION_THRESHOLD`. This takes `O(n³)` time for
+/// the Cholesky factor, like the setup of either backend.
+pub fn conditioning(cov: &Array2<f64>) -> Result<Conditioning, MvDistError> {
+    let n = cov.rows();
+    if n == 0 || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected a non-empty \
+                                                      square matrix",
+                                                     cov.rows(),
+                                                     cov.cols())));
+    }
+    let singular = Conditioning {
+        condition_number: ::std::f64::INFINITY,
+        min_pivot: 0.0,
+    };
+    let sd = (0..n).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0)) {
+        return Ok(singular);
+    }
+    let mut corr = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            corr[[i, j]] = cov[[i, j]] / (sd[i] * sd[j]);
+        }
+    }
+    let l = match cholesky(&corr) {
+        Some(l) => l,
+        None => return Ok(singular),
+    };
+    let largest = largest_eigenvalue(n, |x| {
+        (0..n).map(|i| (0..n).map(|j| corr[[i, j]] * x[j]).sum::<f64>()).collect()
+    });
+    let inverse = largest_eigenvalue(n, |x| solve_lower_transpose(&l, &solve_lower(&l, x)));
+    Ok(Conditioning {
+        condition_number: largest * inverse,
+        min_pivot: (0..n).map(|i| l[[i, i]]).fold(::std::f64::INFINITY, f64::min),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn equicorrelation() {
+        // eigenvalues 1 + 4ρ and 1 - ρ; the last variable has conditional variance
+        // (1 - ρ) (1 + 4ρ) / (1 + 3ρ) given the others
+        let mut cov = Array::from_elem((5, 5), 0.9 * 4.0);
+        for i in 0..5 {
+            cov[[i, i]] = 4.0;
+        }
+        let c = conditioning(&cov).unwrap();
+        assert!((c.condition_number - 46.0).abs() < 1e-4);
+        assert!((c.min_pivot - (0.1f64 * 4.6 / 3.7).sqrt()).abs() < 1e-12);
+        assert!(c.is_well_conditioned());
+        assert!(conditioning(&Array::zeros((2, 3))).is_err());
+    }
+
+    #[test]
+    fn flags_nearly_singular() {
+        let result = MVResult {
+            value: 0.5,
+            error: 1e-6,
+            nevals: 1000,
+            state: MVInform::PtLimitReached,
+        };
+        let near = conditioning(&arr2(&[[1.0, 1.0 - 1e-12], [1.0 - 1e-12, 1.0]])).unwrap();
+        assert!(near.condition_number > CONDITION_THRESHOLD && near.min_pivot < 1e-5);
+        assert_eq!(near.flag(result).state, MVInform::IllConditioned);
+        let singular = conditioning(&arr2(&[[1.0, 1.0], [1.0, 1.0]])).unwrap();
+        assert_eq!(singular.condition_number, ::std::f64::INFINITY);
+        assert_eq!(singular.min_pivot, 0.0);
+        let identity = conditioning(&Array::eye(3)).unwrap();
+        assert!((identity.condition_number - 1.0).abs() < 1e-12);
+        assert_eq!(identity.flag(result), result);
+    }
+}
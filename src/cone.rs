@@ -0,0 +1,410 @@
+//! Probabilities of polyhedral cones and other one-sided regions `{x : A x >= b}`, for `X` with
+//! mean zero, scatter matrix `cov` and distribution `kernel`, and of general polytopes
+//! `{x : A x <= b}` for `X` with any mean. `A` may have more rows than columns.
+//!
+//! The constraints are simplified before integration using the induced covariance `W = A cov Aᵀ`.
+//! A constraint with `W_ii = 0` holds with probability zero or one, so it is either dropped or
+//! makes the probability zero. The others are scaled to unit variance, and constraints that are
+//! then the same (correlation one) or opposite (correlation minus one) are merged into one
+//! constraint with the tightest of their lower and upper limits. If the merged constraints are
+//! still linearly dependent, the problem is integrated by the native backend, which factors
+//! rank-deficient covariances, rather than passed to the Fortran code.
+//!
+//! `PolytopeSampler` draws from a normal distribution truncated to a polytope, using the same
+//! simplified constraints.
+
+use linalg::{cholesky, solve_lower};
+use ndarray::prelude::*;
+use rand::Rng;
+use univariate::{norm_cdf, norm_inv};
+use {Backend, BoundType, EvalConfig, Kernel, MVInform, MVResult, MvDistError, Tolerance};
+
+/// The relative tolerance below which an induced variance is treated as zero.
+const TOL: f64 = 1e-12;
+
+fn zero_probability() -> MVResult {
+    MVResult {
+        value: 0.0,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    }
+}
+
+/// `P(A X >= 0)`, the probability of the polyhedral cone with the rows of `a` as inward normals.
+/// See `one_sided`.
+pub fn probability(cov: &Array2<f64>,
+                   kernel: Kernel,
+                   a: &Array2<f64>,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+    one_sided(cov, kernel, a, &Array::zeros((a.rows(),)), maxpts, abseps)
+}
+
+/// The constraints `lower <= rows Y <= upper` left by simplifying `A Y >= b`, where each row of
+/// `rows Y` has unit variance and `reduced` is their correlation matrix.
+struct Simplified {
+    rows: Array2<f64>,
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    reduced: Array2<f64>,
+}
+
+/// Simplify `A Y >= b` for `Y` with scatter matrix `cov` as described in the module
+/// documentation, or `None` if the constraints hold with probability zero.
+fn simplify(cov: &Array2<f64>,
+            a: &Array2<f64>,
+            b: &Array1<f64>)
+            -> Result<Option<Simplified>, MvDistError> {
+    let (n, m) = (cov.rows(), a.rows());
+    if cov.cols() != n || a.cols() != n || b.len() != m {
+        return Err(MvDistError::InvalidInput(format!("Expected a square covariance, {} \
+                                                      constraint columns and {} limits, got \
+                                                      {}x{}, {}x{} and {}",
+                                                     n,
+                                                     m,
+                                                     n,
+                                                     cov.cols(),
+                                                     m,
+                                                     a.cols(),
+                                                     b.len())));
+    }
+    if m == 0 {
+        return Err(MvDistError::InvalidInput("No constraints given".to_string()));
+    }
+    if b.iter().any(|x| x.is_nan()) {
+        return Err(MvDistError::InvalidInput("Constraint limits must not be NaN".to_string()));
+    }
+    let w = a.dot(cov).dot(&a.t());
+    let scale = (0..m).map(|i| w[[i, i]]).fold(0.0, f64::max);
+    if (0..m).any(|i| w[[i, i]] < -TOL * scale) {
+        return Err(MvDistError::NotPositiveSemidefinite);
+    }
+    // the merged constraints: a representative row and limits for its standardized value
+    let mut merged: Vec<(usize, f64, f64, f64)> = Vec::new();
+    for i in 0..m {
+        if w[[i, i]] <= TOL * scale {
+            // A_i Y is zero
+            if b[i] > 0.0 {
+                return Ok(None);
+            }
+            continue;
+        }
+        let sd = w[[i, i]].sqrt();
+        let lower = b[i] / sd;
+        let same = merged.iter_mut()
+            .map(|c| (w[[c.0, i]] / (c.1 * sd), c))
+            .find(|&(r, _)| (r.abs() - 1.0).abs() <= 1e-9);
+        match same {
+            Some((r, c)) if r > 0.0 => c.2 = c.2.max(lower),
+            Some((_, c)) => c.3 = c.3.min(-lower),
+            None => merged.push((i, sd, lower, ::std::f64::INFINITY)),
+        }
+    }
+    if merged.iter().any(|&(_, _, lo, hi)| !(lo < hi)) {
+        return Ok(None);
+    }
+    let k = merged.len();
+    let mut rows = Array2::zeros((k, n));
+    let mut reduced = Array2::zeros((k, k));
+    for (r, &(i, sd, _, _)) in merged.iter().enumerate() {
+        for j in 0..n {
+            rows[[r, j]] = a[[i, j]] / sd;
+        }
+        for (c, &(j, sd_j, _, _)) in merged.iter().enumerate() {
+            reduced[[r, c]] = w[[i, j]] / (sd * sd_j);
+        }
+    }
+    Ok(Some(Simplified {
+        rows: rows,
+        lower: merged.iter().map(|c| c.2).collect(),
+        upper: merged.iter().map(|c| c.3).collect(),
+        reduced: reduced,
+    }))
+}
+
+/// `P(A X >= b)`, after removing degenerate and repeated constraints as described in the module
+/// documentation. The remaining constraints are evaluated with `mvdist_config`, with `maxpts` and
+/// `abseps`.
+pub fn one_sided(cov: &Array2<f64>,
+                 kernel: Kernel,
+                 a: &Array2<f64>,
+                 b: &Array1<f64>,
+                 maxpts: u64,
+                 abseps: f64)
+                 -> Result<MVResult, MvDistError> {
+    let c = match simplify(cov, a, b)? {
+        Some(c) => c,
+        None => return Ok(zero_probability()),
+    };
+    let k = c.lower.len();
+    if k == 0 {
+        return Ok(MVResult { value: 1.0, ..zero_probability() });
+    }
+    let finite = |x: &f64| if x.is_finite() { *x } else { 0.0 };
+    let types = c.lower
+        .iter()
+        .zip(c.upper.iter())
+        .map(|(&lo, &hi)| BoundType::from_limits(lo, hi))
+        .collect::<Vec<BoundType>>();
+    let config = EvalConfig {
+        maxpts: maxpts,
+        tolerance: Tolerance::absolute(abseps)?,
+        backend: if cholesky(&c.reduced).is_some() {
+            None
+        } else {
+            Some(Backend::Native)
+        },
+        ..EvalConfig::default()
+    };
+    ::mvdist_config(cov,
+                    kernel,
+                    &Array::from_vec(c.lower.iter().map(&finite).collect()),
+                    &Array::from_vec(c.upper.iter().map(&finite).collect()),
+                    &types,
+                    &c.rows,
+                    &Array::zeros((k,)),
+                    &config)
+}
+
+/// `P(A X <= b)` for `X = mean + Y`, where `Y` has scatter matrix `cov` and distribution
+/// `kernel`: the probability of the polytope (or unbounded polyhedron) with the rows of `a` as
+/// outward normals. This is `one_sided` for `-A Y >= -(b - A mean)`, so repeated and degenerate
+/// constraints are handled as described there, and systems with more constraints than variables,
+/// whose induced covariance is singular, are integrated natively.
+pub fn polytope(cov: &Array2<f64>,
+                mean: &Array1<f64>,
+                kernel: Kernel,
+                a: &Array2<f64>,
+                b: &Array1<f64>,
+                maxpts: u64,
+                abseps: f64)
+                -> Result<MVResult, MvDistError> {
+    if mean.len() != a.cols() || b.len() != a.rows() {
+        return Err(MvDistError::InvalidInput(format!("Expected a mean of length {} and {} \
+                                                      limits, got {} and {}",
+                                                     a.cols(),
+                                                     a.rows(),
+                                                     mean.len(),
+                                                     b.len())));
+    }
+    let limits = a.dot(mean) - b;
+    one_sided(cov, kernel, &a.mapv(|x| -x), &limits, maxpts, abseps)
+}
+
+/// A draw from the standard normal distribution truncated to `(lo, hi)`, by inversion in the tail
+/// nearer to zero, where the CDF is accurate.
+fn truncated_normal<R: Rng>(lo: f64, hi: f64, rng: &mut R) -> f64 {
+    let u = rng.next_f64();
+    let z = if lo > 0.0 {
+        let (p, q) = (norm_cdf(-hi), norm_cdf(-lo));
+        -norm_inv(p + u * (q - p))
+    } else {
+        let (p, q) = (norm_cdf(lo), norm_cdf(hi));
+        norm_inv(p + u * (q - p))
+    };
+    z.max(lo).min(hi)
+}
+
+/// A Gibbs sampler for `X` normal with mean `mean` and covariance `cov`, conditioned on
+/// `A X <= b`. The sampler works with `Z = L⁻¹ (X - mean)` for the Cholesky factor `L` of `cov`,
+/// whose coordinates are independent standard normals before conditioning, and each sweep draws
+/// every coordinate of `Z` in turn from its truncated normal conditional distribution (Rodriguez-
+/// Yam, Davis and Scharf, 2004). The constraints are simplified as for `polytope`, so repeated
+/// constraints cost nothing per sweep.
+///
+/// Successive draws are dependent, strongly so for long, thin polytopes; discard a burn-in and
+/// thin the chain as for any Markov chain. `polytope` gives the normalizing constant.
+#[derive(Clone, Debug)]
+pub struct PolytopeSampler {
+    mean: Array1<f64>,
+    chol: Array2<f64>,
+    /// The simplified constraints in terms of `Z`: `lower <= d Z <= upper`.
+    d: Array2<f64>,
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    z: Vec<f64>,
+}
+
+impl PolytopeSampler {
+    /// A sampler whose chain starts at `start`, which must satisfy `A start <= b`. `cov` must be
+    /// positive definite.
+    pub fn new(cov: &Array2<f64>,
+               mean: &Array1<f64>,
+               a: &Array2<f64>,
+               b: &Array1<f64>,
+               start: &Array1<f64>)
+               -> Result<PolytopeSampler, MvDistError> {
+        let n = a.cols();
+        if mean.len() != n || start.len() != n || b.len() != a.rows() {
+            return Err(MvDistError::InvalidInput(format!("Expected a mean and start of length \
+                                                          {} and {} limits, got {}, {} and {}",
+                                                         n,
+                                                         a.rows(),
+                                                         mean.len(),
+                                                         start.len(),
+                                                         b.len())));
+        }
+        let c = simplify(cov, &a.mapv(|x| -x), &(a.dot(mean) - b))?
+            .ok_or_else(|| {
+                MvDistError::InvalidInput("The polytope has probability zero".to_string())
+            })?;
+        let chol = cholesky(cov).ok_or(MvDistError::NotPositiveSemidefinite)?;
+        let sampler = PolytopeSampler {
+            mean: mean.clone(),
+            d: c.rows.dot(&chol),
+            chol: chol,
+            lower: c.lower,
+            upper: c.upper,
+            z: Vec::new(),
+        };
+        let y = (start - mean).to_vec();
+        let z = solve_lower(&sampler.chol, &y);
+        let dz = sampler.d.dot(&Array::from_vec(z.clone()));
+        let slack = |x: f64| 1e-9 * (1.0 + x.abs());
+        for r in 0..dz.len() {
+            if !(dz[r] >= sampler.lower[r] - slack(sampler.lower[r]) &&
+                 dz[r] <= sampler.upper[r] + slack(sampler.upper[r])) {
+                return Err(MvDistError::InvalidInput("The starting point is outside the \
+                                                      polytope"
+                    .to_string()));
+            }
+        }
+        Ok(PolytopeSampler { z: z, ..sampler })
+    }
+
+    /// The current state of the chain.
+    pub fn state(&self) -> Array1<f64> {
+        &self.mean + &self.chol.dot(&Array::from_vec(self.z.clone()))
+    }
+
+    /// Advance the chain by one sweep over the coordinates and return the new state.
+    pub fn sample<R: Rng>(&mut self, rng: &mut R) -> Array1<f64> {
+        let (k, n) = (self.d.rows(), self.d.cols());
+        // d Z, recomputed each sweep so that rounding errors do not accumulate
+        let mut dz = self.d.dot(&Array::from_vec(self.z.clone()));
+        for i in 0..n {
+            let (mut lo, mut hi) = (::std::f64::NEG_INFINITY, ::std::f64::INFINITY);
+            for r in 0..k {
+                let c = self.d[[r, i]];
+                if c == 0.0 {
+                    continue;
+                }
+                let rest = dz[r] - c * self.z[i];
+                let (a, b) = ((self.lower[r] - rest) / c, (self.upper[r] - rest) / c);
+                if c > 0.0 {
+                    lo = lo.max(a);
+                    hi = hi.min(b);
+                } else {
+                    lo = lo.max(b);
+                    hi = hi.min(a);
+                }
+            }
+            // a coordinate pinned between numerically equal limits stays where it is
+            let z = if lo < hi { truncated_normal(lo, hi, rng) } else { self.z[i] };
+            for r in 0..k {
+                dz[r] += self.d[[r, i]] * (z - self.z[i]);
+            }
+            self.z[i] = z;
+        }
+        self.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use univariate::norm_cdf;
+
+    #[test]
+    fn redundant_constraints() {
+        let eye = Array::eye(2);
+        let cone = |a: Array2<f64>| probability(&eye, Kernel::Normal, &a, 100_000, 1e-6).unwrap();
+        // a repeated direction leaves the independent orthant, which is solved exactly
+        let r = cone(arr2(&[[1.0, 0.0], [2.0, 0.0], [0.0, 1.0]]));
+        assert!((r.value - 0.25).abs() < 1e-15 && r.error == 0.0);
+        // a third constraint implied by the others gives a singular induced covariance
+        let r = cone(arr2(&[[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]));
+        assert!((r.value - 0.25).abs() < 1e-5);
+        // opposite constraints merge into a two-sided one
+        let slab = one_sided(&eye,
+                             Kernel::Normal,
+                             &arr2(&[[1.0, 0.0], [-1.0, 0.0]]),
+                             &Array::from_vec(vec![-1.0, -1.0]),
+                             100_000,
+                             1e-8)
+            .unwrap();
+        assert!((slab.value - (norm_cdf(1.0) - norm_cdf(-1.0))).abs() < 1e-7);
+    }
+
+    #[test]
+    fn polytopes() {
+        let (eye, zero) = (Array::eye(2), Array::zeros((2,)));
+        // more constraints than variables: the quadrant x <= 0, with x_1 + x_2 <= 0 implied and x_1
+        // bounded below
+        let a = arr2(&[[1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [-1.0, 0.0]]);
+        let b = Array::from_vec(vec![0.0, 0.0, 0.0, 5.0]);
+        let r = polytope(&eye, &zero, Kernel::Normal, &a, &b, 200_000, 1e-7).unwrap();
+        assert!((r.value - 0.5 * (0.5 - norm_cdf(-5.0))).abs() < 1e-6);
+        // a shifted mean moves the limits
+        let mean = Array::from_vec(vec![1.0, -2.0]);
+        let b = Array::from_vec(vec![1.0, -2.0]);
+        let r = polytope(&eye, &mean, Kernel::Normal, &eye, &b, 100_000, 1e-8).unwrap();
+        assert!((r.value - 0.25).abs() < 1e-7);
+        assert!(polytope(&eye, &Array::zeros((3,)), Kernel::Normal, &eye, &b, 1000, 1e-8)
+            .is_err());
+    }
+
+    #[test]
+    fn samples_stay_in_the_polytope() {
+        use rand::{SeedableRng, StdRng};
+        let cov = arr2(&[[1.0, 0.6], [0.6, 2.0]]);
+        let mean = Array::from_vec(vec![0.5, -0.5]);
+        // the triangle x >= 0, x_1 + x_2 <= 1, with the first constraint repeated
+        let a = arr2(&[[-1.0, 0.0], [-2.0, 0.0], [0.0, -1.0], [1.0, 1.0]]);
+        let b = Array::from_vec(vec![0.0, 0.0, 0.0, 1.0]);
+        let start = Array::from_vec(vec![0.25, 0.25]);
+        let mut sampler = PolytopeSampler::new(&cov, &mean, &a, &b, &start).unwrap();
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4][..]);
+        for _ in 0..1000 {
+            let x = sampler.sample(&mut rng);
+            assert!(x.iter().all(|&x| x >= 0.0) && x[0] + x[1] <= 1.0 + 1e-12);
+        }
+        // a half-space: the mean of a normal truncated to x_1 <= 0 is -sqrt(2 / pi)
+        let eye = Array::eye(2);
+        let zero = Array::zeros((2,));
+        let half = arr2(&[[1.0, 0.0]]);
+        let mut sampler =
+            PolytopeSampler::new(&eye, &zero, &half, &Array::zeros((1,)), &zero).unwrap();
+        let draws = 20_000;
+        let mut sum = Array::zeros((2,));
+        for _ in 0..draws {
+            sum = sum + sampler.sample(&mut rng);
+        }
+        let expected = -(2.0 / ::std::f64::consts::PI).sqrt();
+        assert!((sum[0] / draws as f64 - expected).abs() < 0.02);
+        assert!((sum[1] / draws as f64).abs() < 0.03);
+        let outside = Array::from_vec(vec![1.0, 0.0]);
+        assert!(PolytopeSampler::new(&eye, &zero, &half, &Array::zeros((1,)), &outside).is_err());
+    }
+
+    #[test]
+    fn degenerate_constraints() {
+        let cov = arr2(&[[1.0, 1.0], [1.0, 1.0]]);
+        let t = Kernel::StudentT { nu: 3.0 };
+        let limited = |a: Array2<f64>, b: Vec<f64>| {
+            one_sided(&cov, t, &a, &Array::from_vec(b), 10_000, 1e-6).unwrap().value
+        };
+        // X_1 - X_2 is zero
+        let a = arr2(&[[1.0, -1.0], [1.0, 0.0]]);
+        assert_eq!(limited(a.clone(), vec![0.5, 0.0]), 0.0);
+        assert!((limited(a, vec![-0.5, 0.0]) - 0.5).abs() < 1e-6);
+        assert_eq!(limited(arr2(&[[0.0, 0.0]]), vec![0.0]), 1.0);
+        // X_1 >= 1 and X_2 <= 0 cannot both hold when X_1 = X_2
+        assert_eq!(limited(arr2(&[[1.0, 0.0], [0.0, -1.0]]), vec![1.0, 0.0]), 0.0);
+        assert!(one_sided(&cov, t, &arr2(&[[1.0, 0.0]]), &Array::zeros((2,)), 1000, 1e-3)
+            .is_err());
+    }
+}
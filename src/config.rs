@@ -0,0 +1,417 @@
+//! Settings for evaluations, and the defaults they start from.
+//!
+//! `EvalConfig::default()` gives the current defaults. They are `EvalConfig::builtin()` unless an
+//! application replaced them once at startup with `set_defaults`, and any thread can override them
+//! for a scope with `scoped_defaults`. A library embedding `mvdist` can thus take its settings from
+//! the application without threading an `EvalConfig` through every layer, while an explicit
+//! `EvalConfig`, or the explicit arguments of `mvdist_kernel`, still apply to their own call.
+
+use native::Reorder;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use {Backend, MVInform, MVResult, MvDistError};
+
+/// The error an evaluation is required to reach: it stops once its error is at most
+/// `max(abseps, releps * |value|)`. Both tolerances are finite and non-negative, and at least one
+/// is positive, since requiring an exact result would always spend the whole point budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawTolerance"))]
+pub struct Tolerance {
+    abseps: f64,
+    releps: f64,
+}
+
+impl Tolerance {
+    /// Stop once the error is at most `abseps`.
+    pub fn absolute(abseps: f64) -> Result<Tolerance, MvDistError> {
+        Tolerance::both(abseps, 0.0)
+    }
+
+    /// Stop once the error is at most `releps` times the magnitude of the value.
+    pub fn relative(releps: f64) -> Result<Tolerance, MvDistError> {
+        Tolerance::both(0.0, releps)
+    }
+
+    /// Stop once either `absolute(abseps)` or `relative(releps)` is satisfied. Either may be zero,
+    /// but not both.
+    pub fn both(abseps: f64, releps: f64) -> Result<Tolerance, MvDistError> {
+        for &(name, eps) in &[("abseps", abseps), ("releps", releps)] {
+            if !(eps >= 0.0 && eps.is_finite()) {
+                return Err(MvDistError::InvalidInput(format!("`{}` must be finite and \
+                                                              non-negative, got {}",
+                                                             name,
+                                                             eps)));
+            }
+        }
+        if abseps == 0.0 && releps == 0.0 {
+            return Err(MvDistError::InvalidInput("`abseps` and `releps` are both zero"
+                .to_string()));
+        }
+        Ok(Tolerance {
+            abseps: abseps,
+            releps: releps,
+        })
+    }
+
+    pub fn abseps(&self) -> f64 {
+        self.abseps
+    }
+
+    pub fn releps(&self) -> f64 {
+        self.releps
+    }
+
+    /// The error required of an estimate `value`, `max(abseps, releps * |value|)`.
+    pub fn bound(&self, value: f64) -> f64 {
+        self.abseps.max(self.releps * value.abs())
+    }
+}
+
+/// `absolute(1e-5)`.
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance {
+            abseps: 1e-5,
+            releps: 0.0,
+        }
+    }
+}
+
+/// How an evaluation that reaches its point limit is retried: each retry starts again with
+/// `maxpts` multiplied by `backoff`, up to `max_doublings` retries and `total_eval_budget`
+/// evaluations over all the attempts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RetryPolicy {
+    /// The most retries. Default 0, returning the first result as it is.
+    pub max_doublings: u32,
+    /// The most evaluations over all attempts, or `None` (the default) for no limit. An attempt
+    /// that would exceed it is given what remains, and there are no retries once that is no more
+    /// than the previous attempt had.
+    pub total_eval_budget: Option<u64>,
+    /// The factor by which `maxpts` grows at each retry, greater than 1. Default 2.
+    pub backoff: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_doublings: 0,
+            total_eval_budget: None,
+            backoff: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `eval(maxpts)`, retried with larger budgets while the result is
+    /// `MVInform::PtLimitReached`. The result is that of the last attempt, with `nevals` counting
+    /// every attempt.
+    pub fn run<F>(&self, maxpts: u64, mut eval: F) -> Result<MVResult, MvDistError>
+        where F: FnMut(u64) -> Result<MVResult, MvDistError>
+    {
+        if !(self.backoff > 1.0) {
+            return Err(MvDistError::InvalidInput(format!("Invalid retry policy {:?}", self)));
+        }
+        let budget = self.total_eval_budget.unwrap_or(::std::u64::MAX);
+        let mut maxpts = maxpts.min(budget);
+        let mut result = eval(maxpts)?;
+        let mut spent = result.nevals;
+        for _ in 0..self.max_doublings {
+            let next = (maxpts as f64 * self.backoff).min(::std::u64::MAX as f64) as u64;
+            let next = next.min(budget.saturating_sub(spent));
+            if result.state != MVInform::PtLimitReached || next <= maxpts {
+                break;
+            }
+            maxpts = next;
+            result = eval(maxpts)?;
+            spent = spent.saturating_add(result.nevals);
+        }
+        result.nevals = spent;
+        Ok(result)
+    }
+}
+
+/// The settings of an evaluation, as taken by `mvdist_config` and held by `MvProblem`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EvalConfig {
+    /// The most integrand evaluations to use. Default 100000.
+    pub maxpts: u64,
+    /// Default `Tolerance::default()`.
+    pub tolerance: Tolerance,
+    /// Seed for the random shifts of the native backend; the Fortran backend uses its own internal
+    /// generator. Default `None`, for `native::DEFAULT_SEED`.
+    pub seed: Option<u64>,
+    /// The backend to integrate with, or `None` (the default) to choose one as `mvdist_kernel`
+    /// does.
+    pub backend: Option<Backend>,
+    /// The constraint ordering of the native backend. Default `Reorder::Auto`, which is what the
+    /// Fortran backend always uses.
+    pub reorder: Reorder,
+    /// Default `RetryPolicy::default()`, without retries.
+    pub retry: RetryPolicy,
+}
+
+/// The current defaults: those of the innermost `scoped_defaults` guard of this thread, or else
+/// those of `set_defaults`, or else `EvalConfig::builtin()`.
+impl Default for EvalConfig {
+    fn default() -> EvalConfig {
+        SCOPES.with(|scopes| scopes.borrow().last().cloned())
+            .unwrap_or_else(|| global_defaults().clone())
+    }
+}
+
+impl EvalConfig {
+    /// The settings of the crate: 100000 points, `Tolerance::default()`, `native::DEFAULT_SEED`,
+    /// the backend chosen as `mvdist_kernel` does, `Reorder::Auto` and no retries.
+    pub fn builtin() -> EvalConfig {
+        EvalConfig {
+            maxpts: 100_000,
+            tolerance: Tolerance::default(),
+            seed: None,
+            backend: None,
+            reorder: Reorder::Auto,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Settings for about `target_digits` correct decimal digits of an `n`-dimensional probability:
+    /// an absolute tolerance of `10^-target_digits`, and a budget of `25000 n` points for three
+    /// digits, following the `mvtnorm` default of 25000 points for `abseps = 0.001` scaled with the
+    /// dimension, multiplied by 10 for each further digit since lattice rules converge at close to
+    /// `1 / maxpts`. The budget is at least 1000 points.
+    ///
+    /// `target_digits` must be between 1 and 12; beyond that the rounding error of the integrand
+    /// is comparable to the tolerance. For small probabilities consider a relative tolerance
+    /// instead. The other settings are the defaults.
+    pub fn recommended_for(n: usize, target_digits: u32) -> Result<EvalConfig, MvDistError> {
+        if n == 0 {
+            return Err(MvDistError::InvalidInput("No variables given".to_string()));
+        }
+        if target_digits < 1 || target_digits > 12 {
+            return Err(MvDistError::InvalidInput(format!("Cannot target {} digits, expected 1 \
+                                                          to 12",
+                                                         target_digits)));
+        }
+        let digits = target_digits as i32;
+        let maxpts = 25_000.0 * n as f64 * 10f64.powi(digits - 3);
+        Ok(EvalConfig {
+            maxpts: maxpts.max(1000.0).min(::std::u64::MAX as f64) as u64,
+            tolerance: Tolerance::absolute(10f64.powi(-digits))?,
+            ..EvalConfig::default()
+        })
+    }
+}
+
+/// The defaults set by `set_defaults`, or null before they are set or first used. Once stored they
+/// are never freed, so references to them live for the rest of the program.
+static DEFAULTS: AtomicPtr<EvalConfig> = AtomicPtr::new(ptr::null_mut());
+
+thread_local! {
+    /// The overrides of the live `scoped_defaults` guards of this thread, innermost last.
+    static SCOPES: RefCell<Vec<EvalConfig>> = RefCell::new(Vec::new());
+}
+
+/// Check that `config` can serve as the defaults of problems of any size.
+fn check_defaults(config: &EvalConfig) -> Result<(), MvDistError> {
+    if let Some(backend) = config.backend {
+        if !backend.is_available() {
+            return Err(MvDistError::InvalidInput(format!("The {:?} backend was not built",
+                                                         backend)));
+        }
+    }
+    if let Reorder::Custom(_) = config.reorder {
+        return Err(MvDistError::InvalidInput("A custom constraint order cannot be a default"
+            .to_string()));
+    }
+    Ok(())
+}
+
+fn global_defaults() -> &'static EvalConfig {
+    if DEFAULTS.load(Ordering::Acquire).is_null() {
+        // fixes the built-in defaults unless another thread set them first
+        let _ = set_defaults(EvalConfig::builtin());
+    }
+    unsafe { &*DEFAULTS.load(Ordering::Acquire) }
+}
+
+/// Make `config` the defaults of every thread, for the rest of the program. The defaults can be
+/// set only once, before anything has used them, so that every evaluation sees the same ones; this
+/// is an error otherwise, as it is for a backend that was not built or a `Reorder::Custom`, which
+/// only suits problems of one size.
+pub fn set_defaults(config: EvalConfig) -> Result<(), MvDistError> {
+    check_defaults(&config)?;
+    let new = Box::into_raw(Box::new(config));
+    match DEFAULTS.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            drop(unsafe { Box::from_raw(new) });
+            Err(MvDistError::InvalidInput("The defaults were already set or used".to_string()))
+        }
+    }
+}
+
+/// Make `config` the defaults of this thread until the returned guard is dropped, when the
+/// previous defaults return. Guards nest, and dropping one also ends any made after it. Other
+/// threads, including those of the `rayon` pool, keep their own defaults, so a problem to be
+/// evaluated in parallel should take its `EvalConfig` before it is handed to them. It is an error
+/// to override the defaults with a backend that was not built or a `Reorder::Custom`.
+pub fn scoped_defaults(config: EvalConfig) -> Result<ScopedDefaults, MvDistError> {
+    check_defaults(&config)?;
+    let depth = SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        scopes.push(config);
+        scopes.len() - 1
+    });
+    Ok(ScopedDefaults {
+        depth: depth,
+        thread: PhantomData,
+    })
+}
+
+/// The guard of `scoped_defaults`, which restores the previous defaults when dropped.
+#[derive(Debug)]
+#[must_use]
+pub struct ScopedDefaults {
+    /// The number of overrides made before this one.
+    depth: usize,
+    /// Ties the guard to the thread whose defaults it overrides.
+    thread: PhantomData<*const ()>,
+}
+
+impl Drop for ScopedDefaults {
+    fn drop(&mut self) {
+        let depth = self.depth;
+        SCOPES.with(|scopes| scopes.borrow_mut().truncate(depth));
+    }
+}
+
+/// The unvalidated form of `Tolerance` read by `Deserialize`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawTolerance {
+    abseps: f64,
+    releps: f64,
+}
+
+#[cfg(feature = "serde")]
+impl ::std::convert::TryFrom<RawTolerance> for Tolerance {
+    type Error = MvDistError;
+
+    fn try_from(raw: RawTolerance) -> Result<Tolerance, MvDistError> {
+        Tolerance::both(raw.abseps, raw.releps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation() {
+        let t = Tolerance::both(1e-5, 1e-3).unwrap();
+        assert_eq!((t.abseps(), t.releps()), (1e-5, 1e-3));
+        assert_eq!(t.bound(0.001), 1e-5);
+        assert_eq!(t.bound(-0.5), 5e-4);
+        assert_eq!(Tolerance::relative(1e-3).unwrap().bound(0.0), 0.0);
+        assert_eq!(Tolerance::default(), Tolerance::absolute(1e-5).unwrap());
+        assert!(Tolerance::both(0.0, 0.0).is_err());
+        assert!(Tolerance::absolute(-1e-5).is_err());
+        assert!(Tolerance::relative(::std::f64::NAN).is_err());
+        assert!(Tolerance::both(::std::f64::INFINITY, 0.0).is_err());
+    }
+
+    #[test]
+    fn recommended_budgets() {
+        let c = EvalConfig::recommended_for(50, 3).unwrap();
+        assert_eq!(c.maxpts, 1_250_000);
+        assert!((c.tolerance.abseps() - 1e-3).abs() < 1e-18 && c.tolerance.releps() == 0.0);
+        assert_eq!(EvalConfig::recommended_for(4, 5).unwrap().maxpts, 10_000_000);
+        assert_eq!(EvalConfig::recommended_for(1, 1).unwrap().maxpts, 1000);
+        assert!(EvalConfig::recommended_for(0, 3).is_err());
+        assert!(EvalConfig::recommended_for(2, 0).is_err());
+        assert!(EvalConfig::recommended_for(2, 13).is_err());
+    }
+
+    #[test]
+    fn retries_double_the_budget() {
+        // an evaluation that needs 5000 points
+        let eval = |maxpts: u64| {
+            Ok(MVResult {
+                value: 0.5,
+                error: 0.0,
+                nevals: maxpts.min(5000),
+                state: if maxpts < 5000 {
+                    MVInform::PtLimitReached
+                } else {
+                    MVInform::Normal
+                },
+            })
+        };
+        let policy = RetryPolicy {
+            max_doublings: 5,
+            ..RetryPolicy::default()
+        };
+        let r = policy.run(1000, eval).unwrap();
+        assert_eq!((r.state, r.nevals), (MVInform::Normal, 1000 + 2000 + 4000 + 5000));
+        let r = RetryPolicy::default().run(1000, eval).unwrap();
+        assert_eq!((r.state, r.nevals), (MVInform::PtLimitReached, 1000));
+        // the last retry gets the 4000 points remaining of the budget
+        let capped = RetryPolicy {
+            total_eval_budget: Some(7000),
+            ..policy
+        };
+        let r = capped.run(1000, eval).unwrap();
+        assert_eq!((r.state, r.nevals), (MVInform::PtLimitReached, 7000));
+        let invalid = RetryPolicy {
+            backoff: 1.0,
+            ..policy
+        };
+        assert!(invalid.run(1000, eval).is_err());
+    }
+
+    #[test]
+    fn scoped_overrides() {
+        // setting the built-in defaults leaves the other tests unaffected
+        let _ = set_defaults(EvalConfig::builtin());
+        assert!(set_defaults(EvalConfig::builtin()).is_err());
+        assert_eq!(EvalConfig::default(), EvalConfig::builtin());
+        let fast = EvalConfig {
+            maxpts: 1000,
+            backend: Some(Backend::Native),
+            ..EvalConfig::builtin()
+        };
+        let seeded = EvalConfig {
+            seed: Some(7),
+            ..fast.clone()
+        };
+        {
+            let _outer = scoped_defaults(fast.clone()).unwrap();
+            assert_eq!(EvalConfig::default(), fast);
+            {
+                let _inner = scoped_defaults(seeded.clone()).unwrap();
+                assert_eq!(EvalConfig::default(), seeded);
+                // other threads keep the global defaults
+                let other = ::std::thread::spawn(EvalConfig::default).join().unwrap();
+                assert_eq!(other, EvalConfig::builtin());
+            }
+            assert_eq!(EvalConfig::default(), fast);
+            // dropping a guard ends the overrides made after it
+            let outer = scoped_defaults(seeded.clone()).unwrap();
+            let _inner = scoped_defaults(EvalConfig::builtin()).unwrap();
+            drop(outer);
+            assert_eq!(EvalConfig::default(), fast);
+        }
+        assert_eq!(EvalConfig::default(), EvalConfig::builtin());
+        let custom = EvalConfig {
+            reorder: Reorder::Custom(vec![1, 0]),
+            ..EvalConfig::builtin()
+        };
+        assert!(scoped_defaults(custom.clone()).is_err() && set_defaults(custom).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Evaluation contexts, for workloads of millions of small evaluations where allocating the
+//! working copies of every problem is a measurable share of the time, and for services that must
+//! refuse problems too large for their memory rather than fail partway through.
+//!
+//! An `EvalContext` keeps the buffers of the copies that the Fortran routines need (the arrays in
+//! column-major order and the bound codes) from one evaluation to the next, growing them only for
+//! a larger problem, and likewise the `native::Workspace` of the native backend, which holds the
+//! induced covariance, its factor and the lattice points of each randomization; the native
+//! backend reads standard-layout arrays in place. The transformed constraints of each problem are
+//! still allocated by its evaluation. With a memory limit, problems whose setup would need more
+//! than the limit, as estimated by `memory_required`, are rejected with
+//! `MvDistError::MemoryLimitExceeded` before anything is allocated.
+
+use ndarray::prelude::*;
+use {BoundType, EvalConfig, Kernel, MVResult, MvDistError, MvProblem, Scratch};
+
+/// Reusable buffers and an optional memory limit for a sequence of evaluations on one thread.
+#[derive(Clone, Debug, Default)]
+pub struct EvalContext {
+    scratch: Scratch,
+    memory_limit: Option<usize>,
+}
+
+impl EvalContext {
+    pub fn new() -> EvalContext {
+        EvalContext::default()
+    }
+
+    /// A context refusing problems that need more than about `bytes` bytes of working memory.
+    pub fn with_memory_limit(bytes: usize) -> EvalContext {
+        EvalContext {
+            memory_limit: Some(bytes),
+            ..EvalContext::default()
+        }
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// Evaluate `problem` as by `MvProblem::mvdist`, with the buffers of this context.
+    pub fn mvdist(&mut self, problem: &MvProblem) -> Result<MVResult, MvDistError> {
+        self.mvdist_config(&problem.cov,
+                           problem.kernel,
+                           &problem.lb,
+                           &problem.ub,
+                           &problem.types,
+                           &problem.constraints,
+                           &problem.delta,
+                           &problem.config)
+    }
+
+    /// `mvdist_config` with the buffers of this context.
+    pub fn mvdist_config(&mut self,
+                         cov: &Array2<f64>,
+                         kernel: Kernel,
+                         lb: &Array1<f64>,
+                         ub: &Array1<f64>,
+                         types: &[BoundType],
+                         constraints: &Array2<f64>,
+                         delta: &Array1<f64>,
+                         config: &EvalConfig)
+                         -> Result<MVResult, MvDistError> {
+        if let Some(limit) = self.memory_limit {
+            let required = memory_required(constraints.cols(), constraints.rows());
+            if required > limit {
+                return Err(MvDistError::MemoryLimitExceeded {
+                    required: required,
+                    limit: limit,
+                });
+            }
+        }
+        ::mvdist_config_in(cov,
+                           kernel,
+                           lb,
+                           ub,
+                           types,
+                           constraints,
+                           delta,
+                           config,
+                           &mut self.scratch)
+    }
+}
+
+/// An estimate, in bytes, of the working memory of evaluating `m` constraints on `n` variables:
+/// the copies of the covariance and constraints, the induced covariance of the constraints, its
+/// factor and the transformed constraints, which for large problems dominate the `O(n)` buffers
+/// of the lattice rules.
+pub fn memory_required(n: usize, m: usize) -> usize {
+    let words = n.saturating_mul(n)
+        .saturating_add(m.saturating_mul(n).saturating_mul(3))
+        .saturating_add(m.saturating_mul(m).saturating_mul(3))
+        .saturating_add(m.saturating_mul(16));
+    words.saturating_mul(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {mvdist_config, Backend};
+
+    #[test]
+    fn reuses_buffers_across_problems() {
+        let mut context = EvalContext::new();
+        for &n in &[3, 5, 2] {
+            let cov = ::corr::exchangeable(n, 0.3);
+            let problem = MvProblem {
+                config: EvalConfig {
+                    backend: Some(Backend::current()),
+                    ..EvalConfig::default()
+                },
+                ..MvProblem::new(cov,
+                                 Array::from_elem((n,), -1.0),
+                                 Array::from_elem((n,), 1.5),
+                                 vec![BoundType::Both; n])
+            };
+            assert_eq!(context.mvdist(&problem), problem.mvdist());
+        }
+    }
+
+    #[test]
+    fn reuses_the_native_workspace() {
+        let mut context = EvalContext::new();
+        let problem = |n: usize| {
+            MvProblem {
+                config: EvalConfig {
+                    backend: Some(Backend::Native),
+                    ..EvalConfig::default()
+                },
+                ..MvProblem::new(::corr::exchangeable(n, 0.3),
+                                 Array::from_elem((n,), -1.0),
+                                 Array::from_elem((n,), 1.5),
+                                 vec![BoundType::Both; n])
+            }
+        };
+        assert_eq!(context.scratch.workspace.capacity(), 0);
+        assert_eq!(context.mvdist(&problem(6)), problem(6).mvdist());
+        let capacity = context.scratch.workspace.capacity();
+        assert!(capacity >= 6 * 6);
+        // neither a second evaluation nor a smaller problem grows the buffers
+        assert_eq!(context.mvdist(&problem(6)), problem(6).mvdist());
+        assert_eq!(context.mvdist(&problem(4)), problem(4).mvdist());
+        assert_eq!(context.scratch.workspace.capacity(), capacity);
+    }
+
+    #[test]
+    fn enforces_the_memory_limit() {
+        let n = 100;
+        let zeros = Array::zeros((n,));
+        let required = memory_required(n, n);
+        let huge = ::std::usize::MAX / 2;
+        assert!(required > 8 * n * n && memory_required(huge, huge) == ::std::usize::MAX);
+        let mut context = EvalContext::with_memory_limit(required - 1);
+        let result = context.mvdist_config(&Array::eye(n),
+                                           Kernel::Normal,
+                                           &zeros,
+                                           &zeros,
+                                           &vec![BoundType::Above; n],
+                                           &Array::eye(n),
+                                           &zeros,
+                                           &EvalConfig::default());
+        assert_eq!(result,
+                   Err(MvDistError::MemoryLimitExceeded {
+                       required: required,
+                       limit: required - 1,
+                   }));
+        let mut context = EvalContext::with_memory_limit(required);
+        let result = context.mvdist_config(&Array::eye(n),
+                                           Kernel::Normal,
+                                           &zeros,
+                                           &zeros,
+                                           &vec![BoundType::Above; n],
+                                           &Array::eye(n),
+                                           &zeros,
+                                           &EvalConfig::default());
+        assert_eq!(result,
+                   mvdist_config(&Array::eye(n),
+                                 Kernel::Normal,
+                                 &zeros,
+                                 &zeros,
+                                 &vec![BoundType::Above; n],
+                                 &Array::eye(n),
+                                 &zeros,
+                                 &EvalConfig::default()));
+    }
+}
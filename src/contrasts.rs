@@ -0,0 +1,129 @@
+//! Constructors for contrast matrices used in multiple comparisons. Each returns an `Array2<f64>`
+//! with one row per comparison and one column per group mean, which can be passed directly as the
+//! `constraints` argument of `mvdist` and `mvcrit` together with the covariance of the means.
+
+use ndarray::prelude::*;
+
+/// Many-to-one (Dunnett) contrasts comparing each of `k` treatments against a control: a
+/// `k x (k + 1)` matrix whose row `i` is `mu[i + 1] - mu[0]`. The control is the first group.
+pub fn dunnett(k: usize) -> Array2<f64> {
+    let mut c = Array2::zeros((k, k + 1));
+    for i in 0..k {
+        c[[i, 0]] = -1.0;
+        c[[i, i + 1]] = 1.0;
+    }
+    c
+}
+
+/// All-pairs (Tukey) contrasts among `k` groups: a `k(k - 1)/2 x k` matrix with a row
+/// `mu[j] - mu[i]` for each pair `i < j`, ordered by `i` and then `j`.
+pub fn tukey(k: usize) -> Array2<f64> {
+    let pairs = pairs(k);
+    let mut c = Array2::zeros((pairs.len(), k));
+    for (r, &(i, j)) in pairs.iter().enumerate() {
+        c[[r, i]] = -1.0;
+        c[[r, j]] = 1.0;
+    }
+    c
+}
+
+fn pairs(k: usize) -> Vec<(usize, usize)> {
+    (0..k).flat_map(|i| (i + 1..k).map(move |j| (i, j))).collect()
+}
+
+/// A contrast matrix with a label for each row, for reporting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contrasts {
+    pub labels: Vec<String>,
+    pub matrix: Array2<f64>,
+}
+
+impl Contrasts {
+    /// User-defined contrasts among `groups` groups, one row per `(label, coefficients)` pair.
+    /// Fails if a row does not have `groups` finite coefficients.
+    pub fn new(groups: usize, rows: &[(&str, &[f64])]) -> Result<Contrasts, String> {
+        let mut matrix = Array2::zeros((rows.len(), groups));
+        for (r, &(label, coef)) in rows.iter().enumerate() {
+            if coef.len() != groups {
+                return Err(format!("Contrast `{}` has {} coefficients, expected {}",
+                                   label,
+                                   coef.len(),
+                                   groups));
+            }
+            if coef.iter().any(|c| !c.is_finite()) {
+                return Err(format!("Contrast `{}` has non-finite coefficients", label));
+            }
+            for (g, &c) in coef.iter().enumerate() {
+                matrix[[r, g]] = c;
+            }
+        }
+        Ok(Contrasts {
+            labels: rows.iter().map(|&(label, _)| label.to_string()).collect(),
+            matrix: matrix,
+        })
+    }
+
+    /// `dunnett` for the groups `names`, the first of which is the control, with labels of the
+    /// form `"B - A"`.
+    pub fn dunnett(names: &[&str]) -> Contrasts {
+        let k = names.len().saturating_sub(1);
+        Contrasts {
+            labels: names.iter().skip(1).map(|t| format!("{} - {}", t, names[0])).collect(),
+            matrix: dunnett(k),
+        }
+    }
+
+    /// `tukey` for the groups `names`, with labels of the form `"B - A"`.
+    pub fn tukey(names: &[&str]) -> Contrasts {
+        Contrasts {
+            labels: pairs(names.len())
+                .iter()
+                .map(|&(i, j)| format!("{} - {}", names[j], names[i]))
+                .collect(),
+            matrix: tukey(names.len()),
+        }
+    }
+
+    /// Number of contrasts.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Whether there are no contrasts.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn dunnett_compares_against_first_group() {
+        assert_eq!(dunnett(3),
+                   arr2(&[[-1.0, 1.0, 0.0, 0.0], [-1.0, 0.0, 1.0, 0.0], [-1.0, 0.0, 0.0, 1.0]]));
+        assert_eq!(dunnett(0).dim(), (0, 1));
+    }
+
+    #[test]
+    fn tukey_covers_all_pairs() {
+        assert_eq!(tukey(3),
+                   arr2(&[[-1.0, 1.0, 0.0], [-1.0, 0.0, 1.0], [0.0, -1.0, 1.0]]));
+        assert_eq!(tukey(5).dim(), (10, 5));
+        let c = Contrasts::tukey(&["A", "B", "C"]);
+        assert_eq!(c.labels, vec!["B - A", "C - A", "C - B"]);
+        assert_eq!(c.matrix, tukey(3));
+        assert_eq!(Contrasts::dunnett(&["ctl", "t1"]).labels, vec!["t1 - ctl"]);
+    }
+
+    #[test]
+    fn custom_contrasts() {
+        let c = Contrasts::new(3, &[("trend", &[-1.0, 0.0, 1.0]), ("mid", &[-0.5, 1.0, -0.5])])
+            .unwrap();
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.matrix[[1, 1]], 1.0);
+        assert!(Contrasts::new(3, &[("short", &[1.0, -1.0])]).is_err());
+    }
+}
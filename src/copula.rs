@@ -0,0 +1,328 @@
+//! Goodness-of-fit tests of normal and t copulas, for validating a copula model after fitting it
+//! and evaluating probabilities with it. The statistics of Genest, Rémillard & Beaudoin (2009)
+//! compare the empirical copula `C_n` of the pseudo-observations `U_1, ..., U_n` with the copula
+//! `C` fitted to them, at the observations:
+//!
+//! - Cramér–von Mises, `S_n = Σ_i (C_n(U_i) - C(U_i))²`;
+//! - Kolmogorov–Smirnov, `T_n = sqrt(n) max_i |C_n(U_i) - C(U_i)|`.
+//!
+//! Their p-values are found by parametric bootstrap: samples of the same size are drawn from the
+//! fitted copula with the samplers of `distribution`, refitted, and their statistics compared with
+//! those of the data. The correlation is fitted by inverting Kendall's tau, `ρ = sin(π τ / 2)`,
+//! which holds for every elliptical copula, and which the bootstrap repeats, so the p-values are
+//! those of that estimator. The degrees of freedom of a t copula are taken as given, not fitted.
+
+use distribution::{MultivariateNormal, MultivariateStudent, Sample};
+use linalg::cholesky;
+use native::{SeedStreams, SplitMix64, DEFAULT_SEED};
+use ndarray::prelude::*;
+use rand::Rng;
+use std::f64::consts::PI;
+use univariate::{norm_cdf, norm_inv, t_cdf, t_inv};
+use {mvdist_config, BoundType, EvalConfig, Kernel, MVResult, MvDistError};
+
+/// The pseudo-observations of the rows of `data`: in each column, the rank of each value divided
+/// by `n + 1` for `n` rows, with tied values given their average rank. Fails if any value is NaN,
+/// which has no rank.
+pub fn pseudo_observations(data: &Array2<f64>) -> Result<Array2<f64>, MvDistError> {
+    let (n, d) = (data.rows(), data.cols());
+    if data.iter().any(|x| x.is_nan()) {
+        return Err(MvDistError::InvalidInput(format!("Observations must not be NaN")));
+    }
+    let mut u = Array2::zeros((n, d));
+    for k in 0..d {
+        let mut order = (0..n).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| data[[a, k]].partial_cmp(&data[[b, k]]).unwrap());
+        let mut start = 0;
+        while start < n {
+            let mut end = start + 1;
+            while end < n && data[[order[end], k]] == data[[order[start], k]] {
+                end += 1;
+            }
+            // ranks start + 1 to end share their average
+            let rank = (start + end + 1) as f64 / 2.0;
+            for &i in &order[start..end] {
+                u[[i, k]] = rank / (n + 1) as f64;
+            }
+            start = end;
+        }
+    }
+    Ok(u)
+}
+
+/// The correlation matrix `sin(π τ / 2)` of the Kendall's tau of each pair of columns of `u`,
+/// with tied pairs counting as neither concordant nor discordant. Fails unless it is positive
+/// definite.
+pub fn kendall_correlation(u: &Array2<f64>) -> Result<Array2<f64>, MvDistError> {
+    let (n, d) = (u.rows(), u.cols());
+    if n < 2 {
+        return Err(MvDistError::InvalidInput(format!("At least 2 observations are needed, got {}",
+                                                     n)));
+    }
+    let pairs = (n * (n - 1) / 2) as f64;
+    let mut corr = Array::eye(d);
+    for a in 0..d {
+        for b in 0..a {
+            let mut s = 0.0;
+            for i in 0..n {
+                for j in 0..i {
+                    let c = (u[[i, a]] - u[[j, a]]) * (u[[i, b]] - u[[j, b]]);
+                    s += if c > 0.0 {
+                        1.0
+                    } else if c < 0.0 {
+                        -1.0
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            let rho = (0.5 * PI * s / pairs).sin();
+            corr[[a, b]] = rho;
+            corr[[b, a]] = rho;
+        }
+    }
+    if cholesky(&corr).is_none() {
+        return Err(MvDistError::InvalidInput(format!("The Kendall correlation matrix is not \
+                                                      positive definite")));
+    }
+    Ok(corr)
+}
+
+/// A normal or t copula with correlation matrix `corr`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Copula {
+    corr: Array2<f64>,
+    kernel: Kernel,
+}
+
+impl Copula {
+    /// The copula of `kernel` with `corr`, which must be a positive definite correlation matrix.
+    pub fn new(corr: Array2<f64>, kernel: Kernel) -> Result<Copula, MvDistError> {
+        kernel.nu()?;
+        let d = corr.rows();
+        let unit = |i: usize| corr[[i, i]] == 1.0 && (0..i).all(|j| corr[[i, j]] == corr[[j, i]]);
+        if d == 0 || corr.cols() != d || !(0..d).all(unit) || cholesky(&corr).is_none() {
+            return Err(MvDistError::InvalidInput(format!("Invalid {}x{} copula correlation \
+                                                          matrix",
+                                                         d,
+                                                         corr.cols())));
+        }
+        Ok(Copula {
+            corr: corr,
+            kernel: kernel,
+        })
+    }
+
+    /// The copula of `kernel` fitted to the pseudo-observations `u` by `kendall_correlation`.
+    pub fn fit(u: &Array2<f64>, kernel: Kernel) -> Result<Copula, MvDistError> {
+        Copula::new(kendall_correlation(u)?, kernel)
+    }
+
+    pub fn corr(&self) -> &Array2<f64> {
+        &self.corr
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    /// `C(u)`, the probability that every uniform margin is at most its entry of `u`, evaluated
+    /// with `mvdist_config` at the quantiles of `u`.
+    pub fn cdf(&self, u: &[f64], config: &EvalConfig) -> Result<MVResult, MvDistError> {
+        let d = self.corr.rows();
+        if u.len() != d || u.iter().any(|&p| !(p >= 0.0 && p <= 1.0)) {
+            return Err(MvDistError::InvalidInput(format!("Expected {} probabilities, got {:?}",
+                                                         d,
+                                                         u)));
+        }
+        let q = u.iter()
+            .map(|&p| match self.kernel {
+                Kernel::Normal => norm_inv(p),
+                Kernel::StudentT { nu } => t_inv(nu, p),
+            })
+            .collect::<Vec<f64>>();
+        mvdist_config(&self.corr,
+                      self.kernel,
+                      &Array::zeros((d,)),
+                      &Array::from_vec(q),
+                      &vec![BoundType::Above; d],
+                      &Array::eye(d),
+                      &Array::zeros((d,)),
+                      config)
+    }
+
+    /// `n` draws from the copula, one per row, with the normal or t sampler of `distribution`.
+    pub fn sample<R: Rng>(&self, n: usize, rng: &mut R) -> Result<Array2<f64>, MvDistError> {
+        let d = self.corr.rows();
+        let mut u = Array2::zeros((n, d));
+        match self.kernel {
+            Kernel::Normal => {
+                let dist = MultivariateNormal::new(Array::zeros((d,)), self.corr.clone())?;
+                for i in 0..n {
+                    let x = dist.sample(rng);
+                    for k in 0..d {
+                        u[[i, k]] = norm_cdf(x[k]);
+                    }
+                }
+            }
+            Kernel::StudentT { nu } => {
+                let dist = MultivariateStudent::new(Array::zeros((d,)), self.corr.clone(), nu)?;
+                for i in 0..n {
+                    let x = dist.sample(rng);
+                    for k in 0..d {
+                        u[[i, k]] = t_cdf(nu, x[k]);
+                    }
+                }
+            }
+        }
+        Ok(u)
+    }
+}
+
+/// The goodness-of-fit statistics of pseudo-observations against a copula.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Statistics {
+    pub cramer_von_mises: f64,
+    pub kolmogorov_smirnov: f64,
+}
+
+/// The statistics of the pseudo-observations `u` against `copula`, evaluating `C(U_i)` at each
+/// observation with `config`, in parallel with the `rayon` feature.
+pub fn statistics(u: &Array2<f64>,
+                  copula: &Copula,
+                  config: &EvalConfig)
+                  -> Result<Statistics, MvDistError> {
+    let (n, d) = (u.rows(), u.cols());
+    if d != copula.corr.rows() {
+        return Err(MvDistError::InvalidInput(format!("Expected observations of {} variables, got \
+                                                      {}",
+                                                     copula.corr.rows(),
+                                                     d)));
+    }
+    let fitted = map_observations(n, |i| copula.cdf(&u.row(i).to_vec(), config))
+        .into_iter()
+        .collect::<Result<Vec<MVResult>, MvDistError>>()?;
+    let (mut cvm, mut ks) = (0.0, 0.0f64);
+    for i in 0..n {
+        let below = (0..n).filter(|&j| (0..d).all(|k| u[[j, k]] <= u[[i, k]])).count();
+        let gap = below as f64 / n as f64 - fitted[i].value;
+        cvm += gap * gap;
+        ks = ks.max(gap.abs());
+    }
+    Ok(Statistics {
+        cramer_von_mises: cvm,
+        kolmogorov_smirnov: (n as f64).sqrt() * ks,
+    })
+}
+
+#[cfg(feature = "rayon")]
+fn map_observations<F>(n: usize, eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(usize) -> Result<MVResult, MvDistError> + Sync
+{
+    use rayon::prelude::*;
+    (0..n).into_par_iter().map(|i| eval(i)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_observations<F>(n: usize, eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(usize) -> Result<MVResult, MvDistError>
+{
+    (0..n).map(|i| eval(i)).collect()
+}
+
+/// The result of `goodness_of_fit`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GoodnessOfFit {
+    /// The copula fitted to the data.
+    pub copula: Copula,
+    pub statistics: Statistics,
+    /// The bootstrap p-values of the two statistics, `(1 + k) / (1 + bootstrap)` for the `k`
+    /// bootstrap samples whose statistic is at least that of the data.
+    pub cramer_von_mises_p: f64,
+    pub kolmogorov_smirnov_p: f64,
+    pub bootstrap: usize,
+}
+
+/// Test whether the pseudo-observations `u` come from a copula of `kernel`, fitting it with
+/// `Copula::fit` and finding the p-values with `bootstrap` parametric bootstrap samples. Sample
+/// `b` is drawn from stream `b` of `native::SeedStreams` for the seed of `config` (or
+/// `native::DEFAULT_SEED`), and the copula is evaluated with `config`, so this takes
+/// `(bootstrap + 1) n` evaluations for `n` observations.
+pub fn goodness_of_fit(u: &Array2<f64>,
+                       kernel: Kernel,
+                       bootstrap: usize,
+                       config: &EvalConfig)
+                       -> Result<GoodnessOfFit, MvDistError> {
+    if u.iter().any(|&p| !(p > 0.0 && p < 1.0)) {
+        return Err(MvDistError::InvalidInput(format!("Pseudo-observations must be strictly \
+                                                      between 0 and 1")));
+    }
+    let copula = Copula::fit(u, kernel)?;
+    let observed = statistics(u, &copula, config)?;
+    let streams = SeedStreams::new(config.seed.unwrap_or(DEFAULT_SEED));
+    let (mut cvm, mut ks) = (0, 0);
+    for b in 0..bootstrap {
+        let mut rng = SplitMix64::new(streams.seed(b as u64));
+        let sample = pseudo_observations(&copula.sample(u.rows(), &mut rng)?)?;
+        let s = statistics(&sample, &Copula::fit(&sample, kernel)?, config)?;
+        cvm += (s.cramer_von_mises >= observed.cramer_von_mises) as usize;
+        ks += (s.kolmogorov_smirnov >= observed.kolmogorov_smirnov) as usize;
+    }
+    let p = |k: usize| (1 + k) as f64 / (1 + bootstrap) as f64;
+    Ok(GoodnessOfFit {
+        copula: copula,
+        statistics: observed,
+        cramer_von_mises_p: p(cvm),
+        kolmogorov_smirnov_p: p(ks),
+        bootstrap: bootstrap,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_and_kendall_correlation() {
+        let data = arr2(&[[3.0, 1.0], [1.0, 2.0], [2.0, 2.0], [5.0, 4.0]]);
+        let u = pseudo_observations(&data).unwrap();
+        assert_eq!(u.column(0).to_vec(), vec![0.6, 0.2, 0.4, 0.8]);
+        assert_eq!(u.column(1).to_vec(), vec![0.2, 0.5, 0.5, 0.8]);
+        // 3 concordant, 2 discordant and 1 tied pair: τ = 1 / 6
+        let corr = kendall_correlation(&u).unwrap();
+        assert!((corr[[1, 0]] - (PI / 12.0).sin()).abs() < 1e-15 && corr[[0, 1]] == corr[[1, 0]]);
+        let copula = Copula::new(Array::eye(2), Kernel::Normal).unwrap();
+        let c = copula.cdf(&[0.3, 0.6], &EvalConfig::default()).unwrap();
+        assert!((c.value - 0.18).abs() < 1e-9);
+        assert!(Copula::new(arr2(&[[1.0, 0.5], [0.4, 1.0]]), Kernel::Normal).is_err());
+        assert!(pseudo_observations(&arr2(&[[1.0, 2.0], [::std::f64::NAN, 1.0]])).is_err());
+    }
+
+    #[test]
+    fn normal_copula_goodness_of_fit() {
+        let copula = Copula::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]), Kernel::Normal).unwrap();
+        let mut rng = SplitMix64::new(11);
+        let u = pseudo_observations(&copula.sample(100, &mut rng).unwrap()).unwrap();
+        let config = EvalConfig::default();
+        let fit = goodness_of_fit(&u, Kernel::Normal, 40, &config).unwrap();
+        assert!(fit.cramer_von_mises_p > 0.01 && fit.kolmogorov_smirnov_p > 0.01);
+        assert!((fit.copula.corr()[[1, 0]] - 0.5).abs() < 0.2);
+        // comonotone below 0.3 and independent above: a tail dependence the normal copula lacks
+        let mut data = Array2::zeros((100, 2));
+        for i in 0..100 {
+            let a = rng.next_f64();
+            data[[i, 0]] = a;
+            data[[i, 1]] = if a < 0.3 { a } else { 0.3 + 0.7 * rng.next_f64() };
+        }
+        let misfit = goodness_of_fit(&pseudo_observations(&data).unwrap(),
+                                     Kernel::Normal,
+                                     40,
+                                     &config)
+            .unwrap();
+        assert!(misfit.cramer_von_mises_p < 0.05 && misfit.kolmogorov_smirnov_p < 0.05);
+        assert!(misfit.statistics.cramer_von_mises > fit.statistics.cramer_von_mises);
+    }
+}
@@ -0,0 +1,309 @@
+//! Constructors for commonly used correlation structures. Each returns an `Array2<f64>` that can be
+//! passed directly as the `cov` argument of `mvdist` and `mvcrit`.
+
+use ndarray::prelude::*;
+use rand::Rng;
+use rand::distributions::{Gamma, IndependentSample, Normal};
+use linalg::cholesky;
+use MvDistError;
+
+/// First-order autoregressive correlation: `R[i, j] = rho^|i - j|`.
+pub fn ar1(n: usize, rho: f64) -> Array2<f64> {
+    let mut first_row = Vec::with_capacity(n);
+    let mut r = 1.0;
+    for _ in 0..n {
+        first_row.push(r);
+        r *= rho;
+    }
+    toeplitz(&first_row)
+}
+
+/// Exchangeable (compound symmetry) correlation: ones on the diagonal and `rho` everywhere else.
+pub fn exchangeable(n: usize, rho: f64) -> Array2<f64> {
+    let mut r = Array2::from_elem((n, n), rho);
+    for i in 0..n {
+        r[[i, i]] = 1.0;
+    }
+    r
+}
+
+/// Symmetric Toeplitz matrix with the given first row: `R[i, j] = first_row[|i - j|]`.
+pub fn toeplitz(first_row: &[f64]) -> Array2<f64> {
+    let n = first_row.len();
+    let mut r = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            r[[i, j]] = first_row[if i > j { i - j } else { j - i }];
+        }
+    }
+    r
+}
+
+/// Block-diagonal matrix built from the given square blocks, with zeros between blocks.
+///
+/// Panics if any block is not square.
+pub fn block_diag(blocks: &[Array2<f64>]) -> Array2<f64> {
+    let n: usize = blocks.iter()
+        .map(|b| {
+            assert!(b.rows() == b.cols(), "block_diag requires square blocks");
+            b.rows()
+        })
+        .sum();
+    let mut r = Array2::zeros((n, n));
+    let mut offset = 0;
+    for b in blocks {
+        for i in 0..b.rows() {
+            for j in 0..b.cols() {
+                r[[offset + i, offset + j]] = b[[i, j]];
+            }
+        }
+        offset += b.rows();
+    }
+    r
+}
+
+/// The dimension of a packed triangle of `len` entries whose row `i` has `i + 1 - offset` of them.
+fn packed_dimension(len: usize, offset: usize) -> Result<usize, MvDistError> {
+    let mut n = offset;
+    let mut total = 0;
+    while total < len {
+        n += 1;
+        total += n - offset;
+    }
+    if total == len {
+        Ok(n)
+    } else {
+        Err(MvDistError::InvalidInput(format!("{} entries do not form a packed triangle", len)))
+    }
+}
+
+/// The correlation matrix with the strict lower triangle `packed`, given row by row:
+/// `R[1, 0], R[2, 0], R[2, 1], R[3, 0], ...`, with ones on the diagonal. This is the `CORREL`
+/// argument of Genz's Fortran routines and the layout of most published tables. Fails unless the
+/// length is `n (n - 1) / 2` for some `n` and every entry is in `[-1, 1]`; whether the matrix is
+/// positive semidefinite is left to the routine it is passed to.
+pub fn from_packed_lower(packed: &[f64]) -> Result<Array2<f64>, MvDistError> {
+    let n = packed_dimension(packed.len(), 1)?;
+    if let Some(r) = packed.iter().find(|r| !(r.abs() <= 1.0)) {
+        return Err(MvDistError::InvalidInput(format!("Correlation {} is outside [-1, 1]", r)));
+    }
+    let mut r = Array::eye(n);
+    let mut k = 0;
+    for i in 1..n {
+        for j in 0..i {
+            r[[i, j]] = packed[k];
+            r[[j, i]] = packed[k];
+            k += 1;
+        }
+    }
+    Ok(r)
+}
+
+/// The symmetric matrix with the lower triangle `packed`, including the diagonal, given row by
+/// row: `A[0, 0], A[1, 0], A[1, 1], A[2, 0], ...`, as for a packed covariance. Fails unless the
+/// length is `n (n + 1) / 2` for some `n`, every entry is finite and the diagonal is positive.
+pub fn from_packed_lower_with_diagonal(packed: &[f64]) -> Result<Array2<f64>, MvDistError> {
+    let n = packed_dimension(packed.len(), 0)?;
+    if packed.iter().any(|a| !a.is_finite()) {
+        return Err(MvDistError::InvalidInput(format!("Packed matrix has non-finite entries")));
+    }
+    let mut a = Array2::zeros((n, n));
+    let mut k = 0;
+    for i in 0..n {
+        for j in 0..(i + 1) {
+            a[[i, j]] = packed[k];
+            a[[j, i]] = packed[k];
+            k += 1;
+        }
+        if !(a[[i, i]] > 0.0) {
+            return Err(MvDistError::InvalidInput(format!("Diagonal entry {} is {}, expected a \
+                                                          positive variance",
+                                                         i,
+                                                         a[[i, i]])));
+        }
+    }
+    Ok(a)
+}
+
+fn beta_sample<R: Rng>(a: f64, b: f64, rng: &mut R) -> f64 {
+    let x = Gamma::new(a, 1.0).ind_sample(rng);
+    let y = Gamma::new(b, 1.0).ind_sample(rng);
+    x / (x + y)
+}
+
+/// Draw an `n x n` correlation matrix uniformly from the space of correlation matrices, using the
+/// onion construction of Lewandowski, Kurowicka & Joe (2009) with `eta = 1`.
+pub fn random_correlation<R: Rng>(n: usize, rng: &mut R) -> Array2<f64> {
+    let mut r = Array::eye(n);
+    if n < 2 {
+        return r;
+    }
+    let normal = Normal::new(0.0, 1.0);
+    let mut beta = 1.0 + (n as f64 - 2.0) / 2.0;
+    let r12 = 2.0 * beta_sample(beta, beta, rng) - 1.0;
+    r[[0, 1]] = r12;
+    r[[1, 0]] = r12;
+    for k in 2..n {
+        beta -= 0.5;
+        let y = beta_sample(k as f64 / 2.0, beta, rng);
+        let u = (0..k).map(|_| normal.ind_sample(rng)).collect::<Vec<f64>>();
+        let norm = u.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let w = u.iter().map(|x| y.sqrt() * x / norm).collect::<Vec<f64>>();
+        let mut lead = Array2::zeros((k, k));
+        for i in 0..k {
+            for j in 0..k {
+                lead[[i, j]] = r[[i, j]];
+            }
+        }
+        let a = cholesky(&lead)
+            .expect("onion construction produced a non-positive-definite leading block");
+        for i in 0..k {
+            let z = (0..(i + 1)).map(|j| a[[i, j]] * w[j]).sum::<f64>();
+            r[[i, k]] = z;
+            r[[k, i]] = z;
+        }
+    }
+    r
+}
+
+/// Draw a random `n x n` correlation matrix with 2-norm condition number `kappa`.
+///
+/// Eigenvalues are spaced geometrically between `1` and `kappa` (then scaled to sum to `n`),
+/// rotated by a random orthogonal matrix, and finally brought to unit diagonal with the
+/// eigenvalue-preserving Givens rotations of Bendel & Mickey (1978). Panics if `kappa < 1`.
+pub fn random_correlation_with_condition<R: Rng>(n: usize, kappa: f64, rng: &mut R) -> Array2<f64> {
+    assert!(kappa >= 1.0, "condition number must be at least 1");
+    if n < 2 {
+        return Array::eye(n);
+    }
+    let mut lambda = (0..n)
+        .map(|i| kappa.powf(i as f64 / (n as f64 - 1.0)))
+        .collect::<Vec<f64>>();
+    let total = lambda.iter().sum::<f64>();
+    for l in lambda.iter_mut() {
+        *l *= n as f64 / total;
+    }
+
+    // random orthogonal matrix via modified Gram-Schmidt on a Gaussian matrix
+    let normal = Normal::new(0.0, 1.0);
+    let mut q = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            q[[i, j]] = normal.ind_sample(rng);
+        }
+    }
+    for j in 0..n {
+        for k in 0..j {
+            let dot = (0..n).map(|i| q[[i, j]] * q[[i, k]]).sum::<f64>();
+            for i in 0..n {
+                q[[i, j]] -= dot * q[[i, k]];
+            }
+        }
+        let norm = (0..n).map(|i| q[[i, j]] * q[[i, j]]).sum::<f64>().sqrt();
+        for i in 0..n {
+            q[[i, j]] /= norm;
+        }
+    }
+
+    let mut m = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            m[[i, j]] = (0..n).map(|k| q[[i, k]] * lambda[k] * q[[j, k]]).sum::<f64>();
+        }
+    }
+
+    const TOL: f64 = 1e-12;
+    loop {
+        let i = match (0..n).find(|&i| m[[i, i]] < 1.0 - TOL) {
+            Some(i) => i,
+            None => break,
+        };
+        let j = match (0..n).find(|&j| m[[j, j]] > 1.0 + TOL) {
+            Some(j) => j,
+            None => break,
+        };
+        let (aii, ajj, aij) = (m[[i, i]], m[[j, j]], m[[i, j]]);
+        let t = (aij + (aij * aij - (aii - 1.0) * (ajj - 1.0)).sqrt()) / (ajj - 1.0);
+        let c = 1.0 / (1.0 + t * t).sqrt();
+        let s = c * t;
+        for k in 0..n {
+            let (mik, mjk) = (m[[i, k]], m[[j, k]]);
+            m[[i, k]] = c * mik - s * mjk;
+            m[[j, k]] = s * mik + c * mjk;
+        }
+        for k in 0..n {
+            let (mki, mkj) = (m[[k, i]], m[[k, j]]);
+            m[[k, i]] = c * mki - s * mkj;
+            m[[k, j]] = s * mki + c * mkj;
+        }
+        m[[i, i]] = 1.0;
+    }
+    for i in 0..n {
+        m[[i, i]] = 1.0;
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conditioning::conditioning;
+    use ndarray::prelude::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn ar1_matches_powers() {
+        let r = ar1(3, 0.5);
+        assert_eq!(r,
+                   arr2(&[[1.0, 0.5, 0.25], [0.5, 1.0, 0.5], [0.25, 0.5, 1.0]]));
+    }
+
+    #[test]
+    fn exchangeable_and_block_diag() {
+        let r = block_diag(&[exchangeable(2, 0.3), Array::eye(1)]);
+        assert_eq!(r,
+                   arr2(&[[1.0, 0.3, 0.0], [0.3, 1.0, 0.0], [0.0, 0.0, 1.0]]));
+    }
+
+    #[test]
+    fn packed_triangles() {
+        let r = from_packed_lower(&[0.5, 0.2, -0.3]).unwrap();
+        assert_eq!(r,
+                   arr2(&[[1.0, 0.5, 0.2], [0.5, 1.0, -0.3], [0.2, -0.3, 1.0]]));
+        assert_eq!(from_packed_lower(&[]).unwrap(), Array::eye(1));
+        assert!(from_packed_lower(&[0.5, 0.2]).is_err());
+        assert!(from_packed_lower(&[1.5]).is_err());
+        let a = from_packed_lower_with_diagonal(&[4.0, 1.0, 2.0]).unwrap();
+        assert_eq!(a, arr2(&[[4.0, 1.0], [1.0, 2.0]]));
+        assert!(from_packed_lower_with_diagonal(&[4.0, 1.0]).is_err());
+        assert!(from_packed_lower_with_diagonal(&[4.0, 1.0, 0.0]).is_err());
+    }
+
+    #[test]
+    fn random_correlation_is_valid() {
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4][..]);
+        for &n in &[1, 2, 5, 10] {
+            let r = random_correlation(n, &mut rng);
+            for i in 0..n {
+                assert!((r[[i, i]] - 1.0).abs() < 1e-12);
+                for j in 0..n {
+                    assert!((r[[i, j]] - r[[j, i]]).abs() < 1e-12);
+                }
+            }
+            assert!(cholesky(&r).is_some());
+        }
+    }
+
+    #[test]
+    fn random_correlation_has_unit_diagonal_with_condition() {
+        let mut rng = StdRng::from_seed(&[5, 6, 7, 8][..]);
+        let r = random_correlation_with_condition(6, 50.0, &mut rng);
+        for i in 0..6 {
+            assert!((r[[i, i]] - 1.0).abs() < 1e-12);
+        }
+        assert!(cholesky(&r).is_some());
+        // the rotations to unit diagonal keep the eigenvalues, so the ratio of the extremes is kappa
+        let kappa = conditioning(&r).unwrap().condition_number;
+        assert!((kappa / 50.0 - 1.0).abs() < 1e-4, "condition number {}", kappa);
+    }
+}
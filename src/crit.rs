@@ -0,0 +1,1007 @@
+//! Equicoordinate critical values: the `c` for which `P(C X ∈ L(c)) = 1 - alpha`, where each
+//! constraint's limits `L(c)` are `(-∞, c]`, `[-c, ∞)` or `[-c, c]` as selected by its bound
+//! type. `mvcrit` searches with the `mvcrit` function from `mvdist-sys` with the `fortran`
+//! feature, and as `mvcrit_with` does otherwise; `mvcrit_with` and `mvcrit_native` search with
+//! Brent's method on evaluations of the level `1 - P(C X ∈ L(c))`, within a bracket and to a
+//! tolerance given by a `RootFinder`. Each value found is checked by evaluating the level at it
+//! and around it. `mvcrit_batch` searches many problems at once, in parallel with the `rayon`
+//! feature, starting each search near the value found for a similar problem.
+//!
+//! `max_modulus_cdf` and `max_modulus_quantile` are the distribution of the studentized maximum
+//! modulus, evaluated by `exact::equicorrelated` and searched in the same way. `dunnett_cdf` and
+//! `dunnett_quantile` are Dunnett's distribution of the comparisons of several treatments with a
+//! control, balanced or not, evaluated by `exact::one_factor`.
+
+use exact;
+use multcomp::Alternative;
+use native::{self, Reorder};
+use ndarray::prelude::*;
+use {BoundType, CancellationToken, Kernel, MVInform, MVResult, MvDistError, RetryPolicy};
+
+/// A critical value from `mvcrit`, with the accuracy of the root reported separately from that of
+/// the integration. Values are `f64` except when using the generic functions in `float`.
+#[derive(Clone, Debug, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MvCritResult<F = f64> {
+    /// The equicoordinate critical value.
+    pub crit: F,
+    /// The level `1 - P` at `crit`, from a separate evaluation of `mvdist`.
+    pub achieved_alpha: F,
+    /// The error of `achieved_alpha`, in the sense of `MVResult::error`.
+    pub alpha_error: F,
+    /// The evaluations used by the search and by the checks of `achieved_alpha` and `bracket`.
+    pub nevals: u64,
+    /// Values `(lo, hi)` whose levels are above and below `alpha` by more than their errors, so
+    /// that the exact critical value lies between them. A side is infinite if it could not be
+    /// established within `BRACKET_STEPS` widenings of the interval.
+    pub bracket: (F, F),
+    pub state: MVInform,
+}
+
+impl MvCritResult {
+    fn add(&mut self, r: &MVResult) {
+        self.nevals += r.nevals;
+        if r.state == MVInform::PtLimitReached {
+            self.state = MVInform::PtLimitReached;
+        }
+    }
+}
+
+/// The number of times the distance from the critical value is doubled when bracketing the root.
+pub const BRACKET_STEPS: usize = 20;
+
+/// The search of `mvcrit_with` and `mvcrit_native`.
+#[derive(Clone, Debug, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RootFinder {
+    /// The interval `(lo, hi)` searched. The level must be above `alpha` at `lo` and below it at
+    /// `hi`, or the search fails with `MvDistError::RootNotBracketed`.
+    pub bracket: (f64, f64),
+    /// The maximum number of evaluations of the level inside the bracket.
+    pub max_iterations: usize,
+    /// The search stops once the bracket is narrower than `tolerance`, or when the level is
+    /// within its integration error of `alpha`.
+    pub tolerance: f64,
+    /// How evaluations of the level that reach the point limit are retried.
+    pub retry: RetryPolicy,
+}
+
+impl Default for RootFinder {
+    fn default() -> RootFinder {
+        RootFinder {
+            bracket: (-10.0, 10.0),
+            max_iterations: 100,
+            tolerance: 1e-6,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+fn check_alpha(alpha: f64) -> Result<(), MvDistError> {
+    if alpha > 0.0 && alpha < 1.0 {
+        Ok(())
+    } else {
+        Err(MvDistError::InvalidInput(format!("Level {} is not in (0, 1)", alpha)))
+    }
+}
+
+/// The level as a function of the critical value for one problem, with the evaluations made so
+/// far, which later brackets may reuse. `eval(c)` is `P(C X ∈ L(c))` for `c >= 0`.
+struct Levels<'a, F> {
+    types: &'a [BoundType],
+    eval: F,
+    points: Vec<(f64, MVResult)>,
+}
+
+impl<'a, F> Levels<'a, F>
+    where F: Fn(f64) -> Result<MVResult, MvDistError>
+{
+    fn new(types: &'a [BoundType], eval: F) -> Levels<'a, F> {
+        Levels {
+            types: types,
+            eval: eval,
+            points: Vec::new(),
+        }
+    }
+
+    fn at(&mut self, c: f64) -> Result<MVResult, MvDistError> {
+        let two_sided = self.types.iter().any(|t| match *t {
+            BoundType::Both => true,
+            _ => false,
+        });
+        if c < 0.0 && two_sided {
+            // [-c, c] is empty
+            let r = MVResult {
+                value: 1.0,
+                error: 0.0,
+                nevals: 0,
+                state: MVInform::Normal,
+            };
+            self.points.push((c, r));
+            return Ok(r);
+        }
+        let r = (self.eval)(c)?;
+        let r = MVResult { value: 1.0 - r.value, ..r };
+        self.points.push((c, r));
+        Ok(r)
+    }
+
+    /// Check the critical value `crit` found for `alpha` by a search that used `nevals`
+    /// evaluations and ended in `state`. The level decreases as the critical value grows, so a
+    /// side of the bracket is a point on that side of the root whose level differs from `alpha`
+    /// by more than its error in the right direction.
+    fn check(&mut self,
+             alpha: f64,
+             crit: f64,
+             nevals: u64,
+             state: MVInform)
+             -> Result<MvCritResult, MvDistError> {
+        let mut result = MvCritResult {
+            crit: crit,
+            achieved_alpha: 0.0,
+            alpha_error: 0.0,
+            nevals: nevals,
+            bracket: (::std::f64::NEG_INFINITY, ::std::f64::INFINITY),
+            state: state,
+        };
+        // the search may already have evaluated the level at the root
+        let at = match self.points.iter().find(|p| p.0 == crit).map(|p| p.1) {
+            Some(at) => at,
+            None => {
+                let at = self.at(crit)?;
+                result.add(&at);
+                at
+            }
+        };
+        result.achieved_alpha = at.value;
+        result.alpha_error = at.error;
+        let step = 1e-3 * crit.abs().max(1.0);
+        for &sign in &[-1.0, 1.0] {
+            let brackets = |c: f64, r: &MVResult| {
+                sign * (c - crit) > 0.0 && sign * (alpha - r.value) > r.error
+            };
+            let mut h = step;
+            let mut side = None;
+            for _ in 0..BRACKET_STEPS {
+                // the nearest point already evaluated within h of the root, if any brackets it
+                side = self.points
+                    .iter()
+                    .filter(|&&(c, ref r)| brackets(c, r) && sign * (c - crit) <= h)
+                    .map(|&(c, _)| c)
+                    .fold(None, |best: Option<f64>, c| {
+                        match best {
+                            Some(b) if sign * (b - c) <= 0.0 => Some(b),
+                            _ => Some(c),
+                        }
+                    });
+                if side.is_some() {
+                    break;
+                }
+                let c = crit + sign * h;
+                let r = self.at(c)?;
+                result.add(&r);
+                if brackets(c, &r) {
+                    side = Some(c);
+                    break;
+                }
+                h *= 2.0;
+            }
+            if let Some(c) = side {
+                if sign < 0.0 {
+                    result.bracket.0 = c;
+                } else {
+                    result.bracket.1 = c;
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Compute the equicoordinate critical value for the distribution and constraints at level
+/// `alpha`, using the `mvcrit` function from `mvdist-sys` with the `fortran` feature, and the
+/// search of `mvcrit_with` with its default `RootFinder` otherwise.
+///
+/// The value found is checked with `mvdist`: the level is evaluated at the critical value, for
+/// `achieved_alpha`, and at points on either side of it, moving away until the levels differ
+/// from `alpha` by more than their errors, for `bracket`. Each of these evaluations uses `maxpts`
+/// and `abseps`.
+///
+/// With the `fortran` feature, returns `MvDistError::DimensionTooLarge` for problems with more
+/// than `FORTRAN_MAX_DIMENSION` variables or constraints.
+pub fn mvcrit(cov: &Array2<f64>,
+              nu: i32,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &Vec<BoundType>,
+              constraints: &Array2<f64>,
+              alpha: f64,
+              maxpts: u64,
+              abseps: f64)
+              -> Result<MvCritResult, MvDistError> {
+    mvcrit_many(cov,
+                nu,
+                lb,
+                ub,
+                types,
+                constraints,
+                &[alpha],
+                maxpts,
+                abseps,
+                &RetryPolicy::default())
+        .map(|results| results[0])
+}
+
+/// `mvcrit` for each of `alphas`, in the same order. The levels are searched from the largest to
+/// the smallest, so that the critical values increase, and the evaluations of the level made to
+/// bracket one critical value are reused for the others: for a fine grid of levels, the points
+/// evaluated around a critical value usually bracket its neighbours as well. The evaluations are
+/// counted in the `nevals` of the result that made them. Searches and evaluations that reach the
+/// point limit are retried as set by `retry`.
+pub fn mvcrit_many(cov: &Array2<f64>,
+                   nu: i32,
+                   lb: &Array1<f64>,
+                   ub: &Array1<f64>,
+                   types: &Vec<BoundType>,
+                   constraints: &Array2<f64>,
+                   alphas: &[f64],
+                   maxpts: u64,
+                   abseps: f64,
+                   retry: &RetryPolicy)
+                   -> Result<Vec<MvCritResult>, MvDistError> {
+    for &alpha in alphas {
+        check_alpha(alpha)?;
+    }
+    let mut order = (0..alphas.len()).collect::<Vec<usize>>();
+    order.sort_by(|&i, &j| alphas[j].partial_cmp(&alphas[i]).unwrap());
+    let mut levels = Levels::new(types, |c| {
+        retry.run(maxpts, |maxpts| {
+            equicoordinate_mvdist(cov, nu, types, constraints, maxpts, abseps, c)
+        })
+    });
+    let (n, m) = (cov.rows(), constraints.rows());
+    let mut results = vec![None; alphas.len()];
+    for i in order {
+        let alpha = alphas[i];
+        let search = retry.run(maxpts, |maxpts| {
+            ::instrument::evaluation("mvcrit", n, m, maxpts, || {
+                search(cov, nu, lb, ub, types, constraints, alpha, maxpts, abseps)
+            })
+        })?;
+        results[i] = Some(levels.check(alpha, search.value, search.nevals, search.state)?);
+    }
+    Ok(results.into_iter().map(|r| r.unwrap()).collect())
+}
+
+/// The critical value at `alpha` found by the Fortran `mvcrit`, as the `value` of the result.
+#[cfg(feature = "fortran")]
+fn search(cov: &Array2<f64>,
+          nu: i32,
+          lb: &Array1<f64>,
+          ub: &Array1<f64>,
+          types: &Vec<BoundType>,
+          constraints: &Array2<f64>,
+          alpha: f64,
+          maxpts: u64,
+          abseps: f64)
+          -> Result<MVResult, MvDistError> {
+    ::fortran::mvcrit(cov, nu, lb, ub, types, constraints, alpha, maxpts, abseps)
+}
+
+/// The critical value at `alpha` found by `mvcrit_with`, in the form of the Fortran `mvcrit`.
+/// The limits are set by the search, so `lb` and `ub` are not used.
+#[cfg(not(feature = "fortran"))]
+fn search(cov: &Array2<f64>,
+          nu: i32,
+          _: &Array1<f64>,
+          _: &Array1<f64>,
+          types: &Vec<BoundType>,
+          constraints: &Array2<f64>,
+          alpha: f64,
+          maxpts: u64,
+          abseps: f64)
+          -> Result<MVResult, MvDistError> {
+    let r = mvcrit_with(cov,
+                        nu,
+                        types,
+                        constraints,
+                        alpha,
+                        maxpts,
+                        abseps,
+                        &RootFinder::default())?;
+    Ok(MVResult {
+        value: r.crit,
+        error: r.alpha_error,
+        nevals: r.nevals,
+        state: r.state,
+    })
+}
+
+/// `P(C X ∈ L(c))` with `mvdist`.
+fn equicoordinate_mvdist(cov: &Array2<f64>,
+                         nu: i32,
+                         types: &Vec<BoundType>,
+                         constraints: &Array2<f64>,
+                         maxpts: u64,
+                         abseps: f64,
+                         c: f64)
+                         -> Result<MVResult, MvDistError> {
+    let m = constraints.rows();
+    ::mvdist(cov,
+             nu,
+             &Array::from_elem((m,), -c),
+             &Array::from_elem((m,), c),
+             types,
+             constraints,
+             &Array::zeros((m,)),
+             maxpts,
+             abseps,
+             0.0)
+}
+
+/// Find the root of `levels` at `alpha` by Brent's method within `finder.bracket`, and check it.
+/// The search stops early when the level is within its integration error of `alpha`.
+fn brent<'a, F>(mut levels: Levels<'a, F>,
+                alpha: f64,
+                finder: &RootFinder)
+                -> Result<MvCritResult, MvDistError>
+    where F: Fn(f64) -> Result<MVResult, MvDistError>
+{
+    check_alpha(alpha)?;
+    let (mut a, mut b) = finder.bracket;
+    if !(a < b) || !(finder.tolerance > 0.0) || finder.max_iterations == 0 {
+        return Err(MvDistError::InvalidInput(format!("Invalid root finder {:?}", finder)));
+    }
+    // the level minus alpha, decreasing in c
+    let (mut fa, mut fb) = (levels.at(a)?.value - alpha, levels.at(b)?.value - alpha);
+    if !(fa > 0.0 && fb < 0.0) {
+        return Err(MvDistError::RootNotBracketed {
+            alpha: alpha,
+            lo: a,
+            hi: b,
+        });
+    }
+    // b is the best estimate and c the other end of the bracket; a is the previous b
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+    for _ in 0..finder.max_iterations {
+        if (fb > 0.0) == (fc > 0.0) {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let tol = 2.0 * ::std::f64::EPSILON * b.abs() + 0.5 * finder.tolerance;
+        let m = 0.5 * (c - b);
+        if m.abs() <= tol || fb == 0.0 {
+            break;
+        }
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            // inverse quadratic interpolation, or the secant step if only two points are distinct
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * m * s, 1.0 - s)
+            } else {
+                let (q, r) = (fa / fc, fb / fc);
+                (s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),
+                 (q - 1.0) * (r - 1.0) * (s - 1.0))
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            if 2.0 * p < (3.0 * m * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = m;
+                e = m;
+            }
+        } else {
+            d = m;
+            e = m;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tol { d } else { tol * m.signum() };
+        let r = levels.at(b)?;
+        fb = r.value - alpha;
+        if fb.abs() <= r.error {
+            break;
+        }
+    }
+    let mut state = MVInform::Normal;
+    let mut nevals = 0;
+    for &(_, ref r) in &levels.points {
+        nevals += r.nevals;
+        if r.state == MVInform::PtLimitReached {
+            state = MVInform::PtLimitReached;
+        }
+    }
+    levels.check(alpha, b, nevals, state)
+}
+
+/// The equicoordinate critical value at level `alpha`, found by Brent's method on `mvdist`
+/// evaluations of the level within `finder.bracket`. This does not use the Fortran `mvcrit`, so it
+/// is available with either backend. Each evaluation uses `maxpts` and `abseps`, and the
+/// evaluations of the search are also used for the bracket of the result. Evaluations that reach
+/// the point limit are retried as set by `finder.retry`.
+pub fn mvcrit_with(cov: &Array2<f64>,
+                   nu: i32,
+                   types: &Vec<BoundType>,
+                   constraints: &Array2<f64>,
+                   alpha: f64,
+                   maxpts: u64,
+                   abseps: f64,
+                   finder: &RootFinder)
+                   -> Result<MvCritResult, MvDistError> {
+    let levels = Levels::new(types, |c| {
+        finder.retry.run(maxpts, |maxpts| {
+            equicoordinate_mvdist(cov, nu, types, constraints, maxpts, abseps, c)
+        })
+    });
+    brent(levels, alpha, finder)
+}
+
+/// The equicoordinate critical value at level `alpha` for `X` with distribution `kernel` and
+/// noncentrality `delta`, found by Brent's method on evaluations of the level with the native
+/// integrator, independently of the Fortran code.
+///
+/// The factorization and variable ordering of the problem are computed once, for the limits at
+/// `c = 2`, and every evaluation uses the lattice shifts from `seed`. The level is then the same
+/// smooth function of `c` at every step of the search instead of a new noisy estimate, so the
+/// search converges to the root of that function and its error is that of a single evaluation.
+/// Each evaluation stops once its error is below `max(abseps, releps * value)`, where `value` is
+/// `1 - alpha` near the root, and is retried as set by `finder.retry` if it reaches the point
+/// limit instead.
+pub fn mvcrit_native(cov: &Array2<f64>,
+                     kernel: Kernel,
+                     types: &[BoundType],
+                     constraints: &Array2<f64>,
+                     delta: &Array1<f64>,
+                     alpha: f64,
+                     maxpts: u64,
+                     abseps: f64,
+                     releps: f64,
+                     seed: u64,
+                     finder: &RootFinder)
+                     -> Result<MvCritResult, MvDistError> {
+    let m = constraints.rows();
+    let f = native::integrand(cov,
+                              kernel.nu()?,
+                              &Array::from_elem((m,), -2.0),
+                              &Array::from_elem((m,), 2.0),
+                              types,
+                              constraints,
+                              delta,
+                              &Reorder::Auto)?;
+    let levels = Levels::new(types, |c| {
+        let f = f.with_equicoordinate_limits(c);
+        finder.retry.run(maxpts, |maxpts| {
+            let maxpts = maxpts.min(::std::usize::MAX as u64) as usize;
+            Ok(native::integrate(&f, maxpts, abseps, releps, seed))
+        })
+    });
+    brent(levels, alpha, finder)
+}
+
+/// One problem of `mvcrit_batch`: the equicoordinate critical value at level `alpha` for `X` with
+/// mean zero, scatter matrix `cov` and distribution `kernel`, and the constraints `constraints`
+/// with bound types `types`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CritProblem {
+    pub cov: Array2<f64>,
+    pub kernel: Kernel,
+    pub types: Vec<BoundType>,
+    pub constraints: Array2<f64>,
+    pub alpha: f64,
+}
+
+impl CritProblem {
+    /// Whether the critical values of the two problems are likely to be close: the same kernel
+    /// and the same number and types of constraints.
+    fn similar(&self, other: &CritProblem) -> bool {
+        self.kernel == other.kernel && self.types.len() == other.types.len() &&
+        self.types
+            .iter()
+            .zip(other.types.iter())
+            .all(|(a, b)| ::std::mem::discriminant(a) == ::std::mem::discriminant(b))
+    }
+}
+
+/// The critical values of `problems`, in order, each found by `mvcrit_native` with the given
+/// `maxpts`, `abseps` and `releps`. Problem `i` uses the seed of stream `i` of
+/// `native::SeedStreams::new(seed)`, so that the errors of different problems are independent and
+/// every result is reproducible from `seed` whatever the number of threads.
+///
+/// The problems are grouped by `similar` kernels and constraint types. With the `rayon` feature
+/// the groups are searched in parallel; within a group the problems are searched in turn, each
+/// starting from a bracket around the previous critical value, which typically halves the
+/// evaluations of the search for simulation studies where the problems differ only a little. If
+/// that bracket does not contain the root the search is repeated with `finder.bracket`, so the
+/// hints do not change which roots are found, only the evaluations spent. An error in one problem
+/// does not affect the others. Once `cancel` is cancelled the problems not yet started are given
+/// `MvDistError::Cancelled`, and the results of those completed are kept. With the `pool` feature,
+/// `pool::ProcessPool::mvcrit_batch` searches the groups in worker processes instead, with the same
+/// results.
+pub fn mvcrit_batch(problems: &[CritProblem],
+                    maxpts: u64,
+                    abseps: f64,
+                    releps: f64,
+                    seed: u64,
+                    finder: &RootFinder,
+                    cancel: &CancellationToken)
+                    -> Vec<Result<MvCritResult, MvDistError>> {
+    let groups = group(problems);
+    let searched = search_groups(&groups, |group| {
+        let members = group.iter().map(|&i| (i, &problems[i])).collect::<Vec<_>>();
+        group.iter()
+            .cloned()
+            .zip(search_group(&members, maxpts, abseps, releps, seed, finder, cancel))
+            .collect()
+    });
+    let mut results = (0..problems.len()).map(|_| None).collect::<Vec<_>>();
+    for (i, result) in searched.into_iter().flat_map(|g| g) {
+        results[i] = Some(result);
+    }
+    results.into_iter().map(|r| r.unwrap()).collect()
+}
+
+/// The indices of `problems` grouped by `similar`, in order within each group.
+pub(crate) fn group(problems: &[CritProblem]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, p) in problems.iter().enumerate() {
+        match groups.iter_mut().find(|g| problems[g[0]].similar(p)) {
+            Some(g) => g.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+/// Search the problems of one group of `mvcrit_batch` in turn, each given with its index in the
+/// batch, whose stream of `native::SeedStreams::new(seed)` it uses, until `cancel` is cancelled.
+pub(crate) fn search_group(group: &[(usize, &CritProblem)],
+                           maxpts: u64,
+                           abseps: f64,
+                           releps: f64,
+                           seed: u64,
+                           finder: &RootFinder,
+                           cancel: &CancellationToken)
+                           -> Vec<Result<MvCritResult, MvDistError>> {
+    let streams = native::SeedStreams::new(seed);
+    let search = |i: usize, p: &CritProblem, finder: &RootFinder| {
+        mvcrit_native(&p.cov,
+                      p.kernel,
+                      &p.types,
+                      &p.constraints,
+                      &Array::zeros((p.constraints.rows(),)),
+                      p.alpha,
+                      maxpts,
+                      abseps,
+                      releps,
+                      streams.seed(i as u64),
+                      finder)
+    };
+    let mut hint: Option<f64> = None;
+    group.iter()
+        .map(|&(i, p)| {
+            if cancel.is_cancelled() {
+                return Err(MvDistError::Cancelled);
+            }
+            if let Some(c) = hint {
+                let h = 0.25 * c.abs().max(1.0);
+                let hinted = RootFinder { bracket: (c - h, c + h), ..*finder };
+                match search(i, p, &hinted) {
+                    Err(MvDistError::RootNotBracketed { .. }) => (),
+                    result => {
+                        hint = result.as_ref().ok().map(|r| r.crit).or(hint);
+                        return result;
+                    }
+                }
+            }
+            let result = search(i, p, finder);
+            hint = result.as_ref().ok().map(|r| r.crit).or(hint);
+            result
+        })
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+fn search_groups<F>(groups: &[Vec<usize>],
+                    search: F)
+                    -> Vec<Vec<(usize, Result<MvCritResult, MvDistError>)>>
+    where F: Fn(&[usize]) -> Vec<(usize, Result<MvCritResult, MvDistError>)> + Sync
+{
+    use rayon::prelude::*;
+    groups.par_iter().map(|g| search(g)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn search_groups<F>(groups: &[Vec<usize>],
+                    search: F)
+                    -> Vec<Vec<(usize, Result<MvCritResult, MvDistError>)>>
+    where F: Fn(&[usize]) -> Vec<(usize, Result<MvCritResult, MvDistError>)>
+{
+    groups.iter().map(|g| search(g)).collect()
+}
+
+/// Validate the parameters of the studentized maximum modulus and return the degrees of freedom.
+fn max_modulus_nu(k: usize, rho: f64, kernel: Kernel) -> Result<f64, MvDistError> {
+    if k == 0 {
+        return Err(MvDistError::InvalidInput("No variables given".to_string()));
+    }
+    if !(rho >= 0.0 && rho <= 1.0) {
+        return Err(MvDistError::InvalidInput(format!("Correlation {} is not in [0, 1]", rho)));
+    }
+    kernel.nu()
+}
+
+/// `P(max_i |X_i| <= x)` for `k` standard variables with distribution `kernel` and common
+/// correlation `rho >= 0`. For `kernel = Kernel::StudentT { nu }` this is the CDF of the
+/// studentized maximum modulus, used for simultaneous intervals; with `rho = 0` it is the
+/// classical distribution of the maximum of `k` independent normals divided by a common `chi_nu /
+/// sqrt(nu)`. The probability is evaluated by one- or two-dimensional quadrature with
+/// `exact::equicorrelated`, to within `abseps`.
+pub fn max_modulus_cdf(x: f64,
+                       k: usize,
+                       rho: f64,
+                       kernel: Kernel,
+                       abseps: f64)
+                       -> Result<MVResult, MvDistError> {
+    let nu = max_modulus_nu(k, rho, kernel)?;
+    if !(x > 0.0) {
+        return Ok(MVResult {
+            value: 0.0,
+            error: 0.0,
+            nevals: 0,
+            state: MVInform::Normal,
+        });
+    }
+    Ok(exact::equicorrelated(rho,
+                             &vec![1.0; k],
+                             nu,
+                             &vec![-x; k],
+                             &vec![x; k],
+                             &vec![BoundType::Both; k],
+                             &vec![0.0; k],
+                             abseps,
+                             0.0))
+}
+
+/// The quantile of the studentized maximum modulus at probability `p`: the `x` for which
+/// `max_modulus_cdf(x, k, rho, kernel, abseps)` is `p`, found by Brent's method within
+/// `finder.bracket` and checked as the other critical values. The default bracket, up to 10, may
+/// not contain the upper quantiles of a t with very few degrees of freedom.
+pub fn max_modulus_quantile(p: f64,
+                            k: usize,
+                            rho: f64,
+                            kernel: Kernel,
+                            abseps: f64,
+                            finder: &RootFinder)
+                            -> Result<MvCritResult, MvDistError> {
+    max_modulus_nu(k, rho, kernel)?;
+    let types = vec![BoundType::Both; k];
+    let levels = Levels::new(&types, |x| max_modulus_cdf(x, k, rho, kernel, abseps));
+    brent(levels, 1.0 - p, finder)
+}
+
+/// The loadings of Dunnett's statistics for treatment groups of sizes `sizes` compared with a
+/// control group of size `control`: `sqrt(n_i / (n_i + n_0))`, so that the correlation of the
+/// statistics of groups `i` and `j` is the product of their loadings. Equal group sizes give the
+/// correlation `n / (n + n_0)`, 1/2 when the control is as large as the other groups.
+pub fn dunnett_loadings(control: f64, sizes: &[f64]) -> Vec<f64> {
+    sizes.iter().map(|&n| (n / (n + control)).sqrt()).collect()
+}
+
+/// Validate the parameters of Dunnett's distribution and return the degrees of freedom.
+fn dunnett_nu(loadings: &[f64], kernel: Kernel) -> Result<f64, MvDistError> {
+    if loadings.is_empty() {
+        return Err(MvDistError::InvalidInput("No comparisons given".to_string()));
+    }
+    if let Some(l) = loadings.iter().find(|l| !(l.abs() < 1.0)) {
+        return Err(MvDistError::InvalidInput(format!("Loading {} is not in (-1, 1)", l)));
+    }
+    kernel.nu()
+}
+
+/// Dunnett's distribution: `P(max_i |T_i| <= t)` for `Alternative::TwoSided`, and `P(max_i T_i <=
+/// t)` (equal by symmetry to `P(min_i T_i >= -t)`) for the one-sided alternatives, where the `T_i`
+/// are standard variables with distribution `kernel` and correlations `loadings[i] loadings[j]`
+/// (see `dunnett_loadings`). A balanced design with `k` comparisons and correlation `rho` has
+/// `k` loadings `sqrt(rho)`. With `kernel = Kernel::StudentT { nu }` for the degrees of freedom
+/// of the pooled variance this is the distribution of Dunnett's test statistics. The probability
+/// is evaluated by quadrature with `exact::one_factor`, to within `abseps`.
+pub fn dunnett_cdf(t: f64,
+                   loadings: &[f64],
+                   kernel: Kernel,
+                   alternative: Alternative,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+    let nu = dunnett_nu(loadings, kernel)?;
+    let k = loadings.len();
+    let (lower, types) = match alternative {
+        Alternative::TwoSided => (-t, BoundType::Both),
+        Alternative::Greater | Alternative::Less => (0.0, BoundType::Above),
+    };
+    if alternative == Alternative::TwoSided && !(t > 0.0) {
+        return Ok(MVResult {
+            value: 0.0,
+            error: 0.0,
+            nevals: 0,
+            state: MVInform::Normal,
+        });
+    }
+    Ok(exact::one_factor(loadings,
+                         &vec![1.0; k],
+                         nu,
+                         &vec![lower; k],
+                         &vec![t; k],
+                         &vec![types; k],
+                         &vec![0.0; k],
+                         abseps,
+                         0.0))
+}
+
+/// The quantile of Dunnett's distribution at probability `p`: the critical value `t` for which
+/// `dunnett_cdf(t, ...)` is `p`, so that the simultaneous tests at level `1 - p` reject when a
+/// statistic exceeds it. Found by Brent's method within `finder.bracket` and checked as the
+/// other critical values.
+pub fn dunnett_quantile(p: f64,
+                        loadings: &[f64],
+                        kernel: Kernel,
+                        alternative: Alternative,
+                        abseps: f64,
+                        finder: &RootFinder)
+                        -> Result<MvCritResult, MvDistError> {
+    dunnett_nu(loadings, kernel)?;
+    let types = vec![match alternative {
+                         Alternative::TwoSided => BoundType::Both,
+                         Alternative::Greater | Alternative::Less => BoundType::Above,
+                     };
+                     loadings.len()];
+    let levels = Levels::new(&types, |t| dunnett_cdf(t, loadings, kernel, alternative, abseps));
+    brent(levels, 1.0 - p, finder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use univariate::{norm_inv, t_inv};
+
+    fn independent_pair(alphas: &[f64]) -> Vec<MvCritResult> {
+        mvcrit_many(&Array::eye(2),
+                    0,
+                    &Array::zeros((2,)),
+                    &Array::zeros((2,)),
+                    &vec![BoundType::Both; 2],
+                    &Array::eye(2),
+                    alphas,
+                    100_000,
+                    1e-5,
+                    &RetryPolicy::default())
+            .unwrap()
+    }
+
+    /// The critical value of two independent two-sided statistics: `(2 Φ(c) - 1)² = 1 - alpha`.
+    fn exact(alpha: f64) -> f64 {
+        norm_inv(0.5 + (1.0 - alpha).sqrt() / 2.0)
+    }
+
+    #[test]
+    fn mvcrit_brackets_the_root() {
+        let result = independent_pair(&[0.05])[0];
+        assert!((result.crit - exact(0.05)).abs() < 1e-3);
+        assert!((result.achieved_alpha - 0.05).abs() < 1e-4);
+        assert!(result.bracket.0 < exact(0.05) && exact(0.05) < result.bracket.1);
+        assert!(result.bracket.1 - result.bracket.0 < 0.1);
+    }
+
+    #[test]
+    fn grid_of_levels() {
+        let alphas = [0.05, 0.1, 0.01, 0.0501];
+        let results = independent_pair(&alphas);
+        for (&alpha, r) in alphas.iter().zip(results.iter()) {
+            assert!((r.crit - exact(alpha)).abs() < 1e-3);
+            assert!(r.bracket.0 < exact(alpha) && exact(alpha) < r.bracket.1);
+        }
+        assert!(results[2].crit > results[0].crit && results[0].crit > results[1].crit);
+        assert!(mvcrit_many(&Array::eye(2),
+                            0,
+                            &Array::zeros((2,)),
+                            &Array::zeros((2,)),
+                            &vec![BoundType::Both; 2],
+                            &Array::eye(2),
+                            &[0.05, 1.0],
+                            1000,
+                            1e-3,
+                            &RetryPolicy::default())
+            .is_err());
+    }
+
+    #[test]
+    fn configured_search() {
+        let search = |alpha: f64, finder: &RootFinder| {
+            mvcrit_with(&Array::eye(2),
+                        0,
+                        &vec![BoundType::Both; 2],
+                        &Array::eye(2),
+                        alpha,
+                        100_000,
+                        1e-8,
+                        finder)
+        };
+        for &alpha in &[0.05, 1e-6] {
+            let r = search(alpha, &RootFinder::default()).unwrap();
+            assert!((r.crit - exact(alpha)).abs() < 1e-6);
+            assert!(r.bracket.0 <= exact(alpha) && exact(alpha) <= r.bracket.1);
+        }
+        let narrow = RootFinder { bracket: (0.0, 1.0), ..RootFinder::default() };
+        assert_eq!(search(0.05, &narrow),
+                   Err(MvDistError::RootNotBracketed {
+                       alpha: 0.05,
+                       lo: 0.0,
+                       hi: 1.0,
+                   }));
+        let once = RootFinder { max_iterations: 1, ..RootFinder::default() };
+        assert!(search(0.05, &once).unwrap().nevals > 0);
+    }
+
+    #[test]
+    fn native_search() {
+        let finder = RootFinder::default();
+        let two = vec![BoundType::Both; 2];
+        let (zero, eye) = (Array::zeros((2,)), Array::eye(2));
+        let native = |cov: &Array2<f64>, alpha: f64| {
+            mvcrit_native(cov,
+                          Kernel::Normal,
+                          &two,
+                          &eye,
+                          &zero,
+                          alpha,
+                          1_000_000,
+                          1e-6,
+                          0.0,
+                          ::native::DEFAULT_SEED,
+                          &finder)
+                .unwrap()
+        };
+        assert!((native(&Array::eye(2), 0.05).crit - exact(0.05)).abs() < 1e-5);
+        let cov = arr2(&[[1.0, 0.5], [0.5, 1.0]]);
+        let with = mvcrit_with(&cov, 0, &two, &eye, 0.05, 1_000_000, 1e-6, &finder).unwrap();
+        let r = native(&cov, 0.05);
+        assert!((r.crit - with.crit).abs() < 1e-4);
+        assert!(r.bracket.0 < r.crit && r.crit < r.bracket.1);
+        // a single shifted variable, and a t with non-integer degrees of freedom
+        let one = |kernel: Kernel, delta: f64| {
+            mvcrit_native(&Array::eye(1),
+                          kernel,
+                          &[BoundType::Above],
+                          &Array::eye(1),
+                          &Array::from_elem((1,), delta),
+                          0.05,
+                          1_000_000,
+                          1e-8,
+                          0.0,
+                          ::native::DEFAULT_SEED,
+                          &finder)
+                .unwrap()
+                .crit
+        };
+        assert!((one(Kernel::Normal, 0.5) - (0.5 + norm_inv(0.95))).abs() < 1e-6);
+        assert!((one(Kernel::StudentT { nu: 4.5 }, 0.0) - t_inv(4.5, 0.95)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn batch_of_critical_values() {
+        let pair = |rho: f64, alpha: f64| {
+            CritProblem {
+                cov: arr2(&[[1.0, rho], [rho, 1.0]]),
+                kernel: Kernel::Normal,
+                types: vec![BoundType::Both; 2],
+                constraints: Array::eye(2),
+                alpha: alpha,
+            }
+        };
+        let mut problems = vec![pair(0.0, 0.05), pair(0.0, 0.01), pair(0.1, 0.05), pair(0.0, 2.0)];
+        problems.push(CritProblem {
+            kernel: Kernel::StudentT { nu: 10.0 },
+            ..pair(0.0, 0.05)
+        });
+        let finder = RootFinder::default();
+        let seed = ::native::DEFAULT_SEED;
+        let streams = ::native::SeedStreams::new(seed);
+        let cancel = CancellationToken::new();
+        let results = mvcrit_batch(&problems, 1_000_000, 1e-7, 0.0, seed, &finder, &cancel);
+        assert_eq!(results.len(), 5);
+        for &i in &[0, 1] {
+            assert!((results[i].as_ref().unwrap().crit - exact(problems[i].alpha)).abs() < 1e-5);
+        }
+        assert!(results[3].is_err());
+        // the first problem of each group is searched as on its own, the others from a hint
+        for &i in &[0, 4] {
+            let p = &problems[i];
+            let single = mvcrit_native(&p.cov,
+                                       p.kernel,
+                                       &p.types,
+                                       &p.constraints,
+                                       &Array::zeros((2,)),
+                                       p.alpha,
+                                       1_000_000,
+                                       1e-7,
+                                       0.0,
+                                       streams.seed(i as u64),
+                                       &finder);
+            assert_eq!(results[i], single);
+        }
+        let hinted = results[2].as_ref().unwrap();
+        assert!(hinted.crit < results[0].as_ref().unwrap().crit);
+        assert!(hinted.bracket.0 < hinted.crit && hinted.crit < hinted.bracket.1);
+        cancel.cancel();
+        let cancelled = mvcrit_batch(&problems, 1_000_000, 1e-7, 0.0, seed, &finder, &cancel);
+        assert!(cancelled.iter().all(|r| *r == Err(MvDistError::Cancelled)));
+    }
+
+    #[test]
+    fn studentized_maximum_modulus() {
+        let finder = RootFinder::default();
+        let t = Kernel::StudentT { nu: 10.0 };
+        // a single variable is a two-sided t quantile
+        let one = max_modulus_quantile(0.95, 1, 0.0, t, 1e-10, &finder).unwrap();
+        assert!((one.crit - t_inv(10.0, 0.975)).abs() < 1e-6);
+        let two = max_modulus_quantile(0.95, 2, 0.0, Kernel::Normal, 1e-10, &finder).unwrap();
+        assert!((two.crit - exact(0.05)).abs() < 1e-6);
+        let cdf = max_modulus_cdf(two.crit, 2, 0.0, Kernel::Normal, 1e-10).unwrap();
+        assert!((cdf.value - 0.95).abs() < 1e-8);
+        // heavier tails and more variables need larger intervals, correlation smaller ones
+        let smm = |k: usize, rho: f64, kernel: Kernel| {
+            max_modulus_quantile(0.95, k, rho, kernel, 1e-8, &finder).unwrap().crit
+        };
+        assert!(smm(3, 0.0, t) > smm(3, 0.0, Kernel::Normal));
+        assert!(smm(5, 0.0, t) > smm(3, 0.0, t));
+        assert!(smm(3, 0.5, t) < smm(3, 0.0, t));
+        assert_eq!(max_modulus_cdf(0.0, 3, 0.0, t, 1e-8).unwrap().value, 0.0);
+        assert!(max_modulus_cdf(1.0, 0, 0.0, t, 1e-8).is_err());
+        assert!(max_modulus_cdf(1.0, 2, -0.5, t, 1e-8).is_err());
+    }
+
+    #[test]
+    fn dunnett_distribution() {
+        use std::f64::consts::PI;
+        let finder = RootFinder::default();
+        let t = Kernel::StudentT { nu: 20.0 };
+        // a balanced design is equicorrelated
+        let balanced = dunnett_loadings(10.0, &[10.0; 3]);
+        assert!((balanced[0] - 0.5f64.sqrt()).abs() < 1e-15);
+        let d = dunnett_cdf(2.0, &balanced, t, Alternative::TwoSided, 1e-10).unwrap();
+        let m = max_modulus_cdf(2.0, 3, 0.5, t, 1e-10).unwrap();
+        assert!((d.value - m.value).abs() < 1e-8);
+        // the critical values at the 5% level of Dunnett's tables, 2.54, 1.92 and 2.21
+        let q = dunnett_quantile(0.95, &balanced, t, Alternative::TwoSided, 1e-8, &finder).unwrap();
+        assert!((q.crit - 2.5403).abs() < 1e-3);
+        let pair = &balanced[..2];
+        let normal = Kernel::Normal;
+        let q = dunnett_quantile(0.95, pair, normal, Alternative::Greater, 1e-8, &finder).unwrap();
+        assert!((q.crit - 1.9163).abs() < 1e-3);
+        let q = dunnett_quantile(0.95, pair, normal, Alternative::TwoSided, 1e-8, &finder).unwrap();
+        assert!((q.crit - 2.2121).abs() < 1e-3);
+        // unbalanced: the orthant probability of the one-sided statistics at zero
+        let l = dunnett_loadings(20.0, &[5.0, 10.0, 40.0]);
+        let r = |i: usize, j: usize| (l[i] * l[j]).asin();
+        let orthant = 0.125 + (r(0, 1) + r(0, 2) + r(1, 2)) / (4.0 * PI);
+        let d = dunnett_cdf(0.0, &l, normal, Alternative::Less, 1e-10).unwrap();
+        assert!((d.value - orthant).abs() < 1e-8);
+        assert!(dunnett_cdf(1.0, &[], t, Alternative::TwoSided, 1e-8).is_err());
+        assert!(dunnett_cdf(1.0, &[0.5, 1.0], t, Alternative::TwoSided, 1e-8).is_err());
+    }
+}
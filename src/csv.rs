@@ -0,0 +1,216 @@
+//! Readers for covariance matrices, constraint matrices, and bounds tables stored as CSV, as
+//! produced by spreadsheets or `pandas.DataFrame.to_csv`.
+//!
+//! A header row is detected (and skipped) when any of its fields is not a number, as is a leading
+//! column of row labels. Fields may be quoted with `"`, and a quoted field may contain commas and
+//! quotes written `""`.
+
+use ndarray::prelude::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
+use std::path::Path;
+use {BoundType, MvProblem};
+
+/// Split a line at the commas outside quotes and trim the whitespace around each field.
+fn parse_fields(line: &str) -> Vec<String> {
+    let (mut fields, mut field, mut quoted) = (Vec::new(), String::new(), false);
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(mem::replace(&mut field, String::new())),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+fn is_number(field: &str) -> bool {
+    field.parse::<f64>().is_ok()
+}
+
+/// Split a CSV source into an optional header and data rows, with any row-label column removed
+/// from both.
+fn read_table<R: Read>(src: R) -> Result<(Option<Vec<String>>, Vec<Vec<String>>), String> {
+    let mut rows = Vec::new();
+    for line in BufReader::new(src).lines() {
+        let line = line.map_err(|e| format!("Unable to read CSV: {}", e))?;
+        if !line.trim().is_empty() {
+            rows.push(parse_fields(&line));
+        }
+    }
+    let mut header = None;
+    if rows.first().map_or(false, |r| r.iter().any(|f| !f.is_empty() && !is_number(f))) {
+        header = Some(rows.remove(0));
+    }
+    let labelled = !rows.is_empty() && rows.iter().all(|r| {
+        !r.is_empty() && !r[0].is_empty() && !is_number(&r[0])
+    });
+    if labelled {
+        for r in rows.iter_mut() {
+            r.remove(0);
+        }
+        if let Some(ref mut h) = header {
+            if !h.is_empty() {
+                h.remove(0);
+            }
+        }
+    }
+    Ok((header, rows))
+}
+
+fn parse_number(field: &str, row: usize, col: usize) -> Result<f64, String> {
+    field.parse::<f64>()
+        .map_err(|_| format!("Invalid number {:?} at row {}, column {}", field, row + 1, col + 1))
+}
+
+/// Read a numeric matrix.
+pub fn read_matrix<R: Read>(src: R) -> Result<Array2<f64>, String> {
+    let (_, rows) = read_table(src)?;
+    let cols = rows.first().map_or(0, |r| r.len());
+    let mut flat = Vec::with_capacity(rows.len() * cols);
+    for (i, r) in rows.iter().enumerate() {
+        if r.len() != cols {
+            return Err(format!("Row {} has {} columns, expected {}", i + 1, r.len(), cols));
+        }
+        for (j, f) in r.iter().enumerate() {
+            flat.push(parse_number(f, i, j)?);
+        }
+    }
+    Array::from_shape_vec((rows.len(), cols), flat).map_err(|e| format!("{}", e))
+}
+
+/// Read a square, symmetric covariance matrix.
+pub fn read_covariance<R: Read>(src: R) -> Result<Array2<f64>, String> {
+    let cov = read_matrix(src)?;
+    let n = cov.rows();
+    if cov.cols() != n {
+        return Err(format!("Covariance matrix must be square, but is {}x{}", n, cov.cols()));
+    }
+    for i in 0..n {
+        for j in 0..i {
+            if (cov[[i, j]] - cov[[j, i]]).abs() > 1e-12 * cov[[i, j]].abs().max(1.0) {
+                return Err(format!("Covariance matrix is not symmetric at ({}, {})", i + 1, j + 1));
+            }
+        }
+    }
+    Ok(cov)
+}
+
+/// Read a bounds table: one row per constraint with `lower` and `upper` columns. If a header is
+/// present, the columns are located by (case-insensitive) name; otherwise the first two columns
+/// are used. Empty or infinite entries mean the constraint is unbounded on that side, and the
+/// `BoundType` of each row is chosen accordingly.
+pub fn read_bounds<R: Read>(src: R) -> Result<(Array1<f64>, Array1<f64>, Vec<BoundType>), String> {
+    let (header, rows) = read_table(src)?;
+    let (lo_col, hi_col) = match header {
+        Some(h) => {
+            let find = |name: &str| {
+                h.iter()
+                    .position(|f| f.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| format!("Bounds table has no `{}` column", name))
+            };
+            (find("lower")?, find("upper")?)
+        }
+        None => (0, 1),
+    };
+    let mut lb = Vec::with_capacity(rows.len());
+    let mut ub = Vec::with_capacity(rows.len());
+    let mut types = Vec::with_capacity(rows.len());
+    for (i, r) in rows.iter().enumerate() {
+        let field = |col: usize| -> Result<Option<f64>, String> {
+            match r.get(col).map(|f| f.as_str()) {
+                None | Some("") => Ok(None),
+                Some(f) => {
+                    parse_number(f, i, col).map(|x| if x.is_finite() { Some(x) } else { None })
+                }
+            }
+        };
+        let (lo, hi) = (field(lo_col)?, field(hi_col)?);
+        types.push(match (lo, hi) {
+            (None, None) => BoundType::Unbounded,
+            (None, Some(_)) => BoundType::Above,
+            (Some(_), None) => BoundType::Below,
+            (Some(_), Some(_)) => BoundType::Both,
+        });
+        lb.push(lo.unwrap_or(0.0));
+        ub.push(hi.unwrap_or(0.0));
+    }
+    Ok((Array1::from_vec(lb), Array1::from_vec(ub), types))
+}
+
+fn open<P: AsRef<Path>>(path: P) -> Result<File, String> {
+    let path = path.as_ref();
+    File::open(path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))
+}
+
+impl MvProblem {
+    /// Build a problem from a covariance CSV, a bounds CSV (see `read_bounds`), and optionally a
+    /// constraint-matrix CSV, checking that the dimensions agree. Other settings take the
+    /// `MvProblem::new` defaults.
+    pub fn from_csv<P: AsRef<Path>>(cov: P,
+                                    bounds: P,
+                                    constraints: Option<P>)
+                                    -> Result<MvProblem, String> {
+        let cov = read_covariance(open(cov)?)?;
+        let (lb, ub, types) = read_bounds(open(bounds)?)?;
+        let n = cov.rows();
+        let mut problem = MvProblem::new(cov, lb, ub, types);
+        if let Some(path) = constraints {
+            problem.constraints = read_matrix(open(path)?)?;
+            problem.delta = Array::zeros((problem.constraints.rows(),));
+        }
+        if problem.constraints.cols() != n {
+            return Err(format!("Constraint matrix must have {} columns, but has {}",
+                               n,
+                               problem.constraints.cols()));
+        }
+        if problem.types.len() != problem.constraints.rows() {
+            return Err(format!("Bounds table has {} rows, but there are {} constraints",
+                               problem.types.len(),
+                               problem.constraints.rows()));
+        }
+        Ok(problem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn reads_labelled_covariance() {
+        let src = ",a,b\na,1.0,0.5\nb,0.5,2.0\n";
+        assert_eq!(read_covariance(src.as_bytes()).unwrap(),
+                   arr2(&[[1.0, 0.5], [0.5, 2.0]]));
+        assert!(read_covariance("1,2\n3,4\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn quoted_fields_keep_commas_and_quotes() {
+        assert_eq!(parse_fields(r#""a,b", "say ""hi""",1.5,"#),
+                   vec!["a,b", "say \"hi\"", "1.5", ""]);
+        let src = "\"\",\"a,b\",c\n\"a,b\",1.0,0.5\nc,0.5,2.0\n";
+        assert_eq!(read_covariance(src.as_bytes()).unwrap(),
+                   arr2(&[[1.0, 0.5], [0.5, 2.0]]));
+    }
+
+    #[test]
+    fn reads_bounds_table() {
+        let src = "name,Upper,Lower\nx,1.5,\ny,,-inf\nz,2,-2\n";
+        let (lb, ub, types) = read_bounds(src.as_bytes()).unwrap();
+        assert_eq!(ub, Array1::from_vec(vec![1.5, 0.0, 2.0]));
+        assert_eq!(lb[2], -2.0);
+        match (types[0], types[1], types[2]) {
+            (BoundType::Above, BoundType::Unbounded, BoundType::Both) => (),
+            t => panic!("unexpected bound types {:?}", t),
+        }
+    }
+}
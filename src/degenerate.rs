@@ -0,0 +1,290 @@
+//! Elimination of degenerate constraints. A constraint with `lb_i == ub_i` fixes its value
+//! `W_i = (C X)_i + delta_i` to a point, an event of probability zero, and a constraint whose
+//! value has zero variance is decided before integrating; either presents the integrators with a
+//! region of zero width in some direction, for which they return 0 or a noisy value. `reduce`
+//! removes both kinds: it conditions the distribution of the values `W` on the points and replaces
+//! each constraint of zero conditional variance by whether its known value satisfies it. The
+//! reduced problem is then a rectangle problem on the values of the remaining constraints, and
+//! `Reduction::mvdist` gives the conditional probability of those constraints given the points.
+//!
+//! Conditioning a multivariate t on `k` points adds `k` degrees of freedom and rescales the
+//! conditional scatter, as described in `partition`; it is only defined for a central t, so a
+//! degenerate problem with the t kernel must have zero noncentrality.
+
+use ndarray::prelude::*;
+use native::Reorder;
+use {BoundType, EvalConfig, Kernel, MVInform, MVResult, MvDistError, MvProblem};
+
+/// The conditional variance, relative to the largest variance of the constraint values, at or
+/// below which a value counts as known. Its square root, in the same units, is the slack allowed
+/// when deciding whether a known value satisfies its bounds, so that rounding errors in the
+/// conditioning do not decide a value lying on a bound.
+pub const ZERO_VARIANCE: f64 = 1e-12;
+
+/// How a constraint was removed from the problem.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Elimination {
+    /// `lb == ub` with `BoundType::Both`: the distribution was conditioned on the value of the
+    /// constraint being that point.
+    Point,
+    /// The value of the constraint had zero variance given the points, so it was `value` with
+    /// probability one; `satisfied` is whether that value is within the bounds.
+    ZeroVariance { value: f64, satisfied: bool },
+}
+
+/// A constraint removed by `reduce`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Eliminated {
+    /// The index of the constraint in the original problem.
+    pub constraint: usize,
+    pub kind: Elimination,
+}
+
+/// A problem with its degenerate constraints removed.
+#[derive(Clone, Debug)]
+pub struct Reduction {
+    /// The problem on the values of the remaining constraints, with identity constraints, or
+    /// `None` if no constraints remain. A problem without degenerate constraints is kept as it is.
+    pub problem: Option<MvProblem>,
+    /// The remaining constraints, by their index in the original problem, in the order of the
+    /// variables of `problem`.
+    pub kept: Vec<usize>,
+    /// The removed constraints, in the order they were removed.
+    pub eliminated: Vec<Eliminated>,
+}
+
+impl Reduction {
+    /// Whether every constraint of zero variance is satisfied.
+    pub fn satisfied(&self) -> bool {
+        self.eliminated.iter().all(|e| match e.kind {
+            Elimination::ZeroVariance { satisfied, .. } => satisfied,
+            Elimination::Point => true,
+        })
+    }
+
+    /// The probability of the remaining constraints given the points, which is the probability of
+    /// the original problem when no point was conditioned on: exactly 0 when a constraint of zero
+    /// variance is not satisfied, exactly 1 when none remain, and otherwise the result of
+    /// `MvProblem::mvdist` for `problem`.
+    pub fn mvdist(&self) -> Result<MVResult, MvDistError> {
+        match self.problem {
+            Some(ref problem) if self.satisfied() => problem.mvdist(),
+            _ => {
+                Ok(MVResult {
+                    value: if self.satisfied() { 1.0 } else { 0.0 },
+                    error: 0.0,
+                    nevals: 0,
+                    state: MVInform::Normal,
+                })
+            }
+        }
+    }
+}
+
+/// Remove the degenerate constraints of `problem`. The covariance of the constraint values,
+/// `C cov Cᵀ`, is conditioned on the points one at a time in the order of the constraints, and
+/// before each step every remaining constraint whose conditional variance is at most
+/// `ZERO_VARIANCE` times the largest variance is removed with its conditional mean as its value; a
+/// point of zero conditional variance is removed in the same way, and is satisfied when its value
+/// is the point up to the slack. A custom constraint ordering is replaced by `Reorder::Auto` in
+/// the reduced problem, whose other settings are those of `problem`.
+///
+/// It is an error for a degenerate problem with the t kernel to have a nonzero noncentrality.
+pub fn reduce(problem: &MvProblem) -> Result<Reduction, MvDistError> {
+    problem.validate()?;
+    let m = problem.constraints.rows();
+    let c = &problem.constraints;
+    let mut cov = c.dot(&problem.cov).dot(&c.t());
+    let scale = (0..m).map(|i| cov[[i, i]]).fold(0.0, f64::max);
+    let (zero, slack) = (ZERO_VARIANCE * scale, (ZERO_VARIANCE * scale).sqrt());
+    let is_point = |i: usize| match problem.types[i] {
+        BoundType::Both => problem.lb[i] == problem.ub[i],
+        _ => false,
+    };
+    let degenerate = (0..m).any(|i| is_point(i) || cov[[i, i]] <= zero);
+    if !degenerate {
+        return Ok(Reduction {
+            problem: Some(problem.clone()),
+            kept: (0..m).collect(),
+            eliminated: Vec::new(),
+        });
+    }
+    let nu = problem.kernel.nu()?;
+    let t = nu > 0.0;
+    if t && problem.delta.iter().any(|&d| d != 0.0) {
+        return Err(MvDistError::InvalidInput("Degenerate constraints of a noncentral t cannot be \
+                                              eliminated"
+            .to_string()));
+    }
+    // the conditional means of the values, the sum of the squared standardized residuals of the
+    // points, and the number of points conditioned on
+    let mut mean = if t { Array1::zeros((m,)) } else { problem.delta.clone() };
+    let (mut distance, mut points) = (0.0, 0);
+    let mut remaining = (0..m).collect::<Vec<usize>>();
+    let mut eliminated = Vec::new();
+    loop {
+        let mut kept = Vec::with_capacity(remaining.len());
+        for &i in &remaining {
+            if cov[[i, i]] > zero {
+                kept.push(i);
+                continue;
+            }
+            let (lo, hi) = problem.types[i].limits(problem.lb[i], problem.ub[i]);
+            let value = mean[i];
+            eliminated.push(Eliminated {
+                constraint: i,
+                kind: Elimination::ZeroVariance {
+                    value: value,
+                    satisfied: lo - slack <= value && value <= hi + slack,
+                },
+            });
+        }
+        remaining = kept;
+        let p = match remaining.iter().position(|&i| is_point(i)) {
+            Some(p) => remaining.remove(p),
+            None => break,
+        };
+        // condition on W_p = lb_p
+        let (r, v) = (problem.lb[p] - mean[p], cov[[p, p]]);
+        for &i in &remaining {
+            mean[i] += cov[[i, p]] / v * r;
+        }
+        for &i in &remaining {
+            for &j in &remaining {
+                cov[[i, j]] -= cov[[i, p]] * cov[[p, j]] / v;
+            }
+        }
+        distance += r * r / v;
+        points += 1;
+        eliminated.push(Eliminated {
+            constraint: p,
+            kind: Elimination::Point,
+        });
+    }
+    let (kernel, factor) = if t {
+        (Kernel::StudentT { nu: nu + points as f64 }, (nu + distance) / (nu + points as f64))
+    } else {
+        (problem.kernel, 1.0)
+    };
+    let k = remaining.len();
+    let reduced = if k == 0 {
+        None
+    } else {
+        let mut reduced_cov = Array2::zeros((k, k));
+        for (a, &i) in remaining.iter().enumerate() {
+            for (b, &j) in remaining.iter().enumerate() {
+                reduced_cov[[a, b]] = factor * cov[[i, j]];
+            }
+        }
+        let shift = |v: &Array1<f64>| {
+            Array::from_vec(remaining.iter().map(|&i| v[i] - mean[i]).collect())
+        };
+        Some(MvProblem {
+            cov: reduced_cov,
+            kernel: kernel,
+            lb: shift(&problem.lb),
+            ub: shift(&problem.ub),
+            types: remaining.iter().map(|&i| problem.types[i]).collect(),
+            constraints: Array::eye(k),
+            delta: Array::zeros((k,)),
+            config: EvalConfig {
+                reorder: match problem.config.reorder {
+                    Reorder::Custom(_) => Reorder::Auto,
+                    ref r => r.clone(),
+                },
+                ..problem.config.clone()
+            },
+        })
+    };
+    Ok(Reduction {
+        problem: reduced,
+        kept: remaining,
+        eliminated: eliminated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::ar1;
+    use exact::bvn;
+    use ndarray::prelude::*;
+    use univariate::{norm_cdf, t_cdf};
+
+    #[test]
+    fn eliminates_points_and_known_values() {
+        let inf = ::std::f64::INFINITY;
+        // an AR(1) sequence is Markov, so X_0 and X_2 are independent given X_1 = 0.3, each with
+        // mean 0.15 and variance 0.75
+        let problem = MvProblem::new(ar1(3, 0.5),
+                                     Array::from_vec(vec![-inf, 0.3, -0.2]),
+                                     Array::from_vec(vec![0.5, 0.3, inf]),
+                                     vec![BoundType::Above, BoundType::Both, BoundType::Below]);
+        let r = reduce(&problem).unwrap();
+        assert_eq!(r.kept, vec![0, 2]);
+        assert_eq!(r.eliminated,
+                   vec![Eliminated {
+                            constraint: 1,
+                            kind: Elimination::Point,
+                        }]);
+        let sd = 0.75f64.sqrt();
+        let exact = norm_cdf((0.5 - 0.15) / sd) * norm_cdf((0.15 + 0.2) / sd);
+        assert!((r.mvdist().unwrap().value - exact).abs() < 1e-6);
+        // a variable of zero variance is known to be 0
+        let cov = arr2(&[[1.0, 0.0, 0.4], [0.0, 0.0, 0.0], [0.4, 0.0, 1.0]]);
+        let mut problem = MvProblem::new(cov,
+                                         Array::from_vec(vec![-inf, -inf, -inf]),
+                                         Array::from_vec(vec![0.2, 0.5, 1.0]),
+                                         vec![BoundType::Above; 3]);
+        let r = reduce(&problem).unwrap();
+        assert_eq!(r.kept, vec![0, 2]);
+        assert!(r.satisfied());
+        assert_eq!(r.problem.as_ref().unwrap().cov, arr2(&[[1.0, 0.4], [0.4, 1.0]]));
+        let exact = bvn([-inf, -inf], [0.2, 1.0], 0.4);
+        assert!((r.mvdist().unwrap().value - exact).abs() < 1e-6);
+        problem.ub[1] = -0.5;
+        let r = reduce(&problem).unwrap();
+        assert_eq!(r.eliminated[0].kind,
+                   Elimination::ZeroVariance {
+                       value: 0.0,
+                       satisfied: false,
+                   });
+        assert_eq!(r.mvdist().unwrap().value, 0.0);
+        // X_1 = X_0 is known once X_0 is fixed, and a point on its bound satisfies it
+        let cov = arr2(&[[1.0, 1.0], [1.0, 1.0]]);
+        let problem = MvProblem::new(cov,
+                                     Array::from_vec(vec![0.7, -inf]),
+                                     Array::from_vec(vec![0.7, 0.7]),
+                                     vec![BoundType::Both, BoundType::Above]);
+        let r = reduce(&problem).unwrap();
+        assert!(r.problem.is_none() && r.kept.is_empty() && r.eliminated.len() == 2);
+        assert_eq!(r.mvdist().unwrap().value, 1.0);
+    }
+
+    #[test]
+    fn conditions_the_t() {
+        let inf = ::std::f64::INFINITY;
+        // given X_1 = 2, X_0 is t with 4 degrees of freedom, location 1 and scale 0.75 * 1.75
+        let mut problem = MvProblem::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]),
+                                         Array::from_vec(vec![-inf, 2.0]),
+                                         Array::from_vec(vec![1.5, 2.0]),
+                                         vec![BoundType::Above, BoundType::Both]);
+        problem.kernel = Kernel::StudentT { nu: 3.0 };
+        let r = reduce(&problem).unwrap();
+        let reduced = r.problem.clone().unwrap();
+        assert_eq!(reduced.kernel, Kernel::StudentT { nu: 4.0 });
+        let scale = 0.75f64 * 1.75;
+        assert!((reduced.cov[[0, 0]] - scale).abs() < 1e-14 && (reduced.ub[0] - 0.5).abs() < 1e-14);
+        let exact = t_cdf(4.0, 0.5 / scale.sqrt());
+        assert!((r.mvdist().unwrap().value - exact).abs() < 1e-6);
+        // without degenerate constraints the problem is kept, noncentral or not
+        problem.types[1] = BoundType::Above;
+        problem.delta[1] = 0.5;
+        let r = reduce(&problem).unwrap();
+        assert!(r.eliminated.is_empty() && r.kept == vec![0, 1]);
+        problem.types[1] = BoundType::Both;
+        assert!(reduce(&problem).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! A persistent result cache stored as one JSON file per problem in a directory, enabled by the
+//! `cache-disk` feature, so that restarted jobs can reuse previously computed probabilities.
+//!
+//! Entries are keyed by `MvProblem::fingerprint`, a hash of the problem, and store the full
+//! problem, so hash collisions are detected. Each entry also records a version string made of the
+//! crate version and a user-supplied tag; entries with any other version are treated as missing
+//! and removed, so bumping the tag invalidates the whole cache.
+
+use serde_json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use {MVResult, MvDistError, MvProblem};
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    version: String,
+    problem: MvProblem,
+    result: MVResult,
+}
+
+/// A directory of cached results holding at most `max_entries` entries; the least recently written
+/// entries are removed when the limit is exceeded.
+#[derive(Clone, Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    version: String,
+    max_entries: usize,
+}
+
+impl DiskCache {
+    /// Open (creating if necessary) the cache in `dir`. `tag` is combined with the crate version
+    /// to form the version of new entries.
+    pub fn open<P: AsRef<Path>>(dir: P, tag: &str, max_entries: usize) -> io::Result<DiskCache> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(DiskCache {
+            dir: dir.as_ref().to_path_buf(),
+            version: format!("{}/{}", env!("CARGO_PKG_VERSION"), tag),
+            max_entries: max_entries,
+        })
+    }
+
+    fn path(&self, problem: &MvProblem) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", problem.fingerprint()))
+    }
+
+    /// The cached result for `problem`, if any.
+    pub fn get(&self, problem: &MvProblem) -> Option<MVResult> {
+        let path = self.path(problem);
+        let entry = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Entry>(&s).ok());
+        match entry {
+            Some(ref e) if e.version == self.version && e.problem == *problem => Some(e.result),
+            Some(ref e) if e.version != self.version => {
+                let _ = fs::remove_file(&path);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Store `result` as the result for `problem`, then enforce the size limit.
+    pub fn insert(&self, problem: &MvProblem, result: MVResult) -> io::Result<()> {
+        let entry = Entry {
+            version: self.version.clone(),
+            problem: problem.clone(),
+            result: result,
+        };
+        let json = serde_json::to_string(&entry)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        fs::write(self.path(problem), json)?;
+        self.prune()
+    }
+
+    /// Evaluate `problem`, reusing a cached result if there is one. Failures to write the cache
+    /// are ignored.
+    pub fn evaluate(&self, problem: &MvProblem) -> Result<MVResult, MvDistError> {
+        if let Some(result) = self.get(problem) {
+            return Ok(result);
+        }
+        let result = problem.mvdist()?;
+        let _ = self.insert(problem, result);
+        Ok(result)
+    }
+
+    /// Remove the oldest entries beyond `max_entries`.
+    fn prune(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for e in fs::read_dir(&self.dir)? {
+            let e = e?;
+            if e.path().extension().map_or(false, |x| x == "json") {
+                entries.push((e.metadata()?.modified()?, e.path()));
+            }
+        }
+        if entries.len() > self.max_entries {
+            entries.sort();
+            let excess = entries.len() - self.max_entries;
+            for &(_, ref path) in &entries[..excess] {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        for e in fs::read_dir(&self.dir)? {
+            let path = e?.path();
+            if path.extension().map_or(false, |x| x == "json") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use std::env;
+    use BoundType;
+
+    #[test]
+    fn roundtrip_and_invalidation() {
+        let dir = env::temp_dir().join(format!("mvdist-disk-cache-{}", ::std::process::id()));
+        let problem = MvProblem::new(Array::eye(2),
+                                     Array1::from_vec(vec![0.0, 0.0]),
+                                     Array1::from_vec(vec![0.0, 0.0]),
+                                     vec![BoundType::Above; 2]);
+        let cache = DiskCache::open(&dir, "a", 10).unwrap();
+        assert_eq!(cache.get(&problem), None);
+        let result = cache.evaluate(&problem).unwrap();
+        assert_eq!(cache.get(&problem), Some(result));
+        assert_eq!(DiskCache::open(&dir, "b", 10).unwrap().get(&problem), None);
+        assert_eq!(cache.get(&problem), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,915 @@
+//! Distribution objects for the multivariate normal and t distributions and their skewed
+//! counterparts, implementing a small set of traits modelled on those of `statrs` so that code can
+//! be written generically over them.
+//!
+//! `kl_normal` and `kl_student_normal` are Kullback–Leibler divergences in closed form, and
+//! `kl_divergence` estimates the divergence between any two distributions with densities by Monte
+//! Carlo sampling, as needed from a normal to a t distribution.
+
+use linalg::{cholesky, ln_det_cholesky, solve_lower};
+use ndarray::prelude::*;
+use quadrature::integrate;
+use rand::Rng;
+use rand::distributions::{Gamma, IndependentSample, Normal};
+use std::f64::consts::PI;
+use univariate::{digamma, inc_beta, inc_gamma, inc_gamma_inv, ln_gamma, norm_cdf, scaled_chi_pdf,
+                 t_cdf};
+use {mvdist_kernel, BoundType, Kernel, MVInform, MVResult, MvDistError, ERROR_SCALE};
+
+/// Distributions with a density.
+pub trait Continuous<K> {
+    fn pdf(&self, x: K) -> f64 {
+        self.ln_pdf(x).exp()
+    }
+
+    fn ln_pdf(&self, x: K) -> f64;
+}
+
+/// Distributions whose CDF is computed by numerical integration, with an error estimate.
+pub trait Cdf<K> {
+    /// `P(X <= x)`, computed with `mvdist` using `maxpts = 100_000` and `abseps = 1e-5`.
+    fn cdf(&self, x: K) -> Result<MVResult, MvDistError>;
+}
+
+/// Distributions with a mean vector. `None` if the mean does not exist.
+pub trait Mean {
+    fn mean(&self) -> Option<Array1<f64>>;
+}
+
+/// Distributions with a covariance matrix. `None` if the covariance does not exist.
+pub trait Covariance {
+    fn covariance(&self) -> Option<Array2<f64>>;
+}
+
+/// Elliptically contoured distributions: `X = location + A R U` with `A Aᵀ = scatter`, `U`
+/// uniform on the unit sphere and an independent radius `R` whose law is selected by `kernel`.
+/// Code written against this trait applies to both `MultivariateNormal` and `MultivariateStudent`.
+///
+/// A noncentral `MultivariateStudent` is not elliptical. Its implementation describes the central
+/// distribution, except that `probability` includes the noncentrality.
+pub trait Elliptical {
+    fn location(&self) -> &Array1<f64>;
+
+    fn scatter(&self) -> &Array2<f64>;
+
+    fn kernel(&self) -> Kernel;
+
+    /// The log density generator: the log density at `x` is `ln_generator(q) - ln |scatter| / 2`,
+    /// where `q` is the squared Mahalanobis distance of `x` from `location` under `scatter`.
+    fn ln_generator(&self, q: f64) -> f64 {
+        let n = self.location().len() as f64;
+        match self.kernel() {
+            Kernel::Normal => -0.5 * (n * (2.0 * PI).ln() + q),
+            Kernel::StudentT { nu } => {
+                ln_gamma((nu + n) / 2.0) - ln_gamma(nu / 2.0) - 0.5 * n * (nu * PI).ln() -
+                0.5 * (nu + n) * (1.0 + q / nu).ln()
+            }
+        }
+    }
+
+    /// The Mahalanobis distance `sqrt((x - location)ᵀ scatter⁻¹ (x - location))`, or NaN if the
+    /// scatter matrix is not positive definite.
+    fn mahalanobis(&self, x: &Array1<f64>) -> f64 {
+        cholesky(self.scatter())
+            .map_or(::std::f64::NAN, |l| mahalanobis_sq(&l, self.location(), x).sqrt())
+    }
+
+    /// The probability of the ellipsoid `mahalanobis(X) <= r`. The squared distance is chi-square
+    /// with `n` degrees of freedom for the normal kernel, and `n` times an `F(n, nu)` variable for
+    /// the t kernel.
+    fn ellipsoid_probability(&self, r: f64) -> f64 {
+        if !(r > 0.0) {
+            return 0.0;
+        }
+        let (n, q) = (self.location().len() as f64, r * r);
+        match self.kernel() {
+            Kernel::StudentT { nu } if nu.is_finite() => inc_beta(n / 2.0, nu / 2.0, q / (q + nu)),
+            _ => inc_gamma(n / 2.0, q / 2.0),
+        }
+    }
+
+    /// The radius `r` of the ellipsoid with probability `level`, the inverse of
+    /// `ellipsoid_probability`.
+    fn ellipsoid_radius(&self, level: f64) -> f64 {
+        if !(level > 0.0) {
+            return 0.0;
+        } else if level >= 1.0 {
+            return ::std::f64::INFINITY;
+        }
+        let n = self.location().len() as f64;
+        if let Kernel::StudentT { nu } = self.kernel() {
+            if nu.is_finite() {
+                let (mut lo, mut hi) = (0.0, 1.0);
+                while self.ellipsoid_probability(hi) < level {
+                    lo = hi;
+                    hi *= 2.0;
+                }
+                while hi - lo > 1e-14 * hi {
+                    let mid = 0.5 * (lo + hi);
+                    if self.ellipsoid_probability(mid) < level {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                return 0.5 * (lo + hi);
+            }
+        }
+        (2.0 * inc_gamma_inv(n / 2.0, level)).sqrt()
+    }
+
+    /// `P(lb < X < ub)`, with the bounds used selected by `types`, computed with `mvdist`.
+    fn probability(&self,
+                   lb: &Array1<f64>,
+                   ub: &Array1<f64>,
+                   types: &[BoundType],
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+        let n = self.location().len();
+        rectangle(self.scatter(),
+                  self.kernel(),
+                  self.location(),
+                  &Array::zeros((n,)),
+                  lb,
+                  ub,
+                  types,
+                  maxpts,
+                  abseps)
+    }
+}
+
+/// Distributions with a differential entropy in closed form. `None` if it has none.
+pub trait Entropy {
+    /// `-E[ln pdf(X)]`, in nats.
+    fn entropy(&self) -> Option<f64>;
+}
+
+/// Distributions that can be sampled from.
+pub trait Sample {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Array1<f64>;
+}
+
+/// Multivariate normal distribution with mean `mean` and covariance `cov`.
+#[derive(Clone, Debug)]
+pub struct MultivariateNormal {
+    mean: Array1<f64>,
+    cov: Array2<f64>,
+    chol: Array2<f64>,
+}
+
+/// Multivariate t distribution with location `location`, scale matrix `scale`, and finite `nu > 0`
+/// degrees of freedom, which need not be an integer, optionally noncentral: `X = location + (Y +
+/// delta) / S` with `Y` normal with covariance `scale` and `S` distributed as `sqrt(W / nu)` for
+/// `W` chi-square with `nu` degrees of freedom. This is the noncentral distribution evaluated by
+/// `mvdist_kernel` with `delta`.
+#[derive(Clone, Debug)]
+pub struct MultivariateStudent {
+    location: Array1<f64>,
+    scale: Array2<f64>,
+    nu: f64,
+    delta: Array1<f64>,
+    chol: Array2<f64>,
+}
+
+/// Azzalini's multivariate skew-normal distribution with location `location`, scale matrix
+/// `scale` and shape `shape`: the density is `2 φ(x; location, scale) Φ(shapeᵀ ω⁻¹ (x -
+/// location))`, where `ω` holds the standard deviations of `scale`. `shape = 0` gives the normal
+/// distribution.
+#[derive(Clone, Debug)]
+pub struct MultivariateSkewNormal {
+    base: MultivariateNormal,
+    skew: Skew,
+}
+
+/// Azzalini & Capitanio's multivariate skew-t distribution with location `location`, scale matrix
+/// `scale`, shape `shape` and `nu > 0` degrees of freedom: the skew-normal variable with location
+/// zero divided by an independent `sqrt(W / nu)`, `W` chi-square with `nu` degrees of freedom,
+/// then shifted by `location`.
+#[derive(Clone, Debug)]
+pub struct MultivariateSkewStudent {
+    base: MultivariateStudent,
+    skew: Skew,
+}
+
+/// The parameters shared by the skew distributions.
+#[derive(Clone, Debug)]
+struct Skew {
+    shape: Array1<f64>,
+    /// Standard deviations of the scale matrix.
+    sd: Vec<f64>,
+    /// The `(n + 1) x (n + 1)` correlation matrix `[[R, -d], [-dᵀ, 1]]` of the CDF
+    /// representation, with `R` the correlation matrix of `scale` and `d = R shape / sqrt(1 +
+    /// shapeᵀ R shape)`.
+    augmented: Array2<f64>,
+}
+
+impl Skew {
+    fn new(scale: &Array2<f64>, shape: Array1<f64>) -> Result<Skew, MvDistError> {
+        let n = scale.rows();
+        if shape.len() != n {
+            return Err(MvDistError::InvalidInput(format!("Expected a shape of dimension {}, got \
+                                                          {}",
+                                                         n,
+                                                         shape.len())));
+        }
+        let sd = (0..n).map(|i| scale[[i, i]].sqrt()).collect::<Vec<f64>>();
+        let mut augmented = Array2::zeros((n + 1, n + 1));
+        for i in 0..n {
+            for j in 0..n {
+                augmented[[i, j]] = scale[[i, j]] / (sd[i] * sd[j]);
+            }
+        }
+        let r_shape = (0..n)
+            .map(|i| (0..n).map(|j| augmented[[i, j]] * shape[j]).sum::<f64>())
+            .collect::<Vec<f64>>();
+        let norm = (1.0 + (0..n).map(|i| shape[i] * r_shape[i]).sum::<f64>()).sqrt();
+        for i in 0..n {
+            augmented[[i, n]] = -r_shape[i] / norm;
+            augmented[[n, i]] = -r_shape[i] / norm;
+        }
+        augmented[[n, n]] = 1.0;
+        Ok(Skew {
+            shape: shape,
+            sd: sd,
+            augmented: augmented,
+        })
+    }
+
+    /// `shapeᵀ ω⁻¹ (x - location)`.
+    fn index(&self, location: &Array1<f64>, x: &Array1<f64>) -> f64 {
+        (0..x.len()).map(|i| self.shape[i] * (x[i] - location[i]) / self.sd[i]).sum()
+    }
+
+    /// `P(X <= x) = 2 P(Y <= (ω⁻¹ (x - location), 0))` for `Y` with correlation `augmented` and
+    /// distribution `kernel`.
+    fn cdf(&self,
+           kernel: Kernel,
+           location: &Array1<f64>,
+           x: &Array1<f64>)
+           -> Result<MVResult, MvDistError> {
+        let n = location.len();
+        if x.len() != n {
+            return Err(MvDistError::InvalidInput(format!("Expected a point of dimension {}, \
+                                                          got {}",
+                                                         n,
+                                                         x.len())));
+        }
+        let mut upper = (0..n).map(|i| (x[i] - location[i]) / self.sd[i]).collect::<Vec<f64>>();
+        upper.push(0.0);
+        mvdist_kernel(&self.augmented,
+                      kernel,
+                      &Array::zeros((n + 1,)),
+                      &Array::from_vec(upper),
+                      &vec![BoundType::Above; n + 1],
+                      &Array::eye(n + 1),
+                      &Array::zeros((n + 1,)),
+                      100_000,
+                      1e-5,
+                      0.0)
+            .map(|res| {
+                MVResult {
+                    value: (2.0 * res.value).min(1.0),
+                    error: 2.0 * res.error,
+                    ..res
+                }
+            })
+    }
+}
+
+fn factor(loc: &Array1<f64>, scatter: &Array2<f64>) -> Result<Array2<f64>, MvDistError> {
+    let n = loc.len();
+    if scatter.rows() != n || scatter.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected a {}x{} matrix, got {}x{}",
+                                                     n,
+                                                     n,
+                                                     scatter.rows(),
+                                                     scatter.cols())));
+    }
+    cholesky(scatter).ok_or(MvDistError::NotPositiveSemidefinite)
+}
+
+/// Squared Mahalanobis distance of `x` from `loc` under the factored matrix `chol`.
+fn mahalanobis_sq(chol: &Array2<f64>, loc: &Array1<f64>, x: &Array1<f64>) -> f64 {
+    let diff = x.iter().zip(loc.iter()).map(|(a, b)| a - b).collect::<Vec<f64>>();
+    solve_lower(chol, &diff).iter().map(|z| z * z).sum()
+}
+
+/// `P(lb < X < ub)` for `X = loc + (Y + delta) / S` as in `MultivariateStudent`, or `loc + Y` for
+/// the normal kernel.
+fn rectangle(scatter: &Array2<f64>,
+             kernel: Kernel,
+             loc: &Array1<f64>,
+             delta: &Array1<f64>,
+             lb: &Array1<f64>,
+             ub: &Array1<f64>,
+             types: &[BoundType],
+             maxpts: u64,
+             abseps: f64)
+             -> Result<MVResult, MvDistError> {
+    let n = loc.len();
+    if lb.len() != n || ub.len() != n || types.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected bounds of dimension {}, got {}, {} \
+                                                      and {}",
+                                                     n,
+                                                     lb.len(),
+                                                     ub.len(),
+                                                     types.len())));
+    }
+    mvdist_kernel(scatter,
+                  kernel,
+                  &(lb - loc),
+                  &(ub - loc),
+                  types,
+                  &Array::eye(n),
+                  delta,
+                  maxpts,
+                  abseps,
+                  0.0)
+}
+
+impl MultivariateNormal {
+    pub fn new(mean: Array1<f64>, cov: Array2<f64>) -> Result<MultivariateNormal, MvDistError> {
+        let chol = factor(&mean, &cov)?;
+        Ok(MultivariateNormal {
+            mean: mean,
+            cov: cov,
+            chol: chol,
+        })
+    }
+
+    pub fn dim(&self) -> usize {
+        self.mean.len()
+    }
+}
+
+impl MultivariateStudent {
+    pub fn new(location: Array1<f64>,
+               scale: Array2<f64>,
+               nu: f64)
+               -> Result<MultivariateStudent, MvDistError> {
+        let n = location.len();
+        MultivariateStudent::noncentral(location, scale, nu, Array::zeros((n,)))
+    }
+
+    /// The noncentral distribution with noncentrality `delta`.
+    pub fn noncentral(location: Array1<f64>,
+                      scale: Array2<f64>,
+                      nu: f64,
+                      delta: Array1<f64>)
+                      -> Result<MultivariateStudent, MvDistError> {
+        if !(nu > 0.0 && nu.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Degrees of freedom must be positive \
+                                                          and finite, got {}",
+                                                         nu)));
+        }
+        if delta.len() != location.len() {
+            return Err(MvDistError::InvalidInput(format!("Expected a noncentrality of dimension \
+                                                          {}, got {}",
+                                                         location.len(),
+                                                         delta.len())));
+        }
+        let chol = factor(&location, &scale)?;
+        Ok(MultivariateStudent {
+            location: location,
+            scale: scale,
+            nu: nu,
+            delta: delta,
+            chol: chol,
+        })
+    }
+
+    pub fn dim(&self) -> usize {
+        self.location.len()
+    }
+
+    pub fn nu(&self) -> f64 {
+        self.nu
+    }
+
+    pub fn noncentrality(&self) -> &Array1<f64> {
+        &self.delta
+    }
+
+    fn is_central(&self) -> bool {
+        self.delta.iter().all(|&d| d == 0.0)
+    }
+
+    /// `E[1 / S]`, finite for `nu > 1`.
+    fn mean_inverse_scale(&self) -> f64 {
+        let nu = self.nu;
+        (nu / 2.0).sqrt() * (ln_gamma((nu - 1.0) / 2.0) - ln_gamma(nu / 2.0)).exp()
+    }
+
+    /// The log density of the noncentral distribution by quadrature over the mixing variable:
+    /// given `S = s`, `X` is normal with mean `location + delta / s` and covariance `scale / s²`.
+    fn noncentral_ln_pdf(&self, x: &Array1<f64>) -> f64 {
+        let (n, nu) = (self.dim() as f64, self.nu);
+        let diff = x.iter().zip(self.location.iter()).map(|(a, b)| a - b).collect::<Vec<f64>>();
+        let a = solve_lower(&self.chol, &diff);
+        let b = solve_lower(&self.chol, &self.delta.iter().cloned().collect::<Vec<f64>>());
+        let dot = |u: &[f64], v: &[f64]| u.iter().zip(v.iter()).map(|(p, q)| p * q).sum::<f64>();
+        let (aa, ab, bb) = (dot(&a, &a), dot(&a, &b), dot(&b, &b));
+        let constant = -0.5 * (n * (2.0 * PI).ln() + ln_det_cholesky(&self.chol));
+        // log integrand in s, where |L⁻¹(s (x - location) - delta)|² = s² aa - 2 s ab + bb
+        let ln_f = |s: f64| {
+            n * s.ln() - 0.5 * (s * s * aa - 2.0 * s * ab + bb) + scaled_chi_pdf(nu, s).ln()
+        };
+        // substitute s = u / (1 - u), scaling by the largest value on a grid to avoid underflow
+        let ln_g = |u: f64| ln_f(u / (1.0 - u)) - 2.0 * (1.0 - u).ln();
+        let peak = (1..64).map(|i| ln_g(i as f64 / 64.0)).fold(::std::f64::NEG_INFINITY, f64::max);
+        let q = integrate(|u| {
+                              if u <= 0.0 || u >= 1.0 {
+                                  0.0
+                              } else {
+                                  (ln_g(u) - peak).exp()
+                              }
+                          },
+                          0.0,
+                          1.0,
+                          1e-300,
+                          1e-10);
+        constant + peak + q.value.ln()
+    }
+}
+
+impl MultivariateSkewNormal {
+    pub fn new(location: Array1<f64>,
+               scale: Array2<f64>,
+               shape: Array1<f64>)
+               -> Result<MultivariateSkewNormal, MvDistError> {
+        let skew = Skew::new(&scale, shape)?;
+        Ok(MultivariateSkewNormal {
+            base: MultivariateNormal::new(location, scale)?,
+            skew: skew,
+        })
+    }
+
+    pub fn dim(&self) -> usize {
+        self.base.dim()
+    }
+
+    pub fn shape(&self) -> &Array1<f64> {
+        &self.skew.shape
+    }
+}
+
+impl MultivariateSkewStudent {
+    pub fn new(location: Array1<f64>,
+               scale: Array2<f64>,
+               shape: Array1<f64>,
+               nu: f64)
+               -> Result<MultivariateSkewStudent, MvDistError> {
+        let skew = Skew::new(&scale, shape)?;
+        Ok(MultivariateSkewStudent {
+            base: MultivariateStudent::new(location, scale, nu)?,
+            skew: skew,
+        })
+    }
+
+    pub fn dim(&self) -> usize {
+        self.base.dim()
+    }
+
+    pub fn shape(&self) -> &Array1<f64> {
+        &self.skew.shape
+    }
+
+    pub fn nu(&self) -> f64 {
+        self.base.nu
+    }
+}
+
+impl<'a> Continuous<&'a Array1<f64>> for MultivariateNormal {
+    fn ln_pdf(&self, x: &'a Array1<f64>) -> f64 {
+        self.ln_generator(mahalanobis_sq(&self.chol, &self.mean, x)) -
+        0.5 * ln_det_cholesky(&self.chol)
+    }
+}
+
+impl<'a> Continuous<&'a Array1<f64>> for MultivariateStudent {
+    fn ln_pdf(&self, x: &'a Array1<f64>) -> f64 {
+        if !self.is_central() {
+            return self.noncentral_ln_pdf(x);
+        }
+        self.ln_generator(mahalanobis_sq(&self.chol, &self.location, x)) -
+        0.5 * ln_det_cholesky(&self.chol)
+    }
+}
+
+impl<'a> Continuous<&'a Array1<f64>> for MultivariateSkewNormal {
+    fn ln_pdf(&self, x: &'a Array1<f64>) -> f64 {
+        2f64.ln() + self.base.ln_pdf(x) + norm_cdf(self.skew.index(&self.base.mean, x)).ln()
+    }
+}
+
+impl<'a> Continuous<&'a Array1<f64>> for MultivariateSkewStudent {
+    fn ln_pdf(&self, x: &'a Array1<f64>) -> f64 {
+        let b = &self.base;
+        let (n, nu) = (b.dim() as f64, b.nu);
+        let q = mahalanobis_sq(&b.chol, &b.location, x);
+        let t = self.skew.index(&b.location, x) * ((nu + n) / (q + nu)).sqrt();
+        2f64.ln() + b.ln_pdf(x) + t_cdf(nu + n, t).ln()
+    }
+}
+
+impl<'a> Cdf<&'a Array1<f64>> for MultivariateNormal {
+    fn cdf(&self, x: &'a Array1<f64>) -> Result<MVResult, MvDistError> {
+        let n = self.dim();
+        self.probability(&Array::zeros((n,)), x, &vec![BoundType::Above; n], 100_000, 1e-5)
+    }
+}
+
+impl<'a> Cdf<&'a Array1<f64>> for MultivariateStudent {
+    fn cdf(&self, x: &'a Array1<f64>) -> Result<MVResult, MvDistError> {
+        let n = self.dim();
+        self.probability(&Array::zeros((n,)), x, &vec![BoundType::Above; n], 100_000, 1e-5)
+    }
+}
+
+impl Elliptical for MultivariateNormal {
+    fn location(&self) -> &Array1<f64> {
+        &self.mean
+    }
+
+    fn scatter(&self) -> &Array2<f64> {
+        &self.cov
+    }
+
+    fn kernel(&self) -> Kernel {
+        Kernel::Normal
+    }
+}
+
+impl Elliptical for MultivariateStudent {
+    fn location(&self) -> &Array1<f64> {
+        &self.location
+    }
+
+    fn scatter(&self) -> &Array2<f64> {
+        &self.scale
+    }
+
+    fn kernel(&self) -> Kernel {
+        Kernel::StudentT { nu: self.nu }
+    }
+
+    fn probability(&self,
+                   lb: &Array1<f64>,
+                   ub: &Array1<f64>,
+                   types: &[BoundType],
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+        rectangle(&self.scale,
+                  self.kernel(),
+                  &self.location,
+                  &self.delta,
+                  lb,
+                  ub,
+                  types,
+                  maxpts,
+                  abseps)
+    }
+}
+
+impl<'a> Cdf<&'a Array1<f64>> for MultivariateSkewNormal {
+    fn cdf(&self, x: &'a Array1<f64>) -> Result<MVResult, MvDistError> {
+        self.skew.cdf(Kernel::Normal, &self.base.mean, x)
+    }
+}
+
+impl<'a> Cdf<&'a Array1<f64>> for MultivariateSkewStudent {
+    fn cdf(&self, x: &'a Array1<f64>) -> Result<MVResult, MvDistError> {
+        self.skew.cdf(self.base.kernel(), &self.base.location, x)
+    }
+}
+
+impl Mean for MultivariateNormal {
+    fn mean(&self) -> Option<Array1<f64>> {
+        Some(self.mean.clone())
+    }
+}
+
+impl Mean for MultivariateStudent {
+    fn mean(&self) -> Option<Array1<f64>> {
+        if self.nu > 1.0 {
+            Some(&self.location + &(&self.delta * self.mean_inverse_scale()))
+        } else {
+            None
+        }
+    }
+}
+
+impl Covariance for MultivariateNormal {
+    fn covariance(&self) -> Option<Array2<f64>> {
+        Some(self.cov.clone())
+    }
+}
+
+impl Covariance for MultivariateStudent {
+    fn covariance(&self) -> Option<Array2<f64>> {
+        if self.nu > 2.0 {
+            // E[1 / S²] (scale + delta deltaᵀ) - E[1 / S]² delta deltaᵀ
+            let nu = self.nu;
+            let (v, m) = (nu / (nu - 2.0), self.mean_inverse_scale());
+            let n = self.dim();
+            let mut cov = &self.scale * v;
+            for i in 0..n {
+                for j in 0..n {
+                    cov[[i, j]] += (v - m * m) * self.delta[i] * self.delta[j];
+                }
+            }
+            Some(cov)
+        } else {
+            None
+        }
+    }
+}
+
+impl Sample for MultivariateNormal {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Array1<f64> {
+        let z = standard_normal(self.dim(), rng);
+        &self.mean + &self.chol.dot(&z)
+    }
+}
+
+impl Sample for MultivariateStudent {
+    fn sample<R: Rng>(&self, rng: &mut R) -> Array1<f64> {
+        let nu = self.nu;
+        let w = Gamma::new(nu / 2.0, 2.0).ind_sample(rng);
+        let s = (w / nu).sqrt();
+        let y = self.chol.dot(&standard_normal(self.dim(), rng)) + &self.delta;
+        &self.location + &(y / s)
+    }
+}
+
+impl Entropy for MultivariateNormal {
+    fn entropy(&self) -> Option<f64> {
+        let n = self.dim() as f64;
+        Some(0.5 * (n * (1.0 + (2.0 * PI).ln()) + ln_det_cholesky(&self.chol)))
+    }
+}
+
+impl Entropy for MultivariateStudent {
+    /// The entropy of the central distribution, `ln |scale| / 2 + n/2 ln(nu π) + ln Γ(nu/2) -
+    /// ln Γ(h) + h (ψ(h) - ψ(nu/2))` with `h = (nu + n)/2`. `None` for a noncentral distribution.
+    fn entropy(&self) -> Option<f64> {
+        if !self.is_central() {
+            return None;
+        }
+        let (n, nu) = (self.dim() as f64, self.nu);
+        let h = (nu + n) / 2.0;
+        Some(0.5 * ln_det_cholesky(&self.chol) + 0.5 * n * (nu * PI).ln() + ln_gamma(nu / 2.0) -
+             ln_gamma(h) + h * (digamma(h) - digamma(nu / 2.0)))
+    }
+}
+
+fn check_dimensions(p: usize, q: usize) -> Result<(), MvDistError> {
+    if p != q {
+        return Err(MvDistError::InvalidInput(format!("Distributions of dimensions {} and {}",
+                                                     p,
+                                                     q)));
+    }
+    Ok(())
+}
+
+/// `tr(Σ_q⁻¹ Σ_p)` for the factors `l_p` and `l_q`: the squared Frobenius norm of `L_q⁻¹ L_p`.
+fn trace_of_ratio(l_p: &Array2<f64>, l_q: &Array2<f64>) -> f64 {
+    (0..l_p.cols())
+        .map(|j| {
+            let column = l_p.column(j).iter().cloned().collect::<Vec<f64>>();
+            solve_lower(l_q, &column).iter().map(|z| z * z).sum::<f64>()
+        })
+        .sum()
+}
+
+/// The Kullback–Leibler divergence `KL(p || q) = E_p[ln p(X) - ln q(X)]` between two normal
+/// distributions: `(tr(Σ_q⁻¹ Σ_p) + (μ_q - μ_p)ᵀ Σ_q⁻¹ (μ_q - μ_p) - n + ln |Σ_q| - ln |Σ_p|) / 2`.
+pub fn kl_normal(p: &MultivariateNormal, q: &MultivariateNormal) -> Result<f64, MvDistError> {
+    check_dimensions(p.dim(), q.dim())?;
+    let n = p.dim() as f64;
+    Ok(0.5 *
+       (trace_of_ratio(&p.chol, &q.chol) + mahalanobis_sq(&q.chol, &q.mean, &p.mean) - n +
+        ln_det_cholesky(&q.chol) - ln_det_cholesky(&p.chol)))
+}
+
+/// `KL(p || q)` from a central t distribution `p` to a normal `q`, in closed form from the entropy
+/// of `p` and its covariance `nu / (nu - 2) scale`. Infinite for `nu <= 2`, where `q` has lighter
+/// tails than the variance of `p` can bear.
+pub fn kl_student_normal(p: &MultivariateStudent,
+                         q: &MultivariateNormal)
+                         -> Result<f64, MvDistError> {
+    check_dimensions(p.dim(), q.dim())?;
+    let entropy = p.entropy()
+        .ok_or_else(|| MvDistError::InvalidInput("The t distribution is noncentral".to_string()))?;
+    if p.nu <= 2.0 {
+        return Ok(::std::f64::INFINITY);
+    }
+    let (n, nu) = (p.dim() as f64, p.nu);
+    let cross = 0.5 *
+                (n * (2.0 * PI).ln() + ln_det_cholesky(&q.chol) +
+                 nu / (nu - 2.0) * trace_of_ratio(&p.chol, &q.chol) +
+                 mahalanobis_sq(&q.chol, &q.mean, &p.location));
+    Ok(cross - entropy)
+}
+
+/// A Monte Carlo estimate of `KL(p || q)`, the mean of `ln p(X) - ln q(X)` over `samples` draws of
+/// `X` from `p`, such as from a normal distribution to a t distribution, where the divergence has
+/// no closed form. `error` is `ERROR_SCALE` standard errors of the mean, and `nevals` the number of
+/// samples.
+pub fn kl_divergence<P, Q, R>(p: &P,
+                              q: &Q,
+                              samples: usize,
+                              rng: &mut R)
+                              -> Result<MVResult, MvDistError>
+    where P: for<'a> Continuous<&'a Array1<f64>> + Sample,
+          Q: for<'a> Continuous<&'a Array1<f64>>,
+          R: Rng
+{
+    if samples < 2 {
+        return Err(MvDistError::InvalidInput(format!("At least 2 samples are needed, got {}",
+                                                     samples)));
+    }
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for _ in 0..samples {
+        let x = p.sample(rng);
+        let d = p.ln_pdf(&x) - q.ln_pdf(&x);
+        sum += d;
+        sum_sq += d * d;
+    }
+    let k = samples as f64;
+    let mean = sum / k;
+    let variance = ((sum_sq - k * mean * mean) / (k - 1.0)).max(0.0) / k;
+    Ok(MVResult {
+        value: mean,
+        error: ERROR_SCALE * variance.sqrt(),
+        nevals: samples as u64,
+        state: MVInform::Normal,
+    })
+}
+
+fn standard_normal<R: Rng>(n: usize, rng: &mut R) -> Array1<f64> {
+    let normal = Normal::new(0.0, 1.0);
+    Array::from_vec((0..n).map(|_| normal.ind_sample(rng)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn normal_density_and_cdf() {
+        let d = MultivariateNormal::new(Array::zeros((2,)), Array::eye(2)).unwrap();
+        let x = Array1::from_vec(vec![0.0, 0.0]);
+        assert!((d.pdf(&x) - 1.0 / (2.0 * PI)).abs() < 1e-15);
+        assert!((d.cdf(&x).unwrap().value - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn student_density_and_moments() {
+        // a bivariate t with nu = 1 at the origin has density 1 / (2 pi)
+        let d = MultivariateStudent::new(Array::zeros((2,)), Array::eye(2), 1.0).unwrap();
+        assert!((d.pdf(&Array::zeros((2,))) - 1.0 / (2.0 * PI)).abs() < 1e-14);
+        assert!(d.mean().is_none());
+        let d = MultivariateStudent::new(Array::zeros((2,)), Array::eye(2), 4.0).unwrap();
+        assert_eq!(d.covariance().unwrap(), Array::eye(2) * 2.0);
+        // degrees of freedom need not be integers
+        let d = MultivariateStudent::new(Array::zeros((1,)), Array::eye(1), 2.5).unwrap();
+        assert!((d.covariance().unwrap()[[0, 0]] - 5.0).abs() < 1e-15);
+        let p = d.cdf(&arr1(&[1.0])).unwrap();
+        assert!((p.value - t_cdf(2.5, 1.0)).abs() <= p.error + 1e-12);
+        assert!(MultivariateStudent::new(Array::zeros((1,)), Array::eye(1), 0.0).is_err());
+        assert_eq!(MultivariateNormal::new(Array::zeros((2,)), Array::zeros((2, 2))).unwrap_err(),
+                   MvDistError::NotPositiveSemidefinite);
+    }
+
+    /// Code written once against `Elliptical`: the probability of the box `location ± 1`.
+    fn central_box<D: Elliptical>(d: &D) -> f64 {
+        let n = d.location().len();
+        let ones = Array::from_elem((n,), 1.0);
+        d.probability(&(d.location() - &ones),
+                      &(d.location() + &ones),
+                      &vec![BoundType::Both; n],
+                      100_000,
+                      1e-6)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn elliptical_kernels() {
+        let loc = Array1::from_vec(vec![0.5, -1.0]);
+        let normal = MultivariateNormal::new(loc.clone(), Array::eye(2)).unwrap();
+        let student = MultivariateStudent::new(loc.clone(), Array::eye(2), 3.0).unwrap();
+        let p = 2.0 * norm_cdf(1.0) - 1.0;
+        assert!((central_box(&normal) - p * p).abs() < 1e-6);
+        assert!(central_box(&student) < central_box(&normal));
+        assert_eq!(student.kernel(), Kernel::StudentT { nu: 3.0 });
+        assert!(normal.probability(&loc, &loc, &[BoundType::Both], 1000, 1e-3).is_err());
+    }
+
+    #[test]
+    fn ellipsoids() {
+        let loc = Array1::from_vec(vec![1.0, 2.0]);
+        let normal = MultivariateNormal::new(loc.clone(), arr2(&[[4.0, 0.0], [0.0, 1.0]])).unwrap();
+        let d = normal.mahalanobis(&Array1::from_vec(vec![3.0, 3.0]));
+        assert!((d - 2f64.sqrt()).abs() < 1e-15);
+        // in two dimensions the squared distance is exponential with mean 2
+        assert!((normal.ellipsoid_probability(1.5) - (1.0 - (-1.125f64).exp())).abs() < 1e-14);
+        assert!((normal.ellipsoid_radius(0.95) - (-2.0 * 0.05f64.ln()).sqrt()).abs() < 1e-10);
+        let t = MultivariateStudent::new(Array1::from_vec(vec![0.0]), Array::eye(1), 5.0).unwrap();
+        assert!((t.ellipsoid_probability(2.0) - (2.0 * t_cdf(5.0, 2.0) - 1.0)).abs() < 1e-12);
+        assert!((t.ellipsoid_probability(t.ellipsoid_radius(0.9)) - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn noncentral_student() {
+        let scale = arr2(&[[1.0, 0.4], [0.4, 2.0]]);
+        let loc = Array1::from_vec(vec![0.5, -1.0]);
+        let central = MultivariateStudent::new(loc.clone(), scale.clone(), 5.0).unwrap();
+        let x = Array1::from_vec(vec![1.0, 0.3]);
+        // the quadrature density agrees with the closed form for zero noncentrality
+        assert!((central.noncentral_ln_pdf(&x) - central.ln_pdf(&x)).abs() < 1e-9);
+        let delta = Array1::from_vec(vec![1.0, -0.5]);
+        let d = MultivariateStudent::noncentral(loc, scale, 5.0, delta).unwrap();
+        assert!((d.ln_pdf(&x) - central.ln_pdf(&x)).abs() > 0.1);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4][..]);
+        let draws = 100_000;
+        let mut sum = Array::zeros((2,));
+        let mut below = 0;
+        for _ in 0..draws {
+            let y = d.sample(&mut rng);
+            if y[0] <= x[0] && y[1] <= x[1] {
+                below += 1;
+            }
+            sum = sum + y;
+        }
+        let mean = d.mean().unwrap();
+        for i in 0..2 {
+            assert!((sum[i] / draws as f64 - mean[i]).abs() < 0.02);
+        }
+        let p = d.cdf(&x).unwrap().value;
+        assert!((below as f64 / draws as f64 - p).abs() < 0.005);
+    }
+
+    #[test]
+    fn skew_cdfs() {
+        let scale = arr2(&[[1.0, 0.4], [0.4, 2.0]]);
+        let loc = Array1::from_vec(vec![0.5, -1.0]);
+        let x = Array1::from_vec(vec![1.0, 0.3]);
+        // zero shape gives the symmetric distributions
+        let sn = MultivariateSkewNormal::new(loc.clone(), scale.clone(), Array::zeros((2,)));
+        let normal = MultivariateNormal::new(loc.clone(), scale.clone()).unwrap();
+        let sn = sn.unwrap();
+        assert!((sn.cdf(&x).unwrap().value - normal.cdf(&x).unwrap().value).abs() < 2e-5);
+        assert!((sn.ln_pdf(&x) - normal.ln_pdf(&x)).abs() < 1e-12);
+        // in one dimension, P(X <= location) = 1/2 - atan(shape) / pi for both families
+        let expected = 0.5 - 2f64.atan() / PI;
+        let (one, zero) = (Array::eye(1) * 3.0, Array::zeros((1,)));
+        let sn = MultivariateSkewNormal::new(zero.clone(), one.clone(), Array::from_vec(vec![2.0]));
+        assert!((sn.unwrap().cdf(&zero).unwrap().value - expected).abs() < 1e-5);
+        let st = MultivariateSkewStudent::new(zero.clone(), one, Array::from_vec(vec![2.0]), 3.0);
+        let st = st.unwrap();
+        assert!((st.cdf(&zero).unwrap().value - expected).abs() < 1e-5);
+        // the density integrates to one
+        let h = 0.01;
+        let total = (-4000..4000)
+            .map(|i| st.pdf(&Array::from_vec(vec![(i as f64 + 0.5) * h])) * h)
+            .sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn entropy_and_divergence() {
+        let normal = MultivariateNormal::new(Array::zeros((1,)), Array::eye(1)).unwrap();
+        assert!((normal.entropy().unwrap() - 0.5 * (1.0 + (2.0 * PI).ln())).abs() < 1e-15);
+        // the standard Cauchy distribution has entropy ln 4π
+        let cauchy = MultivariateStudent::new(Array::zeros((1,)), Array::eye(1), 1.0).unwrap();
+        assert!((cauchy.entropy().unwrap() - (4.0 * PI).ln()).abs() < 1e-12);
+        let (zero, delta) = (Array::zeros((1,)), Array::from_elem((1,), 1.0));
+        let noncentral = MultivariateStudent::noncentral(zero, Array::eye(1), 3.0, delta).unwrap();
+        assert!(noncentral.entropy().is_none());
+        // KL(N(0, 1) || N(1, 4)) = ln 2 + 2 / 8 - 1 / 2
+        let wide = MultivariateNormal::new(Array::from_elem((1,), 1.0), Array::eye(1) * 4.0);
+        let wide = wide.unwrap();
+        assert!((kl_normal(&normal, &wide).unwrap() - (2f64.ln() - 0.25)).abs() < 1e-15);
+        assert_eq!(kl_normal(&normal, &normal).unwrap(), 0.0);
+        let mut rng = StdRng::from_seed(&[1, 2, 3, 4][..]);
+        let mc = kl_divergence(&normal, &wide, 100_000, &mut rng).unwrap();
+        assert!((mc.value - (2f64.ln() - 0.25)).abs() < mc.error);
+        // from a normal to a Cauchy distribution, by quadrature of E[ln (1 + X²)]: 0.259245
+        let mc = kl_divergence(&normal, &cauchy, 100_000, &mut rng).unwrap();
+        assert!((mc.value - 0.259245).abs() < mc.error && mc.error < 0.01);
+        // from a t to a normal distribution, in closed form
+        let scale = arr2(&[[1.0, 0.4], [0.4, 2.0]]);
+        let t = MultivariateStudent::new(Array::zeros((2,)), scale.clone(), 5.0).unwrap();
+        let q = MultivariateNormal::new(Array::from_elem((2,), 0.5), scale).unwrap();
+        let mc = kl_divergence(&t, &q, 100_000, &mut rng).unwrap();
+        assert!((mc.value - kl_student_normal(&t, &q).unwrap()).abs() < mc.error);
+        assert!(kl_student_normal(&cauchy, &normal).unwrap().is_infinite());
+        assert!(kl_normal(&normal, &q).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Forward-mode automatic differentiation with dual numbers. A `Dual` carries a value and its
+//! gradient with respect to any number of parameters, and the arithmetic operators and the
+//! functions the native integrand needs propagate the gradient by the chain rule, so one
+//! evaluation gives the value and every partial derivative. `native::gradient` evaluates the
+//! integrand of a problem in this way.
+//!
+//! An empty gradient stands for a constant, whose derivatives are all zero, so that constants
+//! need no allocation; operations on a constant and a variable give the gradient of the variable.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use univariate::{norm_cdf, norm_inv, norm_pdf};
+
+/// A value with its gradient.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Dual {
+    pub value: f64,
+    /// The partial derivatives of `value`, or empty if they are all zero.
+    pub grad: Vec<f64>,
+}
+
+impl Dual {
+    /// A constant.
+    pub fn constant(value: f64) -> Dual {
+        Dual {
+            value: value,
+            grad: Vec::new(),
+        }
+    }
+
+    /// Parameter `i` of `n`, with value `value`.
+    pub fn variable(value: f64, i: usize, n: usize) -> Dual {
+        let mut grad = vec![0.0; n];
+        grad[i] = 1.0;
+        Dual {
+            value: value,
+            grad: grad,
+        }
+    }
+
+    /// Partial derivative `i`.
+    pub fn derivative(&self, i: usize) -> f64 {
+        self.grad.get(i).cloned().unwrap_or(0.0)
+    }
+
+    /// `g(self)` for `g` with value `value` and derivative `slope` at `self.value`.
+    fn chain(mut self, value: f64, slope: f64) -> Dual {
+        for d in self.grad.iter_mut() {
+            *d *= slope;
+        }
+        self.value = value;
+        self
+    }
+
+    /// `a self + b other` for the gradients, with the given value.
+    fn combine(mut self, a: f64, other: &Dual, b: f64, value: f64) -> Dual {
+        if self.grad.len() < other.grad.len() {
+            self.grad.resize(other.grad.len(), 0.0);
+        }
+        for (i, d) in self.grad.iter_mut().enumerate() {
+            *d = a * *d + b * other.derivative(i);
+        }
+        self.value = value;
+        self
+    }
+
+    /// The square root.
+    pub fn sqrt(self) -> Dual {
+        let r = self.value.sqrt();
+        self.chain(r, 0.5 / r)
+    }
+
+    /// The standard normal CDF, constant at infinite values.
+    pub fn norm_cdf(self) -> Dual {
+        if self.value.is_infinite() {
+            return Dual::constant(norm_cdf(self.value));
+        }
+        let v = self.value;
+        self.chain(norm_cdf(v), norm_pdf(v))
+    }
+
+    /// The standard normal quantile function.
+    pub fn norm_inv(self) -> Dual {
+        let z = norm_inv(self.value);
+        self.chain(z, 1.0 / norm_pdf(z))
+    }
+
+    /// The larger of the two, by value.
+    pub fn max(self, other: Dual) -> Dual {
+        if other.value > self.value { other } else { self }
+    }
+
+    /// The smaller of the two, by value.
+    pub fn min(self, other: Dual) -> Dual {
+        if other.value < self.value { other } else { self }
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, other: Dual) -> Dual {
+        let v = self.value + other.value;
+        self.combine(1.0, &other, 1.0, v)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, other: Dual) -> Dual {
+        let v = self.value - other.value;
+        self.combine(1.0, &other, -1.0, v)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, other: Dual) -> Dual {
+        let (a, b) = (self.value, other.value);
+        self.combine(b, &other, a, a * b)
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, other: Dual) -> Dual {
+        let (a, b) = (self.value, other.value);
+        self.combine(1.0 / b, &other, -a / (b * b), a / b)
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        let v = -self.value;
+        self.chain(v, -1.0)
+    }
+}
+
+impl Add<f64> for Dual {
+    type Output = Dual;
+    fn add(mut self, other: f64) -> Dual {
+        self.value += other;
+        self
+    }
+}
+
+impl Sub<f64> for Dual {
+    type Output = Dual;
+    fn sub(mut self, other: f64) -> Dual {
+        self.value -= other;
+        self
+    }
+}
+
+impl Mul<f64> for Dual {
+    type Output = Dual;
+    fn mul(self, other: f64) -> Dual {
+        let v = self.value * other;
+        self.chain(v, other)
+    }
+}
+
+impl Div<f64> for Dual {
+    type Output = Dual;
+    fn div(self, other: f64) -> Dual {
+        let v = self.value / other;
+        self.chain(v, 1.0 / other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use univariate::{norm_cdf, norm_inv};
+
+    #[test]
+    fn chain_rule() {
+        // f(x, y) = Φ(x y + 1) / sqrt(x) - y / x, against central differences
+        let f = |x: f64, y: f64| norm_cdf(x * y + 1.0) / x.sqrt() - y / x;
+        let (x, y) = (0.7, -0.4);
+        let (dx, dy) = (Dual::variable(x, 0, 2), Dual::variable(y, 1, 2));
+        let d = (dx.clone() * dy.clone() + 1.0).norm_cdf() / dx.clone().sqrt() - dy / dx;
+        assert!((d.value - f(x, y)).abs() < 1e-15);
+        let h = 1e-6;
+        let fx = (f(x + h, y) - f(x - h, y)) / (2.0 * h);
+        let fy = (f(x, y + h) - f(x, y - h)) / (2.0 * h);
+        assert!((d.derivative(0) - fx).abs() < 1e-8 && (d.derivative(1) - fy).abs() < 1e-8);
+        // the quantile function inverts the CDF, so their derivatives cancel
+        let p = Dual::variable(0.3, 0, 1).norm_inv();
+        assert!((p.value - norm_inv(0.3)).abs() < 1e-15);
+        assert!((p.clone().norm_cdf().derivative(0) - 1.0).abs() < 1e-12);
+        // constants carry no gradient
+        let c = Dual::constant(2.0) * Dual::constant(3.0);
+        assert!(c.grad.is_empty() && c.value == 6.0);
+        assert_eq!(Dual::constant(::std::f64::INFINITY).norm_cdf(), Dual::constant(1.0));
+    }
+}
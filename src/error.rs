@@ -0,0 +1,67 @@
+//! The error type returned by the evaluation functions.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MvDistError {
+    /// The problem has `n` variables (or constraints), more than the `max` supported by the
+    /// backend.
+    DimensionTooLarge { n: usize, max: usize },
+    /// The covariance matrix is not positive semidefinite.
+    NotPositiveSemidefinite,
+    /// The arguments are inconsistent or otherwise invalid.
+    InvalidInput(String),
+    /// The critical value at level `alpha` is not between `lo` and `hi`.
+    RootNotBracketed { alpha: f64, lo: f64, hi: f64 },
+    /// The evaluation was not started because its batch was cancelled.
+    Cancelled,
+    /// The evaluation would need about `required` bytes of working memory, more than the `limit`
+    /// of its `context::EvalContext`.
+    MemoryLimitExceeded { required: usize, limit: usize },
+}
+
+impl fmt::Display for MvDistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MvDistError::DimensionTooLarge { n, max } => {
+                write!(f, "Dimension {} exceeds the backend limit of {}", n, max)
+            }
+            MvDistError::NotPositiveSemidefinite => {
+                write!(f, "Covariance matrix not positive semidefinite")
+            }
+            MvDistError::InvalidInput(ref msg) => write!(f, "{}", msg),
+            MvDistError::RootNotBracketed { alpha, lo, hi } => {
+                write!(f, "The critical value at level {} is not in [{}, {}]", alpha, lo, hi)
+            }
+            MvDistError::Cancelled => write!(f, "The evaluation was cancelled"),
+            MvDistError::MemoryLimitExceeded { required, limit } => {
+                write!(f,
+                       "The evaluation needs about {} bytes, more than the limit of {}",
+                       required,
+                       limit)
+            }
+        }
+    }
+}
+
+impl Error for MvDistError {}
+
+impl From<String> for MvDistError {
+    fn from(msg: String) -> MvDistError {
+        MvDistError::InvalidInput(msg)
+    }
+}
+
+/// Convert a dimension or count to the `i32` used by the Fortran interface.
+pub fn checked_i32(n: usize) -> Result<i32, MvDistError> {
+    if n > ::std::i32::MAX as usize {
+        Err(MvDistError::DimensionTooLarge {
+            n: n,
+            max: ::std::i32::MAX as usize,
+        })
+    } else {
+        Ok(n as i32)
+    }
+}
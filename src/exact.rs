@@ -0,0 +1,919 @@
+//! Special-purpose evaluation of problems whose structure admits a cheaper, more accurate method
+//! than QMC integration. `mvdist` checks these cases before calling into the Fortran backend.
+//!
+//! With identity constraints, `try_solve` recognizes:
+//!
+//! - independent normal variables, a product of univariate probabilities;
+//! - central orthants `P(±X_i > 0)` of up to three bounded variables, and of any number with a
+//!   common correlation of 0 or 1/2 and the same sign, by the closed forms of `orthant`;
+//! - central bivariate t problems with integer degrees of freedom, by the series of `bvt`;
+//! - at most three variables once those perfectly correlated (`|ρ| = 1`) with another are merged
+//!   into it, by `bvn` and by one-dimensional quadrature of `bvn` for three;
+//! - a common non-negative correlation, by one-dimensional quadrature over the common factor.
+//!
+//! The closed forms, the product and the series report an error of zero, and are accurate to
+//! about `1e-15`; the quadratures report their own error estimate, which meets the requested
+//! tolerance. The t and noncentral cases of the quadratures add an integral over the chi mixing
+//! variable.
+
+use ndarray::prelude::*;
+use quadrature::integrate;
+use std::f64::consts::PI;
+use univariate::{norm_cdf, norm_inv, scaled_chi_pdf, t_cdf};
+use {BoundType, MVInform, MVResult, MvDistError};
+
+/// Lower and upper integration limits of each coordinate, with infinite limits filled in according
+/// to `types`.
+fn limits(lb: &[f64], ub: &[f64], types: &[BoundType]) -> Vec<(f64, f64)> {
+    types.iter()
+        .enumerate()
+        .map(|(i, &t)| t.limits(lb[i], ub[i]))
+        .collect()
+}
+
+fn is_identity(constraints: &Array2<f64>) -> bool {
+    constraints.rows() == constraints.cols() &&
+    constraints.indexed_iter().all(|((i, j), &c)| c == if i == j { 1.0 } else { 0.0 })
+}
+
+/// If `cov` has a common non-negative correlation between all pairs of variables, returns that
+/// correlation and the standard deviations of the variables.
+pub fn equicorrelation(cov: &Array2<f64>) -> Option<(f64, Vec<f64>)> {
+    const TOL: f64 = 1e-12;
+    let n = cov.rows();
+    if n < 2 || cov.cols() != n {
+        return None;
+    }
+    let sd = (0..n).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0)) {
+        return None;
+    }
+    let rho = cov[[0, 1]] / (sd[0] * sd[1]);
+    if rho < 0.0 || rho > 1.0 + TOL {
+        return None;
+    }
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && (cov[[i, j]] / (sd[i] * sd[j]) - rho).abs() > TOL {
+                return None;
+            }
+        }
+    }
+    Some((rho.min(1.0), sd))
+}
+
+/// Probability that standardized, equicorrelated normal variables fall inside `limits`, by
+/// conditioning on the common factor: `X_i = sqrt(rho) Z_0 + sqrt(1 - rho) Z_i`.
+fn equicorrelated_normal(rho: f64,
+                         limits: &[(f64, f64)],
+                         abseps: f64,
+                         releps: f64)
+                         -> (f64, f64, usize) {
+    if rho >= 1.0 - 1e-10 {
+        // perfectly correlated: all coordinates are the same standard normal variable
+        let lo = limits.iter().map(|l| l.0).fold(::std::f64::NEG_INFINITY, f64::max);
+        let hi = limits.iter().map(|l| l.1).fold(::std::f64::INFINITY, f64::min);
+        let p = if hi > lo { norm_cdf(hi) - norm_cdf(lo) } else { 0.0 };
+        return (p, 0.0, 1);
+    }
+    one_factor_normal(&vec![rho.sqrt(); limits.len()], limits, abseps, releps)
+}
+
+/// Probability that standardized normal variables with correlations `loadings[i] loadings[j]`
+/// fall inside `limits`, by conditioning on the common factor: `X_i = l_i Z_0 + sqrt(1 - l_i²)
+/// Z_i`. The loadings must be less than 1 in absolute value.
+fn one_factor_normal(loadings: &[f64],
+                     limits: &[(f64, f64)],
+                     abseps: f64,
+                     releps: f64)
+                     -> (f64, f64, usize) {
+    let q = integrate(|u| {
+                          let z0 = norm_inv(u);
+                          limits.iter()
+                              .zip(loadings.iter())
+                              .map(|(&(lo, hi), &a)| {
+                                  let b = (1.0 - a * a).sqrt();
+                                  norm_cdf((hi - a * z0) / b) - norm_cdf((lo - a * z0) / b)
+                              })
+                              .product::<f64>()
+                      },
+                      0.0,
+                      1.0,
+                      abseps,
+                      releps);
+    (q.value, q.error, q.nevals)
+}
+
+/// Evaluate `P(lb < X < ub)` (with bounds selected by `types`) for `X` multivariate normal or,
+/// when `nu > 0`, multivariate t with noncentrality `delta`, where the correlation between every
+/// pair of variables is `rho >= 0` and the standard deviations are `sd`. The probability reduces
+/// to a one-dimensional integral (two-dimensional for the t case), computed by adaptive
+/// Gauss-Kronrod quadrature.
+pub fn equicorrelated(rho: f64,
+                      sd: &[f64],
+                      nu: f64,
+                      lb: &[f64],
+                      ub: &[f64],
+                      types: &[BoundType],
+                      delta: &[f64],
+                      abseps: f64,
+                      releps: f64)
+                      -> MVResult {
+    scale_mixture(sd,
+                  nu,
+                  lb,
+                  ub,
+                  types,
+                  delta,
+                  abseps,
+                  releps,
+                  |limits, abseps, releps| equicorrelated_normal(rho, limits, abseps, releps))
+}
+
+/// `equicorrelated` for the correlations `loadings[i] loadings[j]` between variables `i != j`, as
+/// of the comparisons of several treatments with one control, where each loading is in `(-1, 1)`.
+/// The normal probability is a one-dimensional integral over the common factor.
+pub fn one_factor(loadings: &[f64],
+                  sd: &[f64],
+                  nu: f64,
+                  lb: &[f64],
+                  ub: &[f64],
+                  types: &[BoundType],
+                  delta: &[f64],
+                  abseps: f64,
+                  releps: f64)
+                  -> MVResult {
+    scale_mixture(sd,
+                  nu,
+                  lb,
+                  ub,
+                  types,
+                  delta,
+                  abseps,
+                  releps,
+                  |limits, abseps, releps| one_factor_normal(loadings, limits, abseps, releps))
+}
+
+/// The probability of a problem with standard deviations `sd`, given the probability `normal` of
+/// standardized normal variables within limits, integrated over the chi mixing variable for
+/// `nu > 0`.
+fn scale_mixture<F>(sd: &[f64],
+                    nu: f64,
+                    lb: &[f64],
+                    ub: &[f64],
+                    types: &[BoundType],
+                    delta: &[f64],
+                    abseps: f64,
+                    releps: f64,
+                    normal: F)
+                    -> MVResult
+    where F: Fn(&[(f64, f64)], f64, f64) -> (f64, f64, usize)
+{
+    let lims = limits(lb, ub, types);
+    // standardized limits for a given value s of the chi mixing variable
+    let scaled = |s: f64| {
+        lims.iter()
+            .enumerate()
+            .map(|(i, &(lo, hi))| ((lo * s - delta[i]) / sd[i], (hi * s - delta[i]) / sd[i]))
+            .collect::<Vec<(f64, f64)>>()
+    };
+    let (value, error, nevals) = if nu <= 0.0 {
+        normal(&scaled(1.0), abseps, releps)
+    } else {
+        let mut inner_evals = 0;
+        let q = integrate(|x| {
+                              if x >= 1.0 {
+                                  return 0.0;
+                              }
+                              let s = x / (1.0 - x);
+                              let (p, _, evals) = normal(&scaled(s), abseps / 2.0, releps / 2.0);
+                              inner_evals += evals;
+                              p * scaled_chi_pdf(nu, s) / ((1.0 - x) * (1.0 - x))
+                          },
+                          0.0,
+                          1.0,
+                          abseps / 2.0,
+                          releps / 2.0);
+        (q.value, q.error + abseps / 2.0, inner_evals)
+    };
+    MVResult {
+        value: value,
+        error: error,
+        nevals: nevals as u64,
+        state: MVInform::Normal,
+    }
+}
+
+/// If `cov` is diagonal with a positive diagonal, returns the standard deviations of the variables.
+pub fn independence(cov: &Array2<f64>) -> Option<Vec<f64>> {
+    let n = cov.rows();
+    if cov.cols() != n {
+        return None;
+    }
+    if cov.indexed_iter().any(|((i, j), &c)| if i == j { !(c > 0.0) } else { c != 0.0 }) {
+        return None;
+    }
+    Some((0..n).map(|i| cov[[i, i]].sqrt()).collect())
+}
+
+/// Evaluate `P(lb < X < ub)` (with bounds selected by `types`) for independent normal variables
+/// with standard deviations `sd` and means `delta`. This is an exact product of univariate
+/// probabilities, so the reported error is zero.
+///
+/// The multivariate t counterpart is not a product, since all coordinates share the chi mixing
+/// variable; use `equicorrelated` with `rho = 0` for that case.
+pub fn independent(sd: &[f64],
+                   lb: &[f64],
+                   ub: &[f64],
+                   types: &[BoundType],
+                   delta: &[f64])
+                   -> MVResult {
+    let value = limits(lb, ub, types)
+        .iter()
+        .enumerate()
+        .map(|(i, &(lo, hi))| {
+            norm_cdf((hi - delta[i]) / sd[i]) - norm_cdf((lo - delta[i]) / sd[i])
+        })
+        .product();
+    MVResult {
+        value: value,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    }
+}
+
+/// `P(X < h, Y < k)` for a standard bivariate t distribution with correlation `r` and `nu >= 1`
+/// degrees of freedom, by the finite series of Dunnett and Sobel (1954) as implemented in Genz's
+/// BVTL.
+fn bvtl(nu: u32, h: f64, k: f64, r: f64) -> f64 {
+    const EPS: f64 = 1e-15;
+    let n = nu as f64;
+    if h == ::std::f64::NEG_INFINITY || k == ::std::f64::NEG_INFINITY {
+        return 0.0;
+    } else if h == ::std::f64::INFINITY {
+        return t_cdf(n, k);
+    } else if k == ::std::f64::INFINITY {
+        return t_cdf(n, h);
+    }
+    if 1.0 - r <= EPS {
+        return t_cdf(n, h.min(k));
+    } else if r + 1.0 <= EPS {
+        return if h > -k { t_cdf(n, h) - t_cdf(n, -k) } else { 0.0 };
+    }
+    let ors = 1.0 - r * r;
+    let (hrk, krh) = (h - r * k, k - r * h);
+    let (xnhk, xnkh) = if hrk.abs() + ors > 0.0 {
+        (hrk * hrk / (hrk * hrk + ors * (n + k * k)), krh * krh / (krh * krh + ors * (n + h * h)))
+    } else {
+        (0.0, 0.0)
+    };
+    let hs = if hrk < 0.0 { -1.0 } else { 1.0 };
+    let ks = if krh < 0.0 { -1.0 } else { 1.0 };
+    let mut bvt;
+    if nu % 2 == 0 {
+        bvt = ors.sqrt().atan2(-r) / (2.0 * PI);
+        let mut gmph = h / (16.0 * (n + h * h)).sqrt();
+        let mut gmpk = k / (16.0 * (n + k * k)).sqrt();
+        let mut btnckh = 2.0 * xnkh.sqrt().atan2((1.0 - xnkh).sqrt()) / PI;
+        let mut btpdkh = 2.0 * (xnkh * (1.0 - xnkh)).sqrt() / PI;
+        let mut btnchk = 2.0 * xnhk.sqrt().atan2((1.0 - xnhk).sqrt()) / PI;
+        let mut btpdhk = 2.0 * (xnhk * (1.0 - xnhk)).sqrt() / PI;
+        for j in 1..(nu / 2 + 1) {
+            let j = j as f64;
+            bvt += gmph * (1.0 + ks * btnckh) + gmpk * (1.0 + hs * btnchk);
+            btnckh += btpdkh;
+            btpdkh = 2.0 * j * btpdkh * (1.0 - xnkh) / (2.0 * j + 1.0);
+            btnchk += btpdhk;
+            btpdhk = 2.0 * j * btpdhk * (1.0 - xnhk) / (2.0 * j + 1.0);
+            gmph = gmph * (2.0 * j - 1.0) / (2.0 * j * (1.0 + h * h / n));
+            gmpk = gmpk * (2.0 * j - 1.0) / (2.0 * j * (1.0 + k * k / n));
+        }
+    } else {
+        let snu = n.sqrt();
+        let qhrk = (h * h + k * k - 2.0 * r * h * k + n * ors).sqrt();
+        let (hkrn, hkn, hpk) = (h * k + r * n, h * k - n, h + k);
+        bvt = (-snu * (hkn * qhrk + hpk * hkrn)).atan2(hkn * hkrn - n * hpk * qhrk) / (2.0 * PI);
+        if bvt < -EPS {
+            bvt += 1.0;
+        }
+        let mut gmph = h / (2.0 * PI * snu * (1.0 + h * h / n));
+        let mut gmpk = k / (2.0 * PI * snu * (1.0 + k * k / n));
+        let (mut btnckh, mut btnchk) = (xnkh.sqrt(), xnhk.sqrt());
+        let (mut btpdkh, mut btpdhk) = (btnckh, btnchk);
+        for j in 1..((nu - 1) / 2 + 1) {
+            let j = j as f64;
+            bvt += gmph * (1.0 + ks * btnckh) + gmpk * (1.0 + hs * btnchk);
+            btpdkh = (2.0 * j - 1.0) * btpdkh * (1.0 - xnkh) / (2.0 * j);
+            btnckh += btpdkh;
+            btpdhk = (2.0 * j - 1.0) * btpdhk * (1.0 - xnhk) / (2.0 * j);
+            btnchk += btpdhk;
+            gmph = gmph * 2.0 * j / ((2.0 * j + 1.0) * (1.0 + h * h / n));
+            gmpk = gmpk * 2.0 * j / ((2.0 * j + 1.0) * (1.0 + k * k / n));
+        }
+    }
+    bvt
+}
+
+/// `P(lower < X < upper)` for a standard bivariate t distribution with correlation `rho` and `nu >=
+/// 1` degrees of freedom, with infinite limits leaving a side unbounded. This uses Genz's
+/// implementation of the finite series of Dunnett and Sobel, accurate to machine precision, at a
+/// cost proportional to `nu`. Fails for `nu = 0` or a correlation outside `[-1, 1]`.
+pub fn bvt(lower: [f64; 2], upper: [f64; 2], rho: f64, nu: u32) -> Result<f64, MvDistError> {
+    if nu < 1 {
+        return Err(MvDistError::InvalidInput(format!("bvt requires at least one degree of \
+                                                      freedom")));
+    }
+    if !(rho >= -1.0 && rho <= 1.0) {
+        return Err(MvDistError::InvalidInput(format!("bvt requires a correlation in [-1, 1], got \
+                                                      {}",
+                                                     rho)));
+    }
+    if !(lower[0] < upper[0] && lower[1] < upper[1]) {
+        return Ok(0.0);
+    }
+    let l = |h, k| bvtl(nu, h, k, rho);
+    let p = l(upper[0], upper[1]) - l(lower[0], upper[1]) - l(upper[0], lower[1]) +
+            l(lower[0], lower[1]);
+    Ok(p.max(0.0).min(1.0))
+}
+
+/// Gauss-Legendre nodes in `(-1, 0)` and their weights, for the rules of 6, 12 and 20 points
+/// used by `bvnu`.
+const GL6_X: [f64; 3] = [-0.9324695142031522, -0.6612093864662647, -0.2386191860831970];
+const GL6_W: [f64; 3] = [0.1713244923791705, 0.3607615730481384, 0.4679139345726904];
+const GL12_X: [f64; 6] = [-0.9815606342467191,
+                          -0.9041172563704750,
+                          -0.7699026741943050,
+                          -0.5873179542866171,
+                          -0.3678314989981802,
+                          -0.1252334085114692];
+const GL12_W: [f64; 6] = [0.04717533638651177,
+                          0.1069393259953183,
+                          0.1600783285433464,
+                          0.2031674267230659,
+                          0.2334925365383547,
+                          0.2491470458134029];
+const GL20_X: [f64; 10] = [-0.9931285991850949,
+                           -0.9639719272779138,
+                           -0.9122344282513259,
+                           -0.8391169718222188,
+                           -0.7463319064601508,
+                           -0.6360536807265150,
+                           -0.5108670019508271,
+                           -0.3737060887154196,
+                           -0.2277858511416451,
+                           -0.07652652113349733];
+const GL20_W: [f64; 10] = [0.01761400713915212,
+                           0.04060142980038694,
+                           0.06267204833410906,
+                           0.08327674157670475,
+                           0.1019301198172404,
+                           0.1181945319615184,
+                           0.1316886384491766,
+                           0.1420961093183821,
+                           0.1491729864726037,
+                           0.1527533871307259];
+
+/// `P(X > h, Y > k)` for a standard bivariate normal distribution with correlation `r`, by the
+/// method of Drezner and Wesolowsky (1990) as implemented in Genz's BVNU.
+fn bvnu(h: f64, k: f64, r: f64) -> f64 {
+    use std::f64::{INFINITY, NEG_INFINITY};
+    if h == INFINITY || k == INFINITY {
+        return 0.0;
+    } else if h == NEG_INFINITY {
+        return norm_cdf(-k);
+    } else if k == NEG_INFINITY {
+        return norm_cdf(-h);
+    }
+    let (x, w): (&[f64], &[f64]) = if r.abs() < 0.3 {
+        (&GL6_X, &GL6_W)
+    } else if r.abs() < 0.75 {
+        (&GL12_X, &GL12_W)
+    } else {
+        (&GL20_X, &GL20_W)
+    };
+    let mut hk = h * k;
+    if r.abs() < 0.925 {
+        // Gauss-Legendre quadrature of Plackett's identity over the correlation
+        let hs = (h * h + k * k) / 2.0;
+        let asr = r.asin();
+        let mut bvn = 0.0;
+        for (&xi, &wi) in x.iter().zip(w.iter()) {
+            for &sign in &[-1.0, 1.0] {
+                let sn = (asr * (sign * xi + 1.0) / 2.0).sin();
+                bvn += wi * ((sn * hk - hs) / (1.0 - sn * sn)).exp();
+            }
+        }
+        return bvn * asr / (4.0 * PI) + norm_cdf(-h) * norm_cdf(-k);
+    }
+    // near perfect correlation: an asymptotic expansion about |r| = 1 and quadrature of the rest
+    let k = if r < 0.0 {
+        hk = -hk;
+        -k
+    } else {
+        k
+    };
+    let mut bvn = 0.0;
+    if r.abs() < 1.0 {
+        let a2 = (1.0 - r) * (1.0 + r);
+        let a = a2.sqrt();
+        let bs = (h - k) * (h - k);
+        let c = (4.0 - hk) / 8.0;
+        let d = (12.0 - hk) / 16.0;
+        bvn = a * (-(bs / a2 + hk) / 2.0).exp() *
+              (1.0 - c * (bs - a2) * (1.0 - d * bs / 5.0) / 3.0 + c * d * a2 * a2 / 5.0);
+        if hk > -160.0 {
+            let b = bs.sqrt();
+            bvn -= (-hk / 2.0).exp() * (2.0 * PI).sqrt() * norm_cdf(-b / a) * b *
+                   (1.0 - c * bs * (1.0 - d * bs / 5.0) / 3.0);
+        }
+        let a = a / 2.0;
+        for (&xi, &wi) in x.iter().zip(w.iter()) {
+            for &sign in &[-1.0, 1.0] {
+                let xs = (a * (sign * xi + 1.0)).powi(2);
+                let rs = (1.0 - xs).sqrt();
+                let asr = -(bs / xs + hk) / 2.0;
+                if asr > -100.0 {
+                    bvn += a * wi * asr.exp() *
+                           ((-hk * xs / (2.0 * (1.0 + rs).powi(2))).exp() / rs -
+                            (1.0 + c * xs * (1.0 + d * xs)));
+                }
+            }
+        }
+        bvn = -bvn / (2.0 * PI);
+    }
+    if r > 0.0 {
+        bvn + norm_cdf(-h.max(k))
+    } else if k > h {
+        -bvn + if h < 0.0 {
+            norm_cdf(k) - norm_cdf(h)
+        } else {
+            norm_cdf(-h) - norm_cdf(-k)
+        }
+    } else {
+        -bvn
+    }
+}
+
+/// `P(lower < X < upper)` for a standard bivariate normal distribution with correlation `rho`,
+/// accurate to about `1e-15`. Infinite limits leave a coordinate unbounded.
+pub fn bvn(lower: [f64; 2], upper: [f64; 2], rho: f64) -> f64 {
+    if !(upper[0] > lower[0] && upper[1] > lower[1]) {
+        return 0.0;
+    }
+    let p = bvnu(lower[0], lower[1], rho) - bvnu(upper[0], lower[1], rho) -
+            bvnu(lower[0], upper[1], rho) + bvnu(upper[0], upper[1], rho);
+    p.max(0.0).min(1.0)
+}
+
+/// `P(X_i > 0 for all i)` for `X` normal, or central elliptical such as the multivariate t, with
+/// the correlation matrix `corr`: `1/4 + asin(ρ) / 2π` for two variables and `1/8 + Σ asin(ρ_ij) /
+/// 4π` for three, and for any number with a common correlation, `2^-n` for 0 and `1 / (n + 1)` for
+/// 1/2. `None` for other structures, which have no closed form.
+pub fn orthant(corr: &Array2<f64>) -> Option<f64> {
+    const TOL: f64 = 1e-12;
+    let n = corr.rows();
+    if corr.cols() != n {
+        return None;
+    }
+    let asin = |i: usize, j: usize| corr[[i, j]].max(-1.0).min(1.0).asin();
+    match n {
+        0 => Some(1.0),
+        1 => Some(0.5),
+        2 => Some(0.25 + asin(0, 1) / (2.0 * PI)),
+        3 => Some(0.125 + (asin(0, 1) + asin(0, 2) + asin(1, 2)) / (4.0 * PI)),
+        _ => {
+            let common = |rho: f64| {
+                corr.indexed_iter().all(|((i, j), &r)| i == j || (r - rho).abs() <= TOL)
+            };
+            if common(0.0) {
+                Some(0.5f64.powi(n as i32))
+            } else if common(0.5) {
+                Some(1.0 / (n as f64 + 1.0))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The central orthant case of `try_solve`: every variable unbounded or bounded on one side only,
+/// by zero, with zero noncentrality, whatever the degrees of freedom.
+fn central_orthant(cov: &Array2<f64>,
+                   lb: &[f64],
+                   ub: &[f64],
+                   types: &[BoundType],
+                   delta: &[f64])
+                   -> Option<MVResult> {
+    if delta.iter().any(|&d| d != 0.0) {
+        return None;
+    }
+    let mut signed = Vec::new();
+    for (i, &t) in types.iter().enumerate() {
+        match t {
+            BoundType::Unbounded => (),
+            BoundType::Below if lb[i] == 0.0 => signed.push((i, 1.0)),
+            BoundType::Above if ub[i] == 0.0 => signed.push((i, -1.0)),
+            _ => return None,
+        }
+    }
+    if signed.iter().any(|&(i, _)| !(cov[[i, i]] > 0.0)) {
+        return None;
+    }
+    let mut corr = Array2::zeros((signed.len(), signed.len()));
+    for (a, &(i, si)) in signed.iter().enumerate() {
+        for (b, &(j, sj)) in signed.iter().enumerate() {
+            corr[[a, b]] = si * sj * cov[[i, j]] / (cov[[i, i]] * cov[[j, j]]).sqrt();
+        }
+    }
+    if corr.iter().any(|r| !(r.abs() <= 1.0 + 1e-12)) {
+        return None;
+    }
+    orthant(&corr).map(|value| {
+        MVResult {
+            value: value,
+            error: 0.0,
+            nevals: 0,
+            state: MVInform::Normal,
+        }
+    })
+}
+
+/// The variables of the correlation matrix `corr` that remain when each variable perfectly
+/// correlated with an earlier one is merged into it, and for every variable the index among those
+/// of the one it equals, with the sign of their correlation. `None` if the perfect correlations
+/// are inconsistent with the others, as they are when `corr` is not positive semidefinite.
+fn merge_perfectly_correlated(corr: &Array2<f64>) -> Option<(Vec<usize>, Vec<(usize, f64)>)> {
+    const TOL: f64 = 1e-10;
+    let n = corr.rows();
+    let mut kept: Vec<usize> = Vec::new();
+    let mut merged = Vec::with_capacity(n);
+    for j in 0..n {
+        match kept.iter().position(|&i| corr[[i, j]].abs() >= 1.0 - TOL) {
+            Some(g) => {
+                let i = kept[g];
+                let sign = corr[[i, j]].signum();
+                if (0..n).any(|l| (corr[[j, l]] - sign * corr[[i, l]]).abs() > 1e-8) {
+                    return None;
+                }
+                merged.push((g, sign));
+            }
+            None => {
+                merged.push((kept.len(), 1.0));
+                kept.push(j);
+            }
+        }
+    }
+    Some((kept, merged))
+}
+
+/// `P(lower < X < upper)` for standard trivariate normal `X` with the correlation matrix `corr`,
+/// whose off-diagonal entries are less than 1 in absolute value, by adaptive quadrature over `X_0`
+/// of the conditional bivariate probabilities.
+fn trivariate_normal(corr: &Array2<f64>,
+                     limits: &[(f64, f64)],
+                     abseps: f64,
+                     releps: f64)
+                     -> (f64, f64, usize) {
+    let (r01, r02) = (corr[[0, 1]], corr[[0, 2]]);
+    let (s1, s2) = ((1.0 - r01 * r01).sqrt(), (1.0 - r02 * r02).sqrt());
+    let rho = ((corr[[1, 2]] - r01 * r02) / (s1 * s2)).max(-1.0).min(1.0);
+    let (p_lo, p_hi) = (norm_cdf(limits[0].0), norm_cdf(limits[0].1));
+    let width = p_hi - p_lo;
+    if !(width > 0.0) {
+        return (0.0, 0.0, 0);
+    }
+    let q = integrate(|u| {
+                          let x = norm_inv(p_lo + u * width);
+                          bvn([(limits[1].0 - r01 * x) / s1, (limits[2].0 - r02 * x) / s2],
+                              [(limits[1].1 - r01 * x) / s1, (limits[2].1 - r02 * x) / s2],
+                              rho)
+                      },
+                      0.0,
+                      1.0,
+                      abseps / width,
+                      releps);
+    (q.value * width, q.error * width, q.nevals)
+}
+
+/// The low-dimensional case of `try_solve`: at most three variables after merging those perfectly
+/// correlated, with any correlations, degrees of freedom and noncentrality.
+fn low_dimensional(cov: &Array2<f64>,
+                   nu: f64,
+                   lb: &[f64],
+                   ub: &[f64],
+                   types: &[BoundType],
+                   delta: &[f64],
+                   abseps: f64,
+                   releps: f64)
+                   -> Option<MVResult> {
+    let n = cov.rows();
+    let sd = (0..n).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0)) {
+        return None;
+    }
+    let mut corr = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            corr[[i, j]] = cov[[i, j]] / (sd[i] * sd[j]);
+        }
+    }
+    if corr.iter().any(|r| !(r.abs() <= 1.0 + 1e-12)) {
+        return None;
+    }
+    let (kept, merged) = merge_perfectly_correlated(&corr)?;
+    let k = kept.len();
+    let mut sub = Array2::zeros((k, k));
+    for (a, &i) in kept.iter().enumerate() {
+        for (b, &j) in kept.iter().enumerate() {
+            sub[[a, b]] = corr[[i, j]].max(-1.0).min(1.0);
+        }
+    }
+    let det = match k {
+        1 | 2 => 0.0,
+        3 => {
+            1.0 - sub[[0, 1]] * sub[[0, 1]] - sub[[0, 2]] * sub[[0, 2]] -
+            sub[[1, 2]] * sub[[1, 2]] + 2.0 * sub[[0, 1]] * sub[[0, 2]] * sub[[1, 2]]
+        }
+        _ => return None,
+    };
+    if det < -1e-12 {
+        return None;
+    }
+    let normal = |limits: &[(f64, f64)], abseps: f64, releps: f64| {
+        // the limits of the merged variables, reflected by the sign of their correlation
+        let mut reduced = vec![(::std::f64::NEG_INFINITY, ::std::f64::INFINITY); k];
+        for (&(lo, hi), &(g, sign)) in limits.iter().zip(merged.iter()) {
+            let (lo, hi) = if sign > 0.0 { (lo, hi) } else { (-hi, -lo) };
+            reduced[g] = (reduced[g].0.max(lo), reduced[g].1.min(hi));
+        }
+        if reduced.iter().any(|&(lo, hi)| !(hi > lo)) {
+            return (0.0, 0.0, 0);
+        }
+        match k {
+            1 => (norm_cdf(reduced[0].1) - norm_cdf(reduced[0].0), 0.0, 0),
+            2 => {
+                (bvn([reduced[0].0, reduced[1].0],
+                     [reduced[0].1, reduced[1].1],
+                     sub[[0, 1]]),
+                 0.0,
+                 0)
+            }
+            _ => trivariate_normal(&sub, &reduced, abseps, releps),
+        }
+    };
+    Some(scale_mixture(&sd, nu, lb, ub, types, delta, abseps, releps, normal))
+}
+
+/// The central bivariate t case of `try_solve`, for integer `nu` and any correlation.
+fn bivariate_t(cov: &Array2<f64>,
+               nu: f64,
+               lb: &[f64],
+               ub: &[f64],
+               types: &[BoundType],
+               delta: &[f64])
+               -> Option<MVResult> {
+    if cov.rows() != 2 || !(nu >= 1.0 && nu.fract() == 0.0 && nu <= ::std::u32::MAX as f64) ||
+       delta.iter().any(|&d| d != 0.0) {
+        return None;
+    }
+    let sd = [cov[[0, 0]].sqrt(), cov[[1, 1]].sqrt()];
+    if !(sd[0] > 0.0 && sd[1] > 0.0) {
+        return None;
+    }
+    let rho = cov[[0, 1]] / (sd[0] * sd[1]);
+    if !(rho.abs() <= 1.0 + 1e-12) {
+        return None;
+    }
+    let lims = limits(lb, ub, types);
+    bvt([lims[0].0 / sd[0], lims[1].0 / sd[1]],
+        [lims[0].1 / sd[0], lims[1].1 / sd[1]],
+        rho.max(-1.0).min(1.0),
+        nu as u32)
+        .ok()
+        .map(|value| {
+            MVResult {
+                value: value,
+                error: 0.0,
+                nevals: 0,
+                state: MVInform::Normal,
+            }
+        })
+}
+
+/// Solve the problem directly if it has a structure handled by this module. Returns `None` if
+/// the problem should be passed on to the general integrator.
+pub fn try_solve(cov: &Array2<f64>,
+                 nu: f64,
+                 lb: &Array1<f64>,
+                 ub: &Array1<f64>,
+                 types: &[BoundType],
+                 constraints: &Array2<f64>,
+                 delta: &Array1<f64>,
+                 abseps: f64,
+                 releps: f64)
+                 -> Option<MVResult> {
+    if !is_identity(constraints) || constraints.rows() != cov.rows() {
+        return None;
+    }
+    let (lb, ub, delta) = match (lb.as_slice(), ub.as_slice(), delta.as_slice()) {
+        (Some(lb), Some(ub), Some(delta)) => (lb, ub, delta),
+        _ => return None,
+    };
+    if nu <= 0.0 {
+        if let Some(sd) = independence(cov) {
+            return Some(independent(&sd, lb, ub, types, delta));
+        }
+    }
+    if let Some(result) = central_orthant(cov, lb, ub, types, delta) {
+        return Some(result);
+    }
+    if let Some(result) = bivariate_t(cov, nu, lb, ub, types, delta) {
+        return Some(result);
+    }
+    if let Some(result) = low_dimensional(cov, nu, lb, ub, types, delta, abseps, releps) {
+        return Some(result);
+    }
+    equicorrelation(cov).map(|(rho, sd)| {
+        equicorrelated(rho, &sd, nu, lb, ub, types, delta, abseps, releps)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::{ar1, exchangeable};
+    use ndarray::prelude::*;
+
+    #[test]
+    fn equicorrelated_orthant() {
+        // P(X_i > 0 for all i) with rho = 1/2 is 1 / (n + 1)
+        let n = 5;
+        let cov = exchangeable(n, 0.5);
+        let (rho, sd) = equicorrelation(&cov).unwrap();
+        let result = equicorrelated(rho,
+                                    &sd,
+                                    0.0,
+                                    &vec![0.0; n],
+                                    &vec![0.0; n],
+                                    &vec![BoundType::Below; n],
+                                    &vec![0.0; n],
+                                    1e-10,
+                                    0.0);
+        assert!((result.value - 1.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn diagonal_is_exact_product() {
+        let cov = arr2(&[[1.0, 0.0], [0.0, 4.0]]);
+        let result = try_solve(&cov,
+                               0.0,
+                               &Array::zeros((2,)),
+                               &Array1::from_vec(vec![0.0, 2.0]),
+                               &vec![BoundType::Below, BoundType::Above],
+                               &Array::eye(2),
+                               &Array::zeros((2,)),
+                               1e-5,
+                               0.0)
+            .unwrap();
+        assert_eq!(result.error, 0.0);
+        assert!((result.value - 0.5 * 0.8413447460685429).abs() < 1e-15);
+    }
+
+    #[test]
+    fn bivariate_t_series() {
+        let inf = ::std::f64::INFINITY;
+        for &nu in &[1, 2, 5, 30] {
+            for &rho in &[-0.9, -0.3, 0.0, 0.6] {
+                // orthant probabilities do not depend on the degrees of freedom
+                let p = bvt([-inf, -inf], [0.0, 0.0], rho, nu).unwrap();
+                assert!((p - (0.25 + rho.asin() / (2.0 * PI))).abs() < 1e-14);
+            }
+            let nu_f = nu as f64;
+            let marginal = bvt([-1.0, -inf], [2.0, inf], 0.4, nu).unwrap();
+            assert!((marginal - (t_cdf(nu_f, 2.0) - t_cdf(nu_f, -1.0))).abs() < 1e-14);
+        }
+        // the 2-D t problem is solved by the series rather than quadrature
+        let cov = arr2(&[[4.0, -1.0], [-1.0, 1.0]]);
+        let result = try_solve(&cov,
+                               3.0,
+                               &Array1::from_vec(vec![-2.0, -1.0]),
+                               &Array1::from_vec(vec![1.0, 0.5]),
+                               &vec![BoundType::Both; 2],
+                               &Array::eye(2),
+                               &Array::zeros((2,)),
+                               1e-10,
+                               0.0)
+            .unwrap();
+        assert_eq!((result.error, result.nevals), (0.0, 0));
+        let r = bvt([-1.0, -1.0], [0.5, 0.5], -0.5, 3).unwrap();
+        assert_eq!(result.value, r);
+        // reflecting one coordinate changes the sign of the correlation
+        assert!((r - bvt([-1.0, -0.5], [0.5, 1.0], 0.5, 3).unwrap()).abs() < 1e-15);
+        assert!(bvt([-1.0, -1.0], [0.5, 0.5], 0.5, 0).is_err());
+        assert!(bvt([-1.0, -1.0], [0.5, 0.5], 1.5, 3).is_err());
+    }
+
+    #[test]
+    fn bivariate_normal() {
+        let inf = ::std::f64::INFINITY;
+        for &rho in &[-0.99, -0.5, 0.0, 0.4, 0.8, 0.95] {
+            let p = bvn([0.0, 0.0], [inf, inf], rho);
+            assert!((p - (0.25 + rho.asin() / (2.0 * PI))).abs() < 1e-15);
+            let marginal = bvn([-1.0, -inf], [2.0, inf], rho);
+            assert!((marginal - (norm_cdf(2.0) - norm_cdf(-1.0))).abs() < 1e-14);
+            // agrees with the quadrature over the common factor
+            if rho > 0.0 {
+                let (q, _, _) = equicorrelated_normal(rho, &[(-1.0, 0.5), (-0.3, 2.0)], 1e-14, 0.0);
+                assert!((bvn([-1.0, -0.3], [0.5, 2.0], rho) - q).abs() < 1e-12);
+            }
+        }
+        // perfect correlation: the intervals of X and of ±X intersect
+        let p = bvn([-1.0, -inf], [0.5, 0.2], 1.0);
+        assert!((p - (norm_cdf(0.2) - norm_cdf(-1.0))).abs() < 1e-14);
+        let p = bvn([0.3, -inf], [inf, 0.1], -1.0);
+        assert!((p - norm_cdf(-0.3)).abs() < 1e-14);
+        assert_eq!(bvn([0.5, 0.0], [0.5, 1.0], 0.2), 0.0);
+    }
+
+    #[test]
+    fn orthant_closed_forms() {
+        assert!((orthant(&exchangeable(5, 0.5)).unwrap() - 1.0 / 6.0).abs() < 1e-15);
+        assert_eq!(orthant(&Array::eye(4)), Some(1.0 / 16.0));
+        assert_eq!(orthant(&ar1(4, 0.5)), None);
+        // central orthants of any sign and degrees of freedom are solved without error
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let types = [BoundType::Below, BoundType::Unbounded, BoundType::Above];
+        for &nu in &[0.0, 3.5] {
+            let result = try_solve(&cov,
+                                   nu,
+                                   &Array::zeros((3,)),
+                                   &Array::zeros((3,)),
+                                   &types,
+                                   &Array::eye(3),
+                                   &Array::zeros((3,)),
+                                   1e-8,
+                                   0.0)
+                .unwrap();
+            assert_eq!((result.error, result.nevals), (0.0, 0));
+            assert!((result.value - (0.25 + 0.2f64.asin() / (2.0 * PI))).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn low_dimensional_problems() {
+        // a trivariate orthant by quadrature matches its closed form, for normal and t variables
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        for &(nu, tol) in &[(0.0, 1e-12), (4.5, 1e-8)] {
+            let result = low_dimensional(&cov,
+                                         nu,
+                                         &[0.0; 3],
+                                         &[0.0; 3],
+                                         &[BoundType::Below; 3],
+                                         &[0.0; 3],
+                                         tol,
+                                         0.0)
+                .unwrap();
+            assert!((result.value - exact).abs() < tol);
+            assert!(result.error <= tol);
+        }
+        // the third variable is minus the first, so this is a bivariate normal problem
+        let inf = ::std::f64::INFINITY;
+        let cov = arr2(&[[4.0, 0.8, -4.0], [0.8, 1.0, -0.8], [-4.0, -0.8, 4.0]]);
+        let result = try_solve(&cov,
+                               0.0,
+                               &Array1::from_vec(vec![-2.0, 0.0, -1.0]),
+                               &Array1::from_vec(vec![2.0, 0.5, 0.0]),
+                               &[BoundType::Both, BoundType::Above, BoundType::Below],
+                               &Array::eye(3),
+                               &Array::zeros((3,)),
+                               1e-8,
+                               0.0)
+            .unwrap();
+        assert_eq!(result.error, 0.0);
+        assert!((result.value - bvn([-1.0, -inf], [0.5, 0.5], 0.4)).abs() < 1e-15);
+        // perfect correlations inconsistent with the others
+        let bad = arr2(&[[1.0, 0.5, 1.0], [0.5, 1.0, -0.5], [1.0, -0.5, 1.0]]);
+        assert!(merge_perfectly_correlated(&bad).is_none());
+    }
+
+    #[test]
+    fn equicorrelated_t_orthant() {
+        // orthant probabilities are unchanged by the chi mixing variable
+        let n = 3;
+        let cov = exchangeable(n, 0.5);
+        let result = try_solve(&cov,
+                               4.0,
+                               &Array::zeros((n,)),
+                               &Array::zeros((n,)),
+                               &vec![BoundType::Below; n],
+                               &Array::eye(n),
+                               &Array::zeros((n,)),
+                               1e-8,
+                               0.0)
+            .unwrap();
+        assert!((result.value - 0.25).abs() < 1e-7);
+    }
+}
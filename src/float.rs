@@ -0,0 +1,146 @@
+//! `mvdist` and `mvcrit` for `f32` (or `f64`) inputs. Inputs are promoted to `f64` for the
+//! computation, and the results are returned in the caller's precision.
+
+use ndarray::prelude::*;
+use std::fmt::Debug;
+use {BoundType, MVResult, MvCritResult, MvDistError};
+
+/// Floating-point types accepted by this module.
+pub trait Float: Copy + Debug + PartialEq + 'static {
+    fn to_f64(self) -> f64;
+    fn from_f64(x: f64) -> Self;
+}
+
+impl Float for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(x: f64) -> f32 {
+        x as f32
+    }
+}
+
+impl Float for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(x: f64) -> f64 {
+        x
+    }
+}
+
+fn promote<F: Float, D: Dimension>(a: &Array<F, D>) -> Array<f64, D> {
+    a.map(|&x| x.to_f64())
+}
+
+fn demote<F: Float>(res: MVResult) -> MVResult<F> {
+    MVResult {
+        value: F::from_f64(res.value),
+        error: F::from_f64(res.error),
+        nevals: res.nevals,
+        state: res.state,
+    }
+}
+
+fn demote_crit<F: Float>(res: MvCritResult) -> MvCritResult<F> {
+    MvCritResult {
+        crit: F::from_f64(res.crit),
+        achieved_alpha: F::from_f64(res.achieved_alpha),
+        alpha_error: F::from_f64(res.alpha_error),
+        nevals: res.nevals,
+        bracket: (F::from_f64(res.bracket.0), F::from_f64(res.bracket.1)),
+        state: res.state,
+    }
+}
+
+/// `mvdist` for any `Float` type.
+pub fn mvdist<F: Float>(cov: &Array2<F>,
+                        nu: i32,
+                        lb: &Array1<F>,
+                        ub: &Array1<F>,
+                        types: &Vec<BoundType>,
+                        constraints: &Array2<F>,
+                        delta: &Array1<F>,
+                        maxpts: u64,
+                        abseps: F,
+                        releps: F)
+                        -> Result<MVResult<F>, MvDistError> {
+    ::mvdist(&promote(cov),
+             nu,
+             &promote(lb),
+             &promote(ub),
+             types,
+             &promote(constraints),
+             &promote(delta),
+             maxpts,
+             abseps.to_f64(),
+             releps.to_f64())
+        .map(demote)
+}
+
+/// `mvcrit` for any `Float` type.
+pub fn mvcrit<F: Float>(cov: &Array2<F>,
+                        nu: i32,
+                        lb: &Array1<F>,
+                        ub: &Array1<F>,
+                        types: &Vec<BoundType>,
+                        constraints: &Array2<F>,
+                        alpha: F,
+                        maxpts: u64,
+                        abseps: F)
+                        -> Result<MvCritResult<F>, MvDistError> {
+    ::mvcrit(&promote(cov),
+             nu,
+             &promote(lb),
+             &promote(ub),
+             types,
+             &promote(constraints),
+             alpha.to_f64(),
+             maxpts,
+             abseps.to_f64())
+        .map(demote_crit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use BoundType;
+
+    #[test]
+    fn f32_inputs() {
+        let cov: Array2<f32> = arr2(&[[1.0, 0.0], [0.0, 1.0]]);
+        let result = mvdist(&cov,
+                            0,
+                            &Array::zeros((2,)),
+                            &Array::zeros((2,)),
+                            &vec![BoundType::Below; 2],
+                            &Array::eye(2),
+                            &Array::zeros((2,)),
+                            10_000,
+                            1e-5,
+                            0.0)
+            .unwrap();
+        assert!((result.value - 0.25f32).abs() < 1e-6);
+    }
+
+    #[test]
+    fn f32_critical_value() {
+        let cov: Array2<f32> = arr2(&[[1.0, 0.0], [0.0, 1.0]]);
+        let result: MvCritResult<f32> = mvcrit(&cov,
+                                               0,
+                                               &Array::zeros((2,)),
+                                               &Array::zeros((2,)),
+                                               &vec![BoundType::Both; 2],
+                                               &Array::eye(2),
+                                               0.05,
+                                               100_000,
+                                               1e-5)
+            .unwrap();
+        // (2 Φ(c) - 1)² = 0.95
+        assert!((result.crit - 2.23648f32).abs() < 1e-3);
+        assert!((result.achieved_alpha - 0.05f32).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,398 @@
+//! The Fortran backend: thin wrappers around `mvdist-sys`, enabled by the (default) `fortran`
+//! feature.
+
+use mvdist_sys::{mvcrit as sys_mvcrit, mvdist as sys_mvdist};
+use ndarray::prelude::*;
+use std::borrow::Cow;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use error::checked_i32;
+use native::contiguous;
+use {BoundType, MVInform, MVResult, MvDistError, Scratch};
+
+lazy_static! {
+    static ref MVDIST_MUTEX: Mutex<()> = Mutex::new(());
+}
+
+/// Acquire `MVDIST_MUTEX`. The mutex guards no data, and the Fortran routines reinitialize their
+/// state on every call, so a poisoned lock (a panic in another thread while it was held) is safe
+/// to recover rather than propagating the panic to every later call.
+pub fn lock() -> MutexGuard<'static, ()> {
+    MVDIST_MUTEX.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The largest number of variables or constraints accepted by the Fortran routines (the `NL`
+/// parameter of the Genz code). Larger arrays would overrun its fixed-size work arrays.
+pub const MAX_DIMENSION: usize = 1000;
+
+/// Check the constraint matrix dimensions against `MAX_DIMENSION` and convert them for the call.
+fn dimensions(constraints: &Array2<f64>) -> Result<(i32, i32), MvDistError> {
+    let (m, n) = (constraints.rows(), constraints.cols());
+    for &x in &[n, m] {
+        if x > MAX_DIMENSION {
+            return Err(MvDistError::DimensionTooLarge {
+                n: x,
+                max: MAX_DIMENSION,
+            });
+        }
+    }
+    Ok((checked_i32(m)?, checked_i32(n)?))
+}
+
+/// The elements of `ar` in column-major order, borrowed directly when `ar` is already laid out
+/// that way (e.g. by `reversed_axes` on a standard-layout array).
+fn column_ordered(ar: &Array2<f64>) -> Cow<[f64]> {
+    if ar.t().is_standard_layout() {
+        if let Some(data) = ar.as_slice_memory_order() {
+            return Cow::Borrowed(data);
+        }
+    }
+    Cow::Owned(ar.t().into_iter().cloned().collect())
+}
+
+/// `column_ordered`, copying into `buffer`, whose allocation is reused, rather than a new vector.
+fn column_ordered_in<'a>(ar: &'a Array2<f64>, buffer: &'a mut Vec<f64>) -> &'a [f64] {
+    if ar.t().is_standard_layout() {
+        if let Some(data) = ar.as_slice_memory_order() {
+            return data;
+        }
+    }
+    buffer.clear();
+    buffer.extend(ar.t().iter().cloned());
+    buffer
+}
+
+/// Check that `cov` is `n x n` and that there are `m` of each of the other arrays, for the `m x n`
+/// constraint matrix, before their pointers are passed to the Fortran routines, which read that
+/// many elements of each.
+fn check_shapes(cov: &Array2<f64>,
+                constraints: &Array2<f64>,
+                lengths: &[usize])
+                -> Result<(), MvDistError> {
+    let (m, n) = (constraints.rows(), constraints.cols());
+    if cov.rows() != n || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected {}x{}",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     n,
+                                                     n)));
+    }
+    if lengths.iter().any(|&l| l != m) {
+        return Err(MvDistError::InvalidInput(format!("Expected bounds, types and noncentralities \
+                                                      for {} constraints, got {:?}",
+                                                     m,
+                                                     lengths)));
+    }
+    Ok(())
+}
+
+fn infin(types: &[BoundType]) -> Vec<i32> {
+    types.iter().map(|&t| t.into()).collect()
+}
+
+/// A single call to `mvdist` from `mvdist-sys`, under the mutex.
+fn mvdist_once(cov: &[f64],
+               n: i32,
+               nu: i32,
+               m: i32,
+               lb: &[f64],
+               ub: &[f64],
+               infin: &[i32],
+               constraints: &[f64],
+               delta: &[f64],
+               maxpts: i32,
+               abseps: f64,
+               releps: f64)
+               -> Result<MVResult, MvDistError> {
+    let guard = lock();
+    let (error, value, nevals, inform) = sys_mvdist(n,
+                                                    cov,
+                                                    nu,
+                                                    m,
+                                                    lb,
+                                                    constraints,
+                                                    ub,
+                                                    infin,
+                                                    delta,
+                                                    maxpts,
+                                                    abseps,
+                                                    releps);
+    // I don't normally like to explicitly drop, but this ensures that the guard doesn't get elided
+    // (which happens if let _ is used) and that I don't need #[allow(unused_variables)] to prevent
+    // the warning.
+    drop(guard);
+    match inform {
+            0 => Ok(MVInform::Normal),
+            1 => Ok(MVInform::PtLimitReached),
+            2 => Err(MvDistError::InvalidInput(format!("Invalid choice of N"))),
+            3 => Err(MvDistError::NotPositiveSemidefinite),
+            x => Err(MvDistError::InvalidInput(format!("Unknown error code {}", x))),
+        }
+        .and_then(|inf| {
+            Ok(MVResult {
+                error: error,
+                value: value,
+                nevals: nevals.max(0) as u64,
+                state: inf,
+            })
+        })
+}
+
+/// Call the `mvdist` function from `mvdist-sys`. This function is *not* thread-safe. **Do not make
+/// calls to it from multiple threads and expect better performance.** A mutex is used to ensure
+/// this is the case.
+///
+/// `maxpts` budgets larger than `i32::MAX` are split over several calls, whose estimates are
+/// combined by inverse-variance weighting until the tolerance is met.
+pub fn mvdist(cov: &Array2<f64>,
+              nu: i32,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &[BoundType],
+              constraints: &Array2<f64>,
+              delta: &Array1<f64>,
+              maxpts: u64,
+              abseps: f64,
+              releps: f64)
+              -> Result<MVResult, MvDistError> {
+    mvdist_in(cov,
+              nu,
+              lb,
+              ub,
+              types,
+              constraints,
+              delta,
+              maxpts,
+              abseps,
+              releps,
+              &mut Scratch::default())
+}
+
+/// `mvdist` with the copies of the arrays made in the buffers of `scratch`.
+pub fn mvdist_in(cov: &Array2<f64>,
+                 nu: i32,
+                 lb: &Array1<f64>,
+                 ub: &Array1<f64>,
+                 types: &[BoundType],
+                 constraints: &Array2<f64>,
+                 delta: &Array1<f64>,
+                 maxpts: u64,
+                 abseps: f64,
+                 releps: f64,
+                 scratch: &mut Scratch)
+                 -> Result<MVResult, MvDistError> {
+    let (m, n) = dimensions(constraints)?;
+    check_shapes(cov,
+                 constraints,
+                 &[lb.len(), ub.len(), types.len(), delta.len()])?;
+    let (lb, ub, delta) = (contiguous(lb), contiguous(ub), contiguous(delta));
+    let Scratch { cov: ref mut cov_buffer,
+                  constraints: ref mut constraints_buffer,
+                  infin: ref mut infin,
+                  .. } = *scratch;
+    infin.clear();
+    infin.extend(types.iter().map(|&t| Into::<i32>::into(t)));
+    let cov = column_ordered_in(cov, cov_buffer);
+    let constraints = column_ordered_in(constraints, constraints_buffer);
+    let mut remaining = maxpts;
+    let mut pooled: Option<MVResult> = None;
+    loop {
+        let pts = remaining.min(::std::i32::MAX as u64);
+        let res = mvdist_once(cov,
+                              n,
+                              nu,
+                              m,
+                              &lb,
+                              &ub,
+                              infin,
+                              constraints,
+                              &delta,
+                              pts as i32,
+                              abseps,
+                              releps)?;
+        remaining -= pts;
+        let res = match pooled {
+            None => res,
+            Some(prev) => combine(&prev, &res),
+        };
+        let converged = res.error <= abseps.max(releps * res.value.abs());
+        if res.state == MVInform::Normal || converged || remaining == 0 {
+            return Ok(MVResult {
+                state: if converged {
+                    MVInform::Normal
+                } else {
+                    res.state
+                },
+                ..res
+            });
+        }
+        pooled = Some(res);
+    }
+}
+
+/// Inverse-variance-weighted combination of two independent estimates.
+fn combine(a: &MVResult, b: &MVResult) -> MVResult {
+    let (wa, wb) = (1.0 / a.error.powi(2), 1.0 / b.error.powi(2));
+    let (value, error) = if wa.is_finite() && wb.is_finite() {
+        ((wa * a.value + wb * b.value) / (wa + wb), (wa + wb).sqrt().recip())
+    } else if a.error <= b.error {
+        (a.value, a.error)
+    } else {
+        (b.value, b.error)
+    };
+    MVResult {
+        value: value,
+        error: error,
+        nevals: a.nevals + b.nevals,
+        state: b.state,
+    }
+}
+
+/// Call the `mvcrit` function from `mvdist-sys`, under the same mutex as `mvdist`. `maxpts` is
+/// capped at `i32::MAX`.
+pub fn mvcrit(cov: &Array2<f64>,
+              nu: i32,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &[BoundType],
+              constraints: &Array2<f64>,
+              alpha: f64,
+              maxpts: u64,
+              abseps: f64)
+              -> Result<MVResult, MvDistError> {
+    let (m, n) = dimensions(constraints)?;
+    check_shapes(cov, constraints, &[lb.len(), ub.len(), types.len()])?;
+    let infin = infin(types);
+    let guard = lock();
+    let (error, value, nevals, inform) = sys_mvcrit(n,
+                                                    &column_ordered(cov),
+                                                    nu,
+                                                    m,
+                                                    &contiguous(lb),
+                                                    &column_ordered(constraints),
+                                                    &contiguous(ub),
+                                                    &infin,
+                                                    alpha,
+                                                    maxpts.min(::std::i32::MAX as u64) as i32,
+                                                    abseps);
+    // I don't normally like to explicitly drop, but this ensures that the guard doesn't get elided
+    // (which happens if let _ is used) and that I don't need #[allow(unused_variables)] to prevent
+    // the warning.
+    drop(guard);
+    match inform {
+            0 => Ok(MVInform::Normal),
+            1 => Ok(MVInform::PtLimitReached),
+            2 => Err(MvDistError::InvalidInput(format!("Invalid bounds given."))),
+            x => Err(MvDistError::InvalidInput(format!("Unknown error code {}", x))),
+        }
+        .and_then(|inf| {
+            Ok(MVResult {
+                error: error,
+                value: value,
+                nevals: nevals.max(0) as u64,
+                state: inf,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn column_ordered_borrows_fortran_layout() {
+        let c = arr2(&[[1.0, 2.0], [3.0, 4.0]]);
+        match column_ordered(&c) {
+            Cow::Owned(v) => assert_eq!(v, vec![1.0, 3.0, 2.0, 4.0]),
+            Cow::Borrowed(_) => panic!("standard layout should be copied"),
+        }
+        let f = Array::from_shape_vec((2, 2), vec![1.0, 3.0, 2.0, 4.0]).unwrap().reversed_axes();
+        assert_eq!(f, c);
+        match column_ordered(&f) {
+            Cow::Borrowed(v) => assert_eq!(v, &[1.0, 3.0, 2.0, 4.0]),
+            Cow::Owned(_) => panic!("fortran layout should be borrowed"),
+        }
+    }
+
+    #[test]
+    fn column_ordered_in_reuses_the_buffer() {
+        let mut buffer = Vec::with_capacity(16);
+        let c = arr2(&[[1.0, 2.0], [3.0, 4.0]]);
+        assert_eq!(column_ordered_in(&c, &mut buffer), &[1.0, 3.0, 2.0, 4.0]);
+        let f = c.clone().reversed_axes();
+        assert_eq!(column_ordered_in(&f, &mut buffer), &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!((buffer.len(), buffer.capacity()), (4, 16));
+    }
+
+    #[test]
+    fn rejects_mismatched_shapes() {
+        let (zeros, eye) = (Array::zeros((2,)), Array::eye(2));
+        let eval = |cov: &Array2<f64>, lb: &Array1<f64>, delta: &Array1<f64>| {
+            mvdist(cov,
+                   0,
+                   lb,
+                   &zeros,
+                   &[BoundType::Above; 2],
+                   &eye,
+                   delta,
+                   1000,
+                   1e-3,
+                   0.0)
+        };
+        assert!(eval(&Array::eye(3), &zeros, &zeros).is_err());
+        assert!(eval(&eye, &Array::zeros((1,)), &zeros).is_err());
+        assert!(eval(&eye, &zeros, &Array::zeros((3,))).is_err());
+        assert!((eval(&eye, &zeros, &zeros).unwrap().value - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn combine_weights_by_inverse_variance() {
+        let a = MVResult {
+            value: 1.0,
+            error: 1.0,
+            nevals: 10,
+            state: MVInform::PtLimitReached,
+        };
+        let b = MVResult {
+            value: 4.0,
+            error: 0.5,
+            ..a
+        };
+        let c = combine(&a, &b);
+        assert!((c.value - 3.4).abs() < 1e-12);
+        assert!((c.error - 0.2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(c.nevals, 20);
+    }
+
+    #[test]
+    fn rejects_oversized_problems() {
+        assert_eq!(dimensions(&Array::zeros((3, MAX_DIMENSION))), Ok((3, MAX_DIMENSION as i32)));
+        assert_eq!(dimensions(&Array::zeros((MAX_DIMENSION + 1, 2))),
+                   Err(MvDistError::DimensionTooLarge {
+                       n: MAX_DIMENSION + 1,
+                       max: MAX_DIMENSION,
+                   }));
+    }
+
+    #[test]
+    fn recovers_from_poisoned_lock() {
+        let _ = ::std::thread::spawn(|| {
+                let _guard = lock();
+                panic!("poison the lock");
+            })
+            .join();
+        assert!(MVDIST_MUTEX.is_poisoned());
+        let result = mvdist(&Array::eye(2),
+                            0,
+                            &Array::zeros((2,)),
+                            &Array::zeros((2,)),
+                            &[BoundType::Above; 2],
+                            &Array::eye(2),
+                            &Array::zeros((2,)),
+                            10_000,
+                            1e-5,
+                            0.0)
+            .unwrap();
+        assert!((result.value - 0.25).abs() < 1e-5);
+    }
+}
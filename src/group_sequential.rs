@@ -0,0 +1,395 @@
+//! Error-spending boundaries of one-sided group-sequential designs. The standardized statistics
+//! `Z_1, ..., Z_K` of the looks at information fractions `t_k` have the correlations of
+//! `sequential::score_statistics` and means `drift sqrt(t_k)`, where `drift` is the expected final
+//! statistic. The trial stops for efficacy at the first look with `Z_k >= b_k`, and, with a
+//! futility boundary, for futility at the first with `Z_k <= a_k`.
+//!
+//! The boundaries are found one look at a time, following Lan and DeMets: `b_k` is the value at
+//! which the probability under `drift = 0` of first crossing the efficacy boundary at look `k`
+//! is the increment `alpha(t_k) - alpha(t_{k-1})` of the spending function, and `a_k`, under the
+//! alternative `drift`, the value at which the probability of first crossing the futility
+//! boundary is the increment of the beta-spending function. Each probability is one rectangle
+//! evaluation of `mvdist_kernel` over the first `k` looks, and each boundary is searched by
+//! bisection within the bracket of a `crit::RootFinder`.
+
+use crit::RootFinder;
+use ndarray::prelude::*;
+use sequential::score_statistics;
+use univariate::{norm_cdf, norm_inv};
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+/// A spending function `alpha(t)`, the error spent by information fraction `t`, increasing from
+/// 0 at `t = 0` to the total at `t = 1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Spending {
+    /// `2 - 2 Φ(z / sqrt(t))` for the `z` with `2 - 2 Φ(z)` the total, which spends little early
+    /// on and gives boundaries close to those of O'Brien and Fleming.
+    OBrienFleming,
+    /// `alpha log(1 + (e - 1) t)`, with boundaries close to the constant ones of Pocock.
+    Pocock,
+    /// `alpha t^rho` for `rho > 0`, the family of Kim and DeMets.
+    Power(f64),
+    /// `alpha (1 - exp(-gamma t)) / (1 - exp(-gamma))`, or `alpha t` for `gamma = 0`, the family
+    /// of Hwang, Shih and DeCani. Negative `gamma` spends less early on.
+    HwangShihDeCani(f64),
+}
+
+impl Spending {
+    /// The error spent by information fraction `t` of a total `alpha`.
+    pub fn spent(&self, alpha: f64, t: f64) -> f64 {
+        if !(t > 0.0) {
+            return 0.0;
+        } else if t >= 1.0 {
+            return alpha;
+        }
+        match *self {
+            Spending::OBrienFleming => 2.0 * norm_cdf(norm_inv(alpha / 2.0) / t.sqrt()),
+            Spending::Pocock => alpha * (1.0 + (::std::f64::consts::E - 1.0) * t).ln(),
+            Spending::Power(rho) => alpha * t.powf(rho),
+            Spending::HwangShihDeCani(gamma) => {
+                if gamma == 0.0 {
+                    alpha * t
+                } else {
+                    alpha * (-gamma * t).exp_m1() / (-gamma).exp_m1()
+                }
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), MvDistError> {
+        match *self {
+            Spending::Power(rho) if !(rho > 0.0) || rho.is_infinite() => {
+                Err(MvDistError::InvalidInput(format!("Power {} is not positive", rho)))
+            }
+            Spending::HwangShihDeCani(gamma) if !gamma.is_finite() => {
+                Err(MvDistError::InvalidInput(format!("Parameter {} is not finite", gamma)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A futility boundary spending the type II error `beta` under the alternative `drift`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Futility {
+    pub beta: f64,
+    pub spending: Spending,
+    /// The expected final statistic under the alternative.
+    pub drift: f64,
+    /// Whether the trial must stop at the futility boundary. A binding boundary is taken into
+    /// account in the efficacy boundaries, which are then lower; a non-binding one is not, so
+    /// that ignoring it keeps the type I error at `alpha`.
+    pub binding: bool,
+}
+
+/// A one-sided group-sequential design.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Design {
+    /// The information of each look, positive and increasing; only the fractions of the last one
+    /// matter.
+    pub information: Vec<f64>,
+    /// The type I error.
+    pub alpha: f64,
+    pub spending: Spending,
+    pub futility: Option<Futility>,
+}
+
+/// The boundaries of a design on the scale of the standardized statistics.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Boundaries {
+    /// The efficacy boundary `b_k` of each look.
+    pub efficacy: Vec<f64>,
+    /// The futility boundary `a_k` of each look, or empty without one. It is at most the efficacy
+    /// boundary, and equal to it at the last look, where the trial ends either way.
+    pub futility: Vec<f64>,
+    /// The evaluations of every search.
+    pub nevals: u64,
+    pub state: MVInform,
+}
+
+impl Boundaries {
+    fn add(&mut self, r: &MVResult) {
+        self.nevals += r.nevals;
+        if r.state != MVInform::Normal {
+            self.state = r.state;
+        }
+    }
+}
+
+/// The information fractions of `information`.
+fn fractions(information: &[f64]) -> Vec<f64> {
+    let last = information[information.len() - 1];
+    information.iter().map(|i| i / last).collect()
+}
+
+/// `P(lower_j <= Z_j <= upper_j for each look j)` over the first `lower.len()` looks.
+fn rectangle(corr: &Array2<f64>,
+             mean: &[f64],
+             lower: &[f64],
+             upper: &[f64],
+             maxpts: u64,
+             abseps: f64,
+             finder: &RootFinder)
+             -> Result<MVResult, MvDistError> {
+    let k = lower.len();
+    let mut leading = Array2::zeros((k, k));
+    for i in 0..k {
+        for j in 0..k {
+            leading[[i, j]] = corr[[i, j]];
+        }
+    }
+    let lb = Array::from_vec((0..k).map(|j| lower[j] - mean[j]).collect());
+    let ub = Array::from_vec((0..k).map(|j| upper[j] - mean[j]).collect());
+    let types = (0..k).map(|j| BoundType::from_limits(lb[j], ub[j])).collect::<Vec<BoundType>>();
+    finder.retry.run(maxpts, |maxpts| {
+        ::mvdist_kernel(&leading,
+                        Kernel::Normal,
+                        &lb,
+                        &ub,
+                        &types,
+                        &Array::eye(k),
+                        &Array::zeros((k,)),
+                        maxpts,
+                        abseps,
+                        0.0)
+    })
+}
+
+/// The `x` in `finder.bracket` at which `crossing(x)`, monotone in `x`, is `target`, by bisection
+/// until the bracket is narrower than `finder.tolerance` or the probability is within its error
+/// of `target`.
+fn solve<F>(target: f64,
+            finder: &RootFinder,
+            boundaries: &mut Boundaries,
+            mut crossing: F)
+            -> Result<f64, MvDistError>
+    where F: FnMut(f64) -> Result<MVResult, MvDistError>
+{
+    let (mut lo, mut hi) = finder.bracket;
+    if !(lo < hi) || !(finder.tolerance > 0.0) || finder.max_iterations == 0 {
+        return Err(MvDistError::InvalidInput(format!("Invalid root finder {:?}", finder)));
+    }
+    let mut at = |x: f64, boundaries: &mut Boundaries| -> Result<(f64, f64), MvDistError> {
+        let r = crossing(x)?;
+        boundaries.add(&r);
+        Ok((r.value - target, r.error))
+    };
+    let (h_lo, _) = at(lo, boundaries)?;
+    let (h_hi, _) = at(hi, boundaries)?;
+    if !(h_lo * h_hi < 0.0) {
+        return Err(MvDistError::RootNotBracketed {
+            alpha: target,
+            lo: lo,
+            hi: hi,
+        });
+    }
+    let mut mid = 0.5 * (lo + hi);
+    for _ in 0..finder.max_iterations {
+        mid = 0.5 * (lo + hi);
+        let (h, error) = at(mid, boundaries)?;
+        if h.abs() <= error || hi - lo < finder.tolerance {
+            break;
+        }
+        if (h > 0.0) == (h_lo > 0.0) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(mid)
+}
+
+/// The boundaries of `design`, each searched within `finder.bracket`, with every probability
+/// evaluated with `maxpts` and `abseps` and retried as set by `finder.retry`. The search of a
+/// boundary fails with `MvDistError::RootNotBracketed` if its increment cannot be spent within
+/// the bracket, as when a futility boundary would exceed the efficacy boundary throughout.
+pub fn boundaries(design: &Design,
+                  maxpts: u64,
+                  abseps: f64,
+                  finder: &RootFinder)
+                  -> Result<Boundaries, MvDistError> {
+    let corr = score_statistics(&design.information)?;
+    if !(design.alpha > 0.0 && design.alpha < 1.0) {
+        return Err(MvDistError::InvalidInput(format!("Level {} is not in (0, 1)", design.alpha)));
+    }
+    design.spending.check()?;
+    if let Some(ref futility) = design.futility {
+        if !(futility.beta > 0.0 && futility.beta < 1.0) || !futility.drift.is_finite() {
+            return Err(MvDistError::InvalidInput(format!("Invalid futility boundary {:?}",
+                                                         futility)));
+        }
+        futility.spending.check()?;
+    }
+    let t = fractions(&design.information);
+    let null = vec![0.0; t.len()];
+    let inf = ::std::f64::INFINITY;
+    let binding = design.futility.map_or(false, |f| f.binding);
+    let mut result = Boundaries {
+        efficacy: Vec::with_capacity(t.len()),
+        futility: Vec::new(),
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    for k in 0..t.len() {
+        let previous = if k == 0 { 0.0 } else { t[k - 1] };
+        // the regions in which the trial continues past the earlier looks
+        let mut lower = if binding {
+            result.futility.clone()
+        } else {
+            vec![-inf; k]
+        };
+        let mut upper = result.efficacy.clone();
+        let increment = design.spending.spent(design.alpha, t[k]) -
+                        design.spending.spent(design.alpha, previous);
+        lower.push(0.0);
+        upper.push(inf);
+        let b = solve(increment, finder, &mut result, |x| {
+                lower[k] = x;
+                rectangle(&corr, &null, &lower, &upper, maxpts, abseps, finder)
+            })?;
+        result.efficacy.push(b);
+
+        if let Some(futility) = design.futility {
+            if k + 1 == t.len() {
+                result.futility.push(b);
+                break;
+            }
+            let mean = t.iter().map(|t| futility.drift * t.sqrt()).collect::<Vec<f64>>();
+            let mut lower = result.futility.clone();
+            let mut upper = result.efficacy[..k].to_vec();
+            let increment = futility.spending.spent(futility.beta, t[k]) -
+                            futility.spending.spent(futility.beta, previous);
+            lower.push(-inf);
+            upper.push(0.0);
+            let a = solve(increment, finder, &mut result, |x| {
+                    upper[k] = x;
+                    rectangle(&corr, &mean, &lower, &upper, maxpts, abseps, finder)
+                })?;
+            result.futility.push(a.min(b));
+        }
+    }
+    Ok(result)
+}
+
+/// The probability of stopping for efficacy at each look under `drift`, whose sum is the power
+/// of the design, or its type I error with `drift = 0`. The trial is taken to stop at the
+/// futility boundary if `boundaries` has one, so with a non-binding boundary the type I error is
+/// below `alpha`. Each probability is evaluated with `maxpts` and `abseps`.
+pub fn rejection_probabilities(information: &[f64],
+                               boundaries: &Boundaries,
+                               drift: f64,
+                               maxpts: u64,
+                               abseps: f64)
+                               -> Result<Vec<MVResult>, MvDistError> {
+    let corr = score_statistics(information)?;
+    let n = information.len();
+    if boundaries.efficacy.len() != n ||
+       !(boundaries.futility.is_empty() || boundaries.futility.len() == n) {
+        return Err(MvDistError::InvalidInput(format!("Expected boundaries for {} looks", n)));
+    }
+    let mean = fractions(information).iter().map(|t| drift * t.sqrt()).collect::<Vec<f64>>();
+    let inf = ::std::f64::INFINITY;
+    let finder = RootFinder::default();
+    (0..n)
+        .map(|k| {
+            let mut lower = if boundaries.futility.is_empty() {
+                vec![-inf; k]
+            } else {
+                boundaries.futility[..k].to_vec()
+            };
+            let mut upper = boundaries.efficacy[..k].to_vec();
+            lower.push(boundaries.efficacy[k]);
+            upper.push(inf);
+            rectangle(&corr, &mean, &lower, &upper, maxpts, abseps, &finder)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use univariate::{norm_cdf, norm_inv};
+
+    #[test]
+    fn spending_functions() {
+        for &s in &[Spending::OBrienFleming,
+                    Spending::Pocock,
+                    Spending::Power(2.0),
+                    Spending::HwangShihDeCani(-4.0),
+                    Spending::HwangShihDeCani(0.0)] {
+            assert_eq!(s.spent(0.025, 0.0), 0.0);
+            assert!((s.spent(0.025, 1.0 - 1e-12) - 0.025).abs() < 1e-10);
+            assert!(s.spent(0.025, 0.3) < s.spent(0.025, 0.6));
+        }
+        assert!((Spending::Power(2.0).spent(0.05, 0.5) - 0.0125).abs() < 1e-15);
+        assert!(Spending::Power(0.0).check().is_err());
+    }
+
+    #[test]
+    fn obrien_fleming_boundaries() {
+        // three equally spaced looks at one-sided level 0.025
+        let design = Design {
+            information: vec![1.0, 2.0, 3.0],
+            alpha: 0.025,
+            spending: Spending::OBrienFleming,
+            futility: None,
+        };
+        let finder = RootFinder::default();
+        let b = boundaries(&design, 100_000, 1e-7, &finder).unwrap();
+        let first = norm_inv(1.0 - Spending::OBrienFleming.spent(0.025, 1.0 / 3.0));
+        assert!((b.efficacy[0] - first).abs() < 1e-5);
+        for (&x, &y) in b.efficacy.iter().zip(&[3.7103, 2.5114, 1.9930]) {
+            assert!((x - y).abs() < 2e-3, "{:?}", b.efficacy);
+        }
+        // the boundaries spend alpha as the spending function does
+        let p = rejection_probabilities(&design.information, &b, 0.0, 100_000, 1e-7).unwrap();
+        let mut spent = 0.0;
+        for (k, r) in p.iter().enumerate() {
+            spent += r.value;
+            let target = Spending::OBrienFleming.spent(0.025, (k + 1) as f64 / 3.0);
+            assert!((spent - target).abs() < 1e-5);
+        }
+        assert!(boundaries(&Design { alpha: 1.5, ..design.clone() }, 1000, 1e-4, &finder).is_err());
+    }
+
+    #[test]
+    fn futility_boundaries() {
+        let futility = Futility {
+            beta: 0.1,
+            spending: Spending::HwangShihDeCani(-2.0),
+            drift: 3.2,
+            binding: false,
+        };
+        let design = Design {
+            information: vec![0.5, 1.0],
+            alpha: 0.025,
+            spending: Spending::Pocock,
+            futility: Some(futility),
+        };
+        let finder = RootFinder::default();
+        let b = boundaries(&design, 100_000, 1e-7, &finder).unwrap();
+        // the first futility boundary spends its share of beta under the alternative
+        let beta = futility.spending.spent(0.1, 0.5);
+        assert!((norm_cdf(b.futility[0] - 3.2 * 0.5f64.sqrt()) - beta).abs() < 1e-6);
+        assert!(b.futility[0] < b.efficacy[0] && b.futility[1] == b.efficacy[1]);
+        // a non-binding boundary leaves the efficacy boundaries as without it
+        let alone = boundaries(&Design { futility: None, ..design.clone() }, 100_000, 1e-7, &finder)
+            .unwrap();
+        assert!((alone.efficacy[1] - b.efficacy[1]).abs() < 1e-4);
+        // a binding one lowers them
+        let binding = Design {
+            futility: Some(Futility { binding: true, ..futility }),
+            ..design.clone()
+        };
+        let bound = boundaries(&binding, 100_000, 1e-7, &finder).unwrap();
+        assert!(bound.efficacy[1] < b.efficacy[1]);
+        let p = rejection_probabilities(&design.information, &bound, 0.0, 100_000, 1e-7).unwrap();
+        assert!((p[0].value + p[1].value - 0.025).abs() < 1e-5);
+        let power = rejection_probabilities(&design.information, &b, 3.2, 100_000, 1e-7).unwrap();
+        assert!(power[0].value + power[1].value > 0.75);
+    }
+}
@@ -0,0 +1,273 @@
+//! Importance sampling of normal probabilities, for far-tail regions that are not symmetric about
+//! the mean, where the randomized lattice rules of `native` spend most of their points where the
+//! integrand is negligible. `X ~ N(0, cov)` is drawn instead from a normal proposal centred in the
+//! region, and each draw inside the region is weighted by the ratio of the densities.
+//!
+//! `Proposal::truncated_moments` builds the proposal with the covariance of the problem and the
+//! first moment of `X` truncated to the region, which for the constraints `Y = C X + delta` is
+//! `cov Cᵀ (C cov Cᵀ)⁻¹ (E[Y | region] - delta)`, exact for a normal distribution, with
+//! `E[Y | region]` from `risk::truncated_mean`.
+
+use linalg::{cholesky, ln_det_cholesky, solve_lower, solve_lower_transpose};
+use native::{SplitMix64, DEFAULT_SEED};
+use ndarray::prelude::*;
+use rand::distributions::{IndependentSample, Normal};
+use risk::truncated_mean;
+use {EvalConfig, Kernel, MVInform, MVResult, MvDistError, MvProblem, Tolerance, ERROR_SCALE};
+
+/// A normal distribution to draw from in place of the distribution of the problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proposal {
+    mean: Array1<f64>,
+    cov: Array2<f64>,
+    /// The Cholesky factor of `cov`.
+    factor: Array2<f64>,
+}
+
+impl Proposal {
+    /// The proposal `N(mean, cov)`, with `cov` positive definite.
+    pub fn new(mean: Array1<f64>, cov: Array2<f64>) -> Result<Proposal, MvDistError> {
+        let n = mean.len();
+        if cov.rows() != n || cov.cols() != n || mean.iter().any(|m| !m.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Invalid proposal with a mean of length \
+                                                          {} and a {}x{} covariance",
+                                                         n,
+                                                         cov.rows(),
+                                                         cov.cols())));
+        }
+        let factor = match cholesky(&cov) {
+            Some(l) => l,
+            None => {
+                return Err(MvDistError::InvalidInput(format!("The proposal covariance is not \
+                                                              positive definite")))
+            }
+        };
+        Ok(Proposal {
+            mean: mean,
+            cov: cov,
+            factor: factor,
+        })
+    }
+
+    /// The proposal with the covariance of `problem` and the mean of its distribution truncated to
+    /// its region, found with the budget of its `config` and, after evaluating the probability of
+    /// the region to a relative tolerance of `1e-3`, an absolute tolerance small enough for the
+    /// mean to be accurate relative to that probability. The constraints must have full row
+    /// rank, so at most as many constraints as variables.
+    pub fn truncated_moments(problem: &MvProblem) -> Result<Proposal, MvDistError> {
+        check(problem)?;
+        let (cov, c) = (&problem.cov, &problem.constraints);
+        let (n, m) = (c.cols(), c.rows());
+        let s = c.dot(cov).dot(&c.t());
+        let l = match cholesky(&s) {
+            Some(l) => l,
+            None => {
+                return Err(MvDistError::InvalidInput(format!("The {} constraints on {} \
+                                                              variables do not have full row \
+                                                              rank",
+                                                             m,
+                                                             n)))
+            }
+        };
+        let (lower, upper): (Vec<f64>, Vec<f64>) =
+            (0..m).map(|i| problem.types[i].limits(problem.lb[i], problem.ub[i])).unzip();
+        let (lower, upper) = (Array::from_vec(lower), Array::from_vec(upper));
+        let relative = MvProblem {
+            config: EvalConfig {
+                tolerance: Tolerance::relative(1e-3)?,
+                ..problem.config.clone()
+            },
+            ..problem.clone()
+        };
+        let p = relative.mvdist()?.value;
+        if !(p > 0.0) {
+            return Err(MvDistError::InvalidInput(format!("The region has probability {}", p)));
+        }
+        let abseps = problem.config.tolerance.abseps().min(1e-3 * p);
+        let (y, _) = truncated_mean(&problem.delta,
+                                    &s,
+                                    Kernel::Normal,
+                                    &lower,
+                                    &upper,
+                                    problem.config.maxpts,
+                                    abseps)?;
+        let centred = (&y - &problem.delta).to_vec();
+        let v = solve_lower_transpose(&l, &solve_lower(&l, &centred));
+        let mean = cov.dot(&c.t()).dot(&Array::from_vec(v));
+        Proposal::new(mean, cov.clone())
+    }
+
+    pub fn mean(&self) -> &Array1<f64> {
+        &self.mean
+    }
+
+    pub fn cov(&self) -> &Array2<f64> {
+        &self.cov
+    }
+}
+
+/// The result of `estimate`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ImportanceEstimate {
+    pub value: f64,
+    /// The standard error of `value`.
+    pub std_error: f64,
+    /// `(Σ w)² / Σ w²` over the weights `w` of the draws, the number of draws from the
+    /// distribution itself that would estimate the probability as well. Far below `samples`, the
+    /// proposal is poorly matched to the region and `std_error` is itself unreliable.
+    pub effective_sample_size: f64,
+    pub samples: usize,
+}
+
+impl ImportanceEstimate {
+    /// The estimate as an `MVResult`, with `error` at `ERROR_SCALE` standard errors.
+    pub fn result(&self) -> MVResult {
+        MVResult {
+            value: self.value,
+            error: ERROR_SCALE * self.std_error,
+            nevals: self.samples as u64,
+            state: MVInform::Normal,
+        }
+    }
+}
+
+fn check(problem: &MvProblem) -> Result<(), MvDistError> {
+    let (n, m) = (problem.cov.rows(), problem.constraints.rows());
+    if n == 0 || problem.cov.cols() != n || problem.constraints.cols() != n ||
+       problem.lb.len() != m || problem.ub.len() != m || problem.types.len() != m ||
+       problem.delta.len() != m {
+        return Err(MvDistError::InvalidInput(format!("Inconsistent problem dimensions")));
+    }
+    if problem.kernel.nu()? != 0.0 {
+        return Err(MvDistError::InvalidInput(format!("Importance sampling supports only the \
+                                                      normal distribution")));
+    }
+    Ok(())
+}
+
+/// Estimate the probability of `problem`, which must have the normal distribution and a positive
+/// definite covariance, from `samples` draws from `proposal`, generated from the problem's seed
+/// (or `native::DEFAULT_SEED`).
+pub fn estimate(problem: &MvProblem,
+                proposal: &Proposal,
+                samples: usize)
+                -> Result<ImportanceEstimate, MvDistError> {
+    check(problem)?;
+    let (n, m) = (problem.cov.rows(), problem.constraints.rows());
+    if proposal.mean.len() != n {
+        return Err(MvDistError::InvalidInput(format!("The proposal has {} variables, expected {}",
+                                                     proposal.mean.len(),
+                                                     n)));
+    }
+    if samples < 2 {
+        return Err(MvDistError::InvalidInput(format!("At least 2 samples are needed, got {}",
+                                                     samples)));
+    }
+    let l = match cholesky(&problem.cov) {
+        Some(l) => l,
+        None => {
+            return Err(MvDistError::InvalidInput(format!("The covariance is not positive \
+                                                          definite")))
+        }
+    };
+    let limits = (0..m)
+        .map(|i| problem.types[i].limits(problem.lb[i], problem.ub[i]))
+        .collect::<Vec<(f64, f64)>>();
+    let ln_ratio = 0.5 * (ln_det_cholesky(&proposal.factor) - ln_det_cholesky(&l));
+    let mut rng = SplitMix64::new(problem.config.seed.unwrap_or(DEFAULT_SEED));
+    let normal = Normal::new(0.0, 1.0);
+    let (mut z, mut x) = (vec![0.0; n], vec![0.0; n]);
+    let (mut sum, mut squares) = (0.0, 0.0);
+    for _ in 0..samples {
+        for zi in z.iter_mut() {
+            *zi = normal.ind_sample(&mut rng);
+        }
+        for i in 0..n {
+            let step = (0..i + 1).map(|j| proposal.factor[[i, j]] * z[j]).sum::<f64>();
+            x[i] = proposal.mean[i] + step;
+        }
+        let inside = limits.iter().enumerate().all(|(i, &(lo, hi))| {
+            let y = (0..n).map(|j| problem.constraints[[i, j]] * x[j]).sum::<f64>() +
+                    problem.delta[i];
+            lo <= y && y <= hi
+        });
+        if inside {
+            let u = solve_lower(&l, &x);
+            let norm = |v: &[f64]| v.iter().map(|a| a * a).sum::<f64>();
+            let w = (0.5 * (norm(&z) - norm(&u)) + ln_ratio).exp();
+            sum += w;
+            squares += w * w;
+        }
+    }
+    let k = samples as f64;
+    let value = sum / k;
+    let variance = ((squares - sum * value) / (k - 1.0)).max(0.0);
+    Ok(ImportanceEstimate {
+        value: value,
+        std_error: (variance / k).sqrt(),
+        effective_sample_size: if squares > 0.0 { sum * sum / squares } else { 0.0 },
+        samples: samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use univariate::norm_cdf;
+    use BoundType;
+
+    #[test]
+    fn far_tail_rectangle() {
+        let inf = ::std::f64::INFINITY;
+        let problem = MvProblem::new(Array::eye(2),
+                                     arr1(&[4.0, 3.0]),
+                                     arr1(&[inf, inf]),
+                                     vec![BoundType::Below; 2]);
+        let exact = norm_cdf(-4.0) * norm_cdf(-3.0);
+        let proposal = Proposal::truncated_moments(&problem).unwrap();
+        // E[X | X > a] = φ(a) / Φ(-a) for each independent coordinate
+        assert!((proposal.mean()[0] - 4.2256).abs() < 1e-3);
+        let r = estimate(&problem, &proposal, 20_000).unwrap();
+        assert!((r.value - exact).abs() < 4.0 * r.std_error && r.std_error < 0.05 * exact);
+        assert!(r.effective_sample_size > 500.0 && r.effective_sample_size <= 20_000.0);
+        // drawing from the distribution itself, every weight is one
+        let quadrant = MvProblem::new(Array::eye(2),
+                                      Array::zeros((2,)),
+                                      arr1(&[inf, inf]),
+                                      vec![BoundType::Below; 2]);
+        let plain = Proposal::new(Array::zeros((2,)), Array::eye(2)).unwrap();
+        let r = estimate(&quadrant, &plain, 1000).unwrap();
+        assert_eq!(r.effective_sample_size, (r.value * 1000.0).round());
+        assert!((r.value - 0.25).abs() < 4.0 * r.std_error);
+    }
+
+    #[test]
+    fn general_constraints() {
+        // X_1 + X_2 > 5 and X_1 - X_2 > 0 are independent, each with variance 2
+        let problem = MvProblem {
+            constraints: arr2(&[[1.0, 1.0], [1.0, -1.0]]),
+            ..MvProblem::new(Array::eye(2),
+                             arr1(&[5.0, 0.0]),
+                             arr1(&[0.0, 0.0]),
+                             vec![BoundType::Below; 2])
+        };
+        let exact = 0.5 * norm_cdf(-5.0 / 2f64.sqrt());
+        let proposal = Proposal::truncated_moments(&problem).unwrap();
+        assert!(proposal.mean()[0] > proposal.mean()[1] && proposal.mean()[1] > 0.0);
+        let r = estimate(&problem, &proposal, 20_000).unwrap();
+        assert!((r.value - exact).abs() < 4.0 * r.std_error && r.std_error < 0.05 * exact);
+        assert!(estimate(&problem, &proposal, 1).is_err());
+        let t = MvProblem { kernel: Kernel::StudentT { nu: 3.0 }, ..problem.clone() };
+        assert!(estimate(&t, &proposal, 100).is_err());
+        let three = MvProblem {
+            constraints: arr2(&[[1.0, 1.0], [1.0, -1.0], [1.0, 0.0]]),
+            lb: arr1(&[5.0, 0.0, 0.0]),
+            ub: arr1(&[0.0, 0.0, 0.0]),
+            types: vec![BoundType::Below; 3],
+            delta: Array::zeros((3,)),
+            ..problem
+        };
+        assert!(Proposal::truncated_moments(&three).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! `tracing` instrumentation of evaluations, enabled by the `tracing` feature. Each evaluation runs
+//! in an `mvdist` span recording the operation, dimensions and budget, and ends with an event
+//! recording the number of evaluations, the estimate and its error, and the wall time in seconds.
+//! Warnings about the inputs are logged as `warn` events with the warning as a field.
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use warning::Warning;
+use {MVResult, MvDistError};
+
+/// Run the evaluation `f`, named `op`, for a problem with `n` variables and `m` constraints.
+#[cfg(feature = "tracing")]
+pub fn evaluation<F>(op: &'static str,
+                     n: usize,
+                     m: usize,
+                     maxpts: u64,
+                     f: F)
+                     -> Result<MVResult, MvDistError>
+    where F: FnOnce() -> Result<MVResult, MvDistError>
+{
+    let span = info_span!("mvdist", op = op, n = n as u64, m = m as u64, maxpts = maxpts);
+    let _enter = span.enter();
+    let start = Instant::now();
+    let res = f();
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    match res {
+        Ok(ref r) => {
+            info!(nevals = r.nevals,
+                  value = r.value,
+                  error = r.error,
+                  state = ?r.state,
+                  elapsed = secs,
+                  "evaluation finished")
+        }
+        Err(ref e) => warn!(error = %e, elapsed = secs, "evaluation failed"),
+    }
+    res
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn evaluation<F>(_: &'static str,
+                     _: usize,
+                     _: usize,
+                     _: u64,
+                     f: F)
+                     -> Result<MVResult, MvDistError>
+    where F: FnOnce() -> Result<MVResult, MvDistError>
+{
+    f()
+}
+
+/// Log each of `warnings`.
+#[cfg(feature = "tracing")]
+pub fn warnings(warnings: &[Warning]) {
+    for w in warnings {
+        warn!(warning = ?w, "{}", w);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn warnings(_: &[Warning]) {}
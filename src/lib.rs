@@ -1,13 +1,110 @@
+#[cfg(feature = "columnar")]
+extern crate arrow;
+#[cfg(feature = "fortran")]
 #[macro_use]
 extern crate lazy_static;
 extern crate ndarray;
+#[cfg(feature = "fortran")]
 extern crate mvdist_sys;
+#[cfg(feature = "columnar")]
+extern crate parquet;
+extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "nalgebra")]
+extern crate nalgebra as na;
 
-use mvdist_sys::{mvcrit as sys_mvcrit, mvdist as sys_mvdist};
+use float::Float;
+use native::Reorder;
 use ndarray::prelude::*;
-use std::sync::Mutex;
+
+pub mod audit;
+pub mod bivariate;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cache;
+pub mod cancel;
+pub mod chibar;
+#[cfg(feature = "columnar")]
+pub mod columnar;
+pub mod conditioning;
+pub mod cone;
+pub mod config;
+pub mod context;
+pub mod contrasts;
+pub mod copula;
+pub mod corr;
+pub mod crit;
+pub mod csv;
+pub mod degenerate;
+#[cfg(feature = "cache-disk")]
+pub mod disk_cache;
+pub mod distribution;
+pub mod dual;
+mod error;
+pub mod exact;
+pub mod float;
+#[cfg(feature = "fortran")]
+mod fortran;
+pub mod group_sequential;
+pub mod importance;
+mod instrument;
+mod linalg;
+pub mod mixture;
+pub mod multcomp;
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra_support;
+pub mod native;
+pub mod order;
+pub mod pairwise;
+pub mod partition;
+#[cfg(feature = "pool")]
+pub mod pool;
+pub mod posterior;
+pub mod power;
+pub mod preprocess;
+pub mod probit;
+pub mod problem;
+#[cfg(feature = "raw")]
+pub mod raw;
+pub mod region;
+pub mod report;
+pub mod risk;
+mod quadrature;
+pub mod sequential;
+pub mod slice;
+mod sobol;
+pub mod sparse_grid;
+pub mod symmetric;
+mod univariate;
+pub mod update;
+pub mod verify;
+pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use cancel::CancellationToken;
+pub use config::{EvalConfig, RetryPolicy, Tolerance};
+pub use context::EvalContext;
+pub use crit::{mvcrit, mvcrit_many, mvcrit_with, MvCritResult, RootFinder};
+pub use error::MvDistError;
+pub use exact::{bvn, bvt};
+#[cfg(feature = "fortran")]
+pub use fortran::MAX_DIMENSION as FORTRAN_MAX_DIMENSION;
+pub use problem::MvProblem;
 
 #[derive(Clone, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BoundType {
     Unbounded,
     Above,
@@ -26,31 +123,180 @@ impl Into<i32> for BoundType {
     }
 }
 
+impl BoundType {
+    /// The integration limits `(lower, upper)` implied by this bound type, with infinities in
+    /// place of unused bounds.
+    fn limits(self, lb: f64, ub: f64) -> (f64, f64) {
+        use std::f64::{INFINITY, NEG_INFINITY};
+        match self {
+            BoundType::Unbounded => (NEG_INFINITY, INFINITY),
+            BoundType::Above => (NEG_INFINITY, ub),
+            BoundType::Below => (lb, INFINITY),
+            BoundType::Both => (lb, ub),
+        }
+    }
+
+    /// The bound type using the finite ones of the limits `lo` and `hi`.
+    pub(crate) fn from_limits(lo: f64, hi: f64) -> BoundType {
+        match (lo.is_finite(), hi.is_finite()) {
+            (true, true) => BoundType::Both,
+            (true, false) => BoundType::Below,
+            (false, true) => BoundType::Above,
+            (false, false) => BoundType::Unbounded,
+        }
+    }
+}
+
+/// The result of an evaluation. Values are `f64` except when using the generic functions in
+/// `float`.
+///
+/// `error` is not a deterministic bound: it is `ERROR_SCALE` standard errors of the randomized
+/// estimator, which the Fortran documentation describes as a 99% confidence bound. Use
+/// `standard_error` and `confidence_interval` for other levels.
 #[derive(Clone, Debug, Copy, PartialEq)]
-pub struct MVResult {
-    pub value: f64,
-    pub error: f64,
-    pub nevals: i32,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MVResult<F = f64> {
+    pub value: F,
+    pub error: F,
+    pub nevals: u64,
     pub state: MVInform,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum MVInform {
+/// The number of standard errors reported as `MVResult::error`, by both backends.
+pub const ERROR_SCALE: f64 = 3.5;
+
+impl<F: Float> MVResult<F> {
+    /// The standard error of `value`, `error / ERROR_SCALE`.
+    pub fn standard_error(&self) -> F {
+        F::from_f64(self.error.to_f64() / ERROR_SCALE)
+    }
+
+    /// A normal-approximation confidence interval `(lo, hi)` for the probability at `level` (e.g.
+    /// `0.95`), clamped to `[0, 1]`.
+    pub fn confidence_interval(&self, level: f64) -> (F, F) {
+        let z = univariate::norm_inv(0.5 + level / 2.0);
+        let (value, se) = (self.value.to_f64(), self.standard_error().to_f64());
+        (F::from_f64((value - z * se).max(0.0)), F::from_f64((value + z * se).min(1.0)))
+    }
+}
+
+/// Independent estimates of one probability merged by `MVResult::combine`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Combined {
+    pub result: MVResult,
+    /// Whether every estimate is within its `error` of the combined value. Estimates of the same
+    /// probability disagree by more than that only rarely, so `false` suggests they are of
+    /// different problems, or that an error is underestimated.
+    pub consistent: bool,
+}
+
+impl MVResult {
+    /// Merge independent estimates of the same probability, such as replicates with different
+    /// seeds, by inverse-variance weighting. If some estimates have zero error the value is their
+    /// mean, with zero error. `nevals` is the total, and the state is `MVInform::IllConditioned`
+    /// if any estimate is, `MVInform::PtLimitReached` if all are, and `MVInform::Normal` otherwise,
+    /// since the combination is at least as accurate as each estimate.
+    pub fn combine(results: &[MVResult]) -> Result<Combined, MvDistError> {
+        if results.is_empty() {
+            return Err(MvDistError::InvalidInput("No estimates to combine".to_string()));
+        }
+        if let Some(r) = results.iter().find(|r| !(r.error >= 0.0 && r.error.is_finite())) {
+            return Err(MvDistError::InvalidInput(format!("Cannot combine an estimate with \
+                                                          error {}",
+                                                         r.error)));
+        }
+        let exact = results.iter().filter(|r| r.error == 0.0).collect::<Vec<&MVResult>>();
+        let (value, error) = if exact.is_empty() {
+            let weights = results.iter().map(|r| r.error.powi(-2)).collect::<Vec<f64>>();
+            let total = weights.iter().sum::<f64>();
+            let value = results.iter().zip(weights.iter()).map(|(r, w)| w * r.value).sum::<f64>();
+            (value / total, total.sqrt().recip())
+        } else {
+            (exact.iter().map(|r| r.value).sum::<f64>() / exact.len() as f64, 0.0)
+        };
+        let state = if results.iter().any(|r| r.state == MVInform::IllConditioned) {
+            MVInform::IllConditioned
+        } else if results.iter().all(|r| r.state == MVInform::PtLimitReached) {
+            MVInform::PtLimitReached
+        } else {
+            MVInform::Normal
+        };
+        Ok(Combined {
+            result: MVResult {
+                value: value,
+                error: error,
+                nevals: results.iter().fold(0u64, |n, r| n.saturating_add(r.nevals)),
+                state: state,
+            },
+            consistent: results.iter().all(|r| (r.value - value).abs() <= r.error),
+        })
+    }
+}
+
+/// The distribution of the variables `X`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Kernel {
+    /// Multivariate normal.
     Normal,
-    PtLimitReached,
+    /// Multivariate t with `nu > 0` degrees of freedom, which need not be an integer.
+    StudentT { nu: f64 },
 }
 
-lazy_static! {
-    static ref MVDIST_MUTEX: Mutex<()> = Mutex::new(());
+impl Kernel {
+    /// The kernel selected by the `nu` argument of `mvdist`: normal for `nu <= 0`, including
+    /// infinite `nu`, and Student t otherwise.
+    pub fn from_nu(nu: f64) -> Kernel {
+        if nu > 0.0 && nu.is_finite() {
+            Kernel::StudentT { nu: nu }
+        } else {
+            Kernel::Normal
+        }
+    }
+
+    /// The degrees of freedom in the convention of the backends, with 0 for the normal
+    /// distribution. Infinite `nu` is normal; other values outside `(0, ∞)` are rejected.
+    fn nu(self) -> Result<f64, MvDistError> {
+        match self {
+            Kernel::Normal => Ok(0.0),
+            Kernel::StudentT { nu } if nu > 0.0 => Ok(if nu.is_finite() { nu } else { 0.0 }),
+            Kernel::StudentT { nu } => {
+                Err(MvDistError::InvalidInput(format!("Invalid degrees of freedom {}", nu)))
+            }
+        }
+    }
 }
 
-fn column_ordered(ar: &Array2<f64>) -> Vec<f64> {
-    ar.t().into_iter().cloned().collect()
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MVInform {
+    Normal,
+    PtLimitReached,
+    /// The covariance matrix is nearly singular, so the value may be wrong whatever the error
+    /// estimate says. Set by `conditioning::Conditioning::flag`, not by the backends.
+    IllConditioned,
 }
 
-/// Call the `mvdist` function from `mvdist-sys`. This function is *not* thread-safe. **Do not make
-/// calls to it from multiple threads and expect better performance.** A mutex is used to ensure
-/// this is the case.
+/// Compute `P(lb < C X < ub)` (with the bounds used selected by `types`) for `X` multivariate
+/// normal with covariance `cov` or, when `nu > 0`, multivariate t with `nu` degrees of freedom and
+/// noncentrality `delta`.
+///
+/// With the `fortran` feature (the default) this calls the `mvdist` function from `mvdist-sys`.
+/// That function is *not* thread-safe. **Do not make calls to it from multiple threads and expect
+/// better performance.** A mutex is used to ensure this is the case. Without the `fortran`
+/// feature, the pure-Rust implementation in `native` is used.
+///
+/// The Fortran routines accept at most `FORTRAN_MAX_DIMENSION` (1000) variables and constraints;
+/// larger problems are passed to the native backend, which has no such limit. See `mvdist_kernel`
+/// for non-integer degrees of freedom.
+///
+/// Problems with identity constraints and either a diagonal covariance (normal case only) or a
+/// common non-negative correlation are instead evaluated exactly (see `exact::independent`) or by
+/// one-dimensional quadrature (see `exact::equicorrelated`), which is faster and much more
+/// accurate than QMC for large dimensions. Two-dimensional central t problems with integer degrees
+/// of freedom are evaluated to machine precision by `bvt`, and the other cases listed in `exact`,
+/// such as central orthants and problems of up to three variables, are solved in the same way.
 pub fn mvdist(cov: &Array2<f64>,
               nu: i32,
               lb: &Array1<f64>,
@@ -58,91 +304,547 @@ pub fn mvdist(cov: &Array2<f64>,
               types: &Vec<BoundType>,
               constraints: &Array2<f64>,
               delta: &Array1<f64>,
-              maxpts: i32,
+              maxpts: u64,
               abseps: f64,
               releps: f64)
-              -> Result<MVResult, String> {
-    let shape = constraints.shape();
-    let (m, n) = (shape[0] as i32, shape[1] as i32);
-    let infin = types.iter().map(|&t| t.into()).collect::<Vec<i32>>();
-    let guard = MVDIST_MUTEX.lock();
-    let (error, value, nevals, inform) = sys_mvdist(n,
-                                                    &column_ordered(cov),
-                                                    nu,
-                                                    m,
-                                                    lb.as_slice().unwrap(),
-                                                    &column_ordered(constraints),
-                                                    ub.as_slice().unwrap(),
-                                                    &infin,
-                                                    delta.as_slice().unwrap(),
-                                                    maxpts,
-                                                    abseps,
-                                                    releps);
-    // I don't normally like to explicitly drop, but this ensures that the guard doesn't get elided
-    // (which happens if let _ is used) and that I don't need #[allow(unused_variables)] to prevent
-    // the warning.
-    drop(guard);
-    match inform {
-            0 => Ok(MVInform::Normal),
-            1 => Ok(MVInform::PtLimitReached),
-            2 => Err(format!("Invalid choice of N")),
-            3 => Err(format!("Covariance matrix not positive semidefinite")),
-            x => Err(format!("Unknown error code {}", x)),
+              -> Result<MVResult, MvDistError> {
+    mvdist_kernel(cov,
+                  Kernel::from_nu(nu as f64),
+                  lb,
+                  ub,
+                  types,
+                  constraints,
+                  delta,
+                  maxpts,
+                  abseps,
+                  releps)
+}
+
+/// `mvdist` with real degrees of freedom `nu`; `nu <= 0` or infinite `nu` selects the normal
+/// distribution.
+#[deprecated(note = "use `mvdist_kernel`, which selects the distribution explicitly")]
+pub fn mvdist_df(cov: &Array2<f64>,
+                 nu: f64,
+                 lb: &Array1<f64>,
+                 ub: &Array1<f64>,
+                 types: &[BoundType],
+                 constraints: &Array2<f64>,
+                 delta: &Array1<f64>,
+                 maxpts: u64,
+                 abseps: f64,
+                 releps: f64)
+                 -> Result<MVResult, MvDistError> {
+    if nu.is_nan() {
+        return Err(MvDistError::InvalidInput(format!("Degrees of freedom are NaN")));
+    }
+    mvdist_kernel(cov,
+                  Kernel::from_nu(nu),
+                  lb,
+                  ub,
+                  types,
+                  constraints,
+                  delta,
+                  maxpts,
+                  abseps,
+                  releps)
+}
+
+/// `mvdist` with the distribution of `X` given by `kernel`. The Fortran code only accepts integer
+/// degrees of freedom, so other values are evaluated by the native backend.
+pub fn mvdist_kernel(cov: &Array2<f64>,
+                     kernel: Kernel,
+                     lb: &Array1<f64>,
+                     ub: &Array1<f64>,
+                     types: &[BoundType],
+                     constraints: &Array2<f64>,
+                     delta: &Array1<f64>,
+                     maxpts: u64,
+                     abseps: f64,
+                     releps: f64)
+                     -> Result<MVResult, MvDistError> {
+    let nu = kernel.nu()?;
+    instrument::evaluation("mvdist", cov.rows(), constraints.rows(), maxpts, || {
+        if let Some(result) = exact::try_solve(cov,
+                                               nu,
+                                               lb,
+                                               ub,
+                                               types,
+                                               constraints,
+                                               delta,
+                                               abseps,
+                                               releps) {
+            return Ok(result);
         }
-        .and_then(|inf| {
-            Ok(MVResult {
-                error: error,
-                value: value,
-                nevals: nevals,
-                state: inf,
-            })
-        })
+        backend_mvdist(cov,
+                       nu,
+                       lb,
+                       ub,
+                       types,
+                       constraints,
+                       delta,
+                       maxpts,
+                       abseps,
+                       releps)
+    })
 }
 
-pub fn mvcrit(cov: &Array2<f64>,
-              nu: i32,
-              lb: &Array1<f64>,
-              ub: &Array1<f64>,
-              types: &Vec<BoundType>,
-              constraints: &Array2<f64>,
-              alpha: f64,
-              maxpts: i32,
-              abseps: f64)
-              -> Result<MVResult, String> {
-    let shape = constraints.shape();
-    let (m, n) = (shape[0] as i32, shape[1] as i32);
-    let infin = types.iter().map(|&t| t.into()).collect::<Vec<i32>>();
-    let guard = MVDIST_MUTEX.lock();
-    let (error, value, nevals, inform) = sys_mvcrit(n,
-                                                    &column_ordered(cov),
-                                                    nu,
-                                                    m,
-                                                    lb.as_slice().unwrap(),
-                                                    &column_ordered(constraints),
-                                                    ub.as_slice().unwrap(),
-                                                    &infin,
-                                                    alpha,
-                                                    maxpts,
-                                                    abseps);
-    // I don't normally like to explicitly drop, but this ensures that the guard doesn't get elided
-    // (which happens if let _ is used) and that I don't need #[allow(unused_variables)] to prevent
-    // the warning.
-    drop(guard);
-    match inform {
-            0 => Ok(MVInform::Normal),
-            1 => Ok(MVInform::PtLimitReached),
-            2 => Err(format!("Invalid bounds given.")),
-            x => Err(format!("Unknown error code {}", x)),
+/// `mvdist_kernel` with the integration settings in `config`. Without `config.backend` the problem
+/// is solved exactly when possible and otherwise routed as described for `backend`, except that
+/// any `reorder` other than `Reorder::Auto` needs the native backend. An explicit backend is
+/// always used, without trying the exact solutions, and it is an error to choose the Fortran
+/// backend for a problem it does not accept, or the sparse grids for one with more than
+/// `sparse_grid::MAX_DIMENSION` integration variables. Results that reach the point limit are
+/// retried as set by `config.retry`.
+pub fn mvdist_config(cov: &Array2<f64>,
+                     kernel: Kernel,
+                     lb: &Array1<f64>,
+                     ub: &Array1<f64>,
+                     types: &[BoundType],
+                     constraints: &Array2<f64>,
+                     delta: &Array1<f64>,
+                     config: &EvalConfig)
+                     -> Result<MVResult, MvDistError> {
+    mvdist_config_in(cov,
+                     kernel,
+                     lb,
+                     ub,
+                     types,
+                     constraints,
+                     delta,
+                     config,
+                     &mut Scratch::default())
+}
+
+/// Buffers reused across the evaluations of a `context::EvalContext`: the column-major copies of
+/// the covariance and constraints and the bound codes passed to the Fortran routines, and the
+/// `native::Workspace` of the native backend, which reads the arrays in place.
+#[derive(Clone, Debug, Default)]
+struct Scratch {
+    cov: Vec<f64>,
+    constraints: Vec<f64>,
+    infin: Vec<i32>,
+    workspace: native::Workspace,
+}
+
+/// `mvdist_config` with the buffers of `scratch`.
+fn mvdist_config_in(cov: &Array2<f64>,
+                    kernel: Kernel,
+                    lb: &Array1<f64>,
+                    ub: &Array1<f64>,
+                    types: &[BoundType],
+                    constraints: &Array2<f64>,
+                    delta: &Array1<f64>,
+                    config: &EvalConfig,
+                    scratch: &mut Scratch)
+                    -> Result<MVResult, MvDistError> {
+    let nu = kernel.nu()?;
+    let (n, m) = (constraints.cols(), constraints.rows());
+    let (abseps, releps) = (config.tolerance.abseps(), config.tolerance.releps());
+    instrument::evaluation("mvdist", cov.rows(), m, config.maxpts, || {
+        let chosen = match config.backend {
+            Some(chosen) => chosen,
+            None => {
+                if let Some(result) = exact::try_solve(cov,
+                                                       nu,
+                                                       lb,
+                                                       ub,
+                                                       types,
+                                                       constraints,
+                                                       delta,
+                                                       abseps,
+                                                       releps) {
+                    return Ok(result);
+                }
+                if config.reorder == Reorder::Auto {
+                    backend(n, m, kernel)
+                } else {
+                    Backend::Native
+                }
+            }
+        };
+        if chosen == Backend::Fortran && config.reorder != Reorder::Auto {
+            return Err(MvDistError::InvalidInput("The Fortran backend only supports \
+                                                  `Reorder::Auto`"
+                .to_string()));
         }
-        .and_then(|inf| {
-            Ok(MVResult {
-                error: error,
-                value: value,
-                nevals: nevals,
-                state: inf,
-            })
+        let options = native::Options {
+            seed: config.seed.unwrap_or(native::DEFAULT_SEED),
+            reorder: config.reorder.clone(),
+            ..native::Options::default()
+        };
+        config.retry.run(config.maxpts, |maxpts| match chosen {
+            Backend::Fortran => {
+                fortran_mvdist(cov,
+                               nu,
+                               lb,
+                               ub,
+                               types,
+                               constraints,
+                               delta,
+                               maxpts,
+                               abseps,
+                               releps,
+                               scratch)
+            }
+            Backend::Native => {
+                native::mvdist_estimate_in(cov,
+                                           nu,
+                                           lb,
+                                           ub,
+                                           types,
+                                           constraints,
+                                           delta,
+                                           maxpts,
+                                           abseps,
+                                           releps,
+                                           &options,
+                                           &mut scratch.workspace)
+                    .map(|e| e.result)
+            }
+            Backend::SparseGrid => {
+                sparse_grid::mvdist(cov,
+                                    nu,
+                                    lb,
+                                    ub,
+                                    types,
+                                    constraints,
+                                    delta,
+                                    maxpts,
+                                    abseps,
+                                    releps,
+                                    &config.reorder)
+            }
         })
+    })
+}
+
+/// Evaluate the probability of one region under each covariance of `covs`, as for bootstrap or
+/// posterior draws of a covariance whose limits stay fixed; this is the transpose of evaluating
+/// many limits under one covariance with `native::evaluate_grid`. The bounds are checked once for
+/// the whole batch, an error in one covariance does not affect the others, and with the `rayon`
+/// feature the covariances are evaluated in parallel, each thread reusing its buffers as an
+/// `EvalContext` does.
+///
+/// Covariance `i` is evaluated as by `mvdist_config` with the seed of stream `i` of
+/// `native::SeedStreams::new(seed)`, for the seed of `config` (or `native::DEFAULT_SEED`), so that
+/// the integration errors of different draws are independent, and with the native backend every
+/// result is reproducible whatever the number of threads. Once `cancel` is cancelled the
+/// covariances not yet started are given `MvDistError::Cancelled`, and the results of those
+/// completed are kept.
+pub fn mvdist_many_covs(covs: &[Array2<f64>],
+                        kernel: Kernel,
+                        lb: &Array1<f64>,
+                        ub: &Array1<f64>,
+                        types: &[BoundType],
+                        constraints: &Array2<f64>,
+                        delta: &Array1<f64>,
+                        config: &EvalConfig,
+                        cancel: &CancellationToken)
+                        -> Result<Vec<Result<MVResult, MvDistError>>, MvDistError> {
+    mvdist_many_draws(covs, None, kernel, lb, ub, types, constraints, delta, config, cancel)
+}
+
+/// `mvdist_many_covs` with row `i` of `shifts`, if given, added to `delta` for covariance `i`, as
+/// for draws of the mean as well as the covariance.
+fn mvdist_many_draws(covs: &[Array2<f64>],
+                     shifts: Option<&Array2<f64>>,
+                     kernel: Kernel,
+                     lb: &Array1<f64>,
+                     ub: &Array1<f64>,
+                     types: &[BoundType],
+                     constraints: &Array2<f64>,
+                     delta: &Array1<f64>,
+                     config: &EvalConfig,
+                     cancel: &CancellationToken)
+                     -> Result<Vec<Result<MVResult, MvDistError>>, MvDistError> {
+    kernel.nu()?;
+    let (n, m) = (constraints.cols(), constraints.rows());
+    if lb.len() != m || ub.len() != m || types.len() != m || delta.len() != m ||
+       shifts.map_or(false, |s| s.rows() != covs.len() || s.cols() != m) {
+        return Err(MvDistError::InvalidInput(format!("Inconsistent problem dimensions")));
+    }
+    let streams = native::SeedStreams::new(config.seed.unwrap_or(native::DEFAULT_SEED));
+    let eval = |i: usize, scratch: &mut Scratch| {
+        if cancel.is_cancelled() {
+            return Err(MvDistError::Cancelled);
+        }
+        let cov = &covs[i];
+        if cov.rows() != n || cov.cols() != n {
+            return Err(MvDistError::InvalidInput(format!("Covariance {} is {}x{}, expected {}x{}",
+                                                         i,
+                                                         cov.rows(),
+                                                         cov.cols(),
+                                                         n,
+                                                         n)));
+        }
+        let config = EvalConfig {
+            seed: Some(streams.seed(i as u64)),
+            ..config.clone()
+        };
+        let shifted = shifts.map(|s| delta + &s.row(i));
+        mvdist_config_in(cov,
+                         kernel,
+                         lb,
+                         ub,
+                         types,
+                         constraints,
+                         shifted.as_ref().unwrap_or(delta),
+                         &config,
+                         scratch)
+    };
+    Ok(map_draws(covs.len(), eval))
+}
+
+#[cfg(feature = "rayon")]
+fn map_draws<F>(k: usize, eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(usize, &mut Scratch) -> Result<MVResult, MvDistError> + Sync
+{
+    use rayon::prelude::*;
+    (0..k).into_par_iter().map_init(Scratch::default, |scratch, i| eval(i, scratch)).collect()
+}
 
+#[cfg(not(feature = "rayon"))]
+fn map_draws<F>(k: usize, eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(usize, &mut Scratch) -> Result<MVResult, MvDistError>
+{
+    let mut scratch = Scratch::default();
+    (0..k).map(|i| eval(i, &mut scratch)).collect()
+}
+
+/// The point budget of `mvdist_quick`.
+pub const QUICK_MAXPTS: u64 = 5_000;
+
+/// The absolute tolerance of `mvdist_quick`, about two significant digits of a probability that
+/// is not small.
+pub const QUICK_ABSEPS: f64 = 5e-3;
+
+/// The most constraints for which `mvdist_quick` keeps the given order rather than reordering.
+pub const QUICK_REORDER_LIMIT: usize = 4;
+
+/// A fast, low-accuracy evaluation of `problem` for screening, with `QUICK_MAXPTS` points and an
+/// absolute tolerance of `QUICK_ABSEPS` in place of the problem's settings, using only its seed.
+/// Problems that are not solved exactly are integrated natively, without reordering up to
+/// `QUICK_REORDER_LIMIT` constraints, where it costs more than it saves.
+///
+/// With so few points the standard error is itself estimated from the few lattice shifts of a
+/// small rule, so `error` is widened to the same confidence as `ERROR_SCALE` standard errors under
+/// the Student t distribution with one degree of freedom fewer than the number of shifts, and the
+/// state is `MVInform::PtLimitReached` whenever the widened error exceeds `QUICK_ABSEPS`.
+pub fn mvdist_quick(problem: &MvProblem) -> Result<MVResult, MvDistError> {
+    let nu = problem.kernel.nu()?;
+    if let Some(result) = exact::try_solve(&problem.cov,
+                                           nu,
+                                           &problem.lb,
+                                           &problem.ub,
+                                           &problem.types,
+                                           &problem.constraints,
+                                           &problem.delta,
+                                           QUICK_ABSEPS,
+                                           0.0) {
+        return Ok(result);
+    }
+    let options = native::Options {
+        seed: problem.config.seed.unwrap_or(native::DEFAULT_SEED),
+        reorder: if problem.constraints.rows() <= QUICK_REORDER_LIMIT {
+            Reorder::None
+        } else {
+            Reorder::Auto
+        },
+        ..native::Options::default()
+    };
+    let mut result = native::mvdist_estimate(&problem.cov,
+                                             nu,
+                                             &problem.lb,
+                                             &problem.ub,
+                                             &problem.types,
+                                             &problem.constraints,
+                                             &problem.delta,
+                                             QUICK_MAXPTS,
+                                             QUICK_ABSEPS,
+                                             0.0,
+                                             &options)?
+        .result;
+    let dof = (options.qmc.randomizations.max(2) - 1) as f64;
+    let coverage = univariate::norm_cdf(ERROR_SCALE);
+    result.error *= univariate::t_inv(dof, coverage) / ERROR_SCALE;
+    if result.error > QUICK_ABSEPS && result.state == MVInform::Normal {
+        result.state = MVInform::PtLimitReached;
+    }
+    Ok(result)
+}
+
+/// The integrators behind `mvdist`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Backend {
+    Fortran,
+    Native,
+    /// The deterministic integration of `sparse_grid`, used only when chosen through
+    /// `EvalConfig::backend`, for problems with at most `sparse_grid::MAX_DIMENSION` integration
+    /// variables.
+    SparseGrid,
+}
+
+impl Backend {
+    /// The backend `mvdist` prefers in this build: `Backend::Fortran` when the crate was built with
+    /// the `fortran` feature, and `Backend::Native` otherwise. Problems the Fortran code does not
+    /// accept are integrated natively either way (see `backend`), and `EvalConfig::backend`
+    /// forces a choice for a single call.
+    pub fn current() -> Backend {
+        if cfg!(feature = "fortran") {
+            Backend::Fortran
+        } else {
+            Backend::Native
+        }
+    }
+
+    /// Whether this backend was built, so that `EvalConfig::backend` may choose it.
+    pub fn is_available(self) -> bool {
+        self != Backend::Fortran || cfg!(feature = "fortran")
+    }
+}
+
+/// The backend `mvdist_kernel` integrates with for `m` constraints on `n` variables with
+/// distribution `kernel`, when the problem is not solved exactly: the Fortran code when the
+/// `fortran` feature is enabled, there are at most `FORTRAN_MAX_DIMENSION` variables and
+/// constraints, and the degrees of freedom are an integer, and the native backend otherwise. The
+/// native backend has no limit on the dimension; its setup takes `O(m²)` memory and `O(m³)` time,
+/// and each integrand evaluation `O(m²)` time.
+pub fn backend(n: usize, m: usize, kernel: Kernel) -> Backend {
+    let nu = match kernel {
+        Kernel::Normal => 0.0,
+        Kernel::StudentT { nu } if nu.is_infinite() => 0.0,
+        Kernel::StudentT { nu } => nu,
+    };
+    if fortran_accepts(n, m, nu) {
+        Backend::Fortran
+    } else {
+        Backend::Native
+    }
+}
+
+#[cfg(feature = "fortran")]
+fn fortran_accepts(n: usize, m: usize, nu: f64) -> bool {
+    n <= fortran::MAX_DIMENSION && m <= fortran::MAX_DIMENSION && nu.fract() == 0.0 &&
+    nu <= ::std::i32::MAX as f64
+}
+
+#[cfg(not(feature = "fortran"))]
+fn fortran_accepts(_: usize, _: usize, _: f64) -> bool {
+    false
+}
+
+/// The Fortran backend, which must accept the problem.
+#[cfg(feature = "fortran")]
+fn fortran_mvdist(cov: &Array2<f64>,
+                  nu: f64,
+                  lb: &Array1<f64>,
+                  ub: &Array1<f64>,
+                  types: &[BoundType],
+                  constraints: &Array2<f64>,
+                  delta: &Array1<f64>,
+                  maxpts: u64,
+                  abseps: f64,
+                  releps: f64,
+                  scratch: &mut Scratch)
+                  -> Result<MVResult, MvDistError> {
+    if !fortran_accepts(constraints.cols(), constraints.rows(), nu) {
+        return Err(MvDistError::InvalidInput(format!("The Fortran backend does not accept {} \
+                                                      constraints on {} variables with {} \
+                                                      degrees of freedom",
+                                                     constraints.rows(),
+                                                     constraints.cols(),
+                                                     nu)));
+    }
+    fortran::mvdist_in(cov,
+                       nu as i32,
+                       lb,
+                       ub,
+                       types,
+                       constraints,
+                       delta,
+                       maxpts,
+                       abseps,
+                       releps,
+                       scratch)
+}
+
+#[cfg(not(feature = "fortran"))]
+fn fortran_mvdist(_: &Array2<f64>,
+                  _: f64,
+                  _: &Array1<f64>,
+                  _: &Array1<f64>,
+                  _: &[BoundType],
+                  _: &Array2<f64>,
+                  _: &Array1<f64>,
+                  _: u64,
+                  _: f64,
+                  _: f64,
+                  _: &mut Scratch)
+                  -> Result<MVResult, MvDistError> {
+    Err(MvDistError::InvalidInput("The Fortran backend requires the `fortran` feature".to_string()))
+}
+
+#[cfg(feature = "fortran")]
+fn backend_mvdist(cov: &Array2<f64>,
+                  nu: f64,
+                  lb: &Array1<f64>,
+                  ub: &Array1<f64>,
+                  types: &[BoundType],
+                  constraints: &Array2<f64>,
+                  delta: &Array1<f64>,
+                  maxpts: u64,
+                  abseps: f64,
+                  releps: f64)
+                  -> Result<MVResult, MvDistError> {
+    if backend(constraints.cols(), constraints.rows(), Kernel::from_nu(nu)) == Backend::Native {
+        return native::mvdist(cov,
+                              nu,
+                              lb,
+                              ub,
+                              types,
+                              constraints,
+                              delta,
+                              maxpts,
+                              abseps,
+                              releps,
+                              native::DEFAULT_SEED);
+    }
+    fortran::mvdist(cov,
+                    nu as i32,
+                    lb,
+                    ub,
+                    types,
+                    constraints,
+                    delta,
+                    maxpts,
+                    abseps,
+                    releps)
+}
+
+#[cfg(not(feature = "fortran"))]
+fn backend_mvdist(cov: &Array2<f64>,
+                  nu: f64,
+                  lb: &Array1<f64>,
+                  ub: &Array1<f64>,
+                  types: &[BoundType],
+                  constraints: &Array2<f64>,
+                  delta: &Array1<f64>,
+                  maxpts: u64,
+                  abseps: f64,
+                  releps: f64)
+                  -> Result<MVResult, MvDistError> {
+    native::mvdist(cov,
+                   nu,
+                   lb,
+                   ub,
+                   types,
+                   constraints,
+                   delta,
+                   maxpts,
+                   abseps,
+                   releps,
+                   native::DEFAULT_SEED)
 }
 
 #[cfg(test)]
@@ -176,6 +878,143 @@ mod tests {
         assert!(result.error <= 1e-5);
     }
 
+    #[test]
+    fn many_covariances_with_shared_bounds() {
+        let (lb, ub) = (Array::from_elem((3,), -1.0), Array::from_elem((3,), 1.5));
+        let types = vec![BoundType::Both; 3];
+        let (constraints, delta) = (Array::eye(3), Array::zeros((3,)));
+        let config = EvalConfig {
+            backend: Some(Backend::Native),
+            seed: Some(7),
+            ..EvalConfig::default()
+        };
+        let mut covs = [0.0, 0.3, 0.6]
+            .iter()
+            .map(|&rho| corr::exchangeable(3, rho))
+            .collect::<Vec<_>>();
+        covs.push(Array::eye(2));
+        let cancel = CancellationToken::new();
+        let many = |delta: &Array1<f64>| {
+            mvdist_many_covs(&covs,
+                             Kernel::Normal,
+                             &lb,
+                             &ub,
+                             &types,
+                             &constraints,
+                             delta,
+                             &config,
+                             &cancel)
+        };
+        let results = many(&delta).unwrap();
+        assert_eq!(results.len(), 4);
+        let streams = native::SeedStreams::new(7);
+        for i in 0..3 {
+            let seeded = EvalConfig { seed: Some(streams.seed(i as u64)), ..config.clone() };
+            assert_eq!(results[i],
+                       mvdist_config(&covs[i],
+                                     Kernel::Normal,
+                                     &lb,
+                                     &ub,
+                                     &types,
+                                     &constraints,
+                                     &delta,
+                                     &seeded));
+        }
+        // positive correlation raises the probability of the rectangle
+        assert!(results[2].as_ref().unwrap().value > results[0].as_ref().unwrap().value);
+        assert!(results[3].is_err());
+        assert!(many(&Array::zeros((2,))).is_err());
+        cancel.cancel();
+        assert!(many(&delta).unwrap().iter().all(|r| *r == Err(MvDistError::Cancelled)));
+    }
+
+    #[test]
+    fn non_integer_degrees_of_freedom() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let eval = |kernel: Kernel| {
+            mvdist_kernel(&cov,
+                          kernel,
+                          &Array1::from_vec(vec![-1.0; 3]),
+                          &Array1::from_vec(vec![1.5; 3]),
+                          &vec![BoundType::Both; 3],
+                          &Array::eye(3),
+                          &Array::zeros((3,)),
+                          1_000_000,
+                          1e-6,
+                          0.0)
+        };
+        let p = |nu: f64| eval(Kernel::StudentT { nu: nu }).unwrap().value;
+        // heavier tails put less mass in the box
+        let (p4, p45, p5) = (p(4.0), p(4.5), p(5.0));
+        assert!(p4 < p45 && p45 < p5);
+        assert_eq!(p(::std::f64::INFINITY), eval(Kernel::Normal).unwrap().value);
+        assert!(eval(Kernel::StudentT { nu: ::std::f64::NAN }).is_err());
+        assert!(eval(Kernel::StudentT { nu: -1.0 }).is_err());
+        assert_eq!(Kernel::from_nu(0.0), Kernel::Normal);
+        assert_eq!(Kernel::from_nu(2.5), Kernel::StudentT { nu: 2.5 });
+    }
+
+    #[test]
+    fn backend_routing() {
+        let fortran = Backend::current();
+        assert!(fortran.is_available());
+        assert_eq!(Backend::Fortran.is_available(), cfg!(feature = "fortran"));
+        assert_eq!(backend(10, 12, Kernel::Normal), fortran);
+        assert_eq!(backend(10, 12, Kernel::StudentT { nu: 4.0 }), fortran);
+        assert_eq!(backend(10, 12, Kernel::StudentT { nu: 4.5 }), Backend::Native);
+        assert_eq!(backend(1001, 1001, Kernel::Normal), Backend::Native);
+        assert_eq!(backend(10, 1001, Kernel::Normal), Backend::Native);
+    }
+
+    #[test]
+    fn config_selects_backend() {
+        let cov = Array::eye(2);
+        let (lb, ub) = (Array::from_elem((2,), -1.0), Array::from_elem((2,), 1.0));
+        let types = [BoundType::Both; 2];
+        let eval = |config: &EvalConfig| {
+            mvdist_config(&cov,
+                          Kernel::Normal,
+                          &lb,
+                          &ub,
+                          &types,
+                          &Array::eye(2),
+                          &Array::zeros((2,)),
+                          config)
+        };
+        let exact = eval(&EvalConfig::default()).unwrap();
+        assert_eq!(exact.nevals, 0);
+        let native = EvalConfig {
+            backend: Some(Backend::Native),
+            ..EvalConfig::default()
+        };
+        let forced = eval(&native).unwrap();
+        assert!(forced.nevals > 0 && (forced.value - exact.value).abs() < 1e-5);
+        let unordered = EvalConfig {
+            reorder: Reorder::None,
+            ..EvalConfig::default()
+        };
+        assert!(eval(&unordered).unwrap().nevals > 0);
+        let fortran = EvalConfig {
+            backend: Some(Backend::Fortran),
+            ..unordered
+        };
+        assert!(eval(&fortran).is_err());
+    }
+
+    #[test]
+    fn confidence_interval_from_error() {
+        let result = MVResult {
+            value: 0.5,
+            error: 0.035,
+            nevals: 1000,
+            state: MVInform::Normal,
+        };
+        assert!((result.standard_error() - 0.01).abs() < 1e-15);
+        let (lo, hi) = result.confidence_interval(0.95);
+        assert!((lo - (0.5 - 0.0195996)).abs() < 1e-6 && (hi - (0.5 + 0.0195996)).abs() < 1e-6);
+        assert_eq!(MVResult { value: 0.99, ..result }.confidence_interval(0.99).1, 1.0);
+    }
+
     #[test]
     fn mvdist_works2() {
         let a: Array2<f64> = arr2(&[[90.0, 60.0, 90.0],
@@ -200,4 +1039,63 @@ mod tests {
                0.0)
             .unwrap();
     }
+
+    #[test]
+    fn combines_by_inverse_variance() {
+        let estimate = |value: f64, error: f64, state: MVInform| {
+            MVResult {
+                value: value,
+                error: error,
+                nevals: 1000,
+                state: state,
+            }
+        };
+        // the second estimate has twice the standard error, so a quarter of the weight
+        let c = MVResult::combine(&[estimate(0.5, 1e-3, MVInform::PtLimitReached),
+                                    estimate(0.5005, 2e-3, MVInform::Normal)])
+            .unwrap();
+        assert!((c.result.value - 0.5001).abs() < 1e-12);
+        assert!((c.result.error - 2e-3 / 5f64.sqrt()).abs() < 1e-15);
+        assert_eq!((c.result.nevals, c.result.state), (2000, MVInform::Normal));
+        assert!(c.consistent);
+        let c = MVResult::combine(&[estimate(0.5, 1e-3, MVInform::PtLimitReached),
+                                    estimate(0.25, 0.0, MVInform::PtLimitReached)])
+            .unwrap();
+        assert_eq!((c.result.value, c.result.error), (0.25, 0.0));
+        assert_eq!(c.result.state, MVInform::PtLimitReached);
+        assert!(!c.consistent);
+        assert!(MVResult::combine(&[]).is_err());
+        assert!(MVResult::combine(&[estimate(0.5, ::std::f64::NAN, MVInform::Normal)]).is_err());
+    }
+
+    #[test]
+    fn quick_estimates_are_honest() {
+        // AR(1) correlations, which no exact method handles
+        let mut cov = Array::zeros((5, 5));
+        for i in 0..5 {
+            for j in 0..5 {
+                cov[[i, j]] = 0.5f64.powi((i as i32 - j as i32).abs());
+            }
+        }
+        // the first m variables, so that the constraints are reordered only for m = 5
+        for &m in &[2, 5] {
+            let mut constraints = Array::zeros((m, 5));
+            for i in 0..m {
+                constraints[[i, i]] = 1.0;
+            }
+            let mut problem = MvProblem::new(cov.clone(),
+                                             Array::from_elem((m,), -1.0),
+                                             Array::from_elem((m,), 1.5),
+                                             vec![BoundType::Both; m]);
+            problem.constraints = constraints;
+            problem.delta = Array::zeros((m,));
+            problem.config.maxpts = 1_000_000;
+            problem.config.tolerance = Tolerance::absolute(1e-6).unwrap();
+            let precise = problem.mvdist().unwrap();
+            let quick = mvdist_quick(&problem).unwrap();
+            assert!(quick.nevals <= QUICK_MAXPTS);
+            assert!(quick.error > 0.0);
+            assert!((quick.value - precise.value).abs() <= quick.error);
+        }
+    }
 }
@@ -0,0 +1,88 @@
+//! Small dense linear-algebra helpers used internally. The matrices involved are at most a few
+//! hundred rows, so plain loops are adequate and avoid pulling in a LAPACK dependency.
+
+use ndarray::prelude::*;
+
+/// Lower-triangular Cholesky factor `L` with `a = L Lᵀ`. Returns `None` if `a` is not (numerically)
+/// positive definite.
+pub fn cholesky(a: &Array2<f64>) -> Option<Array2<f64>> {
+    let n = a.rows();
+    let mut l = Array2::zeros((n, n));
+    for j in 0..n {
+        let mut d = a[[j, j]];
+        for k in 0..j {
+            d -= l[[j, k]] * l[[j, k]];
+        }
+        if d <= 0.0 {
+            return None;
+        }
+        let d = d.sqrt();
+        l[[j, j]] = d;
+        for i in (j + 1)..n {
+            let mut s = a[[i, j]];
+            for k in 0..j {
+                s -= l[[i, k]] * l[[j, k]];
+            }
+            l[[i, j]] = s / d;
+        }
+    }
+    Some(l)
+}
+
+/// Solve `L x = b` for lower-triangular `L`.
+pub fn solve_lower(l: &Array2<f64>, b: &[f64]) -> Vec<f64> {
+    let n = l.rows();
+    let mut x = vec![0.0; n];
+    for i in 0..n {
+        let s = (0..i).map(|j| l[[i, j]] * x[j]).sum::<f64>();
+        x[i] = (b[i] - s) / l[[i, i]];
+    }
+    x
+}
+
+/// `ln |A|` given the Cholesky factor `L` of `A`.
+pub fn ln_det_cholesky(l: &Array2<f64>) -> f64 {
+    2.0 * (0..l.rows()).map(|i| l[[i, i]].ln()).sum::<f64>()
+}
+
+/// Solve `Lᵀ x = b` for lower-triangular `L`.
+pub fn solve_lower_transpose(l: &Array2<f64>, b: &[f64]) -> Vec<f64> {
+    let n = l.rows();
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let s = ((i + 1)..n).map(|j| l[[j, i]] * x[j]).sum::<f64>();
+        x[i] = (b[i] - s) / l[[i, i]];
+    }
+    x
+}
+
+/// Replace the Cholesky factor `L` of `A` by that of `A + w wᵀ`, in `O(n²)` operations. `w` is
+/// overwritten.
+pub fn cholesky_update(l: &mut Array2<f64>, w: &mut [f64]) {
+    rotate(l, w, 1.0);
+}
+
+/// Replace the Cholesky factor `L` of `A` by that of `A - w wᵀ`, in `O(n²)` operations. Returns
+/// `false`, leaving `l` and `w` partly modified, if `A - w wᵀ` is not positive definite.
+pub fn cholesky_downdate(l: &mut Array2<f64>, w: &mut [f64]) -> bool {
+    rotate(l, w, -1.0)
+}
+
+/// The rotations of `cholesky_update` (`sign = 1`) and `cholesky_downdate` (`sign = -1`).
+fn rotate(l: &mut Array2<f64>, w: &mut [f64], sign: f64) -> bool {
+    let n = l.rows();
+    for k in 0..n {
+        let d = l[[k, k]] * l[[k, k]] + sign * w[k] * w[k];
+        if d <= 0.0 {
+            return false;
+        }
+        let r = d.sqrt();
+        let (c, s) = (r / l[[k, k]], w[k] / l[[k, k]]);
+        l[[k, k]] = r;
+        for i in (k + 1)..n {
+            l[[i, k]] = (l[[i, k]] + sign * s * w[i]) / c;
+            w[i] = c * w[i] - s * l[[i, k]];
+        }
+    }
+    true
+}
@@ -0,0 +1,173 @@
+//! Rectangle probabilities of finite mixtures of multivariate normal and t distributions. The
+//! probability of the mixture is the weighted sum of those of its components, each evaluated with
+//! `mvdist_kernel`; with the `rayon` feature the components are evaluated in parallel.
+//!
+//! The error of the mixture is the weighted sum of the component errors, so the components share
+//! `abseps` as `abseps_i = abseps / (sqrt(w_i) Σ_j sqrt(w_j))`. This makes `Σ w_i abseps_i` equal
+//! to `abseps`, and minimizes the total cost when the points needed by a component are
+//! proportional to `1 / abseps_i`, as for lattice rules: components with small weights are given
+//! looser tolerances rather than an equal share.
+
+use ndarray::prelude::*;
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+/// One component of a mixture: `X = mean + Y` with probability `weight`, where `Y` has scatter
+/// matrix `cov` and distribution `kernel`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Component {
+    pub weight: f64,
+    pub mean: Array1<f64>,
+    pub cov: Array2<f64>,
+    pub kernel: Kernel,
+}
+
+/// The tolerance of each component with normalized weight `w`, given the sum of the square roots
+/// of the weights.
+fn share(abseps: f64, w: f64, root_sum: f64) -> f64 {
+    abseps / (w.sqrt() * root_sum)
+}
+
+/// `P(lower < X < upper)` for `X` distributed as the mixture of `components`, with infinite limits
+/// leaving a side unbounded. The weights are normalized to sum to one. Each component is evaluated
+/// with the given `maxpts` and its share of `abseps`, and components with zero weight are skipped.
+/// `error` is the weighted sum of the component errors, `nevals` the total over the components,
+/// and `state` is `MVInform::Normal` only if it is for every component.
+pub fn probability(components: &[Component],
+                   lower: &Array1<f64>,
+                   upper: &Array1<f64>,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+    let n = lower.len();
+    if components.is_empty() {
+        return Err(MvDistError::InvalidInput("No mixture components given".to_string()));
+    }
+    if upper.len() != n {
+        return Err(MvDistError::InvalidInput(format!("{} lower and {} upper limits given",
+                                                     n,
+                                                     upper.len())));
+    }
+    for (i, c) in components.iter().enumerate() {
+        if !(c.weight >= 0.0 && c.weight.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Component {} has weight {}",
+                                                         i,
+                                                         c.weight)));
+        }
+        if c.mean.len() != n || c.cov.rows() != n || c.cov.cols() != n {
+            return Err(MvDistError::InvalidInput(format!("Component {} has mean of length {} \
+                                                          and {}x{} covariance, expected {} \
+                                                          variables",
+                                                         i,
+                                                         c.mean.len(),
+                                                         c.cov.rows(),
+                                                         c.cov.cols(),
+                                                         n)));
+        }
+    }
+    let total = components.iter().map(|c| c.weight).sum::<f64>();
+    if !(total > 0.0) {
+        return Err(MvDistError::InvalidInput("The weights sum to zero".to_string()));
+    }
+    let root_sum = components.iter().map(|c| (c.weight / total).sqrt()).sum::<f64>();
+    let active = components.iter().filter(|c| c.weight > 0.0).collect::<Vec<&Component>>();
+    let results = evaluate_components(&active, |c| {
+        let (a, b) = (lower - &c.mean, upper - &c.mean);
+        let types = (0..n).map(|i| BoundType::from_limits(a[i], b[i])).collect::<Vec<BoundType>>();
+        ::mvdist_kernel(&c.cov,
+                        c.kernel,
+                        &a,
+                        &b,
+                        &types,
+                        &Array::eye(n),
+                        &Array::zeros((n,)),
+                        maxpts,
+                        share(abseps, c.weight / total, root_sum),
+                        0.0)
+    });
+    let mut mixture = MVResult {
+        value: 0.0,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    for (c, result) in active.iter().zip(results) {
+        let result = result?;
+        let w = c.weight / total;
+        mixture.value += w * result.value;
+        mixture.error += w * result.error;
+        mixture.nevals += result.nevals;
+        if result.state != MVInform::Normal {
+            mixture.state = result.state;
+        }
+    }
+    mixture.value = mixture.value.max(0.0).min(1.0);
+    Ok(mixture)
+}
+
+#[cfg(feature = "rayon")]
+fn evaluate_components<F>(components: &[&Component],
+                          eval: F)
+                          -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(&Component) -> Result<MVResult, MvDistError> + Sync
+{
+    use rayon::prelude::*;
+    components.par_iter().map(|&c| eval(c)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn evaluate_components<F>(components: &[&Component],
+                          eval: F)
+                          -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(&Component) -> Result<MVResult, MvDistError>
+{
+    components.iter().map(|&c| eval(c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use univariate::{norm_cdf, t_cdf};
+
+    fn component(weight: f64, mean: f64, var: f64, kernel: Kernel) -> Component {
+        Component {
+            weight: weight,
+            mean: Array::from_elem((2,), mean),
+            cov: &Array2::<f64>::eye(2) * var,
+            kernel: kernel,
+        }
+    }
+
+    #[test]
+    fn weighted_sum_of_components() {
+        let t = Kernel::StudentT { nu: 5.0 };
+        // unnormalized weights 0.3 and 0.7
+        let components = [component(3.0, 0.0, 1.0, Kernel::Normal), component(7.0, 2.0, 4.0, t)];
+        let (lower, upper) = (Array::from_elem((2,), -1.0), Array::from_elem((2,), 1.0));
+        let p = probability(&components, &lower, &upper, 1_000_000, 1e-6).unwrap();
+        let single = |c: &Component| probability(&[c.clone()], &lower, &upper, 1_000_000, 1e-7);
+        let second = single(&components[1]).unwrap();
+        let first = (norm_cdf(1.0) - norm_cdf(-1.0)).powi(2);
+        assert!((p.value - (0.3 * first + 0.7 * second.value)).abs() < 2e-6);
+        assert!(p.error <= 1e-6);
+        // the coordinates of the t component are dependent, so only bounded by the marginal
+        assert!(second.value < t_cdf(5.0, -0.5) - t_cdf(5.0, -1.5));
+        assert_eq!(p.state, MVInform::Normal);
+    }
+
+    #[test]
+    fn shares_sum_to_the_tolerance() {
+        let w = [0.01, 0.09, 0.9];
+        let root_sum = w.iter().map(|w| w.sqrt()).sum::<f64>();
+        let total = w.iter().map(|&w| w * share(1e-4, w, root_sum)).sum::<f64>();
+        assert!((total - 1e-4).abs() < 1e-18);
+        assert!(share(1e-4, 0.01, root_sum) > share(1e-4, 0.9, root_sum));
+        let (lower, upper) = (Array::zeros((2,)), Array::from_elem((2,), 1.0));
+        let zero = [component(0.0, 0.0, 1.0, Kernel::Normal)];
+        assert!(probability(&zero, &lower, &upper, 1000, 1e-3).is_err());
+        assert!(probability(&[], &lower, &upper, 1000, 1e-3).is_err());
+        let wrong = [component(1.0, 0.0, 1.0, Kernel::Normal)];
+        assert!(probability(&wrong, &Array::zeros((3,)), &upper, 1000, 1e-3).is_err());
+    }
+}
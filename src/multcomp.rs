@@ -0,0 +1,391 @@
+//! Multiple-comparison procedures based on the joint distribution of the test statistics, in the
+//! style of the R package `multcomp`. The statistics are `estimates[i] / sqrt(cov[i, i])`; under
+//! the null hypotheses their distribution is given by a `Kernel`, normal for known variances or
+//! multivariate t with the degrees of freedom of the variance estimate, and their correlation
+//! matrix is that of `cov`. The degrees of freedom need not be an integer, so Satterthwaite
+//! approximations can be used directly. `contrasts` builds the usual comparison matrices `C`, for
+//! which the estimates and their covariance are `C mu` and `C cov(mu) Cᵀ`.
+
+use ndarray::prelude::*;
+use {BoundType, Kernel, MvDistError};
+
+/// The alternative hypotheses tested.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Alternative {
+    /// `theta != 0`, rejecting for large `|t|`.
+    TwoSided,
+    /// `theta > 0`, rejecting for large `t`.
+    Greater,
+    /// `theta < 0`, rejecting for small `t`.
+    Less,
+}
+
+/// Adjusted p-values for a family of hypotheses.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Adjusted {
+    /// The standardized statistics `estimates[i] / sqrt(cov[i, i])`.
+    pub statistics: Vec<f64>,
+    /// The adjusted p-value of each hypothesis.
+    pub p_values: Vec<f64>,
+    /// The integration error of each p-value, in the sense of `MVResult::error`.
+    pub errors: Vec<f64>,
+}
+
+/// Simultaneous confidence intervals for a family of contrasts.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Intervals {
+    /// The contrast estimates `C mu`.
+    pub estimates: Vec<f64>,
+    /// Lower limits, infinite for `Alternative::Less`.
+    pub lower: Vec<f64>,
+    /// Upper limits, infinite for `Alternative::Greater`.
+    pub upper: Vec<f64>,
+    /// The equicoordinate critical value.
+    pub critical: f64,
+}
+
+/// The standardized statistics and their correlation matrix.
+fn statistics(estimates: &Array1<f64>,
+              cov: &Array2<f64>)
+              -> Result<(Vec<f64>, Array2<f64>), MvDistError> {
+    let k = estimates.len();
+    if k == 0 {
+        return Err(MvDistError::InvalidInput("No hypotheses given".to_string()));
+    }
+    if cov.rows() != k || cov.cols() != k {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected {}x{}",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     k,
+                                                     k)));
+    }
+    if estimates.iter().any(|e| !e.is_finite()) {
+        return Err(MvDistError::InvalidInput("Estimates must be finite".to_string()));
+    }
+    let sd = (0..k).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0)) {
+        return Err(MvDistError::InvalidInput("Variances must be positive".to_string()));
+    }
+    let mut corr = Array2::zeros((k, k));
+    for i in 0..k {
+        for j in 0..k {
+            corr[[i, j]] = cov[[i, j]] / (sd[i] * sd[j]);
+        }
+    }
+    Ok(((0..k).map(|i| estimates[i] / sd[i]).collect(), corr))
+}
+
+/// The probability, with its error, that the statistics in `subset` are at least as extreme as
+/// `t` under `alternative`: for `TwoSided`, `P(max |T_j| >= |t|)`.
+fn max_tail(corr: &Array2<f64>,
+            subset: &[usize],
+            t: f64,
+            kernel: Kernel,
+            alternative: Alternative,
+            maxpts: u64,
+            abseps: f64)
+            -> Result<(f64, f64), MvDistError> {
+    let k = subset.len();
+    let mut r = Array2::zeros((k, k));
+    for (a, &i) in subset.iter().enumerate() {
+        for (b, &j) in subset.iter().enumerate() {
+            r[[a, b]] = corr[[i, j]];
+        }
+    }
+    let (lower, upper, bound) = match alternative {
+        Alternative::TwoSided => (-t.abs(), t.abs(), BoundType::Both),
+        Alternative::Greater => (t, t, BoundType::Above),
+        Alternative::Less => (t, t, BoundType::Below),
+    };
+    let result = ::mvdist_kernel(&r,
+                                 kernel,
+                                 &Array::from_elem((k,), lower),
+                                 &Array::from_elem((k,), upper),
+                                 &vec![bound; k],
+                                 &Array::eye(k),
+                                 &Array::zeros((k,)),
+                                 maxpts,
+                                 abseps,
+                                 0.0)?;
+    Ok(((1.0 - result.value).max(0.0).min(1.0), result.error))
+}
+
+/// The equicoordinate critical value `c` for which the statistics with correlation matrix `corr`
+/// all lie within `c` (`|T_j| <= c` for `TwoSided`, `T_j <= c` for `Greater` and `T_j >= -c` for
+/// `Less`) with probability `level`. The root is found by the Illinois method on `mvdist`
+/// evaluations, stopping once the coverage is within `abseps` of `level`.
+pub fn critical_value(corr: &Array2<f64>,
+                      kernel: Kernel,
+                      alternative: Alternative,
+                      level: f64,
+                      maxpts: u64,
+                      abseps: f64)
+                      -> Result<f64, MvDistError> {
+    if !(level > 0.0 && level < 1.0) {
+        return Err(MvDistError::InvalidInput(format!("Level {} is not in (0, 1)", level)));
+    }
+    let all = (0..corr.rows()).collect::<Vec<usize>>();
+    // coverage minus level, increasing in c
+    let excess = |c: f64| -> Result<f64, MvDistError> {
+        let t = if alternative == Alternative::Less { -c } else { c };
+        let (tail, _) = max_tail(corr, &all, t, kernel, alternative, maxpts, abseps)?;
+        Ok(1.0 - tail - level)
+    };
+    let (mut lo, mut hi) = (0.0, 1.0);
+    let (mut f_lo, mut f_hi) = (excess(lo)?, excess(hi)?);
+    while f_hi < 0.0 {
+        lo = hi;
+        f_lo = f_hi;
+        hi *= 2.0;
+        f_hi = excess(hi)?;
+    }
+    while f_lo > 0.0 {
+        hi = lo;
+        f_hi = f_lo;
+        lo -= 1.0;
+        f_lo = excess(lo)?;
+    }
+    let mut side = 0;
+    for _ in 0..100 {
+        let c = if f_hi > f_lo {
+            hi - f_hi * (hi - lo) / (f_hi - f_lo)
+        } else {
+            0.5 * (lo + hi)
+        };
+        let f = excess(c)?;
+        if f.abs() <= abseps || hi - lo <= 1e-10 * hi.abs().max(1.0) {
+            return Ok(c);
+        }
+        // the Illinois modification halves the value kept at the same end twice in a row
+        if f < 0.0 {
+            lo = c;
+            f_lo = f;
+            if side == -1 {
+                f_hi *= 0.5;
+            }
+            side = -1;
+        } else {
+            hi = c;
+            f_hi = f;
+            if side == 1 {
+                f_lo *= 0.5;
+            }
+            side = 1;
+        }
+    }
+    Ok(0.5 * (lo + hi))
+}
+
+/// Simultaneous confidence intervals at `level` for the contrasts `contrasts * mu`, given
+/// estimates `means` of `mu` with covariance `cov` and distribution `kernel` (`Kernel::Normal`
+/// for known variances). Two-sided intervals are `estimate ± c se`; for `Greater` only the lower
+/// limit is finite and for `Less` only the upper one.
+pub fn simultaneous_intervals(means: &Array1<f64>,
+                              cov: &Array2<f64>,
+                              kernel: Kernel,
+                              contrasts: &Array2<f64>,
+                              alternative: Alternative,
+                              level: f64,
+                              maxpts: u64,
+                              abseps: f64)
+                              -> Result<Intervals, MvDistError> {
+    let g = means.len();
+    if contrasts.cols() != g || cov.rows() != g || cov.cols() != g {
+        return Err(MvDistError::InvalidInput(format!("Contrasts ({}x{}) and covariance ({}x{}) do \
+                                                      not match {} means",
+                                                     contrasts.rows(),
+                                                     contrasts.cols(),
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     g)));
+    }
+    let estimates = contrasts.dot(means);
+    let contrast_cov = contrasts.dot(cov).dot(&contrasts.t());
+    let (_, corr) = statistics(&estimates, &contrast_cov)?;
+    let critical = critical_value(&corr, kernel, alternative, level, maxpts, abseps)?;
+    let k = estimates.len();
+    let se = (0..k).map(|i| contrast_cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    let lower = (0..k)
+        .map(|i| match alternative {
+            Alternative::Less => ::std::f64::NEG_INFINITY,
+            _ => estimates[i] - critical * se[i],
+        })
+        .collect();
+    let upper = (0..k)
+        .map(|i| match alternative {
+            Alternative::Greater => ::std::f64::INFINITY,
+            _ => estimates[i] + critical * se[i],
+        })
+        .collect();
+    Ok(Intervals {
+        estimates: estimates.iter().cloned().collect(),
+        lower: lower,
+        upper: upper,
+        critical: critical,
+    })
+}
+
+/// Single-step max-t adjusted p-values: the p-value of hypothesis `i` is the probability that the
+/// most extreme of all the statistics is at least as extreme as `t_i`. These control the
+/// familywise error rate in the strong sense. `maxpts` and `abseps` are passed to `mvdist` for each
+/// hypothesis.
+pub fn single_step(estimates: &Array1<f64>,
+                   cov: &Array2<f64>,
+                   kernel: Kernel,
+                   alternative: Alternative,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<Adjusted, MvDistError> {
+    let (t, corr) = statistics(estimates, cov)?;
+    let all = (0..t.len()).collect::<Vec<usize>>();
+    let mut p_values = Vec::with_capacity(t.len());
+    let mut errors = Vec::with_capacity(t.len());
+    for &ti in &t {
+        let (p, error) = max_tail(&corr, &all, ti, kernel, alternative, maxpts, abseps)?;
+        p_values.push(p);
+        errors.push(error);
+    }
+    Ok(Adjusted {
+        statistics: t,
+        p_values: p_values,
+        errors: errors,
+    })
+}
+
+/// Step-down max-t adjusted p-values under free combinations (Westfall & Young): the hypotheses
+/// are tested from the most extreme statistic down, comparing each only against those not yet
+/// rejected, and the p-values are made monotone in that order. These are never larger than the
+/// single-step p-values and also control the familywise error rate in the strong sense. The
+/// procedure does not exploit logical restrictions among the hypotheses, which makes it
+/// conservative for all-pairs comparisons of more than three groups.
+pub fn step_down(estimates: &Array1<f64>,
+                 cov: &Array2<f64>,
+                 kernel: Kernel,
+                 alternative: Alternative,
+                 maxpts: u64,
+                 abseps: f64)
+                 -> Result<Adjusted, MvDistError> {
+    let (t, corr) = statistics(estimates, cov)?;
+    let extremeness = |ti: f64| match alternative {
+        Alternative::TwoSided => ti.abs(),
+        Alternative::Greater => ti,
+        Alternative::Less => -ti,
+    };
+    let mut order = (0..t.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| extremeness(t[b]).partial_cmp(&extremeness(t[a])).unwrap());
+    let mut p_values = vec![0.0; t.len()];
+    let mut errors = vec![0.0; t.len()];
+    let mut running = 0.0f64;
+    for (step, &i) in order.iter().enumerate() {
+        let rest = &order[step..];
+        let (p, error) = max_tail(&corr, rest, t[i], kernel, alternative, maxpts, abseps)?;
+        running = running.max(p);
+        p_values[i] = running;
+        errors[i] = error;
+    }
+    Ok(Adjusted {
+        statistics: t,
+        p_values: p_values,
+        errors: errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use univariate::{norm_cdf, t_cdf};
+
+    const NORMAL: Kernel = Kernel::Normal;
+
+    #[test]
+    fn single_step_independent() {
+        let estimates = Array1::from_vec(vec![4.0, -1.0]);
+        let cov = arr2(&[[4.0, 0.0], [0.0, 1.0]]);
+        let two = single_step(&estimates, &cov, NORMAL, Alternative::TwoSided, 100_000, 1e-8);
+        let two = two.unwrap();
+        assert_eq!(two.statistics, vec![2.0, -1.0]);
+        for (&t, &p) in two.statistics.iter().zip(two.p_values.iter()) {
+            let q = 2.0 * norm_cdf(t.abs()) - 1.0;
+            assert!((p - (1.0 - q * q)).abs() < 1e-8);
+        }
+        let greater = single_step(&estimates, &cov, NORMAL, Alternative::Greater, 100_000, 1e-8);
+        assert!((greater.unwrap().p_values[0] - (1.0 - norm_cdf(2.0).powi(2))).abs() < 1e-8);
+        let less = single_step(&estimates, &cov, NORMAL, Alternative::Less, 100_000, 1e-8);
+        assert!((less.unwrap().p_values[1] - (1.0 - norm_cdf(1.0).powi(2))).abs() < 1e-8);
+        assert!(single_step(&estimates, &Array::eye(3), NORMAL, Alternative::TwoSided, 1000, 1e-3)
+            .is_err());
+    }
+
+    #[test]
+    fn single_hypothesis_is_t_test() {
+        let t = Kernel::StudentT { nu: 10.5 };
+        let one = single_step(&Array1::from_vec(vec![2.5]),
+                              &Array::eye(1),
+                              t,
+                              Alternative::TwoSided,
+                              100_000,
+                              1e-8)
+            .unwrap();
+        assert!((one.p_values[0] - 2.0 * (1.0 - t_cdf(10.5, 2.5))).abs() < 1e-6);
+    }
+
+    #[test]
+    fn intervals_from_critical_value() {
+        // two treatments against a control with unit variances: Dunnett contrasts with equal
+        // correlation 1/2, so the critical value is found by the exact equicorrelated method
+        let means = Array1::from_vec(vec![0.0, 1.0, 3.0]);
+        let cov = Array::eye(3);
+        let c = ::contrasts::dunnett(2);
+        let ci = simultaneous_intervals(&means,
+                                        &cov,
+                                        NORMAL,
+                                        &c,
+                                        Alternative::TwoSided,
+                                        0.95,
+                                        100_000,
+                                        1e-7)
+            .unwrap();
+        assert_eq!(ci.estimates, vec![1.0, 3.0]);
+        // the critical value lies between the unadjusted and Bonferroni quantiles
+        assert!(ci.critical > 1.96 && ci.critical < 2.2414);
+        let (_, corr) = statistics(&Array1::from_vec(ci.estimates.clone()),
+                                   &c.dot(&cov).dot(&c.t()))
+            .unwrap();
+        let two = Alternative::TwoSided;
+        let (tail, _) = max_tail(&corr, &[0, 1], ci.critical, NORMAL, two, 0, 1e-7).unwrap();
+        assert!((tail - 0.05).abs() < 1e-6);
+        assert!((ci.upper[1] - (3.0 + ci.critical * 2f64.sqrt())).abs() < 1e-12);
+        let one = simultaneous_intervals(&means,
+                                         &cov,
+                                         NORMAL,
+                                         &c,
+                                         Alternative::Greater,
+                                         0.95,
+                                         100_000,
+                                         1e-7)
+            .unwrap();
+        assert!(one.critical < ci.critical && one.upper[0].is_infinite());
+    }
+
+    #[test]
+    fn step_down_independent() {
+        let estimates = Array1::from_vec(vec![1.0, 3.0, 2.0]);
+        let cov = Array::eye(3);
+        let single = single_step(&estimates, &cov, NORMAL, Alternative::TwoSided, 100_000, 1e-8);
+        let single = single.unwrap();
+        let down = step_down(&estimates, &cov, NORMAL, Alternative::TwoSided, 100_000, 1e-8);
+        let down = down.unwrap();
+        // with independent statistics, step j compares against the k - j + 1 remaining ones
+        let q = |t: f64, k: i32| 1.0 - (2.0 * norm_cdf(t) - 1.0).powi(k);
+        assert!((down.p_values[1] - single.p_values[1]).abs() < 1e-8);
+        assert!((down.p_values[2] - q(2.0, 2)).abs() < 1e-8);
+        assert!((down.p_values[0] - q(1.0, 1)).abs() < 1e-8);
+        for (d, s) in down.p_values.iter().zip(single.p_values.iter()) {
+            assert!(d <= &(s + 1e-12));
+        }
+    }
+}
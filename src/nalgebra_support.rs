@@ -0,0 +1,92 @@
+//! Conversions between `nalgebra` and `ndarray` types, and `mvdist` overloads taking `nalgebra`
+//! arguments directly. Enabled by the `nalgebra` feature.
+//!
+//! The orphan rules prevent `From` implementations between the two crates' types, so the
+//! conversions are plain functions.
+
+use na::{DMatrix, DVector};
+use ndarray::prelude::*;
+use {BoundType, MVResult, MvCritResult, MvDistError};
+
+pub fn matrix_to_array(m: &DMatrix<f64>) -> Array2<f64> {
+    let mut a = Array2::zeros((m.nrows(), m.ncols()));
+    for i in 0..m.nrows() {
+        for j in 0..m.ncols() {
+            a[[i, j]] = m[(i, j)];
+        }
+    }
+    a
+}
+
+pub fn vector_to_array(v: &DVector<f64>) -> Array1<f64> {
+    Array1::from_vec(v.iter().cloned().collect())
+}
+
+pub fn array_to_matrix(a: &Array2<f64>) -> DMatrix<f64> {
+    DMatrix::from_fn(a.rows(), a.cols(), |i, j| a[[i, j]])
+}
+
+pub fn array_to_vector(a: &Array1<f64>) -> DVector<f64> {
+    DVector::from_iterator(a.len(), a.iter().cloned())
+}
+
+/// `mvdist` with `nalgebra` arguments.
+pub fn mvdist(cov: &DMatrix<f64>,
+              nu: i32,
+              lb: &DVector<f64>,
+              ub: &DVector<f64>,
+              types: &Vec<BoundType>,
+              constraints: &DMatrix<f64>,
+              delta: &DVector<f64>,
+              maxpts: u64,
+              abseps: f64,
+              releps: f64)
+              -> Result<MVResult, MvDistError> {
+    ::mvdist(&matrix_to_array(cov),
+             nu,
+             &vector_to_array(lb),
+             &vector_to_array(ub),
+             types,
+             &matrix_to_array(constraints),
+             &vector_to_array(delta),
+             maxpts,
+             abseps,
+             releps)
+}
+
+/// `mvcrit` with `nalgebra` arguments.
+pub fn mvcrit(cov: &DMatrix<f64>,
+              nu: i32,
+              lb: &DVector<f64>,
+              ub: &DVector<f64>,
+              types: &Vec<BoundType>,
+              constraints: &DMatrix<f64>,
+              alpha: f64,
+              maxpts: u64,
+              abseps: f64)
+              -> Result<MvCritResult, MvDistError> {
+    ::mvcrit(&matrix_to_array(cov),
+             nu,
+             &vector_to_array(lb),
+             &vector_to_array(ub),
+             types,
+             &matrix_to_array(constraints),
+             alpha,
+             maxpts,
+             abseps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::DMatrix;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn conversions_roundtrip() {
+        let a = arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let m = array_to_matrix(&a);
+        assert_eq!(m, DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(matrix_to_array(&m), a);
+    }
+}
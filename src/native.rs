@@ -0,0 +1,3246 @@
+//! Pure-Rust implementation of the Genz-Bretz method, for use where the Fortran backend is not
+//! available (e.g. `wasm32-unknown-unknown`) and for problems beyond its limits.
+//!
+//! The problem `P(a < (C X + delta) / S < b)` is transformed by a Cholesky factorization of the
+//! induced covariance `C Σ Cᵀ`, with Genz's variable reordering and rank-deficiency handling, into
+//! an integral over the unit hypercube by separation of variables. For the t case, `S` is handled
+//! as an additional integration variable. The integral is estimated with randomly shifted
+//! Richtmyer lattice rules, and the reported error is 3.5 times the standard error across the
+//! random shifts.
+//!
+//! The points and shifts are always summed with compensated (Neumaier) summation, and
+//! `Precision::Extended` also accumulates the products and the factorization in double-double
+//! arithmetic, for 12 or more digits on small problems. Besides `mvdist`, the module evaluates
+//! related problems with common random numbers (`evaluate_grid`, `evaluate_correlated` and
+//! `evaluate_shifted`), gradients in the dual numbers of `dual` (`gradient`), the conditional
+//! probabilities of the separation of variables (`contributions`), and the estimate after each
+//! rule (`mvdist_stream`). `SeedStreams` derives reproducible seeds for the problems of a batch,
+//! and `integrate_with_rng` draws the shifts from any `rand::Rng`.
+
+use corr::exchangeable;
+use dual::Dual;
+use ndarray::prelude::*;
+use ndarray::Dimension;
+use quadrature::integrate as integrate_1d;
+use rand::Rng;
+use sobol::{owen_scramble, to_unit, Sobol};
+use std::borrow::Cow;
+use std::mem;
+use univariate::{norm_cdf, norm_inv, norm_pdf, scaled_chi_inv};
+use {Backend, BoundType, EvalConfig, Kernel, MVInform, MVResult, MvDistError, MvProblem,
+     Tolerance, ERROR_SCALE};
+
+/// Seed used for the random lattice shifts when none is given.
+pub const DEFAULT_SEED: u64 = 0x6d76_6469_7374;
+
+/// Default number of independent randomizations of each rule.
+const NSHIFTS: usize = 8;
+
+/// Largest number of points per shift in a rule.
+const MAX_POINTS: usize = 1 << 31;
+
+/// Number of leading factors of the integrand used as its control variate.
+const CONTROL_FACTORS: usize = 2;
+
+/// Default number of points per randomization in the first rule.
+const INITIAL_POINTS: usize = 32;
+
+/// How the constraints are ordered for the separation of variables.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Reorder {
+    /// Genz's heuristic: at each step, take the constraint with the smallest expected conditional
+    /// probability.
+    Auto,
+    /// Keep the constraints in the given order.
+    None,
+    /// Use the given permutation of the constraint indices `0..m`.
+    Custom(Vec<usize>),
+}
+
+/// The precision of the sums of the integration and of the factorization of the induced
+/// covariance.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Precision {
+    /// `f64` arithmetic, with every sum compensated as in Neumaier's variant of Kahan summation,
+    /// so that the rounding error of the sums over the points and shifts does not grow with the
+    /// number of points. The products are rounded as usual, which limits the achievable accuracy
+    /// to about `1e-12`.
+    Double,
+    /// Double-double accumulation: each inner product of the factorization and of the control
+    /// variate regression also carries its rounding error in a second `f64`, computed exactly with
+    /// fused multiply-adds, giving about 30 significant digits for the sums themselves. This costs
+    /// a few extra operations per point, which matters little beside the normal CDFs of the
+    /// integrand, and is intended for small problems integrated to 12 or more digits.
+    Extended,
+}
+
+impl Default for Precision {
+    fn default() -> Precision {
+        Precision::Double
+    }
+}
+
+/// A sum in the precision `precision`: `hi + lo`, where `lo` collects the rounding errors of the
+/// additions and, for `Precision::Extended`, of the products.
+#[derive(Clone, Copy, Debug)]
+struct Accumulator {
+    hi: f64,
+    lo: f64,
+    extended: bool,
+}
+
+impl Accumulator {
+    fn new(x: f64, precision: Precision) -> Accumulator {
+        Accumulator {
+            hi: x,
+            lo: 0.0,
+            extended: precision == Precision::Extended,
+        }
+    }
+
+    fn add(&mut self, x: f64) {
+        // Neumaier's compensation, the exact rounding error of hi + x
+        let s = self.hi + x;
+        self.lo += if self.hi.abs() >= x.abs() {
+            (self.hi - s) + x
+        } else {
+            (x - s) + self.hi
+        };
+        self.hi = s;
+    }
+
+    fn add_product(&mut self, a: f64, b: f64) {
+        let p = a * b;
+        self.add(p);
+        if self.extended {
+            self.lo += a.mul_add(b, -p);
+        }
+    }
+
+    fn value(&self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+/// The point sets used to estimate the integral.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum QmcRule {
+    /// Randomly shifted Richtmyer lattice rules with the tent transformation, growing by half at
+    /// each step.
+    Lattice,
+    /// Sobol sequences, doubling in size at each step, randomized by Owen scrambling or (without
+    /// `scramble`) by a random digital shift.
+    Sobol { scramble: bool },
+}
+
+/// The generating vector of the lattice rules.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LatticeGenerator {
+    /// The Kronecker sequence `i * frac(sqrt(p_j))` over the primes `p_j`, as in the Fortran code.
+    Richtmyer,
+    /// Korobov rules `i * a^j / N (mod 1)` for the rule size `N`.
+    Korobov { a: u64 },
+    /// The Kronecker sequence `i * z_j (mod 1)` for the given `z`, which must have at least one
+    /// entry per integration variable.
+    Custom(Vec<f64>),
+}
+
+/// Advanced parameters of the quasi-Monte Carlo rules.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QmcConfig {
+    /// Number of independent randomizations of each rule, at least 2. Default 8.
+    pub randomizations: usize,
+    /// Number of points per randomization in the first rule. Default 32.
+    pub initial_points: usize,
+    /// Factor by which each rule is larger than the previous one, greater than 1. Default 1.5.
+    /// Sobol rules are rounded up to a power of two.
+    pub growth: f64,
+    /// The lattice generating vector, used by `QmcRule::Lattice`.
+    pub generator: LatticeGenerator,
+}
+
+impl Default for QmcConfig {
+    fn default() -> QmcConfig {
+        QmcConfig {
+            randomizations: NSHIFTS,
+            initial_points: INITIAL_POINTS,
+            growth: 1.5,
+            generator: LatticeGenerator::Richtmyer,
+        }
+    }
+}
+
+impl QmcConfig {
+    /// Check the parameters for an integrand of dimension `d`.
+    pub fn validate(&self, d: usize) -> Result<(), String> {
+        if self.randomizations < 2 {
+            return Err(format!("At least 2 randomizations are needed, got {}",
+                               self.randomizations));
+        }
+        if self.initial_points == 0 || !(self.growth > 1.0) {
+            return Err(format!("Invalid rule schedule: {} initial points, growth {}",
+                               self.initial_points,
+                               self.growth));
+        }
+        if let LatticeGenerator::Custom(ref z) = self.generator {
+            if z.len() < d {
+                return Err(format!("Generating vector has {} entries, expected {}", z.len(), d));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the quasi-Monte Carlo integration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Options {
+    /// Seed for the random lattice shifts.
+    pub seed: u64,
+    /// Evaluate the integrand at each point `w` and its reflection `1 - w`, averaging the two.
+    /// Each pair counts as two evaluations. This reduces the variance when the integrand is close
+    /// to symmetric about the center of the hypercube.
+    pub antithetic: bool,
+    /// Use the product of the first two one-dimensional probabilities of the integrand as a
+    /// control variate (see `Integrand::eval_with_control`), with the coefficient estimated by
+    /// regression over the points of each rule.
+    pub control_variate: bool,
+    /// The constraint ordering. Constraints whose variance is zero given the earlier ones are
+    /// moved after the others whatever the ordering.
+    pub reorder: Reorder,
+    pub rule: QmcRule,
+    pub qmc: QmcConfig,
+    /// The precision of the point sums and of the factorization (see `integrand_with`).
+    pub precision: Precision,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            seed: DEFAULT_SEED,
+            antithetic: false,
+            control_variate: false,
+            reorder: Reorder::Auto,
+            rule: QmcRule::Lattice,
+            qmc: QmcConfig::default(),
+            precision: Precision::Double,
+        }
+    }
+}
+
+/// SplitMix64 generator, used for the random lattice shifts.
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform on `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Skip `n` draws.
+    fn skip(&mut self, n: u64) {
+        self.state = self.state.wrapping_add(n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    }
+}
+
+impl Rng for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (SplitMix64::next_u64(self) >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        SplitMix64::next_u64(self)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        SplitMix64::next_f64(self)
+    }
+}
+
+/// Seeds for the problems of a batch evaluated in parallel, derived from one master seed. The
+/// seed of problem `index` is the `index`-th output of `SplitMix64::new(master)`, computed directly
+/// from the counter, so it does not depend on which thread evaluates the problem or in what order:
+/// results are reproducible from the master seed, while different problems get unrelated lattice
+/// shifts, and so independent errors, rather than all sharing one seed. (The randomizations within
+/// a single integration are drawn before any are evaluated, and need no streams of their own.)
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SeedStreams {
+    master: u64,
+}
+
+impl SeedStreams {
+    pub fn new(master: u64) -> SeedStreams {
+        SeedStreams { master: master }
+    }
+
+    /// The seed of stream `index`.
+    pub fn seed(&self, index: u64) -> u64 {
+        let mut rng = SplitMix64::new(self.master);
+        rng.skip(index);
+        rng.next_u64()
+    }
+}
+
+/// The first `n` primes.
+fn primes(n: usize) -> Vec<u64> {
+    let mut ps = Vec::with_capacity(n);
+    let mut k = 2u64;
+    while ps.len() < n {
+        if ps.iter().take_while(|&&p| p * p <= k).all(|&p| k % p != 0) {
+            ps.push(k);
+        }
+        k += 1;
+    }
+    ps
+}
+
+/// A constraint `lower < (Σ_j coef[j] y_j + delta) / s < upper` after transformation. `coef` ends
+/// with its last nonzero entry, which is the variable the row bounds.
+#[derive(Clone, Debug)]
+struct Row {
+    /// The index of the constraint in the problem.
+    constraint: usize,
+    coef: Vec<f64>,
+    lower: f64,
+    upper: f64,
+    delta: f64,
+}
+
+/// The transformed integrand.
+#[derive(Clone, Debug)]
+pub struct Integrand {
+    /// Rows grouped by the last variable they involve. `rows[k]` is used to bound `y_k`.
+    rows: Vec<Vec<Row>>,
+    /// Rows that involve no variables: they are either satisfied or not, given `s`.
+    fixed: Vec<Row>,
+    /// Degrees of freedom, or `0` for the normal distribution.
+    nu: f64,
+    /// Indices of the active constraints in the order used.
+    order: Vec<usize>,
+}
+
+impl Integrand {
+    /// Build the integrand from a row-major `n x n` covariance, row-major `m x n` constraints, and
+    /// per-constraint limits and noncentralities. Limits may be infinite.
+    pub fn new(cov: &[f64],
+               n: usize,
+               constraints: &[f64],
+               lower: &[f64],
+               upper: &[f64],
+               delta: &[f64],
+               nu: f64)
+               -> Result<Integrand, String> {
+        Integrand::with_order(cov, n, constraints, lower, upper, delta, nu, &Reorder::Auto)
+    }
+
+    /// `new` with the constraint ordering given by `reorder`.
+    pub fn with_order(cov: &[f64],
+                      n: usize,
+                      constraints: &[f64],
+                      lower: &[f64],
+                      upper: &[f64],
+                      delta: &[f64],
+                      nu: f64,
+                      reorder: &Reorder)
+                      -> Result<Integrand, String> {
+        Integrand::with_precision(cov,
+                                  n,
+                                  constraints,
+                                  lower,
+                                  upper,
+                                  delta,
+                                  nu,
+                                  reorder,
+                                  Precision::Double)
+    }
+
+    /// `with_order`, computing the induced covariance and its factorization in `precision`.
+    pub fn with_precision(cov: &[f64],
+                          n: usize,
+                          constraints: &[f64],
+                          lower: &[f64],
+                          upper: &[f64],
+                          delta: &[f64],
+                          nu: f64,
+                          reorder: &Reorder,
+                          precision: Precision)
+                          -> Result<Integrand, String> {
+        Integrand::with_workspace(cov,
+                                  n,
+                                  constraints,
+                                  lower,
+                                  upper,
+                                  delta,
+                                  nu,
+                                  reorder,
+                                  precision,
+                                  &mut Workspace::new())
+    }
+
+    /// `with_precision`, computing the induced covariance and its factor in the buffers of
+    /// `workspace`.
+    pub fn with_workspace(cov: &[f64],
+                          n: usize,
+                          constraints: &[f64],
+                          lower: &[f64],
+                          upper: &[f64],
+                          delta: &[f64],
+                          nu: f64,
+                          reorder: &Reorder,
+                          precision: Precision,
+                          workspace: &mut Workspace)
+                          -> Result<Integrand, String> {
+        let m = lower.len();
+        let initial = match *reorder {
+            Reorder::Custom(ref p) => {
+                let mut seen = vec![false; m];
+                for &i in p {
+                    if i >= m || seen[i] {
+                        return Err(format!("Invalid constraint ordering {:?}", p));
+                    }
+                    seen[i] = true;
+                }
+                if p.len() != m {
+                    return Err(format!("Invalid constraint ordering {:?}", p));
+                }
+                p.clone()
+            }
+            _ => (0..m).collect(),
+        };
+        // drop constraints that do not restrict anything
+        let active = initial.into_iter()
+            .filter(|&i| lower[i] > ::std::f64::NEG_INFINITY || upper[i] < ::std::f64::INFINITY)
+            .collect::<Vec<usize>>();
+        let m = active.len();
+
+        // induced covariance R = C Σ Cᵀ of the active constraints, one row of C Σ at a time and
+        // skipping zero coefficients, so that sparse constraints such as the identity take O(m n)
+        // operations and the only m² storage is R itself
+        let nonzero = active.iter()
+            .map(|&i| {
+                (0..n)
+                    .filter(|&j| constraints[i * n + j] != 0.0)
+                    .map(|j| (j, constraints[i * n + j]))
+                    .collect::<Vec<(usize, f64)>>()
+            })
+            .collect::<Vec<_>>();
+        let mut r = mem::replace(&mut workspace.induced, Vec::new());
+        r.clear();
+        r.resize(m * m, 0.0);
+        let mut cs = vec![Accumulator::new(0.0, precision); n];
+        for a in 0..m {
+            for x in cs.iter_mut() {
+                *x = Accumulator::new(0.0, precision);
+            }
+            for &(j, c) in &nonzero[a] {
+                for k in 0..n {
+                    cs[k].add_product(c, cov[j * n + k]);
+                }
+            }
+            for b in 0..(a + 1) {
+                let mut v = Accumulator::new(0.0, precision);
+                for &(k, c) in &nonzero[b] {
+                    v.add_product(cs[k].hi, c);
+                    v.add_product(cs[k].lo, c);
+                }
+                r[a * m + b] = v.value();
+                r[b * m + a] = v.value();
+            }
+        }
+        drop(nonzero);
+        let scale = (0..m).map(|a| r[a * m + a]).fold(0.0, f64::max);
+        let tol = 1e-12 * scale.max(::std::f64::MIN_POSITIVE);
+
+        // pivoted Cholesky R = L Lᵀ, choosing at each step the constraint with the smallest
+        // expected conditional probability (Genz's reordering heuristic). The rows of L grow by one
+        // entry per step, and the conditional variances and means of the remaining constraints
+        // are updated as each column is computed, so that choosing a pivot takes O(m) operations.
+        // Rows move as constraints are pivoted, so each has room for m entries, and a workspace
+        // reused for problems of at most m constraints never grows.
+        let mut perm = (0..m).collect::<Vec<usize>>();
+        let mut l = mem::replace(&mut workspace.factor, Vec::new());
+        if l.len() < m {
+            l.resize(m, Vec::new());
+        }
+        for row in l.iter_mut() {
+            row.clear();
+            row.reserve(m);
+        }
+        let mut var = (0..m).map(|a| Accumulator::new(r[a * m + a], precision)).collect::<Vec<_>>();
+        let mut mean = vec![0.0; m];
+        let mut rank = 0;
+        for k in 0..m {
+            let mut best: Option<(usize, f64, f64, f64, f64)> = None;
+            for i in k..m {
+                let var_i = var[i].value();
+                if var_i < -tol * 1e4 {
+                    return Err(format!("Covariance matrix not positive semidefinite"));
+                } else if var_i <= tol {
+                    continue;
+                }
+                let sd = var_i.sqrt();
+                let orig = active[perm[i]];
+                let lo = (lower[orig] - delta[orig] - mean[i]) / sd;
+                let hi = (upper[orig] - delta[orig] - mean[i]) / sd;
+                let prob = norm_cdf(hi) - norm_cdf(lo);
+                if *reorder != Reorder::Auto {
+                    best = Some((i, prob, lo, hi, sd));
+                    break;
+                } else if best.map_or(true, |b| prob < b.1) {
+                    best = Some((i, prob, lo, hi, sd));
+                }
+            }
+            let (i, prob, lo, hi, sd) = match best {
+                Some(b) => b,
+                None => break,
+            };
+            perm.swap(k, i);
+            l.swap(k, i);
+            var.swap(k, i);
+            mean.swap(k, i);
+            l[k].push(sd);
+            let y_hat = if prob > 1e-300 {
+                (norm_pdf(lo) - norm_pdf(hi)) / prob
+            } else if lo.is_infinite() {
+                hi
+            } else if hi.is_infinite() {
+                lo
+            } else {
+                0.5 * (lo + hi)
+            };
+            let pk = perm[k];
+            for i in (k + 1)..m {
+                let mut s = Accumulator::new(r[perm[i] * m + pk], precision);
+                for (a, b) in l[i].iter().zip(l[k][..k].iter()) {
+                    s.add_product(-a, *b);
+                }
+                let v = s.value() / sd;
+                l[i].push(v);
+                var[i].add_product(-v, v);
+                mean[i] += v * y_hat;
+            }
+            rank = k + 1;
+        }
+
+        let variances = perm.iter().map(|&p| r[p * m + p]).collect::<Vec<f64>>();
+        let f = Integrand::from_factor(&l,
+                                       &variances,
+                                       rank,
+                                       perm.iter().map(|&p| active[p]).collect(),
+                                       lower,
+                                       upper,
+                                       delta,
+                                       nu);
+        workspace.induced = r;
+        workspace.factor = l;
+        Ok(f)
+    }
+
+    /// The integrand of the factor `L` of the induced covariance of the constraints `order`, in
+    /// that order, given by its rows up to at most column `rank` and the diagonal `variances` of
+    /// `L Lᵀ`. The limits and noncentralities are indexed by constraint.
+    pub(crate) fn from_factor(l: &[Vec<f64>],
+                              variances: &[f64],
+                              rank: usize,
+                              order: Vec<usize>,
+                              lower: &[f64],
+                              upper: &[f64],
+                              delta: &[f64],
+                              nu: f64)
+                              -> Integrand {
+        let mut rows = vec![Vec::new(); rank];
+        let mut fixed = Vec::new();
+        for (i, &orig) in order.iter().enumerate() {
+            let sd = variances[i].max(0.0).sqrt();
+            // only the coefficients up to the last nonzero one are kept
+            let last = (0..l[i].len()).rev().find(|&j| l[i][j].abs() > 1e-10 * sd);
+            let row = Row {
+                constraint: orig,
+                coef: last.map_or(Vec::new(), |j| l[i][..(j + 1)].to_vec()),
+                lower: lower[orig],
+                upper: upper[orig],
+                delta: delta[orig],
+            };
+            match last {
+                Some(j) => rows[j].push(row),
+                None => fixed.push(row),
+            }
+        }
+        Integrand {
+            rows: rows,
+            fixed: fixed,
+            nu: nu,
+            order: order,
+        }
+    }
+
+    /// The integrand of the same problem with `nu` degrees of freedom (normal for `nu <= 0` or
+    /// infinite `nu`). The factorization and variable ordering do not depend on `nu`, so they are
+    /// reused.
+    pub fn with_nu(&self, nu: f64) -> Integrand {
+        Integrand {
+            nu: if nu > 0.0 && nu.is_finite() { nu } else { 0.0 },
+            ..self.clone()
+        }
+    }
+
+    /// The integrand with each finite lower limit replaced by `-c` and each finite upper limit by
+    /// `c`, as for the equicoordinate critical values of `crit`. The factorization and variable
+    /// ordering are reused.
+    pub fn with_equicoordinate_limits(&self, c: f64) -> Integrand {
+        let set = |row: &Row| {
+            Row {
+                constraint: row.constraint,
+                coef: row.coef.clone(),
+                lower: if row.lower.is_finite() { -c } else { row.lower },
+                upper: if row.upper.is_finite() { c } else { row.upper },
+                delta: row.delta,
+            }
+        };
+        Integrand {
+            rows: self.rows.iter().map(|rows| rows.iter().map(&set).collect()).collect(),
+            fixed: self.fixed.iter().map(&set).collect(),
+            nu: self.nu,
+            order: self.order.clone(),
+        }
+    }
+
+    /// The integrand with the limits of constraint `constraint` replaced by `lower` and `upper`,
+    /// reusing the factorization and variable ordering. A constraint with infinite limits on both
+    /// sides was dropped when the integrand was built, so its limits cannot be set here.
+    pub fn with_limits(&self, constraint: usize, lower: f64, upper: f64) -> Integrand {
+        let set = |row: &Row| {
+            if row.constraint == constraint {
+                Row {
+                    lower: lower,
+                    upper: upper,
+                    ..row.clone()
+                }
+            } else {
+                row.clone()
+            }
+        };
+        Integrand {
+            rows: self.rows.iter().map(|rows| rows.iter().map(&set).collect()).collect(),
+            fixed: self.fixed.iter().map(&set).collect(),
+            nu: self.nu,
+            order: self.order.clone(),
+        }
+    }
+
+    /// The integrand with `c` added to each finite limit, reusing the factorization and variable
+    /// ordering.
+    pub fn with_shifted_limits(&self, c: f64) -> Integrand {
+        let shift = |x: f64| if x.is_finite() { x + c } else { x };
+        let set = |row: &Row| {
+            Row {
+                lower: shift(row.lower),
+                upper: shift(row.upper),
+                ..row.clone()
+            }
+        };
+        Integrand {
+            rows: self.rows.iter().map(|rows| rows.iter().map(&set).collect()).collect(),
+            fixed: self.fixed.iter().map(&set).collect(),
+            nu: self.nu,
+            order: self.order.clone(),
+        }
+    }
+
+    /// Indices of the constraints in the order used, omitting those with infinite limits on both
+    /// sides.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// The constraints bounding each variable `y_k`, which may be several where the induced
+    /// covariance is singular. Constraints that involve no variables are not included.
+    pub fn variable_constraints(&self) -> Vec<Vec<usize>> {
+        self.rows.iter().map(|rows| rows.iter().map(|row| row.constraint).collect()).collect()
+    }
+
+    /// Number of variables after removing rank deficiency.
+    pub fn rank(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Dimension of the unit hypercube integrated over.
+    pub fn ndim(&self) -> usize {
+        self.rank().saturating_sub(1) + if self.nu > 0.0 { 1 } else { 0 }
+    }
+
+    /// Evaluate the integrand at `w` (of length `ndim()`), using `y` (of length `rank()`) as
+    /// scratch space.
+    pub fn eval(&self, w: &[f64], y: &mut [f64]) -> f64 {
+        self.eval_to(w, y, self.rank()).0
+    }
+
+    /// Evaluate the integrand and its control variate, the product of only the first
+    /// `CONTROL_FACTORS` one-dimensional probabilities. The control is larger than the integrand
+    /// and strongly correlated with it, since the variable ordering puts the most restrictive
+    /// constraints first, and its integral is computed cheaply by `control_mean`.
+    pub fn eval_with_control(&self, w: &[f64], y: &mut [f64]) -> (f64, f64) {
+        self.eval_to(w, y, self.rank())
+    }
+
+    /// The integral of the control variate of `eval_with_control`, by adaptive quadrature over the
+    /// one or two coordinates it depends on. `None` if the integrand has no more factors than the
+    /// control, which then offers no savings.
+    pub fn control_mean(&self) -> Option<f64> {
+        if self.rank() <= CONTROL_FACTORS {
+            return None;
+        }
+        let mut w = vec![0.5; self.ndim()];
+        let mut y = vec![0.0; self.rank()];
+        let (abseps, releps) = (1e-13, 1e-11);
+        // the control depends on w[0] only, or on (w[0], w[1]) when w[0] determines `s`
+        let mut control = |u: f64, v: f64| {
+            w[0] = u;
+            if self.nu > 0.0 {
+                w[1] = v;
+            }
+            self.eval_to(&w, &mut y, CONTROL_FACTORS).1
+        };
+        let value = if self.nu > 0.0 {
+            integrate_1d(|u| integrate_1d(|v| control(u, v), 0.0, 1.0, abseps, releps).value,
+                         0.0,
+                         1.0,
+                         abseps,
+                         releps)
+        } else {
+            integrate_1d(|u| control(u, 0.0), 0.0, 1.0, abseps, releps)
+        };
+        Some(value.value)
+    }
+
+    /// The partial products of the integrand at `w`: `partial[k]` is the product of its first
+    /// `k + 1` factors, the conditional probabilities of the constraints bounding `y_0, ..., y_k`
+    /// given the earlier variables. `partial` has length `rank()`, and its last entry is `eval`.
+    pub fn partial_products(&self, w: &[f64], y: &mut [f64], partial: &mut [f64]) {
+        for p in partial.iter_mut() {
+            *p = 0.0;
+        }
+        self.eval_recording(w, y, self.rank(), partial);
+    }
+
+    /// The product of the first `depth` factors of the integrand, and of the first
+    /// `CONTROL_FACTORS` of those.
+    fn eval_to(&self, w: &[f64], y: &mut [f64], depth: usize) -> (f64, f64) {
+        self.eval_recording(w, y, depth, &mut [])
+    }
+
+    /// `eval_to`, also writing the product after each factor to `partial` as far as its length
+    /// allows. Entries after a zero factor are left as they are.
+    fn eval_recording(&self,
+                      w: &[f64],
+                      y: &mut [f64],
+                      depth: usize,
+                      partial: &mut [f64])
+                      -> (f64, f64) {
+        let (s, w) = if self.nu > 0.0 {
+            (scaled_chi_inv(self.nu, w[0]), &w[1..])
+        } else {
+            (1.0, w)
+        };
+        let scaled = |bound: f64| if bound.is_infinite() { bound } else { bound * s };
+        for row in &self.fixed {
+            if !(scaled(row.lower) - row.delta <= 0.0 && 0.0 <= scaled(row.upper) - row.delta) {
+                return (0.0, 0.0);
+            }
+        }
+        let rank = self.rank();
+        let mut prod = 1.0;
+        let mut control = 0.0;
+        for k in 0..depth {
+            let (mut lo, mut hi) = (::std::f64::NEG_INFINITY, ::std::f64::INFINITY);
+            for row in &self.rows[k] {
+                let mean = (0..k).map(|j| row.coef[j] * y[j]).sum::<f64>() + row.delta;
+                let c = row.coef[k];
+                let a = (scaled(row.lower) - mean) / c;
+                let b = (scaled(row.upper) - mean) / c;
+                let (a, b) = if c < 0.0 { (b, a) } else { (a, b) };
+                lo = lo.max(a);
+                hi = hi.min(b);
+            }
+            let (pl, ph) = (norm_cdf(lo), norm_cdf(hi));
+            if ph <= pl {
+                return (0.0, control);
+            }
+            prod *= ph - pl;
+            if k < partial.len() {
+                partial[k] = prod;
+            }
+            if k + 1 == CONTROL_FACTORS.min(rank) {
+                control = prod;
+            }
+            if k + 1 < rank {
+                let p = pl + w[k] * (ph - pl);
+                y[k] = norm_inv(p.max(::std::f64::MIN_POSITIVE).min(1.0 - 1e-16));
+            }
+        }
+        (prod, control)
+    }
+}
+
+/// Buffers for building integrands and integrating them, kept from one problem to the next by
+/// `EvalContext` and grown only for a larger problem: the induced covariance and its factor, and
+/// the points and transformed variables of each randomization of a rule.
+#[derive(Clone, Debug, Default)]
+pub struct Workspace {
+    induced: Vec<f64>,
+    factor: Vec<Vec<f64>>,
+    shifts: Vec<ShiftBuffers>,
+}
+
+impl Workspace {
+    pub fn new() -> Workspace {
+        Workspace::default()
+    }
+
+    /// The number of `f64`s the buffers have room for.
+    pub fn capacity(&self) -> usize {
+        self.induced.capacity() + self.factor.iter().map(|row| row.capacity()).sum::<usize>() +
+        self.shifts.iter().map(ShiftBuffers::capacity).sum::<usize>()
+    }
+}
+
+/// The buffers `shift_sums` evaluates one randomization in.
+#[derive(Clone, Debug, Default)]
+struct ShiftBuffers {
+    point: Vec<f64>,
+    y: Vec<f64>,
+}
+
+impl ShiftBuffers {
+    fn capacity(&self) -> usize {
+        self.point.capacity() + self.y.capacity()
+    }
+}
+
+/// Clear `buffer` and fill it with `len` zeros.
+fn zeroed(buffer: &mut Vec<f64>, len: usize) {
+    buffer.clear();
+    buffer.resize(len, 0.0);
+}
+
+/// Generator for the randomized points of a `QmcRule`.
+#[derive(Clone)]
+enum Points {
+    Lattice {
+        generator: LatticeGenerator,
+        z: Vec<f64>,
+        shift: Vec<f64>,
+    },
+    Sobol {
+        sobol: Sobol,
+        scramble: bool,
+        seeds: Vec<u32>,
+        x: Vec<u32>,
+    },
+}
+
+impl Points {
+    fn new(rule: &QmcRule, generator: &LatticeGenerator, d: usize) -> Points {
+        match *rule {
+            QmcRule::Lattice => {
+                Points::Lattice {
+                    z: match *generator {
+                        LatticeGenerator::Richtmyer => {
+                            primes(d).iter().map(|&p| (p as f64).sqrt().fract()).collect()
+                        }
+                        LatticeGenerator::Korobov { .. } => vec![0.0; d],
+                        LatticeGenerator::Custom(ref z) => z[..d].to_vec(),
+                    },
+                    generator: generator.clone(),
+                    shift: vec![0.0; d],
+                }
+            }
+            QmcRule::Sobol { scramble } => {
+                Points::Sobol {
+                    sobol: Sobol::new(d),
+                    scramble: scramble,
+                    seeds: vec![0; d],
+                    x: vec![0; d],
+                }
+            }
+        }
+    }
+
+    /// The size of a rule of about `npts` points.
+    fn size(&self, npts: usize) -> usize {
+        match *self {
+            Points::Lattice { .. } => npts,
+            Points::Sobol { .. } => npts.next_power_of_two(),
+        }
+    }
+
+    /// Prepare for a rule of `npts` points.
+    fn start_rule(&mut self, npts: usize) {
+        if let Points::Lattice { generator: LatticeGenerator::Korobov { a }, ref mut z, .. } =
+               *self {
+            let n = npts as u64;
+            let mut power = 1 % n;
+            for zj in z.iter_mut() {
+                *zj = power as f64 / n as f64;
+                power = power * (a % n) % n;
+            }
+        }
+    }
+
+    /// Draw a new independent randomization.
+    fn randomize<R: Rng>(&mut self, rng: &mut R) {
+        match *self {
+            Points::Lattice { ref mut shift, .. } => {
+                for s in shift.iter_mut() {
+                    *s = rng.next_f64();
+                }
+            }
+            Points::Sobol { ref mut seeds, ref mut x, .. } => {
+                for (s, xj) in seeds.iter_mut().zip(x.iter_mut()) {
+                    *s = rng.next_u64() as u32;
+                    *xj = 0;
+                }
+            }
+        }
+    }
+
+    /// Write point `i` of the current randomization to `w`. Points must be requested in order
+    /// `0, 1, ...` after each `randomize`.
+    fn point(&mut self, i: usize, w: &mut [f64]) {
+        match *self {
+            Points::Lattice { ref z, ref shift, .. } => {
+                for j in 0..w.len() {
+                    // baker's (tent) transformation of the shifted lattice point
+                    let x = ((i + 1) as f64 * z[j] + shift[j]).fract();
+                    w[j] = (2.0 * x - 1.0).abs();
+                }
+            }
+            Points::Sobol { ref sobol, scramble, ref seeds, ref mut x } => {
+                if i > 0 {
+                    sobol.next(i as u32, x);
+                }
+                for j in 0..w.len() {
+                    w[j] = to_unit(if scramble {
+                        owen_scramble(x[j], seeds[j])
+                    } else {
+                        x[j] ^ seeds[j]
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Sums over the points of one randomization of the integrand `f`, the control `g`, and the
+/// products needed for the control-variate regression.
+struct ShiftSums {
+    f: f64,
+    g: f64,
+    fg: f64,
+    gg: f64,
+}
+
+/// Evaluate the `npts` points of the randomization `points` in `buffers`, accumulating in
+/// `precision`.
+fn shift_sums(f: &Integrand,
+              points: &mut Points,
+              buffers: &mut ShiftBuffers,
+              npts: usize,
+              antithetic: bool,
+              precision: Precision)
+              -> ShiftSums {
+    let d = f.ndim();
+    let ShiftBuffers { point: ref mut w, ref mut y } = *buffers;
+    zeroed(w, d);
+    zeroed(y, f.rank());
+    let zero = Accumulator::new(0.0, precision);
+    let (mut sf, mut sg, mut sfg, mut sgg) = (zero, zero, zero, zero);
+    for i in 0..npts {
+        points.point(i, w);
+        let (mut v, mut g) = f.eval_with_control(w, y);
+        if antithetic {
+            for wj in w.iter_mut() {
+                *wj = 1.0 - *wj;
+            }
+            let (v2, g2) = f.eval_with_control(w, y);
+            v = 0.5 * (v + v2);
+            g = 0.5 * (g + g2);
+        }
+        sf.add(v);
+        sg.add(g);
+        sfg.add_product(v, g);
+        sgg.add_product(g, g);
+    }
+    ShiftSums {
+        f: sf.value(),
+        g: sg.value(),
+        fg: sfg.value(),
+        gg: sgg.value(),
+    }
+}
+
+/// `shift_sums` for each of the randomizations `shifts`, in order, each in its own `buffers`.
+/// With the `rayon` feature they are evaluated in parallel; the results do not depend on the
+/// number of threads.
+#[cfg(feature = "rayon")]
+fn evaluate_shifts(f: &Integrand,
+                   shifts: Vec<Points>,
+                   buffers: &mut [ShiftBuffers],
+                   npts: usize,
+                   antithetic: bool,
+                   precision: Precision)
+                   -> Vec<ShiftSums> {
+    use rayon::prelude::*;
+    shifts.into_par_iter()
+        .zip(buffers.par_iter_mut())
+        .map(|(mut p, b)| shift_sums(f, &mut p, b, npts, antithetic, precision))
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn evaluate_shifts(f: &Integrand,
+                   shifts: Vec<Points>,
+                   buffers: &mut [ShiftBuffers],
+                   npts: usize,
+                   antithetic: bool,
+                   precision: Precision)
+                   -> Vec<ShiftSums> {
+    shifts.into_iter()
+        .zip(buffers.iter_mut())
+        .map(|(mut p, b)| shift_sums(f, &mut p, b, npts, antithetic, precision))
+        .collect()
+}
+
+/// The estimates of one rule.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RuleEstimate {
+    /// Number of lattice points per shift.
+    pub points: usize,
+    /// The estimate from each random shift; these are independent and identically distributed.
+    /// With `Options::control_variate`, these are the control-adjusted estimates.
+    pub estimates: Vec<f64>,
+}
+
+impl RuleEstimate {
+    /// The mean of the per-shift estimates.
+    pub fn mean(&self) -> f64 {
+        let mut sum = Accumulator::new(0.0, Precision::Double);
+        for &e in &self.estimates {
+            sum.add(e);
+        }
+        sum.value() / self.estimates.len() as f64
+    }
+
+    /// The estimated variance of `mean`, from the between-shift variance.
+    pub fn variance(&self) -> f64 {
+        let (k, mean) = (self.estimates.len() as f64, self.mean());
+        let mut sum = Accumulator::new(0.0, Precision::Double);
+        for &e in &self.estimates {
+            sum.add((e - mean) * (e - mean));
+        }
+        sum.value() / (k * (k - 1.0))
+    }
+}
+
+/// Pool the estimate `est` with variance `var` of a rule with the estimate `value` with variance
+/// `variance` of the previous rules, if any, by inverse-variance weighting.
+fn pool(value: f64, variance: Option<f64>, est: f64, var: f64) -> (f64, f64) {
+    match variance {
+        None => (est, var),
+        Some(prev) if prev + var > 0.0 => {
+            (value + (est - value) * prev / (prev + var), prev * var / (prev + var))
+        }
+        Some(prev) => (est, prev),
+    }
+}
+
+/// The state of an integration after one of its rules, as given by `Estimate::trace`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TracePoint {
+    /// Number of lattice points per shift of the rule.
+    pub points: usize,
+    /// The lattice points evaluated by this and the previous rules, over all shifts.
+    pub total_points: usize,
+    /// The estimate of this rule alone, and its error in the sense of `MVResult::error`.
+    pub rule_value: f64,
+    pub rule_error: f64,
+    /// The estimate pooled over this and the previous rules, and its error.
+    pub value: f64,
+    pub error: f64,
+}
+
+/// The result of `integrate_estimate`: the pooled result together with the per-rule estimates it
+/// was computed from.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Estimate {
+    pub result: MVResult,
+    /// The rules used, in order of increasing size. Empty if the integrand needed no integration.
+    pub rules: Vec<RuleEstimate>,
+    /// The variance of `result.value`, pooled across `rules`.
+    pub variance: f64,
+    /// The constraint ordering used (see `Integrand::order`).
+    pub order: Vec<usize>,
+}
+
+impl Estimate {
+    /// The convergence trace: the estimate after each rule, alone and pooled with the previous
+    /// rules as the integrator pooled them. A converged integration has rule estimates that agree
+    /// within their errors and errors that shrink steadily; a final rule whose estimate is far
+    /// from the others, or an error that drops suddenly, suggests the tolerance was met by chance.
+    pub fn trace(&self) -> Vec<TracePoint> {
+        let (mut value, mut variance, mut total) = (0.0, None, 0);
+        self.rules
+            .iter()
+            .map(|rule| {
+                let (est, var) = (rule.mean(), rule.variance());
+                let (v, pooled) = pool(value, variance, est, var);
+                value = v;
+                variance = Some(pooled);
+                total += rule.points * rule.estimates.len();
+                TracePoint {
+                    points: rule.points,
+                    total_points: total,
+                    rule_value: est,
+                    rule_error: ERROR_SCALE * var.sqrt(),
+                    value: value,
+                    error: ERROR_SCALE * pooled.sqrt(),
+                }
+            })
+            .collect()
+    }
+
+    /// An effective sample size: the number of independent draws of crude Monte Carlo that would
+    /// estimate the probability with the same variance, `p (1 - p) / variance` for the pooled
+    /// estimate `p`. Dividing it by the lattice points used gives the gain of the lattice rules
+    /// over crude Monte Carlo. Infinite if the variance is zero, as for integrands needing no
+    /// integration.
+    pub fn effective_sample_size(&self) -> f64 {
+        let p = self.result.value.max(0.0).min(1.0);
+        if self.variance > 0.0 {
+            p * (1.0 - p) / self.variance
+        } else {
+            ::std::f64::INFINITY
+        }
+    }
+}
+
+/// Estimate the integral of `f` over the unit hypercube with randomly shifted Richtmyer lattice
+/// rules of increasing size, until the error is below `max(abseps, releps * |value|)` or `maxpts`
+/// evaluations have been used. `integrate_estimate` allows other rules.
+pub fn integrate(f: &Integrand, maxpts: usize, abseps: f64, releps: f64, seed: u64) -> MVResult {
+    let options = Options {
+        seed: seed,
+        ..Options::default()
+    };
+    integrate_estimate(f, maxpts, abseps, releps, &options).result
+}
+
+/// The state of `integrate_resumable` between two rules, from which the integration can be
+/// continued later, or in another process after `save`. A run resumed with the same integrand,
+/// tolerances and options gives the same result as one that was never interrupted.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Checkpoint {
+    /// The dimension of the integrand and the number of randomizations of each rule, which the
+    /// resumed run must share.
+    ndim: usize,
+    randomizations: usize,
+    /// The state of the generator of the randomizations.
+    rng: u64,
+    /// The number of points per randomization of the next rule.
+    npts: usize,
+    nevals: usize,
+    value: f64,
+    variance: f64,
+    rules: Vec<RuleEstimate>,
+}
+
+impl Checkpoint {
+    /// The evaluations used so far, which count towards the `maxpts` of the resumed run.
+    pub fn nevals(&self) -> u64 {
+        self.nevals as u64
+    }
+
+    /// The pooled estimate and its error so far.
+    pub fn result(&self) -> (f64, f64) {
+        (self.value, ERROR_SCALE * self.variance.sqrt())
+    }
+
+    pub fn rules(&self) -> &[RuleEstimate] {
+        &self.rules
+    }
+
+    /// Write the checkpoint to `path` as JSON. The file is written under a temporary name and
+    /// then renamed, so an interrupted write leaves any earlier checkpoint at `path` intact.
+    #[cfg(feature = "json")]
+    pub fn save<P: AsRef<::std::path::Path>>(&self, path: P) -> ::std::io::Result<()> {
+        let path = path.as_ref();
+        let json = ::serde_json::to_string(self)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+        let tmp = path.with_extension("tmp");
+        ::std::fs::write(&tmp, json)?;
+        ::std::fs::rename(&tmp, path)
+    }
+
+    /// Read a checkpoint written by `save`.
+    #[cfg(feature = "json")]
+    pub fn load<P: AsRef<::std::path::Path>>(path: P) -> ::std::io::Result<Checkpoint> {
+        let json = ::std::fs::read_to_string(path)?;
+        ::serde_json::from_str(&json)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// `integrate` with the given `options`, also returning the per-shift estimates of every rule.
+/// `options.qmc` should be valid for `f.ndim()` (see `QmcConfig::validate`).
+pub fn integrate_estimate(f: &Integrand,
+                          maxpts: usize,
+                          abseps: f64,
+                          releps: f64,
+                          options: &Options)
+                          -> Estimate {
+    integrate_from(f, maxpts, abseps, releps, options, None, |_| ())
+}
+
+/// `integrate_estimate` with the randomizations drawn from `rng` instead of a `SplitMix64` seeded
+/// with `options.seed`, which is ignored, for callers whose policy requires a particular
+/// generator: any `rand::Rng`, such as a `rand::ChaChaRng` or the operating system's `rand::OsRng`.
+/// A generator in the same state gives the same result; the generator itself need not be `Send`
+/// with the `rayon` feature, as the randomizations of each rule are drawn before it is evaluated.
+pub fn integrate_with_rng<R: Rng>(f: &Integrand,
+                                  maxpts: usize,
+                                  abseps: f64,
+                                  releps: f64,
+                                  options: &Options,
+                                  rng: R)
+                                  -> Estimate {
+    if f.ndim() == 0 {
+        return constant(f);
+    }
+    let mut integration = Integration::with_rng(f, options, None, rng);
+    loop {
+        if let Some(state) = integration.step(f, maxpts, abseps, releps) {
+            return integration.estimate(f, state);
+        }
+    }
+}
+
+/// `integrate_estimate`, starting from `resume` if given and passing a `Checkpoint` to
+/// `checkpoint` after every rule that does not finish the integration, for integrations long
+/// enough to be interrupted. `maxpts` is the budget of the whole integration, including the
+/// evaluations made before `resume`. Fails if `resume` is from an integrand of another dimension
+/// or with another number of randomizations; other differences from the interrupted run are not
+/// detected, and make the result meaningless.
+pub fn integrate_resumable<C>(f: &Integrand,
+                              maxpts: usize,
+                              abseps: f64,
+                              releps: f64,
+                              options: &Options,
+                              resume: Option<Checkpoint>,
+                              checkpoint: C)
+                              -> Result<Estimate, MvDistError>
+    where C: FnMut(&Checkpoint)
+{
+    if let Some(ref c) = resume {
+        if c.ndim != f.ndim() || c.randomizations != options.qmc.randomizations.max(2) {
+            return Err(MvDistError::InvalidInput(format!("Checkpoint is for {} dimensions and \
+                                                          {} randomizations, expected {} and {}",
+                                                         c.ndim,
+                                                         c.randomizations,
+                                                         f.ndim(),
+                                                         options.qmc.randomizations.max(2))));
+        }
+    }
+    Ok(integrate_from(f, maxpts, abseps, releps, options, resume, checkpoint))
+}
+
+fn integrate_from<C>(f: &Integrand,
+                     maxpts: usize,
+                     abseps: f64,
+                     releps: f64,
+                     options: &Options,
+                     resume: Option<Checkpoint>,
+                     mut checkpoint: C)
+                     -> Estimate
+    where C: FnMut(&Checkpoint)
+{
+    if f.ndim() == 0 {
+        return constant(f);
+    }
+    let mut integration = Integration::new(f, options, resume);
+    loop {
+        if let Some(state) = integration.step(f, maxpts, abseps, releps) {
+            return integration.estimate(f, state);
+        }
+        checkpoint(&integration.checkpoint());
+    }
+}
+
+/// `integrate_estimate` in the buffers of `workspace`.
+pub fn integrate_in(f: &Integrand,
+                    maxpts: usize,
+                    abseps: f64,
+                    releps: f64,
+                    options: &Options,
+                    workspace: &mut Workspace)
+                    -> Estimate {
+    if f.ndim() == 0 {
+        return constant(f);
+    }
+    let mut integration = Integration::new(f, options, None);
+    integration.buffers = mem::replace(&mut workspace.shifts, Vec::new());
+    loop {
+        if let Some(state) = integration.step(f, maxpts, abseps, releps) {
+            workspace.shifts = mem::replace(&mut integration.buffers, Vec::new());
+            return integration.estimate(f, state);
+        }
+    }
+}
+
+/// The estimate of an integrand without integration variables.
+fn constant(f: &Integrand) -> Estimate {
+    let mut y = vec![0.0; f.rank()];
+    Estimate {
+        result: MVResult {
+            value: f.eval(&[], &mut y),
+            error: 0.0,
+            nevals: 1,
+            state: MVInform::Normal,
+        },
+        rules: Vec::new(),
+        variance: 0.0,
+        order: f.order().to_vec(),
+    }
+}
+
+/// The state of the integration of an integrand with at least one integration variable, advanced
+/// one rule at a time by `step`.
+struct Integration<R = SplitMix64> {
+    ndim: usize,
+    options: Options,
+    points: Points,
+    nshifts: usize,
+    evals_per_point: usize,
+    control_mean: Option<f64>,
+    rng: R,
+    npts: usize,
+    nevals: usize,
+    value: f64,
+    /// The variance of `value`, or `None` before the first rule.
+    variance: Option<f64>,
+    rules: Vec<RuleEstimate>,
+    /// The buffers of each randomization, allocated by the first rule unless given.
+    buffers: Vec<ShiftBuffers>,
+}
+
+impl Integration {
+    fn new(f: &Integrand, options: &Options, resume: Option<Checkpoint>) -> Integration {
+        let rng = SplitMix64::new(resume.as_ref().map_or(options.seed, |c| c.rng));
+        Integration::with_rng(f, options, resume, rng)
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            ndim: self.ndim,
+            randomizations: self.nshifts,
+            rng: self.rng.state,
+            npts: self.npts,
+            nevals: self.nevals,
+            value: self.value,
+            variance: self.variance.unwrap(),
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+impl<R: Rng> Integration<R> {
+    /// The integration drawing its randomizations from `rng`, which is in the state following
+    /// `resume` if given.
+    fn with_rng(f: &Integrand,
+                options: &Options,
+                resume: Option<Checkpoint>,
+                rng: R)
+                -> Integration<R> {
+        let config = &options.qmc;
+        let points = Points::new(&options.rule, &config.generator, f.ndim());
+        let (npts, nevals, value, variance, rules) = match resume {
+            Some(c) => (c.npts, c.nevals, c.value, Some(c.variance), c.rules),
+            None => (points.size(config.initial_points.max(1)), 0, 0.0, None, Vec::new()),
+        };
+        Integration {
+            ndim: f.ndim(),
+            options: options.clone(),
+            points: points,
+            nshifts: config.randomizations.max(2),
+            evals_per_point: if options.antithetic { 2 } else { 1 },
+            control_mean: if options.control_variate {
+                f.control_mean()
+            } else {
+                None
+            },
+            rng: rng,
+            npts: npts,
+            nevals: nevals,
+            value: value,
+            variance: variance,
+            rules: rules,
+            buffers: Vec::new(),
+        }
+    }
+
+    /// The pooled error after at least one rule.
+    fn error(&self) -> f64 {
+        ERROR_SCALE * self.variance.unwrap().sqrt()
+    }
+
+    /// Evaluate the next rule, returning the final state if the integration is finished.
+    fn step(&mut self,
+            f: &Integrand,
+            maxpts: usize,
+            abseps: f64,
+            releps: f64)
+            -> Option<MVInform> {
+        self.evaluate_rule(f);
+        if self.converged(abseps, releps) {
+            return Some(MVInform::Normal);
+        }
+        if self.exhausted(maxpts) {
+            return Some(MVInform::PtLimitReached);
+        }
+        self.npts = self.next_size();
+        None
+    }
+
+    /// The size of the rule after the current one.
+    fn next_size(&self) -> usize {
+        let growth = self.options.qmc.growth;
+        self.points.size(((self.npts as f64 * growth) as usize).max(self.npts + 1))
+    }
+
+    /// Whether the error is within the tolerance.
+    fn converged(&self, abseps: f64, releps: f64) -> bool {
+        self.error() <= abseps.max(releps * self.value.abs())
+    }
+
+    /// Whether the next rule would exceed `maxpts` or the largest rule.
+    fn exhausted(&self, maxpts: usize) -> bool {
+        let next = self.next_size();
+        self.nevals + self.nshifts * next * self.evals_per_point > maxpts || next > MAX_POINTS
+    }
+
+    /// Evaluate the rule of the current size and pool it with the previous rules.
+    fn evaluate_rule(&mut self, f: &Integrand) {
+        let (nshifts, npts) = (self.nshifts, self.npts);
+        self.points.start_rule(npts);
+        let mut estimates = Vec::with_capacity(nshifts);
+        let mut controls = Vec::with_capacity(nshifts);
+        // sums over all points of the rule for the control-variate regression
+        let (mut sf, mut sg, mut sfg, mut sgg) = (0.0, 0.0, 0.0, 0.0);
+        let shifts = (0..nshifts)
+            .map(|_| {
+                let mut shift = self.points.clone();
+                shift.randomize(&mut self.rng);
+                shift
+            })
+            .collect::<Vec<_>>();
+        let (antithetic, precision) = (self.options.antithetic, self.options.precision);
+        self.buffers.resize(nshifts, ShiftBuffers::default());
+        for sums in evaluate_shifts(f, shifts, &mut self.buffers, npts, antithetic, precision) {
+            estimates.push(sums.f / npts as f64);
+            controls.push(sums.g / npts as f64);
+            sf += sums.f;
+            sg += sums.g;
+            sfg += sums.fg;
+            sgg += sums.gg;
+        }
+        if let Some(mu) = self.control_mean {
+            let k = (nshifts * npts) as f64;
+            let var_g = sgg - sg * sg / k;
+            let beta = if var_g > 0.0 {
+                (sfg - sf * sg / k) / var_g
+            } else {
+                0.0
+            };
+            for (e, c) in estimates.iter_mut().zip(controls.iter()) {
+                *e -= beta * (c - mu);
+            }
+        }
+        self.nevals += nshifts * npts * self.evals_per_point;
+        let rule = RuleEstimate {
+            points: npts,
+            estimates: estimates,
+        };
+        let (est, var) = (rule.mean(), rule.variance());
+        self.rules.push(rule);
+        let (value, variance) = pool(self.value, self.variance, est, var);
+        self.value = value;
+        self.variance = Some(variance);
+    }
+
+    fn result(&self, state: MVInform) -> MVResult {
+        MVResult {
+            value: self.value,
+            error: self.error(),
+            nevals: self.nevals as u64,
+            state: state,
+        }
+    }
+
+    fn estimate(self, f: &Integrand, state: MVInform) -> Estimate {
+        Estimate {
+            result: self.result(state),
+            variance: self.variance.unwrap(),
+            rules: self.rules,
+            order: f.order().to_vec(),
+        }
+    }
+
+}
+
+/// Native equivalent of `mvdist`, with an explicit `seed` for the random lattice shifts. `nu` need
+/// not be an integer; infinite `nu` gives the normal distribution, like `nu <= 0`.
+pub fn mvdist(cov: &Array2<f64>,
+              nu: f64,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &[BoundType],
+              constraints: &Array2<f64>,
+              delta: &Array1<f64>,
+              maxpts: u64,
+              abseps: f64,
+              releps: f64,
+              seed: u64)
+              -> Result<MVResult, MvDistError> {
+    let options = Options {
+        seed: seed,
+        ..Options::default()
+    };
+    mvdist_estimate(cov,
+                    nu,
+                    lb,
+                    ub,
+                    types,
+                    constraints,
+                    delta,
+                    maxpts,
+                    abseps,
+                    releps,
+                    &options)
+        .map(|e| e.result)
+}
+
+/// `mvdist` with the given `options`, also returning the per-shift estimates of every lattice rule
+/// (see `Estimate`).
+pub fn mvdist_estimate(cov: &Array2<f64>,
+                       nu: f64,
+                       lb: &Array1<f64>,
+                       ub: &Array1<f64>,
+                       types: &[BoundType],
+                       constraints: &Array2<f64>,
+                       delta: &Array1<f64>,
+                       maxpts: u64,
+                       abseps: f64,
+                       releps: f64,
+                       options: &Options)
+                       -> Result<Estimate, MvDistError> {
+    mvdist_estimate_in(cov,
+                       nu,
+                       lb,
+                       ub,
+                       types,
+                       constraints,
+                       delta,
+                       maxpts,
+                       abseps,
+                       releps,
+                       options,
+                       &mut Workspace::new())
+}
+
+/// `mvdist_estimate` in the buffers of `workspace`.
+pub fn mvdist_estimate_in(cov: &Array2<f64>,
+                          nu: f64,
+                          lb: &Array1<f64>,
+                          ub: &Array1<f64>,
+                          types: &[BoundType],
+                          constraints: &Array2<f64>,
+                          delta: &Array1<f64>,
+                          maxpts: u64,
+                          abseps: f64,
+                          releps: f64,
+                          options: &Options,
+                          workspace: &mut Workspace)
+                          -> Result<Estimate, MvDistError> {
+    let f = integrand_in(cov,
+                         nu,
+                         lb,
+                         ub,
+                         types,
+                         constraints,
+                         delta,
+                         &options.reorder,
+                         options.precision,
+                         workspace)?;
+    options.qmc.validate(f.ndim())?;
+    let maxpts = maxpts.min(::std::usize::MAX as u64) as usize;
+    Ok(integrate_in(&f, maxpts, abseps, releps, options, workspace))
+}
+
+/// One point of `profile_nu`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProfilePoint {
+    pub nu: f64,
+    /// `ln result.value`, the log-likelihood of a censored observation described by the problem.
+    pub log_likelihood: f64,
+    pub result: MVResult,
+}
+
+/// Evaluate `problem` with each of the degrees of freedom in `nu_grid` in place of its own, as when
+/// profiling the likelihood of a censored or copula model over `nu`. The problem is factored once
+/// for the whole grid, and with the `rayon` feature the grid points are evaluated in parallel.
+/// Every point uses the problem's seed (or `DEFAULT_SEED`), so the profile is free of independent
+/// noise between neighbouring points.
+pub fn profile_nu(problem: &MvProblem, nu_grid: &[f64]) -> Result<Vec<ProfilePoint>, MvDistError> {
+    if nu_grid.iter().any(|nu| nu.is_nan()) {
+        return Err(MvDistError::InvalidInput(format!("Degrees of freedom are NaN")));
+    }
+    let f = integrand(&problem.cov,
+                      0.0,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let maxpts = problem.config.maxpts.min(::std::usize::MAX as u64) as usize;
+    let seed = problem.config.seed.unwrap_or(DEFAULT_SEED);
+    Ok(map_grid(nu_grid, |nu| {
+        let result = integrate(&f.with_nu(nu),
+                               maxpts,
+                               problem.config.tolerance.abseps(),
+                               problem.config.tolerance.releps(),
+                               seed);
+        ProfilePoint {
+            nu: nu,
+            log_likelihood: result.value.ln(),
+            result: result,
+        }
+    }))
+}
+
+/// The share of one variable of the separation of variables in the probability, as given by
+/// `contributions`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Contribution {
+    /// The constraints bounding the variable (see `Integrand::variable_constraints`).
+    pub constraints: Vec<usize>,
+    /// The probability that the constraints of this and the earlier variables hold.
+    pub probability: f64,
+    /// `probability` divided by that of the earlier variables: the probability that the
+    /// constraints of this variable hold given that the earlier ones do. 0 if the earlier ones
+    /// never hold.
+    pub conditional: f64,
+}
+
+/// The sequential conditional probabilities of `problem`, one per variable of its separation of
+/// variables in the order integrated, for finding the constraints that drive a probability
+/// towards zero. The partial products of the integrand are averaged over `points` points of each
+/// of the shifts of a randomized lattice rule, with the problem's seed (or `DEFAULT_SEED`). The
+/// last `probability` estimates the probability of the problem; the `conditional`s multiply to
+/// it. Constraints that involve no variables, because their variance is zero given the others,
+/// are reflected in every entry.
+pub fn contributions(problem: &MvProblem, points: usize) -> Result<Vec<Contribution>, MvDistError> {
+    if points == 0 {
+        return Err(MvDistError::InvalidInput(format!("Expected at least one point")));
+    }
+    let f = integrand(&problem.cov,
+                      problem.kernel.nu()?,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let (d, rank) = (f.ndim(), f.rank());
+    let mut sums = vec![Accumulator::new(0.0, Precision::Double); rank];
+    let (mut w, mut y, mut partial) = (vec![0.0; d], vec![0.0; rank], vec![0.0; rank]);
+    let mut total = 0;
+    if d == 0 {
+        f.partial_products(&w, &mut y, &mut partial);
+        for (sum, &p) in sums.iter_mut().zip(partial.iter()) {
+            sum.add(p);
+        }
+        total = 1;
+    } else {
+        let mut lattice = Points::new(&QmcRule::Lattice, &LatticeGenerator::Richtmyer, d);
+        lattice.start_rule(points);
+        let mut rng = SplitMix64::new(problem.config.seed.unwrap_or(DEFAULT_SEED));
+        for _ in 0..NSHIFTS {
+            lattice.randomize(&mut rng);
+            for i in 0..points {
+                lattice.point(i, &mut w);
+                f.partial_products(&w, &mut y, &mut partial);
+                for (sum, &p) in sums.iter_mut().zip(partial.iter()) {
+                    sum.add(p);
+                }
+            }
+            total += points;
+        }
+    }
+    let mut previous = 1.0;
+    Ok(f.variable_constraints()
+        .into_iter()
+        .zip(sums.iter())
+        .map(|(constraints, sum)| {
+            let probability = sum.value() / total as f64;
+            let conditional = if previous > 0.0 { probability / previous } else { 0.0 };
+            previous = probability;
+            Contribution {
+                constraints: constraints,
+                probability: probability,
+                conditional: conditional,
+            }
+        })
+        .collect())
+}
+
+/// The parameter of a problem varied by `evaluate_grid`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GridParameter {
+    /// The lower limit of a constraint that is bounded below.
+    Lower(usize),
+    /// The upper limit of a constraint that is bounded above.
+    Upper(usize),
+    /// The common correlation `rho` of an exchangeable structure, in place of the correlations of
+    /// the problem's covariance, whose variances are kept.
+    Correlation,
+}
+
+/// One point of `evaluate_grid`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GridPoint {
+    /// The value of the parameter.
+    pub value: f64,
+    pub result: MVResult,
+}
+
+/// Evaluate `problem` with `parameter` set to each of `values`, as for a sensitivity plot of the
+/// probability. For a limit the problem is factored once for the whole grid; for the correlation
+/// each point is factored with the constraint order chosen for the problem itself, so the curve
+/// does not jump where the reordering would change. Every point uses the problem's seed (or
+/// `DEFAULT_SEED`), and with the `rayon` feature the points are evaluated in parallel.
+pub fn evaluate_grid(problem: &MvProblem,
+                     parameter: GridParameter,
+                     values: &[f64])
+                     -> Result<Vec<GridPoint>, MvDistError> {
+    if values.iter().any(|x| x.is_nan()) {
+        return Err(MvDistError::InvalidInput(format!("Grid values are NaN")));
+    }
+    let nu = problem.kernel.nu()?;
+    let f = integrand(&problem.cov,
+                      nu,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let maxpts = problem.config.maxpts.min(::std::usize::MAX as u64) as usize;
+    let seed = problem.config.seed.unwrap_or(DEFAULT_SEED);
+    let eval = |f: &Integrand| {
+        integrate(f,
+                  maxpts,
+                  problem.config.tolerance.abseps(),
+                  problem.config.tolerance.releps(),
+                  seed)
+    };
+    match parameter {
+        GridParameter::Lower(i) |
+        GridParameter::Upper(i) => {
+            let lower = parameter == GridParameter::Lower(i);
+            let bounded = match problem.types.get(i) {
+                Some(&BoundType::Both) => true,
+                Some(&BoundType::Below) => lower,
+                Some(&BoundType::Above) => !lower,
+                _ => false,
+            };
+            if !bounded {
+                let side = if lower { "lower" } else { "upper" };
+                return Err(MvDistError::InvalidInput(format!("Constraint {} has no {} limit",
+                                                             i,
+                                                             side)));
+            }
+            let (lo, hi) = problem.types[i].limits(problem.lb[i], problem.ub[i]);
+            Ok(map_grid(values, |x| {
+                let g = if lower { f.with_limits(i, x, hi) } else { f.with_limits(i, lo, x) };
+                GridPoint {
+                    value: x,
+                    result: eval(&g),
+                }
+            }))
+        }
+        GridParameter::Correlation => {
+            let n = problem.cov.rows();
+            let min = if n > 1 { -1.0 / (n - 1) as f64 } else { -1.0 };
+            if let Some(rho) = values.iter().find(|&&rho| !(rho >= min && rho <= 1.0)) {
+                return Err(MvDistError::InvalidInput(format!("Correlation {} is outside [{}, 1]",
+                                                             rho,
+                                                             min)));
+            }
+            let m = problem.constraints.rows();
+            let mut order = f.order().to_vec();
+            order.extend((0..m).filter(|i| !f.order().contains(i)));
+            let reorder = Reorder::Custom(order);
+            let sd = (0..n).map(|i| problem.cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+            map_grid(values, |rho| {
+                let mut cov = exchangeable(n, rho);
+                for i in 0..n {
+                    for j in 0..n {
+                        cov[[i, j]] *= sd[i] * sd[j];
+                    }
+                }
+                let g = integrand(&cov,
+                                  nu,
+                                  &problem.lb,
+                                  &problem.ub,
+                                  &problem.types,
+                                  &problem.constraints,
+                                  &problem.delta,
+                                  &reorder)?;
+                Ok(GridPoint {
+                    value: rho,
+                    result: eval(&g),
+                })
+            })
+                .into_iter()
+                .collect()
+        }
+    }
+}
+
+/// Evaluate closely related `problems` with common random numbers, so that the differences of
+/// their results are smooth in whatever parameters distinguish them, as optimizers and
+/// finite-difference derivatives need. Every problem is integrated natively with the constraint
+/// order chosen for the first problem, the lattice shifts of `seed` and the same sequence of rules,
+/// which continues until every problem meets the tolerance of its `config` or the next rule would
+/// exceed the `maxpts` of one of them; the problems that have not met their tolerance then end
+/// with `MVInform::PtLimitReached`. The exact solutions and the Fortran backend are not used, since
+/// they would break the correlation. The problems must have the same number of variables and of
+/// constraints and give integrands of the same dimension, as perturbations of one problem do.
+pub fn evaluate_correlated(problems: &[MvProblem],
+                           seed: u64)
+                           -> Result<Vec<MVResult>, MvDistError> {
+    let first = match problems.first() {
+        Some(p) => p,
+        None => return Ok(Vec::new()),
+    };
+    let (n, m) = (first.cov.rows(), first.constraints.rows());
+    if problems.iter().any(|p| p.cov.rows() != n || p.constraints.rows() != m) {
+        return Err(MvDistError::InvalidInput(format!("Problems must all have {} variables and {} \
+                                                      constraints",
+                                                     n,
+                                                     m)));
+    }
+    let build = |p: &MvProblem, reorder: &Reorder| {
+        integrand(&p.cov,
+                  p.kernel.nu()?,
+                  &p.lb,
+                  &p.ub,
+                  &p.types,
+                  &p.constraints,
+                  &p.delta,
+                  reorder)
+    };
+    let f = build(first, &first.config.reorder)?;
+    let mut order = f.order().to_vec();
+    order.extend((0..m).filter(|i| !f.order().contains(i)));
+    let reorder = Reorder::Custom(order);
+    let mut integrands = vec![f];
+    for p in &problems[1..] {
+        integrands.push(build(p, &reorder)?);
+    }
+    let d = integrands[0].ndim();
+    if let Some(g) = integrands.iter().find(|g| g.ndim() != d) {
+        return Err(MvDistError::InvalidInput(format!("Integrands of dimension {} and {} cannot \
+                                                      share points",
+                                                     d,
+                                                     g.ndim())));
+    }
+    let settings = problems.iter().map(|p| budget(&p.config)).collect::<Vec<_>>();
+    Ok(integrate_jointly(&integrands, &settings, seed))
+}
+
+/// The `maxpts`, `abseps` and `releps` of `config`.
+pub(crate) fn budget(config: &EvalConfig) -> (usize, f64, f64) {
+    (config.maxpts.min(::std::usize::MAX as u64) as usize,
+     config.tolerance.abseps(),
+     config.tolerance.releps())
+}
+
+/// Integrate `integrands` of the same dimension with the lattice shifts of `seed` and the same
+/// sequence of rules, until each meets the tolerance of its `settings`, as given by `budget`, or
+/// the next rule would exceed one of their `maxpts`.
+fn integrate_jointly(integrands: &[Integrand],
+                     settings: &[(usize, f64, f64)],
+                     seed: u64)
+                     -> Vec<MVResult> {
+    if integrands.first().map_or(true, |f| f.ndim() == 0) {
+        return integrands.iter().map(|f| constant(f).result).collect();
+    }
+    let options = Options {
+        seed: seed,
+        ..Options::default()
+    };
+    let mut integrations = integrands.iter()
+        .map(|f| Integration::new(f, &options, None))
+        .collect::<Vec<Integration>>();
+    loop {
+        for (integration, f) in integrations.iter_mut().zip(integrands) {
+            integration.evaluate_rule(f);
+        }
+        let converged = integrations.iter()
+            .zip(settings)
+            .map(|(integration, &(_, abseps, releps))| integration.converged(abseps, releps))
+            .collect::<Vec<bool>>();
+        let all = converged.iter().all(|&c| c);
+        if all || integrations.iter().zip(settings).any(|(i, s)| i.exhausted(s.0)) {
+            return integrations.iter()
+                .zip(converged)
+                .map(|(integration, c)| {
+                    integration.result(if c {
+                        MVInform::Normal
+                    } else {
+                        MVInform::PtLimitReached
+                    })
+                })
+                .collect();
+        }
+        for integration in integrations.iter_mut() {
+            integration.npts = integration.next_size();
+        }
+    }
+}
+
+/// Evaluate `P(X_i <= c + shifts[i] for all i)` at each `c` of `values`, for `X` with covariance
+/// `cov` and distribution `kernel`, as in the inner loop of ranking-and-selection procedures,
+/// where the shifts are the fixed differences of the means. The covariance is factored once, with
+/// the variables ordered for `c = 0`, and the points are integrated together with common random
+/// numbers, as by `evaluate_correlated`, with the tolerance, `maxpts`, ordering and seed (or
+/// `DEFAULT_SEED`) of `config`, so the curve is smooth in `c`. Infinite values of `c` give 0 and 1.
+pub fn evaluate_shifted(cov: &Array2<f64>,
+                        kernel: Kernel,
+                        shifts: &Array1<f64>,
+                        values: &[f64],
+                        config: &EvalConfig)
+                        -> Result<Vec<GridPoint>, MvDistError> {
+    let n = cov.rows();
+    if shifts.len() != n || shifts.iter().any(|d| !d.is_finite()) {
+        return Err(MvDistError::InvalidInput(format!("Expected {} finite shifts", n)));
+    }
+    if values.iter().any(|x| x.is_nan()) {
+        return Err(MvDistError::InvalidInput(format!("Grid values are NaN")));
+    }
+    let f = integrand(cov,
+                      kernel.nu()?,
+                      &Array::zeros((n,)),
+                      shifts,
+                      &vec![BoundType::Above; n],
+                      &Array::eye(n),
+                      &Array::zeros((n,)),
+                      &config.reorder)?;
+    let integrands = values.iter().map(|&c| f.with_shifted_limits(c)).collect::<Vec<_>>();
+    let settings = vec![budget(config); values.len()];
+    let seed = config.seed.unwrap_or(DEFAULT_SEED);
+    Ok(values.iter()
+        .zip(integrate_jointly(&integrands, &settings, seed))
+        .map(|(&c, result)| {
+            GridPoint {
+                value: c,
+                result: result,
+            }
+        })
+        .collect())
+}
+
+/// The derivative of the probability of `problem` with respect to the degrees of freedom at `nu`,
+/// by the central difference with step `h`. Both evaluations use the problem's seed, so their
+/// lattice shifts are the same and the difference is not swamped by the integration noise. The
+/// estimate and its error come from the paired per-shift differences of the largest rule used by
+/// both evaluations, and `nevals` counts both.
+pub fn nu_derivative(problem: &MvProblem, nu: f64, h: f64) -> Result<MVResult, MvDistError> {
+    if !(nu.is_finite() && h > 0.0 && nu - h > 0.0) {
+        return Err(MvDistError::InvalidInput(format!("Invalid degrees of freedom {} or step {}",
+                                                     nu,
+                                                     h)));
+    }
+    let f = integrand(&problem.cov,
+                      nu,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let options = Options {
+        seed: problem.config.seed.unwrap_or(DEFAULT_SEED),
+        ..Options::default()
+    };
+    let maxpts = problem.config.maxpts.min(::std::usize::MAX as u64) as usize;
+    let abseps = problem.config.tolerance.abseps();
+    let eval = |nu: f64| integrate_estimate(&f.with_nu(nu), maxpts, abseps, 0.0, &options);
+    let (hi, lo) = (eval(nu + h), eval(nu - h));
+    let nevals = hi.result.nevals + lo.result.nevals;
+    let state = if hi.result.state == MVInform::Normal && lo.result.state == MVInform::Normal {
+        MVInform::Normal
+    } else {
+        MVInform::PtLimitReached
+    };
+    let k = hi.rules.len().min(lo.rules.len());
+    if k == 0 {
+        // no integration was needed
+        return Ok(MVResult {
+            value: (hi.result.value - lo.result.value) / (2.0 * h),
+            error: 0.0,
+            nevals: nevals,
+            state: state,
+        });
+    }
+    let diff = RuleEstimate {
+        points: hi.rules[k - 1].points,
+        estimates: hi.rules[k - 1]
+            .estimates
+            .iter()
+            .zip(lo.rules[k - 1].estimates.iter())
+            .map(|(a, b)| (a - b) / (2.0 * h))
+            .collect(),
+    };
+    Ok(MVResult {
+        value: diff.mean(),
+        error: ERROR_SCALE * diff.variance().sqrt(),
+        nevals: nevals,
+        state: state,
+    })
+}
+
+/// A parameter of a problem differentiated by `gradient`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Parameter {
+    /// The lower limit of a constraint that is bounded below.
+    Lower(usize),
+    /// The upper limit of a constraint that is bounded above.
+    Upper(usize),
+    /// The noncentrality of a constraint. A mean `mu` of `X` shifts constraint `i` by `C_ij mu_j`,
+    /// so the derivative with respect to `mu_j` is `Σ_i C_ij` times that with respect to `delta_i`.
+    Delta(usize),
+    /// The covariance `Σ_jk`, varied together with `Σ_kj`.
+    Covariance(usize, usize),
+}
+
+/// The probability of a problem and its derivatives, from `gradient`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Gradient {
+    pub value: MVResult,
+    /// The derivative with respect to each parameter, with its error in the sense of
+    /// `MVResult::error`.
+    pub derivatives: Vec<MVResult>,
+}
+
+/// A `Row` whose coefficients, limits and noncentrality carry their derivatives.
+struct DualRow {
+    coef: Vec<Dual>,
+    lower: Dual,
+    upper: Dual,
+    delta: Dual,
+}
+
+/// An `Integrand` in dual numbers, for `gradient`.
+struct DualIntegrand {
+    rows: Vec<Vec<DualRow>>,
+    fixed: Vec<DualRow>,
+    nu: f64,
+}
+
+impl DualIntegrand {
+    /// The integrand `f` of the problem with covariance `cov` and constraints `constraints`, with
+    /// the derivatives with respect to `parameters`. The factorization is repeated in dual numbers
+    /// with the constraint order and rank of `f`.
+    fn new(f: &Integrand,
+           cov: &Array2<f64>,
+           constraints: &Array2<f64>,
+           parameters: &[Parameter])
+           -> DualIntegrand {
+        let (n, p) = (cov.rows(), parameters.len());
+        let order = f.order();
+        let m = order.len();
+        let c = |i: usize, j: usize| constraints[[i, j]];
+        // the induced covariance C Σ Cᵀ of the ordered constraints, with its derivatives
+        let cs = order.iter()
+            .map(|&a| (0..n).map(|k| (0..n).map(|j| c(a, j) * cov[[j, k]]).sum::<f64>()).collect())
+            .collect::<Vec<Vec<f64>>>();
+        let r = |a: usize, b: usize| {
+            let (ca, cb) = (order[a], order[b]);
+            let grad = parameters.iter()
+                .map(|&parameter| match parameter {
+                    Parameter::Covariance(j, k) if j == k => c(ca, j) * c(cb, j),
+                    Parameter::Covariance(j, k) => c(ca, j) * c(cb, k) + c(ca, k) * c(cb, j),
+                    _ => 0.0,
+                })
+                .collect();
+            Dual {
+                value: (0..n).map(|k| cs[a][k] * c(cb, k)).sum::<f64>(),
+                grad: grad,
+            }
+        };
+        let mut l: Vec<Vec<Dual>> = vec![Vec::new(); m];
+        for k in 0..f.rank() {
+            let mut var = r(k, k);
+            for j in 0..k {
+                var = var - l[k][j].clone() * l[k][j].clone();
+            }
+            let sd = var.sqrt();
+            for i in (k + 1)..m {
+                let mut s = r(i, k);
+                for j in 0..k {
+                    s = s - l[i][j].clone() * l[k][j].clone();
+                }
+                l[i].push(s / sd.clone());
+            }
+            l[k].push(sd);
+        }
+        let limit = |value: f64, own: Parameter| if value.is_infinite() {
+            Dual::constant(value)
+        } else {
+            Dual {
+                value: value,
+                grad: (0..p).map(|q| if parameters[q] == own { 1.0 } else { 0.0 }).collect(),
+            }
+        };
+        let row = |row: &Row| {
+            let i = order.iter().position(|&o| o == row.constraint).unwrap();
+            DualRow {
+                coef: l[i][..row.coef.len()].to_vec(),
+                lower: limit(row.lower, Parameter::Lower(row.constraint)),
+                upper: limit(row.upper, Parameter::Upper(row.constraint)),
+                delta: limit(row.delta, Parameter::Delta(row.constraint)),
+            }
+        };
+        DualIntegrand {
+            rows: f.rows.iter().map(|rows| rows.iter().map(&row).collect()).collect(),
+            fixed: f.fixed.iter().map(&row).collect(),
+            nu: f.nu,
+        }
+    }
+
+    /// `Integrand::eval` in dual numbers, using `y` as scratch space.
+    fn eval(&self, w: &[f64], y: &mut Vec<Dual>) -> Dual {
+        let (s, w) = if self.nu > 0.0 {
+            (scaled_chi_inv(self.nu, w[0]), &w[1..])
+        } else {
+            (1.0, w)
+        };
+        let scaled = |bound: f64| if bound.is_infinite() { bound } else { bound * s };
+        for row in &self.fixed {
+            let (lower, upper) = (scaled(row.lower.value), scaled(row.upper.value));
+            if !(lower - row.delta.value <= 0.0 && 0.0 <= upper - row.delta.value) {
+                return Dual::constant(0.0);
+            }
+        }
+        let rank = self.rows.len();
+        y.clear();
+        let mut prod = Dual::constant(1.0);
+        for k in 0..rank {
+            let (mut lo, mut hi) = (Dual::constant(::std::f64::NEG_INFINITY),
+                                    Dual::constant(::std::f64::INFINITY));
+            for row in &self.rows[k] {
+                let mut mean = row.delta.clone();
+                for j in 0..k {
+                    mean = mean + row.coef[j].clone() * y[j].clone();
+                }
+                let c = &row.coef[k];
+                let bound = |limit: &Dual| if limit.value.is_infinite() {
+                    Dual::constant(if c.value < 0.0 { -limit.value } else { limit.value })
+                } else {
+                    (limit.clone() * s - mean.clone()) / c.clone()
+                };
+                let (a, b) = (bound(&row.lower), bound(&row.upper));
+                let (a, b) = if c.value < 0.0 { (b, a) } else { (a, b) };
+                lo = lo.max(a);
+                hi = hi.min(b);
+            }
+            let (pl, ph) = (lo.norm_cdf(), hi.norm_cdf());
+            if ph.value <= pl.value {
+                return Dual::constant(0.0);
+            }
+            let width = ph - pl.clone();
+            prod = prod * width.clone();
+            if k + 1 < rank {
+                let p = pl + width * w[k];
+                let clamped = p.value.max(::std::f64::MIN_POSITIVE).min(1.0 - 1e-16);
+                y.push(if clamped == p.value {
+                    p.norm_inv()
+                } else {
+                    Dual::constant(norm_inv(clamped))
+                });
+            }
+        }
+        prod
+    }
+}
+
+/// The probability of `problem` and its derivatives with respect to `parameters`, from a single
+/// pass over the points in which the native integrand is evaluated in dual numbers (see `dual`).
+/// At a fixed point of the unit cube the integrand of the separation of variables is a smooth
+/// function of the limits, noncentralities and covariances almost everywhere, so the means of its
+/// derivatives over the points estimate those of the probability, with the lattice noise of one
+/// evaluation rather than the amplified noise of finite differences of two. The points are
+/// `points` points of each of the shifts of a randomized lattice rule with the problem's seed (or
+/// `DEFAULT_SEED`), as for `contributions`, and each error is `ERROR_SCALE` standard errors across
+/// the shifts. The constraint order and rank of the problem are held fixed, so a singular
+/// covariance is differentiated along perturbations that keep it singular.
+pub fn gradient(problem: &MvProblem,
+                parameters: &[Parameter],
+                points: usize)
+                -> Result<Gradient, MvDistError> {
+    if points == 0 {
+        return Err(MvDistError::InvalidInput(format!("Expected at least one point")));
+    }
+    let f = integrand(&problem.cov,
+                      problem.kernel.nu()?,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let (n, m) = (problem.cov.rows(), problem.constraints.rows());
+    for &parameter in parameters {
+        let valid = match parameter {
+            Parameter::Lower(i) => {
+                match problem.types.get(i) {
+                    Some(&BoundType::Both) |
+                    Some(&BoundType::Below) => true,
+                    _ => false,
+                }
+            }
+            Parameter::Upper(i) => {
+                match problem.types.get(i) {
+                    Some(&BoundType::Both) |
+                    Some(&BoundType::Above) => true,
+                    _ => false,
+                }
+            }
+            Parameter::Delta(i) => i < m,
+            Parameter::Covariance(j, k) => j < n && k < n,
+        };
+        if !valid {
+            return Err(MvDistError::InvalidInput(format!("Invalid parameter {:?}", parameter)));
+        }
+    }
+    let g = DualIntegrand::new(&f, &problem.cov, &problem.constraints, parameters);
+    let (d, p) = (f.ndim(), parameters.len());
+    let mut y = Vec::with_capacity(f.rank());
+    // the means of the value and of each derivative over the points of each shift
+    let mut shifts: Vec<Vec<f64>> = Vec::new();
+    let nevals = if d == 0 {
+        let v = g.eval(&[], &mut y);
+        shifts.push(Some(v.value).into_iter().chain((0..p).map(|q| v.derivative(q))).collect());
+        1
+    } else {
+        let mut lattice = Points::new(&QmcRule::Lattice, &LatticeGenerator::Richtmyer, d);
+        lattice.start_rule(points);
+        let mut rng = SplitMix64::new(problem.config.seed.unwrap_or(DEFAULT_SEED));
+        let mut w = vec![0.0; d];
+        for _ in 0..NSHIFTS {
+            lattice.randomize(&mut rng);
+            let mut sums = vec![Accumulator::new(0.0, Precision::Double); p + 1];
+            for i in 0..points {
+                lattice.point(i, &mut w);
+                let v = g.eval(&w, &mut y);
+                sums[0].add(v.value);
+                for q in 0..p {
+                    sums[q + 1].add(v.derivative(q));
+                }
+            }
+            shifts.push(sums.iter().map(|s| s.value() / points as f64).collect::<Vec<f64>>());
+        }
+        NSHIFTS * points
+    };
+    let mut results = (0..(p + 1)).map(|q| {
+        let rule = RuleEstimate {
+            points: points,
+            estimates: shifts.iter().map(|s| s[q]).collect(),
+        };
+        MVResult {
+            value: rule.mean(),
+            error: if d == 0 {
+                0.0
+            } else {
+                ERROR_SCALE * rule.variance().sqrt()
+            },
+            nevals: nevals as u64,
+            state: MVInform::Normal,
+        }
+    });
+    Ok(Gradient {
+        value: results.next().unwrap(),
+        derivatives: results.collect(),
+    })
+}
+
+/// Continue the native evaluation of `problem` from `previous`, an estimate of it made with the
+/// problem's settings by `mvdist_estimate` (with the default `Options` apart from the seed and
+/// ordering) or by an earlier `refine`, using up to `extra_maxpts` further evaluations. The
+/// further rules continue the sequence of `previous` and are pooled with its rules, so when
+/// `previous` stopped at its point limit the result is that of evaluating with a budget of
+/// `previous.result.nevals + extra_maxpts` from the start. At least one rule is added unless the
+/// budget allows none, so `previous` can also be refined to a tighter tolerance.
+pub fn refine(problem: &MvProblem,
+              previous: &Estimate,
+              extra_maxpts: u64)
+              -> Result<Estimate, MvDistError> {
+    let f = integrand(&problem.cov,
+                      problem.kernel.nu()?,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &problem.config.reorder)?;
+    let options = Options {
+        seed: problem.config.seed.unwrap_or(DEFAULT_SEED),
+        reorder: problem.config.reorder.clone(),
+        ..Options::default()
+    };
+    let config = &options.qmc;
+    let nshifts = config.randomizations.max(2);
+    let used = previous.rules.iter().map(|r| r.points * r.estimates.len()).sum::<usize>();
+    if previous.order != f.order() || previous.rules.iter().any(|r| r.estimates.len() != nshifts) ||
+       used as u64 != previous.result.nevals {
+        return Err(MvDistError::InvalidInput("The estimate was not made from this problem with \
+                                              its settings"
+            .to_string()));
+    }
+    let last = match previous.rules.last() {
+        Some(last) => last.points,
+        // the problem needed no integration
+        None => return Ok(previous.clone()),
+    };
+    let d = f.ndim();
+    let points = Points::new(&options.rule, &config.generator, d);
+    let next = points.size(((last as f64 * config.growth) as usize).max(last + 1));
+    let budget = previous.result.nevals.saturating_add(extra_maxpts);
+    if next > MAX_POINTS || (used + nshifts * next) as u64 > budget {
+        return Ok(previous.clone());
+    }
+    let mut rng = SplitMix64::new(options.seed);
+    rng.skip((previous.rules.len() * nshifts * d) as u64);
+    let resume = Checkpoint {
+        ndim: d,
+        randomizations: nshifts,
+        rng: rng.state,
+        npts: next,
+        nevals: used,
+        value: previous.result.value,
+        variance: previous.variance,
+        rules: previous.rules.clone(),
+    };
+    let maxpts = budget.min(::std::usize::MAX as u64) as usize;
+    let abseps = problem.config.tolerance.abseps();
+    let releps = problem.config.tolerance.releps();
+    Ok(integrate_from(&f, maxpts, abseps, releps, &options, Some(resume), |_| ()))
+}
+
+/// The successively refined estimates of a problem, as returned by `mvdist_stream`.
+pub struct Stream {
+    f: Integrand,
+    maxpts: usize,
+    tolerance: Tolerance,
+    /// `None` for an integrand without integration variables.
+    integration: Option<Integration>,
+    finished: bool,
+}
+
+/// Each item is the estimate after one more rule, the result of evaluating with a point budget
+/// that ends at that rule; all but the last have state `MVInform::PtLimitReached`. The last is the
+/// result the evaluation stops with, by reaching the tolerance or the point limit.
+impl Iterator for Stream {
+    type Item = MVResult;
+
+    fn next(&mut self) -> Option<MVResult> {
+        if self.finished {
+            return None;
+        }
+        let integration = match self.integration {
+            Some(ref mut integration) => integration,
+            None => {
+                self.finished = true;
+                return Some(constant(&self.f).result);
+            }
+        };
+        let state = integration.step(&self.f,
+                                     self.maxpts,
+                                     self.tolerance.abseps(),
+                                     self.tolerance.releps());
+        self.finished = state.is_some();
+        Some(integration.result(state.unwrap_or(MVInform::PtLimitReached)))
+    }
+}
+
+/// Evaluate `problem` with the native backend and the settings of `config` in place of its own,
+/// yielding the estimate after each rule as the budget grows, for watching the estimate converge.
+/// The last estimate is the result of `mvdist_config` with `Backend::Native` and without retries.
+/// The retry policy is not used, and the backend must be `None` or `Backend::Native`.
+pub fn mvdist_stream(problem: &MvProblem, config: &EvalConfig) -> Result<Stream, MvDistError> {
+    if let Some(Backend::Fortran) = config.backend {
+        return Err(MvDistError::InvalidInput("Only the native backend can stream estimates"
+            .to_string()));
+    }
+    let f = integrand(&problem.cov,
+                      problem.kernel.nu()?,
+                      &problem.lb,
+                      &problem.ub,
+                      &problem.types,
+                      &problem.constraints,
+                      &problem.delta,
+                      &config.reorder)?;
+    let options = Options {
+        seed: config.seed.unwrap_or(DEFAULT_SEED),
+        reorder: config.reorder.clone(),
+        ..Options::default()
+    };
+    options.qmc.validate(f.ndim())?;
+    let integration = if f.ndim() == 0 {
+        None
+    } else {
+        Some(Integration::new(&f, &options, None))
+    };
+    Ok(Stream {
+        f: f,
+        maxpts: config.maxpts.min(::std::usize::MAX as u64) as usize,
+        tolerance: config.tolerance,
+        integration: integration,
+        finished: false,
+    })
+}
+
+#[cfg(feature = "rayon")]
+fn map_grid<T, F>(grid: &[f64], eval: F) -> Vec<T>
+    where T: Send,
+          F: Fn(f64) -> T + Sync
+{
+    use rayon::prelude::*;
+    grid.par_iter().map(|&x| eval(x)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn map_grid<T, F>(grid: &[f64], eval: F) -> Vec<T>
+    where F: Fn(f64) -> T
+{
+    grid.iter().map(|&x| eval(x)).collect()
+}
+
+/// The elements of `ar` in row-major order, borrowed directly when `ar` is laid out that way, as
+/// arrays built by `ndarray` are unless they have been transposed or sliced.
+pub(crate) fn contiguous<D: Dimension>(ar: &Array<f64, D>) -> Cow<[f64]> {
+    match ar.as_slice() {
+        Some(data) => Cow::Borrowed(data),
+        None => Cow::Owned(ar.iter().cloned().collect()),
+    }
+}
+
+/// Check the dimensions of the problem given as to `mvdist` and build its integrand, with the
+/// constraints ordered by `reorder`.
+pub fn integrand(cov: &Array2<f64>,
+             nu: f64,
+             lb: &Array1<f64>,
+             ub: &Array1<f64>,
+             types: &[BoundType],
+             constraints: &Array2<f64>,
+             delta: &Array1<f64>,
+             reorder: &Reorder)
+             -> Result<Integrand, MvDistError> {
+    integrand_with(cov, nu, lb, ub, types, constraints, delta, reorder, Precision::Double)
+}
+
+/// `integrand` with the induced covariance and its factorization computed in `precision`. With
+/// `Precision::Extended` the factor is accurate to close to `f64` precision even when the
+/// covariance is ill-conditioned, where plain `f64` inner products lose several digits.
+pub fn integrand_with(cov: &Array2<f64>,
+                      nu: f64,
+                      lb: &Array1<f64>,
+                      ub: &Array1<f64>,
+                      types: &[BoundType],
+                      constraints: &Array2<f64>,
+                      delta: &Array1<f64>,
+                      reorder: &Reorder,
+                      precision: Precision)
+                      -> Result<Integrand, MvDistError> {
+    integrand_in(cov,
+                 nu,
+                 lb,
+                 ub,
+                 types,
+                 constraints,
+                 delta,
+                 reorder,
+                 precision,
+                 &mut Workspace::new())
+}
+
+/// `integrand_with`, computing the factorization in the buffers of `workspace`.
+pub fn integrand_in(cov: &Array2<f64>,
+                    nu: f64,
+                    lb: &Array1<f64>,
+                    ub: &Array1<f64>,
+                    types: &[BoundType],
+                    constraints: &Array2<f64>,
+                    delta: &Array1<f64>,
+                    reorder: &Reorder,
+                    precision: Precision,
+                    workspace: &mut Workspace)
+                    -> Result<Integrand, MvDistError> {
+    let n = cov.rows();
+    let m = constraints.rows();
+    if n == 0 || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Invalid choice of N")));
+    }
+    if constraints.cols() != n || lb.len() != m || ub.len() != m || types.len() != m ||
+       delta.len() != m {
+        return Err(MvDistError::InvalidInput(format!("Inconsistent problem dimensions")));
+    }
+    if nu.is_nan() {
+        return Err(MvDistError::InvalidInput(format!("Degrees of freedom are NaN")));
+    }
+    let (lower, upper): (Vec<f64>, Vec<f64>) =
+        (0..m).map(|i| types[i].limits(lb[i], ub[i])).unzip();
+    Integrand::with_workspace(&contiguous(cov),
+                              n,
+                              &contiguous(constraints),
+                              &lower,
+                              &upper,
+                              &contiguous(delta),
+                              if nu > 0.0 && nu.is_finite() { nu } else { 0.0 },
+                              reorder,
+                              precision,
+                              workspace)
+        .map_err(MvDistError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use std::f64::consts::PI;
+    use {Backend, BoundType, MvProblem, Tolerance};
+
+    fn orthant(cov: &Array2<f64>, nu: f64) -> MVResult {
+        let n = cov.rows();
+        mvdist(cov,
+               nu,
+               &Array::zeros((n,)),
+               &Array::zeros((n,)),
+               &vec![BoundType::Below; n],
+               &Array::eye(n),
+               &Array::zeros((n,)),
+               1_000_000,
+               1e-6,
+               0.0,
+               DEFAULT_SEED)
+            .unwrap()
+    }
+
+    #[test]
+    fn seed_streams_are_counter_based() {
+        let streams = SeedStreams::new(DEFAULT_SEED);
+        let mut rng = SplitMix64::new(DEFAULT_SEED);
+        let sequential = (0..100).map(|_| rng.next_u64()).collect::<Vec<u64>>();
+        // in any order, the same seeds as drawn in turn
+        for i in (0..100).rev() {
+            assert_eq!(streams.seed(i), sequential[i as usize]);
+        }
+        let mut distinct = sequential.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 100);
+        assert!(SeedStreams::new(DEFAULT_SEED + 1).seed(0) != streams.seed(0));
+    }
+
+    #[test]
+    fn trivariate_orthant() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        for &nu in &[0.0, 5.0, 4.5] {
+            let result = orthant(&cov, nu);
+            assert_eq!(result.state, MVInform::Normal);
+            assert!((result.value - exact).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn matches_fortran_example() {
+        // the problem from `mvdist_works`, whose induced covariance is singular
+        let con = arr2(&[[1.0, 0.0, 0.0, 0.0],
+                         [0.0, 1.0, 0.0, 0.0],
+                         [0.0, 0.0, 1.0, 0.0],
+                         [0.0, 0.0, 0.0, 1.0],
+                         [1.0, 1.0, 1.0, 1.0]]);
+        let result = mvdist(&Array::eye(4),
+                            8.0,
+                            &Array1::from_vec(vec![0.0; 5]),
+                            &Array1::from_vec(vec![1.0; 5]),
+                            &vec![BoundType::Both; 5],
+                            &con,
+                            &Array1::from_vec(vec![0.0; 5]),
+                            100_000,
+                            1e-5,
+                            0.0,
+                            DEFAULT_SEED)
+            .unwrap();
+        assert!((result.value - 0.001).abs() < 0.0001);
+    }
+
+    #[test]
+    fn high_dimensional_sum() {
+        // by symmetry half of the mass has a negative sum, and the box holds nearly all of it
+        let n = 200;
+        let mut cov = Array::from_elem((n, n), 0.3);
+        let mut con = Array::zeros((n + 1, n));
+        for i in 0..n {
+            cov[[i, i]] = 1.0;
+            con[[i, i]] = 1.0;
+            con[[n, i]] = 1.0;
+        }
+        let mut types = vec![BoundType::Both; n];
+        types.push(BoundType::Above);
+        let mut ub = Array::from_elem((n + 1,), 20.0);
+        ub[n] = 0.0;
+        let result = mvdist(&cov,
+                            0.0,
+                            &Array::from_elem((n + 1,), -20.0),
+                            &ub,
+                            &types,
+                            &con,
+                            &Array::zeros((n + 1,)),
+                            20_000,
+                            1e-4,
+                            0.0,
+                            DEFAULT_SEED)
+            .unwrap();
+        assert!((result.value - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn estimate_reports_rules() {
+        let cov = arr2(&[[1.0, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 1.0]]);
+        let est = mvdist_estimate(&cov,
+                                  0.0,
+                                  &Array::zeros((3,)),
+                                  &Array1::from_vec(vec![1.0, 0.5, 0.0]),
+                                  &vec![BoundType::Above; 3],
+                                  &Array::eye(3),
+                                  &Array::zeros((3,)),
+                                  100_000,
+                                  1e-5,
+                                  0.0,
+                                  &Options::default())
+            .unwrap();
+        assert!(!est.rules.is_empty());
+        assert!(est.rules.iter().all(|r| r.estimates.len() == NSHIFTS));
+        let used = est.rules.iter().map(|r| r.points * r.estimates.len()).sum::<usize>();
+        assert_eq!(used as u64, est.result.nevals);
+        assert!((est.result.error - ERROR_SCALE * est.variance.sqrt()).abs() < 1e-15);
+    }
+
+    #[test]
+    fn resumes_from_checkpoints() {
+        let cov = [1.0, 0.3, 0.3, 0.3, 1.0, 0.3, 0.3, 0.3, 1.0];
+        let c = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let (lo, up) = ([-1.0, -0.5, -2.0], [1.0, 2.0, 0.5]);
+        let f = Integrand::new(&cov, 3, &c, &lo, &up, &[0.1; 3], 0.0).unwrap();
+        let options = Options::default();
+        let mut checkpoints = Vec::new();
+        let full = integrate_resumable(&f, 1_000_000, 1e-7, 0.0, &options, None, |c| {
+                checkpoints.push(c.clone())
+            })
+            .unwrap();
+        assert_eq!(full, integrate_estimate(&f, 1_000_000, 1e-7, 0.0, &options));
+        assert_eq!(checkpoints.len() + 1, full.rules.len());
+        for c in &checkpoints {
+            assert!(c.nevals() < full.result.nevals);
+            assert_eq!(c.rules(), &full.rules[..c.rules().len()]);
+            let resumed =
+                integrate_resumable(&f, 1_000_000, 1e-7, 0.0, &options, Some(c.clone()), |_| ())
+                    .unwrap();
+            assert_eq!(resumed, full);
+        }
+        let mut other = checkpoints[0].clone();
+        other.ndim += 1;
+        assert!(integrate_resumable(&f, 1_000_000, 1e-7, 0.0, &options, Some(other), |_| ())
+            .is_err());
+    }
+
+    #[test]
+    fn refining_continues_the_rules() {
+        let cov = arr2(&[[1.0, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 1.0]]);
+        let mut problem = MvProblem::new(cov,
+                                         Array::zeros((3,)),
+                                         Array1::from_vec(vec![1.0, 0.5, 0.0]),
+                                         vec![BoundType::Above; 3]);
+        problem.config.tolerance = Tolerance::absolute(1e-9).unwrap();
+        let estimate = |maxpts: u64| {
+            mvdist_estimate(&problem.cov,
+                            0.0,
+                            &problem.lb,
+                            &problem.ub,
+                            &problem.types,
+                            &problem.constraints,
+                            &problem.delta,
+                            maxpts,
+                            1e-9,
+                            0.0,
+                            &Options::default())
+                .unwrap()
+        };
+        let small = estimate(5_000);
+        assert_eq!(small.result.state, MVInform::PtLimitReached);
+        let refined = refine(&problem, &small, 45_000).unwrap();
+        assert_eq!(refined, estimate(small.result.nevals + 45_000));
+        assert!(refined.rules.len() > small.rules.len());
+        assert_eq!(refine(&problem, &refined, 0).unwrap(), refined);
+        let mut other = small.clone();
+        other.result.nevals += 1;
+        assert!(refine(&problem, &other, 45_000).is_err());
+    }
+
+    #[test]
+    fn stream_converges_to_the_result() {
+        let cov = arr2(&[[1.0, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 1.0]]);
+        let mut problem = MvProblem::new(cov,
+                                         Array::zeros((3,)),
+                                         Array1::from_vec(vec![1.0, 0.5, 0.0]),
+                                         vec![BoundType::Above; 3]);
+        problem.config.backend = Some(Backend::Native);
+        problem.config.tolerance = Tolerance::absolute(1e-7).unwrap();
+        let estimates = mvdist_stream(&problem, &problem.config).unwrap().collect::<Vec<_>>();
+        assert!(estimates.len() > 1);
+        assert_eq!(*estimates.last().unwrap(), problem.mvdist().unwrap());
+        for pair in estimates.windows(2) {
+            assert_eq!(pair[0].state, MVInform::PtLimitReached);
+            assert!(pair[0].nevals < pair[1].nevals);
+        }
+        let mut fortran = problem.config.clone();
+        fortran.backend = Some(Backend::Fortran);
+        assert!(mvdist_stream(&problem, &fortran).is_err());
+    }
+
+    #[test]
+    fn extended_precision_sums() {
+        // the additions are compensated in either precision, and the products only when extended
+        for &precision in &[Precision::Double, Precision::Extended] {
+            let mut acc = Accumulator::new(1e16, precision);
+            acc.add(1.0);
+            acc.add(-1e16);
+            assert_eq!(acc.value(), 1.0);
+        }
+        let mut double = Accumulator::new(0.0, Precision::Double);
+        let mut extended = Accumulator::new(0.0, Precision::Extended);
+        for acc in [&mut double, &mut extended].iter_mut() {
+            acc.add_product(1.0 + 2f64.powi(-30), 1.0 + 2f64.powi(-30));
+            acc.add(-1.0);
+        }
+        assert_eq!(double.value(), 2f64.powi(-29));
+        assert_eq!(extended.value(), 2f64.powi(-29) + 2f64.powi(-60));
+        // ten million additions of 0.1 lose nothing to cancellation
+        let mut long = Accumulator::new(0.0, Precision::Double);
+        let mut plain = 0.0;
+        for _ in 0..10_000_000 {
+            long.add(0.1);
+            plain += 0.1;
+        }
+        assert_eq!(long.value(), 1e6);
+        assert!((plain - 1e6f64).abs() > 1e-6);
+        // the same rules in either precision agree to within their rounding errors
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let estimate = |precision: Precision| {
+            let options = Options {
+                precision: precision,
+                ..Options::default()
+            };
+            mvdist_estimate(&cov,
+                            0.0,
+                            &Array::zeros((3,)),
+                            &Array::zeros((3,)),
+                            &vec![BoundType::Below; 3],
+                            &Array::eye(3),
+                            &Array::zeros((3,)),
+                            100_000,
+                            1e-8,
+                            0.0,
+                            &options)
+                .unwrap()
+        };
+        let (double, extended) = (estimate(Precision::Double), estimate(Precision::Extended));
+        assert_eq!(double.rules.len(), extended.rules.len());
+        assert!((double.result.value - extended.result.value).abs() < 1e-13);
+    }
+
+    #[test]
+    fn trace_replays_the_pooling() {
+        let cov = arr2(&[[1.0, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 1.0]]);
+        let est = mvdist_estimate(&cov,
+                                  0.0,
+                                  &Array::zeros((3,)),
+                                  &Array1::from_vec(vec![1.0, 0.5, 0.0]),
+                                  &vec![BoundType::Above; 3],
+                                  &Array::eye(3),
+                                  &Array::zeros((3,)),
+                                  1_000_000,
+                                  1e-7,
+                                  0.0,
+                                  &Options::default())
+            .unwrap();
+        let trace = est.trace();
+        assert_eq!(trace.len(), est.rules.len());
+        let last = trace.last().unwrap();
+        assert_eq!((last.value, last.error), (est.result.value, est.result.error));
+        assert!(last.total_points as u64 <= est.result.nevals);
+        for pair in trace.windows(2) {
+            assert!(pair[0].points < pair[1].points && pair[0].error >= pair[1].error);
+        }
+        assert_eq!(trace[0].value, trace[0].rule_value);
+        // lattice rules need far fewer points than crude Monte Carlo
+        assert!(est.effective_sample_size() > 10.0 * last.total_points as f64);
+    }
+
+    #[test]
+    fn antithetic_matches() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        let options = Options {
+            antithetic: true,
+            ..Options::default()
+        };
+        let est = mvdist_estimate(&cov,
+                                  0.0,
+                                  &Array::zeros((3,)),
+                                  &Array::zeros((3,)),
+                                  &vec![BoundType::Below; 3],
+                                  &Array::eye(3),
+                                  &Array::zeros((3,)),
+                                  1_000_000,
+                                  1e-6,
+                                  0.0,
+                                  &options)
+            .unwrap();
+        assert!((est.result.value - exact).abs() < 1e-5);
+        let used = est.rules.iter().map(|r| r.points * r.estimates.len()).sum::<usize>();
+        assert_eq!(2 * used as u64, est.result.nevals);
+    }
+
+    #[test]
+    fn control_variate_matches() {
+        let cov = arr2(&[[1.0, 0.5, 0.5, 0.5], [0.5, 1.0, 0.5, 0.5], [0.5, 0.5, 1.0, 0.5],
+                         [0.5, 0.5, 0.5, 1.0]]);
+        let exact = 0.2; // orthant probability of an equicorrelated (rho = 1/2) normal: 1 / (n + 1)
+        for &nu in &[0.0, 6.0] {
+            let options = Options {
+                control_variate: true,
+                ..Options::default()
+            };
+            let est = mvdist_estimate(&cov,
+                                      nu,
+                                      &Array::zeros((4,)),
+                                      &Array::zeros((4,)),
+                                      &vec![BoundType::Below; 4],
+                                      &Array::eye(4),
+                                      &Array::zeros((4,)),
+                                      1_000_000,
+                                      1e-6,
+                                      0.0,
+                                      &options)
+                .unwrap();
+            assert!((est.result.value - exact).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn reordering_options() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let ub = Array1::from_vec(vec![2.0, -1.0, 0.0]);
+        let run = |reorder: Reorder| {
+            let options = Options {
+                reorder: reorder,
+                ..Options::default()
+            };
+            mvdist_estimate(&cov,
+                            0.0,
+                            &Array::zeros((3,)),
+                            &ub,
+                            &vec![BoundType::Above; 3],
+                            &Array::eye(3),
+                            &Array::zeros((3,)),
+                            1_000_000,
+                            1e-6,
+                            0.0,
+                            &options)
+        };
+        let auto = run(Reorder::Auto).unwrap();
+        assert_eq!(auto.order[0], 1);
+        assert_eq!(run(Reorder::None).unwrap().order, vec![0, 1, 2]);
+        let custom = run(Reorder::Custom(vec![2, 0, 1])).unwrap();
+        assert_eq!(custom.order, vec![2, 0, 1]);
+        assert!((custom.result.value - auto.result.value).abs() < 1e-5);
+        assert!(run(Reorder::Custom(vec![0, 0, 1])).is_err());
+    }
+
+    #[test]
+    fn sobol_rules() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        for &scramble in &[false, true] {
+            let options = Options {
+                rule: QmcRule::Sobol { scramble: scramble },
+                ..Options::default()
+            };
+            let est = mvdist_estimate(&cov,
+                                      5.0,
+                                      &Array::zeros((3,)),
+                                      &Array::zeros((3,)),
+                                      &vec![BoundType::Below; 3],
+                                      &Array::eye(3),
+                                      &Array::zeros((3,)),
+                                      1_000_000,
+                                      1e-6,
+                                      0.0,
+                                      &options)
+                .unwrap();
+            assert!((est.result.value - exact).abs() < 1e-5);
+            assert!(est.rules.iter().all(|r| r.points.is_power_of_two()));
+        }
+    }
+
+    #[test]
+    fn qmc_config() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        let run = |qmc: QmcConfig| {
+            let options = Options {
+                qmc: qmc,
+                ..Options::default()
+            };
+            mvdist_estimate(&cov,
+                            0.0,
+                            &Array::zeros((3,)),
+                            &Array::zeros((3,)),
+                            &vec![BoundType::Below; 3],
+                            &Array::eye(3),
+                            &Array::zeros((3,)),
+                            1_000_000,
+                            1e-6,
+                            0.0,
+                            &options)
+        };
+        let qmc = QmcConfig {
+            randomizations: 12,
+            initial_points: 101,
+            growth: 2.0,
+            generator: LatticeGenerator::Richtmyer,
+        };
+        let est = run(qmc.clone()).unwrap();
+        assert!((est.result.value - exact).abs() < 1e-5);
+        assert_eq!(est.rules[0].estimates.len(), 12);
+        assert_eq!((est.rules[0].points, est.rules[1].points), (101, 202));
+        let korobov = run(QmcConfig {
+                generator: LatticeGenerator::Korobov { a: 17 },
+                ..qmc
+            })
+            .unwrap();
+        assert!((korobov.result.value - exact).abs() < 1e-4);
+        assert!(run(QmcConfig {
+                    generator: LatticeGenerator::Custom(vec![0.5]),
+                    ..QmcConfig::default()
+                })
+            .is_err());
+    }
+
+    #[test]
+    fn profile_matches_separate_evaluations() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let problem = MvProblem::new(cov.clone(),
+                                     Array::from_elem((3,), -1.0),
+                                     Array::from_elem((3,), 1.5),
+                                     vec![BoundType::Both; 3]);
+        let grid = [2.0, 4.5, ::std::f64::INFINITY];
+        let profile = profile_nu(&problem, &grid).unwrap();
+        for (point, &nu) in profile.iter().zip(grid.iter()) {
+            let single = mvdist(&cov,
+                                nu,
+                                &problem.lb,
+                                &problem.ub,
+                                &problem.types,
+                                &problem.constraints,
+                                &problem.delta,
+                                problem.config.maxpts,
+                                problem.config.tolerance.abseps(),
+                                problem.config.tolerance.releps(),
+                                DEFAULT_SEED)
+                .unwrap();
+            assert_eq!(point.result, single);
+            assert_eq!(point.log_likelihood, single.value.ln());
+        }
+        // heavier tails put less mass in the box
+        assert!(profile[0].log_likelihood < profile[1].log_likelihood);
+        assert!(profile[1].log_likelihood < profile[2].log_likelihood);
+        assert!(profile_nu(&problem, &[::std::f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn randomizations_from_any_generator() {
+        use rand::{ChaChaRng, SeedableRng};
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let f = integrand(&cov,
+                          0.0,
+                          &Array::zeros((3,)),
+                          &Array::zeros((3,)),
+                          &[BoundType::Below; 3],
+                          &Array::eye(3),
+                          &Array::zeros((3,)),
+                          &Reorder::Auto)
+            .unwrap();
+        let options = Options::default();
+        // the built-in generator through the trait gives the usual result
+        let usual = integrate_estimate(&f, 100_000, 1e-6, 0.0, &options);
+        let split = SplitMix64::new(DEFAULT_SEED);
+        assert_eq!(integrate_with_rng(&f, 100_000, 1e-6, 0.0, &options, split), usual);
+        let chacha = || ChaChaRng::from_seed(&[1, 2, 3, 4][..]);
+        let a = integrate_with_rng(&f, 100_000, 1e-6, 0.0, &options, chacha());
+        let b = integrate_with_rng(&f, 100_000, 1e-6, 0.0, &options, chacha());
+        assert_eq!(a, b);
+        assert!(a != usual);
+        let exact = 0.125 + (0.3f64.asin() + (-0.2f64).asin() + 0.6f64.asin()) / (4.0 * PI);
+        assert!((a.result.value - exact).abs() < 1e-5);
+    }
+
+    #[test]
+    fn contributions_by_variable() {
+        // independent variables: each conditional probability is a marginal one, in the order of
+        // increasing probability that the reordering chooses
+        let inf = ::std::f64::INFINITY;
+        let problem = MvProblem::new(Array::eye(3),
+                                     Array::from_vec(vec![-1.0, 0.0, -2.0]),
+                                     Array::from_vec(vec![1.0, inf, 2.0]),
+                                     vec![BoundType::Both; 3]);
+        let parts = contributions(&problem, 100).unwrap();
+        let order = parts.iter().map(|c| c.constraints.clone()).collect::<Vec<_>>();
+        assert_eq!(order, vec![vec![1], vec![0], vec![2]]);
+        let marginals = [0.5, 1.0 - 2.0 * norm_cdf(-1.0), 1.0 - 2.0 * norm_cdf(-2.0)];
+        for (c, &p) in parts.iter().zip(marginals.iter()) {
+            assert!((c.conditional - p).abs() < 1e-12);
+        }
+        // correlated variables: the probabilities decrease to that of the problem
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 1.0, 0.6], [-0.2, 0.6, 1.0]]);
+        let problem = MvProblem::new(cov,
+                                     Array::from_elem((3,), -1.0),
+                                     Array::from_elem((3,), 1.5),
+                                     vec![BoundType::Both; 3]);
+        let parts = contributions(&problem, 10_000).unwrap();
+        let value = problem.mvdist().unwrap().value;
+        assert!((parts[2].probability - value).abs() < 1e-4);
+        let product = parts.iter().map(|c| c.conditional).product::<f64>();
+        assert!((product - parts[2].probability).abs() < 1e-12);
+        assert!(parts[0].probability > parts[1].probability);
+        assert!(parts[1].probability > parts[2].probability);
+        assert!(contributions(&problem, 0).is_err());
+    }
+
+    #[test]
+    fn grids_of_limits_and_correlations() {
+        // independent variables: the probability is a product and is integrated exactly
+        let problem = MvProblem::new(Array::eye(2),
+                                     Array::from_elem((2,), -1.0),
+                                     Array::from_elem((2,), 1.0),
+                                     vec![BoundType::Both; 2]);
+        let grid = [-2.0, -1.0, 0.0, 0.5];
+        let curve = evaluate_grid(&problem, GridParameter::Lower(0), &grid).unwrap();
+        for (point, &x) in curve.iter().zip(grid.iter()) {
+            let exact = (norm_cdf(1.0) - norm_cdf(x)) * (norm_cdf(1.0) - norm_cdf(-1.0));
+            assert_eq!(point.value, x);
+            assert!((point.result.value - exact).abs() < 1e-12);
+        }
+        // correlated orthants, with the closed form 1/8 + 3 asin(rho) / 4π
+        let problem = MvProblem::new(arr2(&[[4.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.25]]),
+                                     Array::zeros((3,)),
+                                     Array::zeros((3,)),
+                                     vec![BoundType::Below; 3]);
+        let grid = [-0.4, 0.0, 0.5, 0.9];
+        let curve = evaluate_grid(&problem, GridParameter::Correlation, &grid).unwrap();
+        for (point, &rho) in curve.iter().zip(grid.iter()) {
+            let exact = 0.125 + 3.0 * rho.asin() / (4.0 * PI);
+            assert!((point.result.value - exact).abs() < 1e-5);
+        }
+        assert!(evaluate_grid(&problem, GridParameter::Correlation, &[-0.6]).is_err());
+        assert!(evaluate_grid(&problem, GridParameter::Upper(0), &[1.0]).is_err());
+        assert!(evaluate_grid(&problem, GridParameter::Lower(3), &[1.0]).is_err());
+        assert!(evaluate_grid(&problem, GridParameter::Lower(0), &[::std::f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn derivative_with_common_random_numbers() {
+        use univariate::t_cdf;
+        let problem = MvProblem::new(Array::eye(1),
+                                     Array::from_elem((1,), -1.5),
+                                     Array::from_elem((1,), 1.5),
+                                     vec![BoundType::Both]);
+        let (nu, h) = (4.0, 1e-3);
+        let d = nu_derivative(&problem, nu, h).unwrap();
+        let exact = (t_cdf(nu + h, 1.5) - t_cdf(nu - h, 1.5)) / h;
+        assert!((d.value - exact).abs() < 1e-4 && d.error < 1e-4);
+        assert!(nu_derivative(&problem, 1.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn gradients_in_one_pass() {
+        // P(X_0 < a, X_1 < b) has derivatives φ(a) Φ((b - ρ a) / sqrt(1 - ρ²)) in a and the
+        // bivariate density in ρ
+        let (a, b, rho) = (0.3, -0.5, 0.6);
+        let cov = arr2(&[[1.0, rho], [rho, 1.0]]);
+        let problem = MvProblem::new(cov,
+                                     Array::zeros((2,)),
+                                     Array::from_vec(vec![a, b]),
+                                     vec![BoundType::Above; 2]);
+        let parameters = [Parameter::Upper(0), Parameter::Covariance(0, 1), Parameter::Delta(0)];
+        let g = gradient(&problem, &parameters, 1000).unwrap();
+        let s = (1.0 - rho * rho).sqrt();
+        let density = (-(a * a - 2.0 * rho * a * b + b * b) / (2.0 * s * s)).exp() / (2.0 * PI * s);
+        let expected = [norm_pdf(a) * norm_cdf((b - rho * a) / s), density];
+        for (d, &e) in g.derivatives.iter().zip(&expected) {
+            assert!((d.value - e).abs() < 1e-6 && d.error < 1e-6, "{:?}", g);
+        }
+        assert!((g.derivatives[2].value + g.derivatives[0].value).abs() < 1e-15);
+        assert_eq!(g.value.nevals, (NSHIFTS * 1000) as u64);
+        // a t problem in three variables, against differences of the same estimator
+        let cov = arr2(&[[1.0, 0.4, 0.2], [0.4, 2.0, -0.3], [0.2, -0.3, 1.5]]);
+        let mut problem = MvProblem::new(cov,
+                                         Array::from_vec(vec![-1.0, -2.0, -0.5]),
+                                         Array::from_vec(vec![1.0, 0.5, 2.0]),
+                                         vec![BoundType::Both; 3]);
+        problem.kernel = Kernel::StudentT { nu: 5.0 };
+        problem.config.reorder = Reorder::None;
+        let parameters = [Parameter::Lower(1), Parameter::Covariance(0, 2)];
+        let g = gradient(&problem, &parameters, 2000).unwrap();
+        let h = 1e-5;
+        let at = |lower: f64, sigma: f64| {
+            let mut p = problem.clone();
+            p.lb[1] += lower;
+            p.cov[[0, 2]] += sigma;
+            p.cov[[2, 0]] += sigma;
+            gradient(&p, &[], 2000).unwrap().value.value
+        };
+        let differences = [(at(h, 0.0) - at(-h, 0.0)) / (2.0 * h),
+                           (at(0.0, h) - at(0.0, -h)) / (2.0 * h)];
+        for (d, &e) in g.derivatives.iter().zip(&differences) {
+            assert!((d.value - e).abs() < 1e-6, "{:?} {:?}", g, differences);
+        }
+        assert!(g.derivatives[0].value < 0.0);
+        assert!(gradient(&problem, &[Parameter::Covariance(0, 3)], 100).is_err());
+        let one_sided = MvProblem::new(Array::eye(1),
+                                       Array::zeros((1,)),
+                                       Array::zeros((1,)),
+                                       vec![BoundType::Above]);
+        assert!(gradient(&one_sided, &[Parameter::Lower(0)], 100).is_err());
+    }
+
+    #[test]
+    fn common_random_numbers() {
+        use exact::bvn;
+        // a central difference in the first limit of an AR(1) orthant-type probability, against
+        // φ(b_0) times the conditional probability of the others given X_0 = b_0
+        let (rho, b, h) = (0.5, [0.2, 0.8, -0.1], 1e-3);
+        let problems = [h, -h]
+            .iter()
+            .map(|&e| {
+                let mut p = MvProblem::new(::corr::ar1(3, rho),
+                                           Array::zeros((3,)),
+                                           Array::from_vec(vec![b[0] + e, b[1], b[2]]),
+                                           vec![BoundType::Above; 3]);
+                p.config.tolerance = Tolerance::absolute(1e-5).unwrap();
+                p
+            })
+            .collect::<Vec<MvProblem>>();
+        let r = evaluate_correlated(&problems, 7).unwrap();
+        assert_eq!(r[0].nevals, r[1].nevals);
+        assert!(r.iter().all(|r| r.state == MVInform::Normal && r.error <= 1e-5));
+        let (s1, s2) = ((1.0 - rho * rho).sqrt(), (1.0 - rho.powi(4)).sqrt());
+        let c = rho * (1.0 - rho * rho) / (s1 * s2);
+        let upper = [(b[1] - rho * b[0]) / s1, (b[2] - rho * rho * b[0]) / s2];
+        let inf = ::std::f64::INFINITY;
+        let exact = norm_pdf(b[0]) * bvn([-inf, -inf], upper, c);
+        assert!(((r[0].value - r[1].value) / (2.0 * h) - exact).abs() < 1e-4);
+        // a single problem is integrated as by `integrate`
+        let alone = evaluate_correlated(&problems[..1], 7).unwrap();
+        let p = &problems[0];
+        let f = integrand(&p.cov,
+                          0.0,
+                          &p.lb,
+                          &p.ub,
+                          &p.types,
+                          &p.constraints,
+                          &p.delta,
+                          &p.config.reorder)
+            .unwrap();
+        assert_eq!(alone[0], integrate(&f, p.config.maxpts as usize, 1e-5, 0.0, 7));
+        let mut other = problems[0].clone();
+        other.constraints = Array::eye(4);
+        assert!(evaluate_correlated(&[problems[0].clone(), other], 7).is_err());
+    }
+
+    #[test]
+    fn shifted_equicoordinate_grid() {
+        use exact::bvn;
+        let cov = arr2(&[[1.0, 0.5], [0.5, 1.0]]);
+        let shifts = arr1(&[0.0, -0.7]);
+        let mut config = EvalConfig::default();
+        config.tolerance = Tolerance::absolute(1e-6).unwrap();
+        let inf = ::std::f64::INFINITY;
+        let grid = [-inf, -1.0, 0.0, 0.5, 2.0, inf];
+        let curve = evaluate_shifted(&cov, Kernel::Normal, &shifts, &grid, &config).unwrap();
+        assert_eq!((curve[0].result.value, curve[5].result.value), (0.0, 1.0));
+        for point in &curve[1..5] {
+            let c = point.value;
+            assert!((point.result.value - bvn([-inf, -inf], [c, c - 0.7], 0.5)).abs() < 1e-5);
+            assert_eq!(point.result.nevals, curve[1].result.nevals);
+        }
+        assert!(curve.windows(2).all(|pair| pair[0].result.value < pair[1].result.value));
+        assert!(evaluate_shifted(&cov, Kernel::Normal, &arr1(&[0.0]), &grid, &config).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Order statistics of `X = mean + Y`, where `Y` is multivariate normal or t with scatter matrix
+//! `cov` and distribution `kernel`: the probabilities of orderings, evaluated with `mvdist_kernel`
+//! on the successive differences of the variables, and the distributions of the maximum and
+//! minimum. The latter also accept a noncentrality `delta` in the sense of `mvdist`, so that
+//! `X = mean + (Y + delta) / S` for the t kernel and `mean + Y + delta` for the normal one.
+//! `max_moment` integrates the distribution of the maximum to find its moments.
+
+use ndarray::prelude::*;
+use quadrature::integrate;
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+fn check(mean: &Array1<f64>, cov: &Array2<f64>, delta: &Array1<f64>) -> Result<(), MvDistError> {
+    let n = mean.len();
+    if n == 0 {
+        return Err(MvDistError::InvalidInput("No variables given".to_string()));
+    }
+    if cov.rows() != n || cov.cols() != n || delta.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{} and delta has length \
+                                                      {}, expected {} variables",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     delta.len(),
+                                                     n)));
+    }
+    Ok(())
+}
+
+/// `P(a < X < b)` with identity constraints.
+fn orthant(mean: &Array1<f64>,
+           cov: &Array2<f64>,
+           kernel: Kernel,
+           delta: &Array1<f64>,
+           a: f64,
+           b: f64,
+           bound: BoundType,
+           maxpts: u64,
+           abseps: f64)
+           -> Result<MVResult, MvDistError> {
+    check(mean, cov, delta)?;
+    let n = mean.len();
+    ::mvdist_kernel(cov,
+                    kernel,
+                    &(&Array::from_elem((n,), a) - mean),
+                    &(&Array::from_elem((n,), b) - mean),
+                    &vec![bound; n],
+                    &Array::eye(n),
+                    delta,
+                    maxpts,
+                    abseps,
+                    0.0)
+}
+
+/// `P(max_i X_i <= c)`.
+pub fn max_cdf(mean: &Array1<f64>,
+               cov: &Array2<f64>,
+               kernel: Kernel,
+               delta: &Array1<f64>,
+               c: f64,
+               maxpts: u64,
+               abseps: f64)
+               -> Result<MVResult, MvDistError> {
+    orthant(mean, cov, kernel, delta, c, c, BoundType::Above, maxpts, abseps)
+}
+
+/// `P(min_i X_i <= c)`, computed as `1 - P(X_i > c for all i)`.
+pub fn min_cdf(mean: &Array1<f64>,
+               cov: &Array2<f64>,
+               kernel: Kernel,
+               delta: &Array1<f64>,
+               c: f64,
+               maxpts: u64,
+               abseps: f64)
+               -> Result<MVResult, MvDistError> {
+    let above = orthant(mean, cov, kernel, delta, c, c, BoundType::Below, maxpts, abseps)?;
+    Ok(MVResult { value: (1.0 - above.value).max(0.0), ..above })
+}
+
+/// `P(max_i |X_i| <= c)`, from the symmetric rectangle `-c <= X_i <= c`. Zero for `c <= 0`.
+pub fn max_abs_cdf(mean: &Array1<f64>,
+                   cov: &Array2<f64>,
+                   kernel: Kernel,
+                   delta: &Array1<f64>,
+                   c: f64,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+    if !(c > 0.0) {
+        check(mean, cov, delta)?;
+        return Ok(MVResult {
+            value: 0.0,
+            error: 0.0,
+            nevals: 0,
+            state: MVInform::Normal,
+        });
+    }
+    orthant(mean, cov, kernel, delta, -c, c, BoundType::Both, maxpts, abseps)
+}
+
+/// `E[(max_i X_i)^k]`, from `E[M^k] = ∫ k m^(k-1) (1[m > 0] - P(M <= m)) dm` with the substitution
+/// `m = s u / (1 - u²)` onto `(-1, 1)`, where `s` is the scale of the largest variable. The
+/// integral is found by adaptive quadrature to within `abseps / 2`, and `max_cdf` is evaluated at
+/// each node with an `abseps` scaled by the size of the variables. `error` adds the quadrature
+/// error estimate to the evaluation errors integrated against the same weights, and `nevals` is
+/// the total over all evaluations. For the t kernel the moment exists only for `k < nu`.
+pub fn max_moment(mean: &Array1<f64>,
+                  cov: &Array2<f64>,
+                  kernel: Kernel,
+                  k: u32,
+                  maxpts: u64,
+                  abseps: f64)
+                  -> Result<MVResult, MvDistError> {
+    let n = mean.len();
+    let delta = Array::zeros((n,));
+    check(mean, cov, &delta)?;
+    if let Kernel::StudentT { nu } = kernel {
+        if k as f64 >= nu {
+            return Err(MvDistError::InvalidInput(format!("The moment of order {} does not exist \
+                                                          for {} degrees of freedom",
+                                                         k,
+                                                         nu)));
+        }
+    }
+    let mut total = MVResult {
+        value: 1.0,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    if k == 0 {
+        return Ok(total);
+    }
+    let scale = mean.iter().fold(0.0f64, |a, m| a.max(m.abs())) +
+                (0..n).map(|i| cov[[i, i]].sqrt()).fold(0.0, f64::max);
+    let inner = abseps / (2.0 * k as f64 * (8.0 * scale).powi(k as i32));
+    // the weight k m^(k-1) dm/du of the CDF at each node
+    let weight = |u: f64| {
+        let m = scale * u / (1.0 - u * u);
+        k as f64 * m.powi(k as i32 - 1) * scale * (1.0 + u * u) / (1.0 - u * u).powi(2)
+    };
+    let mut nodes = Vec::new();
+    let mut failure = None;
+    let mut value = 0.0;
+    for &(a, b) in &[(-1.0, 0.0), (0.0, 1.0)] {
+        let q = integrate(|u| {
+                              if u <= -1.0 || u >= 1.0 || failure.is_some() {
+                                  return 0.0;
+                              }
+                              let m = scale * u / (1.0 - u * u);
+                              match max_cdf(mean, cov, kernel, &delta, m, maxpts, inner) {
+                                  Ok(r) => {
+                                      nodes.push((u, r.error));
+                                      total.nevals += r.nevals;
+                                      if r.state == MVInform::PtLimitReached {
+                                          total.state = MVInform::PtLimitReached;
+                                      }
+                                      let tail = if u > 0.0 { 1.0 - r.value } else { -r.value };
+                                      weight(u) * tail
+                                  }
+                                  Err(e) => {
+                                      failure = Some(e);
+                                      0.0
+                                  }
+                              }
+                          },
+                          a,
+                          b,
+                          abseps / 4.0,
+                          0.0);
+        value += q.value;
+        total.error += q.error;
+    }
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    nodes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let propagated = |&(u, e): &(f64, f64)| weight(u).abs() * e;
+    total.error += nodes.windows(2)
+        .map(|w| 0.5 * (w[1].0 - w[0].0) * (propagated(&w[0]) + propagated(&w[1])))
+        .sum::<f64>();
+    total.value = value;
+    Ok(total)
+}
+
+/// The successive-differences matrix of `order`: row `i` has `-1` in column `order[i]` and `1` in
+/// column `order[i + 1]`, so that `(D x)_i = x[order[i + 1]] - x[order[i]]`. `n` is the number
+/// of columns.
+pub fn successive_differences(n: usize, order: &[usize]) -> Array2<f64> {
+    let rows = order.len().saturating_sub(1);
+    let mut d = Array2::zeros((rows, n));
+    for i in 0..rows {
+        d[[i, order[i]]] = -1.0;
+        d[[i, order[i + 1]]] = 1.0;
+    }
+    d
+}
+
+/// `P(X[order[0]] < X[order[1]] < … )`, the probability that the variables in `order` are ranked
+/// in that order. Variables not in `order` are unrestricted, so passing `0..n` gives the
+/// probability of the complete ordering `P(X[0] < X[1] < … < X[n - 1])`.
+pub fn ordering(mean: &Array1<f64>,
+                cov: &Array2<f64>,
+                kernel: Kernel,
+                order: &[usize],
+                maxpts: u64,
+                abseps: f64)
+                -> Result<MVResult, MvDistError> {
+    check(mean, cov, &Array::zeros((mean.len(),)))?;
+    let n = mean.len();
+    if order.len() < 2 {
+        return Err(MvDistError::InvalidInput("An ordering needs at least two variables"
+            .to_string()));
+    }
+    let mut seen = vec![false; n];
+    for &i in order {
+        if i >= n || seen[i] {
+            return Err(MvDistError::InvalidInput(format!("Invalid or repeated index {} in \
+                                                          ordering of {} variables",
+                                                         i,
+                                                         n)));
+        }
+        seen[i] = true;
+    }
+    // D X > 0 is D Y > -D mean
+    let d = successive_differences(n, order);
+    let m = d.rows();
+    ::mvdist_kernel(cov,
+                    kernel,
+                    &-d.dot(mean),
+                    &Array::zeros((m,)),
+                    &vec![BoundType::Below; m],
+                    &d,
+                    &Array::zeros((m,)),
+                    maxpts,
+                    abseps,
+                    0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use univariate::{norm_cdf, t_cdf};
+
+    #[test]
+    fn exchangeable_orderings() {
+        let mean = Array::zeros((3,));
+        let cov = arr2(&[[1.0, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 1.0]]);
+        for &kernel in &[Kernel::Normal, Kernel::StudentT { nu: 5.0 }] {
+            let p = ordering(&mean, &cov, kernel, &[0, 1, 2], 100_000, 1e-6).unwrap();
+            assert!((p.value - 1.0 / 6.0).abs() < 1e-5);
+            let p = ordering(&mean, &cov, kernel, &[2, 0], 100_000, 1e-6).unwrap();
+            assert!((p.value - 0.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn shifted_pair() {
+        let mean = Array1::from_vec(vec![0.0, 1.0]);
+        let p = ordering(&mean, &Array::eye(2), Kernel::Normal, &[0, 1], 100_000, 1e-8).unwrap();
+        assert!((p.value - norm_cdf(1.0 / 2f64.sqrt())).abs() < 1e-6);
+        assert!(ordering(&mean, &Array::eye(2), Kernel::Normal, &[0], 1000, 1e-3).is_err());
+        assert!(ordering(&mean, &Array::eye(2), Kernel::Normal, &[0, 0], 1000, 1e-3).is_err());
+        assert!(ordering(&mean, &Array::eye(2), Kernel::Normal, &[0, 2], 1000, 1e-3).is_err());
+    }
+
+    #[test]
+    fn extremes_of_independent_variables() {
+        let mean = Array1::from_vec(vec![0.0, 1.0]);
+        let cov = Array::eye(2);
+        let zero = Array::zeros((2,));
+        let (n, eps) = (Kernel::Normal, 1e-8);
+        let max = max_cdf(&mean, &cov, n, &zero, 0.5, 100_000, eps).unwrap();
+        assert!((max.value - norm_cdf(0.5) * norm_cdf(-0.5)).abs() < 1e-8);
+        let min = min_cdf(&mean, &cov, n, &zero, 0.5, 100_000, eps).unwrap();
+        assert!((min.value - (1.0 - norm_cdf(-0.5) * norm_cdf(0.5))).abs() < 1e-8);
+        let abs = max_abs_cdf(&zero, &cov, n, &mean, 1.0, 100_000, eps).unwrap();
+        assert!((abs.value - (2.0 * norm_cdf(1.0) - 1.0) * (norm_cdf(0.0) - norm_cdf(-2.0))).abs() <
+                1e-8);
+        assert_eq!(max_abs_cdf(&zero, &cov, n, &zero, 0.0, 1000, 1e-3).unwrap().value, 0.0);
+        assert!(max_cdf(&mean, &Array::eye(3), n, &zero, 0.0, 1000, 1e-3).is_err());
+        // the t maximum of a single variable is the univariate t distribution
+        let one = Array::zeros((1,));
+        let t3 = Kernel::StudentT { nu: 3.0 };
+        let t = max_cdf(&one, &Array::eye(1), t3, &one, 1.5, 100_000, eps);
+        assert!((t.unwrap().value - t_cdf(3.0, 1.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn moments_of_the_maximum() {
+        let zero = Array::zeros((2,));
+        let m = |k: u32| max_moment(&zero, &Array::eye(2), Kernel::Normal, k, 100_000, 1e-8);
+        // for two independent standard normals E[max] = 1 / sqrt(pi), and max² + min² = X² + Y²
+        assert!((m(1).unwrap().value - 1.0 / ::std::f64::consts::PI.sqrt()).abs() < 1e-7);
+        assert!((m(2).unwrap().value - 1.0).abs() < 1e-7);
+        assert_eq!(m(0).unwrap().value, 1.0);
+        let shifted = max_moment(&Array1::from_vec(vec![2.0]),
+                                 &Array::eye(1),
+                                 Kernel::Normal,
+                                 1,
+                                 100_000,
+                                 1e-8)
+            .unwrap();
+        assert!((shifted.value - 2.0).abs() < 1e-7);
+        let t5 = Kernel::StudentT { nu: 5.0 };
+        let one = Array::zeros((1,));
+        let var = max_moment(&one, &Array::eye(1), t5, 2, 100_000, 1e-6).unwrap();
+        assert!((var.value - 5.0 / 3.0).abs() < 1e-4);
+        assert!(max_moment(&one, &Array::eye(1), t5, 5, 100_000, 1e-6).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Pairwise likelihoods of interval-censored multivariate normal and t observations. The full
+//! log-likelihood of an observation known only to lie in the rectangle `lower < X < upper` is the
+//! log of an `n`-dimensional rectangle probability, which is costly and noisy to evaluate for
+//! `n` in the hundreds. The pairwise (composite) log-likelihood replaces it by
+//!
+//! `Σ_{i<j} w_ij ln P(lower_i < X_i < upper_i, lower_j < X_j < upper_j)`,
+//!
+//! whose terms are bivariate probabilities evaluated to machine precision by `exact::bvn` and
+//! `exact::bvt`. Its maximizer is a consistent estimator of the parameters under the usual
+//! conditions, though not an efficient one, and its value is not on the scale of the full
+//! log-likelihood; with two variables the two coincide.
+
+use exact::{bvn, bvt};
+use ndarray::prelude::*;
+use {Kernel, MvDistError};
+
+/// An observation censored to the rectangle `lower < X < upper`, with infinite limits for the
+/// coordinates censored on one side only or missing.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Censored {
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+/// The standard deviations, correlations and degrees of freedom of the distribution.
+struct Standardized {
+    sd: Vec<f64>,
+    corr: Array2<f64>,
+    nu: u32,
+}
+
+fn standardize(mean: &Array1<f64>,
+               cov: &Array2<f64>,
+               kernel: Kernel,
+               weights: Option<&Array2<f64>>)
+               -> Result<Standardized, MvDistError> {
+    let n = mean.len();
+    if n < 2 || cov.rows() != n || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected at least two variables and a \
+                                                      {}x{} covariance, got {}x{}",
+                                                     n,
+                                                     n,
+                                                     cov.rows(),
+                                                     cov.cols())));
+    }
+    if let Some(w) = weights {
+        if w.rows() != n || w.cols() != n || w.iter().any(|&w| !(w >= 0.0) || w.is_infinite()) {
+            return Err(MvDistError::InvalidInput(format!("Weights must be a {}x{} matrix of \
+                                                          finite, non-negative values",
+                                                         n,
+                                                         n)));
+        }
+    }
+    let nu = kernel.nu()?;
+    if nu != nu.trunc() || nu > ::std::u32::MAX as f64 {
+        return Err(MvDistError::InvalidInput(format!("The pairwise likelihood requires integer \
+                                                      degrees of freedom, got {}",
+                                                     nu)));
+    }
+    let sd = (0..n).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0) || s.is_infinite()) {
+        return Err(MvDistError::InvalidInput("Variances must be positive and finite".to_string()));
+    }
+    let mut corr = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            corr[[i, j]] = (cov[[i, j]] / (sd[i] * sd[j])).max(-1.0).min(1.0);
+        }
+    }
+    if corr.iter().any(|r| r.is_nan()) {
+        return Err(MvDistError::InvalidInput("Covariances must not be NaN".to_string()));
+    }
+    Ok(Standardized {
+        sd: sd,
+        corr: corr,
+        nu: nu as u32,
+    })
+}
+
+/// The pairwise log-likelihood of one observation.
+fn observation(mean: &Array1<f64>,
+               s: &Standardized,
+               x: &Censored,
+               weights: Option<&Array2<f64>>)
+               -> Result<f64, MvDistError> {
+    let n = mean.len();
+    if x.lower.len() != n || x.upper.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected {} limits, got {} and {}",
+                                                     n,
+                                                     x.lower.len(),
+                                                     x.upper.len())));
+    }
+    if (0..n).any(|i| !(x.lower[i] < x.upper[i])) {
+        return Err(MvDistError::InvalidInput(format!("Empty censoring interval in {:?}", x)));
+    }
+    let lower = (0..n).map(|i| (x.lower[i] - mean[i]) / s.sd[i]).collect::<Vec<f64>>();
+    let upper = (0..n).map(|i| (x.upper[i] - mean[i]) / s.sd[i]).collect::<Vec<f64>>();
+    let mut ll = 0.0;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let w = weights.map_or(1.0, |w| w[[i, j]]);
+            if w == 0.0 {
+                continue;
+            }
+            let (a, b) = ([lower[i], lower[j]], [upper[i], upper[j]]);
+            let p = if s.nu == 0 {
+                bvn(a, b, s.corr[[i, j]])
+            } else {
+                bvt(a, b, s.corr[[i, j]], s.nu)?
+            };
+            ll += w * p.ln();
+        }
+    }
+    Ok(ll)
+}
+
+/// The pairwise log-likelihood of the independent observations `data` of `X` with location
+/// `mean`, scatter matrix `cov` and distribution `kernel`, which must have integer degrees of
+/// freedom. The term of the pair `(i, j)` for `i < j` is weighted by `weights[[i, j]]`, or by 1
+/// without weights; pairs of weight 0 are not evaluated, so a banded weight matrix keeps only the
+/// pairs of nearby variables of a long sequence. A pair with probability 0 gives `-∞`. With the
+/// `rayon` feature the observations are evaluated in parallel.
+pub fn log_likelihood(mean: &Array1<f64>,
+                      cov: &Array2<f64>,
+                      kernel: Kernel,
+                      data: &[Censored],
+                      weights: Option<&Array2<f64>>)
+                      -> Result<f64, MvDistError> {
+    let s = standardize(mean, cov, kernel, weights)?;
+    let terms = evaluate_observations(data, |x| observation(mean, &s, x, weights));
+    terms.into_iter().sum()
+}
+
+#[cfg(feature = "rayon")]
+fn evaluate_observations<F>(data: &[Censored], eval: F) -> Vec<Result<f64, MvDistError>>
+    where F: Fn(&Censored) -> Result<f64, MvDistError> + Sync
+{
+    use rayon::prelude::*;
+    data.par_iter().map(|x| eval(x)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn evaluate_observations<F>(data: &[Censored], eval: F) -> Vec<Result<f64, MvDistError>>
+    where F: Fn(&Censored) -> Result<f64, MvDistError>
+{
+    data.iter().map(|x| eval(x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::ar1;
+    use ndarray::prelude::*;
+    use univariate::norm_cdf;
+    use BoundType;
+
+    #[test]
+    fn pairwise_terms() {
+        let inf = ::std::f64::INFINITY;
+        // two variables: the full log-likelihood
+        let cov = arr2(&[[2.0, 0.6], [0.6, 1.0]]);
+        let mean = Array::from_vec(vec![0.5, -0.2]);
+        let x = Censored {
+            lower: vec![-1.0, 0.0],
+            upper: vec![1.5, inf],
+        };
+        for &kernel in &[Kernel::Normal, Kernel::StudentT { nu: 5.0 }] {
+            let ll = log_likelihood(&mean, &cov, kernel, &[x.clone()], None).unwrap();
+            let lb = Array::from_vec(vec![-1.5, 0.2]);
+            let ub = Array::from_vec(vec![1.0, inf]);
+            let full = ::mvdist_kernel(&cov,
+                                       kernel,
+                                       &lb,
+                                       &ub,
+                                       &[BoundType::Both, BoundType::Above],
+                                       &Array::eye(2),
+                                       &Array::zeros((2,)),
+                                       100_000,
+                                       1e-12,
+                                       0.0)
+                .unwrap();
+            assert!((ll - full.value.ln()).abs() < 1e-8);
+        }
+        // independent variables: each marginal appears in n - 1 pairs
+        let mean = Array::zeros((3,));
+        let x = Censored {
+            lower: vec![-inf, -1.0, 0.5],
+            upper: vec![0.3, 1.0, inf],
+        };
+        let data = vec![x.clone(), x.clone()];
+        let ll = log_likelihood(&mean, &Array::eye(3), Kernel::Normal, &data, None).unwrap();
+        let marginals = norm_cdf(0.3) * (norm_cdf(1.0) - norm_cdf(-1.0)) * norm_cdf(-0.5);
+        assert!((ll - 2.0 * 2.0 * marginals.ln()).abs() < 1e-12);
+        // weights select the pairs
+        let mut weights = Array2::zeros((3, 3));
+        weights[[0, 2]] = 2.0;
+        let cov = ar1(3, 0.5);
+        let ll = log_likelihood(&mean, &cov, Kernel::Normal, &[x.clone()], Some(&weights)).unwrap();
+        let p = bvn([-inf, 0.5], [0.3, inf], 0.25);
+        assert!((ll - 2.0 * p.ln()).abs() < 1e-12);
+        // errors
+        let fractional = Kernel::StudentT { nu: 2.5 };
+        assert!(log_likelihood(&mean, &cov, fractional, &[x.clone()], None).is_err());
+        let empty = Censored {
+            lower: vec![0.0, 0.0, 0.0],
+            upper: vec![1.0, 0.0, 1.0],
+        };
+        assert!(log_likelihood(&mean, &cov, Kernel::Normal, &[empty], None).is_err());
+    }
+}
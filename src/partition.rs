@@ -0,0 +1,212 @@
+//! Distributions of sub-vectors of `X = mean + Y`, where `Y` is multivariate normal or t with
+//! scatter matrix `cov` and distribution `kernel`.
+//!
+//! A marginal of a multivariate t is multivariate t with the same degrees of freedom. Conditioning
+//! a multivariate t on `X_B = b` gives another multivariate t, with `nu + |B|`
+//! degrees of freedom and the normal conditional scatter multiplied by
+//! `(nu + d²) / (nu + |B|)`, where `d²` is the squared Mahalanobis distance of `b` from its mean.
+
+use linalg::{cholesky, solve_lower};
+use ndarray::prelude::*;
+use {BoundType, Kernel, MVResult, MvDistError};
+
+/// The distribution of the variables `indices` of `X`, either marginally or given the values of
+/// the others.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subvector {
+    /// The variables of `X` described, in the order of the components of `mean`.
+    pub indices: Vec<usize>,
+    pub mean: Array1<f64>,
+    pub cov: Array2<f64>,
+    pub kernel: Kernel,
+}
+
+impl Subvector {
+    /// `P(lb < X_A < ub)`, or `P(lb < X_A < ub | X_B = b)` for a conditional distribution, with
+    /// the bounds used selected by `types`. The bounds refer to the variables in the order of
+    /// `indices`.
+    pub fn probability(&self,
+                       lb: &Array1<f64>,
+                       ub: &Array1<f64>,
+                       types: &[BoundType],
+                       maxpts: u64,
+                       abseps: f64)
+                       -> Result<MVResult, MvDistError> {
+        let n = self.indices.len();
+        if lb.len() != n || ub.len() != n || types.len() != n {
+            return Err(MvDistError::InvalidInput(format!("Expected bounds for {} variables, got \
+                                                          {}, {} and {}",
+                                                         n,
+                                                         lb.len(),
+                                                         ub.len(),
+                                                         types.len())));
+        }
+        ::mvdist_kernel(&self.cov,
+                        self.kernel,
+                        &(lb - &self.mean),
+                        &(ub - &self.mean),
+                        types,
+                        &Array::eye(n),
+                        &Array::zeros((n,)),
+                        maxpts,
+                        abseps,
+                        0.0)
+    }
+}
+
+fn check(mean: &Array1<f64>, cov: &Array2<f64>) -> Result<(), MvDistError> {
+    let n = mean.len();
+    if cov.rows() != n || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected {}x{}",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     n,
+                                                     n)));
+    }
+    Ok(())
+}
+
+/// The marginal distribution of the variables `indices` of `X`, in that order.
+pub fn marginal(cov: &Array2<f64>,
+                mean: &Array1<f64>,
+                kernel: Kernel,
+                indices: &[usize])
+                -> Result<Subvector, MvDistError> {
+    check(mean, cov)?;
+    let n = mean.len();
+    if indices.is_empty() {
+        return Err(MvDistError::InvalidInput("No variables selected".to_string()));
+    }
+    let mut seen = vec![false; n];
+    for &i in indices {
+        if i >= n || seen[i] {
+            return Err(MvDistError::InvalidInput(format!("Invalid or repeated index {} in \
+                                                          selection from {} variables",
+                                                         i,
+                                                         n)));
+        }
+        seen[i] = true;
+    }
+    let k = indices.len();
+    let mut sub_cov = Array2::zeros((k, k));
+    for (r, &i) in indices.iter().enumerate() {
+        for (c, &j) in indices.iter().enumerate() {
+            sub_cov[[r, c]] = cov[[i, j]];
+        }
+    }
+    Ok(Subvector {
+        indices: indices.to_vec(),
+        mean: Array::from_vec(indices.iter().map(|&i| mean[i]).collect()),
+        cov: sub_cov,
+        kernel: kernel,
+    })
+}
+
+/// The conditional distribution of `X` given `X_i = b` for each `(i, b)` in `given`:
+/// `mean_A + Σ_AB Σ_BB⁻¹ (b - mean_B)` and `Σ_AA - Σ_AB Σ_BB⁻¹ Σ_BA` for the normal kernel,
+/// with the degrees of freedom and scale adjusted as described in the module documentation for
+/// the t kernel. The remaining variables are in increasing order.
+pub fn conditional(cov: &Array2<f64>,
+                   mean: &Array1<f64>,
+                   kernel: Kernel,
+                   given: &[(usize, f64)])
+                   -> Result<Subvector, MvDistError> {
+    check(mean, cov)?;
+    let n = mean.len();
+    let mut is_given = vec![false; n];
+    for &(i, b) in given {
+        if i >= n || is_given[i] {
+            return Err(MvDistError::InvalidInput(format!("Invalid or repeated index {} in \
+                                                          conditioning on {} variables",
+                                                         i,
+                                                         n)));
+        }
+        if !b.is_finite() {
+            return Err(MvDistError::InvalidInput(format!("Conditioning value {} of variable {} \
+                                                          is not finite",
+                                                         b,
+                                                         i)));
+        }
+        is_given[i] = true;
+    }
+    let rest = (0..n).filter(|&i| !is_given[i]).collect::<Vec<usize>>();
+    if rest.is_empty() {
+        return Err(MvDistError::InvalidInput("No variables remain after conditioning"
+            .to_string()));
+    }
+    let (a, p) = (rest.len(), given.len());
+    let mut cov_bb = Array2::zeros((p, p));
+    for (r, &(i, _)) in given.iter().enumerate() {
+        for (c, &(j, _)) in given.iter().enumerate() {
+            cov_bb[[r, c]] = cov[[i, j]];
+        }
+    }
+    let l = cholesky(&cov_bb).ok_or(MvDistError::NotPositiveSemidefinite)?;
+    // z = L⁻¹ (b - mean_B) and the columns of w = L⁻¹ Σ_BA
+    let z = solve_lower(&l, &given.iter().map(|&(i, b)| b - mean[i]).collect::<Vec<f64>>());
+    let w = rest.iter()
+        .map(|&j| solve_lower(&l, &given.iter().map(|&(i, _)| cov[[i, j]]).collect::<Vec<f64>>()))
+        .collect::<Vec<Vec<f64>>>();
+    let dot = |u: &[f64], v: &[f64]| u.iter().zip(v.iter()).map(|(x, y)| x * y).sum::<f64>();
+    let (kernel, factor) = match kernel {
+        Kernel::StudentT { nu } if nu.is_finite() => {
+            (Kernel::StudentT { nu: nu + p as f64 }, (nu + dot(&z, &z)) / (nu + p as f64))
+        }
+        k => (k, 1.0),
+    };
+    let mut cond_mean = Array1::zeros((a,));
+    let mut cond_cov = Array2::zeros((a, a));
+    for r in 0..a {
+        cond_mean[r] = mean[rest[r]] + dot(&w[r], &z);
+        for c in 0..a {
+            cond_cov[[r, c]] = factor * (cov[[rest[r], rest[c]]] - dot(&w[r], &w[c]));
+        }
+    }
+    Ok(Subvector {
+        indices: rest,
+        mean: cond_mean,
+        cov: cond_cov,
+        kernel: kernel,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn bivariate_conditionals() {
+        let cov = arr2(&[[1.0, 0.5], [0.5, 1.0]]);
+        let mean = Array1::from_vec(vec![0.0, 0.0]);
+        let normal = conditional(&cov, &mean, Kernel::Normal, &[(1, 1.0)]).unwrap();
+        assert_eq!(normal.indices, vec![0]);
+        assert!((normal.mean[0] - 0.5).abs() < 1e-15 && (normal.cov[[0, 0]] - 0.75).abs() < 1e-15);
+        let p = normal.probability(&Array::zeros((1,)),
+                                   &Array::from_elem((1,), 0.5),
+                                   &[BoundType::Above],
+                                   100_000,
+                                   1e-8)
+            .unwrap();
+        assert!((p.value - 0.5).abs() < 1e-8);
+        // d² = 4 for b = 2, so the scale grows by (3 + 4) / 4
+        let t = conditional(&cov, &mean, Kernel::StudentT { nu: 3.0 }, &[(1, 2.0)]).unwrap();
+        assert_eq!(t.kernel, Kernel::StudentT { nu: 4.0 });
+        assert!((t.mean[0] - 1.0).abs() < 1e-15 && (t.cov[[0, 0]] - 0.75 * 1.75).abs() < 1e-14);
+        assert!(conditional(&cov, &mean, Kernel::Normal, &[(0, 0.0), (1, 0.0)]).is_err());
+        assert!(conditional(&cov, &mean, Kernel::Normal, &[(2, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn marginals() {
+        let cov = arr2(&[[1.0, 0.3, -0.2], [0.3, 2.0, 0.6], [-0.2, 0.6, 3.0]]);
+        let mean = Array1::from_vec(vec![1.0, 2.0, 3.0]);
+        let t = Kernel::StudentT { nu: 4.0 };
+        let m = marginal(&cov, &mean, t, &[2, 0]).unwrap();
+        assert_eq!(m.mean, Array1::from_vec(vec![3.0, 1.0]));
+        assert_eq!(m.cov, arr2(&[[3.0, -0.2], [-0.2, 1.0]]));
+        assert_eq!(m.kernel, t);
+        assert!(marginal(&cov, &mean, t, &[0, 0]).is_err());
+        assert!(marginal(&cov, &mean, t, &[]).is_err());
+    }
+}
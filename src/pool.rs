@@ -0,0 +1,927 @@
+//! Evaluation of independent problems in parallel worker processes, enabled by the `pool` feature.
+//! The Fortran routines keep their state in global variables, so a process evaluates one problem at
+//! a time with the Fortran backend whatever its number of threads; `ProcessPool` runs problems in
+//! several worker subprocesses instead.
+//!
+//! A worker is any program that calls `serve` with its standard input and output, such as
+//! `mvdist worker` from the command-line interface. Each request and response is a frame of a
+//! little-endian `u32` length followed by that many bytes: a request holds a problem and its
+//! settings written field by field as little-endian numbers, with the length of every array before
+//! it, and a response holds the `MVResult` or `MvDistError` of `MvProblem::mvdist`. A request may
+//! instead hold a group of the critical-value problems of `crit::mvcrit_batch`, answered with the
+//! results of searching them in turn. The format is private to this module and may change between
+//! versions, so the workers should be built from the same version of the crate as the pool.
+//!
+//! A `CancellationToken` stops a batch between problems: the pool sends no further requests once
+//! it is cancelled, and a worker answers the requests it reads after its own token is cancelled
+//! with `MvDistError::Cancelled`. The problems in progress are finished either way, so a cancelled
+//! batch returns the results of the problems completed.
+
+use crit::{self, CritProblem, MvCritResult, RootFinder};
+use native::Reorder;
+use ndarray::prelude::*;
+use std::ffi::OsStr;
+use std::io::{self, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use {Backend, BoundType, CancellationToken, EvalConfig, Kernel, MVInform, MVResult, MvDistError,
+     MvProblem, RetryPolicy, Tolerance};
+
+/// The first byte of a request for `MvProblem::mvdist`.
+const MVDIST_REQUEST: u8 = 0;
+
+/// The first byte of a request for `crit::search_group`.
+const CRIT_REQUEST: u8 = 1;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A message being written.
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn f64(&mut self, x: f64) {
+        self.u64(x.to_bits());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u64(n as u64);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn option_u64(&mut self, x: Option<u64>) {
+        match x {
+            Some(x) => {
+                self.u8(1);
+                self.u64(x);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn vector(&mut self, v: &Array1<f64>) {
+        self.len(v.len());
+        for &x in v.iter() {
+            self.f64(x);
+        }
+    }
+
+    fn matrix(&mut self, a: &Array2<f64>) {
+        self.len(a.rows());
+        self.len(a.cols());
+        for &x in a.iter() {
+            self.f64(x);
+        }
+    }
+
+    fn types(&mut self, types: &[BoundType]) {
+        self.len(types.len());
+        for &t in types {
+            let code: i32 = t.into();
+            self.u8((code + 1) as u8);
+        }
+    }
+
+    fn retry(&mut self, retry: &RetryPolicy) {
+        self.u32(retry.max_doublings);
+        self.option_u64(retry.total_eval_budget);
+        self.f64(retry.backoff);
+    }
+
+    fn kernel(&mut self, kernel: Kernel) {
+        match kernel {
+            Kernel::Normal => self.u8(0),
+            Kernel::StudentT { nu } => {
+                self.u8(1);
+                self.f64(nu);
+            }
+        }
+    }
+
+    fn state(&mut self, state: MVInform) {
+        self.u8(match state {
+            MVInform::Normal => 0,
+            MVInform::PtLimitReached => 1,
+            MVInform::IllConditioned => 2,
+        });
+    }
+
+    fn error(&mut self, error: &MvDistError) {
+        match *error {
+            MvDistError::DimensionTooLarge { n, max } => {
+                self.u8(1);
+                self.u64(n as u64);
+                self.u64(max as u64);
+            }
+            MvDistError::NotPositiveSemidefinite => self.u8(2),
+            MvDistError::InvalidInput(ref msg) => {
+                self.u8(3);
+                self.str(msg);
+            }
+            MvDistError::RootNotBracketed { alpha, lo, hi } => {
+                self.u8(4);
+                self.f64(alpha);
+                self.f64(lo);
+                self.f64(hi);
+            }
+            MvDistError::Cancelled => self.u8(5),
+            MvDistError::MemoryLimitExceeded { required, limit } => {
+                self.u8(6);
+                self.u64(required as u64);
+                self.u64(limit as u64);
+            }
+        }
+    }
+}
+
+/// A message being read.
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("Truncated message"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    /// A length of items of `size` bytes, checked against the rest of the message.
+    fn len(&mut self, size: usize) -> io::Result<usize> {
+        let n = self.u64()?;
+        if n > (self.0.len() / size) as u64 {
+            return Err(invalid("Truncated message"));
+        }
+        Ok(n as usize)
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let n = self.len(1)?;
+        String::from_utf8(self.bytes(n)?.to_vec()).map_err(|_| invalid("Invalid string"))
+    }
+
+    fn option_u64(&mut self) -> io::Result<Option<u64>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.u64()?)),
+            _ => Err(invalid("Invalid option")),
+        }
+    }
+
+    fn f64s(&mut self, n: usize) -> io::Result<Vec<f64>> {
+        (0..n).map(|_| self.f64()).collect()
+    }
+
+    fn vector(&mut self) -> io::Result<Array1<f64>> {
+        let n = self.len(8)?;
+        Ok(Array::from_vec(self.f64s(n)?))
+    }
+
+    fn matrix(&mut self) -> io::Result<Array2<f64>> {
+        let rows = self.u64()?;
+        let cols = self.u64()?;
+        let n = rows.checked_mul(cols).ok_or_else(|| invalid("Invalid matrix shape"))?;
+        if n > (self.0.len() / 8) as u64 {
+            return Err(invalid("Truncated message"));
+        }
+        Array::from_shape_vec((rows as usize, cols as usize), self.f64s(n as usize)?)
+            .map_err(|_| invalid("Invalid matrix shape"))
+    }
+
+    fn types(&mut self) -> io::Result<Vec<BoundType>> {
+        let n = self.len(1)?;
+        (0..n)
+            .map(|_| match self.u8()? {
+                0 => Ok(BoundType::Unbounded),
+                1 => Ok(BoundType::Above),
+                2 => Ok(BoundType::Below),
+                3 => Ok(BoundType::Both),
+                _ => Err(invalid("Invalid bound type")),
+            })
+            .collect()
+    }
+
+    fn retry(&mut self) -> io::Result<RetryPolicy> {
+        Ok(RetryPolicy {
+            max_doublings: self.u32()?,
+            total_eval_budget: self.option_u64()?,
+            backoff: self.f64()?,
+        })
+    }
+
+    fn kernel(&mut self) -> io::Result<Kernel> {
+        match self.u8()? {
+            0 => Ok(Kernel::Normal),
+            1 => Ok(Kernel::StudentT { nu: self.f64()? }),
+            _ => Err(invalid("Invalid kernel")),
+        }
+    }
+
+    fn state(&mut self) -> io::Result<MVInform> {
+        match self.u8()? {
+            0 => Ok(MVInform::Normal),
+            1 => Ok(MVInform::PtLimitReached),
+            2 => Ok(MVInform::IllConditioned),
+            _ => Err(invalid("Invalid state")),
+        }
+    }
+
+    /// The error written by `Encoder::error`, whose first byte is `tag`.
+    fn error(&mut self, tag: u8) -> io::Result<MvDistError> {
+        Ok(match tag {
+            1 => {
+                MvDistError::DimensionTooLarge {
+                    n: self.u64()? as usize,
+                    max: self.u64()? as usize,
+                }
+            }
+            2 => MvDistError::NotPositiveSemidefinite,
+            3 => MvDistError::InvalidInput(self.str()?),
+            4 => {
+                MvDistError::RootNotBracketed {
+                    alpha: self.f64()?,
+                    lo: self.f64()?,
+                    hi: self.f64()?,
+                }
+            }
+            5 => MvDistError::Cancelled,
+            6 => {
+                MvDistError::MemoryLimitExceeded {
+                    required: self.u64()? as usize,
+                    limit: self.u64()? as usize,
+                }
+            }
+            _ => return Err(invalid("Invalid response")),
+        })
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("Trailing bytes in message"))
+        }
+    }
+}
+
+fn encode_problem(problem: &MvProblem) -> Vec<u8> {
+    let mut e = Encoder(Vec::new());
+    e.matrix(&problem.cov);
+    e.kernel(problem.kernel);
+    e.vector(&problem.lb);
+    e.vector(&problem.ub);
+    e.types(&problem.types);
+    e.matrix(&problem.constraints);
+    e.vector(&problem.delta);
+    let config = &problem.config;
+    e.u64(config.maxpts);
+    e.f64(config.tolerance.abseps());
+    e.f64(config.tolerance.releps());
+    e.option_u64(config.seed);
+    e.u8(match config.backend {
+        None => 0,
+        Some(Backend::Fortran) => 1,
+        Some(Backend::Native) => 2,
+        Some(Backend::SparseGrid) => 3,
+    });
+    match config.reorder {
+        Reorder::Auto => e.u8(0),
+        Reorder::None => e.u8(1),
+        Reorder::Custom(ref order) => {
+            e.u8(2);
+            e.len(order.len());
+            for &i in order {
+                e.u64(i as u64);
+            }
+        }
+    }
+    e.retry(&config.retry);
+    e.0
+}
+
+fn decode_problem(message: &[u8]) -> io::Result<MvProblem> {
+    let mut d = Decoder(message);
+    let cov = d.matrix()?;
+    let kernel = d.kernel()?;
+    let lb = d.vector()?;
+    let ub = d.vector()?;
+    let types = d.types()?;
+    let constraints = d.matrix()?;
+    let delta = d.vector()?;
+    let maxpts = d.u64()?;
+    let (abseps, releps) = (d.f64()?, d.f64()?);
+    let tolerance = Tolerance::both(abseps, releps).map_err(|e| invalid(&e.to_string()))?;
+    let seed = d.option_u64()?;
+    let backend = match d.u8()? {
+        0 => None,
+        1 => Some(Backend::Fortran),
+        2 => Some(Backend::Native),
+        3 => Some(Backend::SparseGrid),
+        _ => return Err(invalid("Invalid backend")),
+    };
+    let reorder = match d.u8()? {
+        0 => Reorder::Auto,
+        1 => Reorder::None,
+        2 => {
+            let n = d.len(8)?;
+            let order = (0..n).map(|_| d.u64().map(|i| i as usize)).collect::<io::Result<_>>()?;
+            Reorder::Custom(order)
+        }
+        _ => return Err(invalid("Invalid ordering")),
+    };
+    let retry = d.retry()?;
+    d.finish()?;
+    Ok(MvProblem {
+        cov: cov,
+        kernel: kernel,
+        lb: lb,
+        ub: ub,
+        types: types,
+        constraints: constraints,
+        delta: delta,
+        config: EvalConfig {
+            maxpts: maxpts,
+            tolerance: tolerance,
+            seed: seed,
+            backend: backend,
+            reorder: reorder,
+            retry: retry,
+        },
+    })
+}
+
+fn encode_response(response: &Result<MVResult, MvDistError>) -> Vec<u8> {
+    let mut e = Encoder(Vec::new());
+    match *response {
+        Ok(ref r) => {
+            e.u8(0);
+            e.f64(r.value);
+            e.f64(r.error);
+            e.u64(r.nevals);
+            e.state(r.state);
+        }
+        Err(ref error) => e.error(error),
+    }
+    e.0
+}
+
+fn decode_response(message: &[u8]) -> io::Result<Result<MVResult, MvDistError>> {
+    let mut d = Decoder(message);
+    let response = match d.u8()? {
+        0 => {
+            Ok(MVResult {
+                value: d.f64()?,
+                error: d.f64()?,
+                nevals: d.u64()?,
+                state: d.state()?,
+            })
+        }
+        tag => Err(d.error(tag)?),
+    };
+    d.finish()?;
+    Ok(response)
+}
+
+/// The settings of a batch of critical values, as given to `crit::search_group`.
+struct CritSettings {
+    maxpts: u64,
+    abseps: f64,
+    releps: f64,
+    seed: u64,
+    finder: RootFinder,
+}
+
+fn encode_crit_request(group: &[(usize, &CritProblem)], settings: &CritSettings) -> Vec<u8> {
+    let mut e = Encoder(vec![CRIT_REQUEST]);
+    e.u64(settings.maxpts);
+    e.f64(settings.abseps);
+    e.f64(settings.releps);
+    e.u64(settings.seed);
+    let finder = &settings.finder;
+    e.f64(finder.bracket.0);
+    e.f64(finder.bracket.1);
+    e.len(finder.max_iterations);
+    e.f64(finder.tolerance);
+    e.retry(&finder.retry);
+    e.len(group.len());
+    for &(i, p) in group {
+        e.len(i);
+        e.matrix(&p.cov);
+        e.kernel(p.kernel);
+        e.types(&p.types);
+        e.matrix(&p.constraints);
+        e.f64(p.alpha);
+    }
+    e.0
+}
+
+/// The group and settings of a request of `encode_crit_request`, without its first byte.
+fn decode_crit_request(message: &[u8]) -> io::Result<(Vec<(usize, CritProblem)>, CritSettings)> {
+    let mut d = Decoder(message);
+    let (maxpts, abseps, releps, seed) = (d.u64()?, d.f64()?, d.f64()?, d.u64()?);
+    let finder = RootFinder {
+        bracket: (d.f64()?, d.f64()?),
+        max_iterations: d.u64()? as usize,
+        tolerance: d.f64()?,
+        retry: d.retry()?,
+    };
+    let n = d.u64()?;
+    let mut group = Vec::new();
+    for _ in 0..n {
+        let i = d.u64()? as usize;
+        let cov = d.matrix()?;
+        let kernel = d.kernel()?;
+        group.push((i,
+                    CritProblem {
+                        cov: cov,
+                        kernel: kernel,
+                        types: d.types()?,
+                        constraints: d.matrix()?,
+                        alpha: d.f64()?,
+                    }));
+    }
+    d.finish()?;
+    let settings = CritSettings {
+        maxpts: maxpts,
+        abseps: abseps,
+        releps: releps,
+        seed: seed,
+        finder: finder,
+    };
+    Ok((group, settings))
+}
+
+fn encode_crit_response(results: &[Result<MvCritResult, MvDistError>]) -> Vec<u8> {
+    let mut e = Encoder(Vec::new());
+    e.len(results.len());
+    for result in results {
+        match *result {
+            Ok(ref r) => {
+                e.u8(0);
+                e.f64(r.crit);
+                e.f64(r.achieved_alpha);
+                e.f64(r.alpha_error);
+                e.u64(r.nevals);
+                e.f64(r.bracket.0);
+                e.f64(r.bracket.1);
+                e.state(r.state);
+            }
+            Err(ref error) => e.error(error),
+        }
+    }
+    e.0
+}
+
+fn decode_crit_response(message: &[u8]) -> io::Result<Vec<Result<MvCritResult, MvDistError>>> {
+    let mut d = Decoder(message);
+    let n = d.len(1)?;
+    let mut results = Vec::with_capacity(n);
+    for _ in 0..n {
+        results.push(match d.u8()? {
+            0 => {
+                Ok(MvCritResult {
+                    crit: d.f64()?,
+                    achieved_alpha: d.f64()?,
+                    alpha_error: d.f64()?,
+                    nevals: d.u64()?,
+                    bracket: (d.f64()?, d.f64()?),
+                    state: d.state()?,
+                })
+            }
+            tag => Err(d.error(tag)?),
+        });
+    }
+    d.finish()?;
+    Ok(results)
+}
+
+fn write_frame<W: Write>(output: &mut W, message: &[u8]) -> io::Result<()> {
+    if message.len() > ::std::u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Message too long"));
+    }
+    output.write_all(&(message.len() as u32).to_le_bytes())?;
+    output.write_all(message)?;
+    output.flush()
+}
+
+/// The next frame, or `None` at the end of the input.
+fn read_frame<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    loop {
+        match input.read(&mut len[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    input.read_exact(&mut len[1..])?;
+    let mut message = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+/// `problem` as a request for `MvProblem::mvdist`.
+fn mvdist_request(problem: &MvProblem) -> Vec<u8> {
+    let mut request = vec![MVDIST_REQUEST];
+    request.extend(encode_problem(problem));
+    request
+}
+
+/// Answer the requests of a `ProcessPool` read from `input` until it is closed, evaluating each
+/// problem with `MvProblem::mvdist`, or searching each group of critical-value problems as
+/// `crit::mvcrit_batch` does, and writing the response to `output`. Once `cancel` is cancelled
+/// the problems are answered with `MvDistError::Cancelled` instead of being evaluated.
+pub fn serve<R: Read, W: Write>(input: R,
+                                mut output: W,
+                                cancel: &CancellationToken)
+                                -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    while let Some(request) = read_frame(&mut input)? {
+        let response = match request.split_first() {
+            Some((&MVDIST_REQUEST, problem)) => {
+                let problem = decode_problem(problem)?;
+                encode_response(&if cancel.is_cancelled() {
+                    Err(MvDistError::Cancelled)
+                } else {
+                    problem.mvdist()
+                })
+            }
+            Some((&CRIT_REQUEST, group)) => {
+                let (group, s) = decode_crit_request(group)?;
+                let members = group.iter().map(|&(i, ref p)| (i, p)).collect::<Vec<_>>();
+                let results = crit::search_group(&members,
+                                                 s.maxpts,
+                                                 s.abseps,
+                                                 s.releps,
+                                                 s.seed,
+                                                 &s.finder,
+                                                 cancel);
+                encode_crit_response(&results)
+            }
+            _ => return Err(invalid("Invalid request")),
+        };
+        write_frame(&mut output, &response)?;
+    }
+    Ok(())
+}
+
+struct Worker {
+    child: Child,
+    /// `None` once the worker has been told to exit.
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn exchange(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        let stdin = self.stdin.as_mut().expect("worker already closed");
+        write_frame(stdin, request)?;
+        match read_frame(&mut self.stdout)? {
+            Some(response) => Ok(response),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Worker exited")),
+        }
+    }
+}
+
+/// A set of worker processes evaluating problems in parallel. The workers exit when the pool is
+/// dropped.
+pub struct ProcessPool {
+    workers: Vec<Worker>,
+}
+
+impl ProcessPool {
+    /// Start `workers` copies of `program` with `args`, each of which should call `serve` with its
+    /// standard input and output, e.g. `ProcessPool::new(4, "mvdist", &["worker"])`. Their standard
+    /// error is inherited.
+    pub fn new<P, S>(workers: usize, program: P, args: &[S]) -> io::Result<ProcessPool>
+        where P: AsRef<OsStr>,
+              S: AsRef<OsStr>
+    {
+        if workers == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A pool needs a worker"));
+        }
+        let mut pool = ProcessPool { workers: Vec::with_capacity(workers) };
+        for _ in 0..workers {
+            let mut child = Command::new(program.as_ref())
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let (stdin, stdout) = (child.stdin.take(), child.stdout.take());
+            pool.workers.push(Worker {
+                child: child,
+                stdin: stdin,
+                stdout: BufReader::new(stdout.expect("stdout is piped")),
+            });
+        }
+        Ok(pool)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Evaluate `problems` with `MvProblem::mvdist` in the workers, each worker taking the next
+    /// problem as it finishes the previous one, until `cancel` is cancelled. The results are in the
+    /// order of `problems`, with `MvDistError::Cancelled` for those not sent to a worker. Fails if
+    /// communicating with any worker fails, in which case the pool should not be used again.
+    pub fn evaluate_all(&mut self,
+                        problems: &[MvProblem],
+                        cancel: &CancellationToken)
+                        -> io::Result<Vec<Result<MVResult, MvDistError>>> {
+        let requests = problems.iter().map(mvdist_request).collect();
+        self.exchange_all(requests, cancel)?
+            .iter()
+            .map(|r| r.as_ref().map_or(Ok(Err(MvDistError::Cancelled)), |r| decode_response(r)))
+            .collect()
+    }
+
+    /// `crit::mvcrit_batch` with the groups of similar problems searched in the workers, each
+    /// worker taking the next group as it finishes the previous one, until `cancel` is cancelled.
+    /// The results are those of `mvcrit_batch`, in the order of `problems`, with
+    /// `MvDistError::Cancelled` for the groups not sent to a worker. Fails as `evaluate_all` does.
+    pub fn mvcrit_batch(&mut self,
+                        problems: &[CritProblem],
+                        maxpts: u64,
+                        abseps: f64,
+                        releps: f64,
+                        seed: u64,
+                        finder: &RootFinder,
+                        cancel: &CancellationToken)
+                        -> io::Result<Vec<Result<MvCritResult, MvDistError>>> {
+        let settings = CritSettings {
+            maxpts: maxpts,
+            abseps: abseps,
+            releps: releps,
+            seed: seed,
+            finder: *finder,
+        };
+        let groups = crit::group(problems);
+        let requests = groups.iter()
+            .map(|g| {
+                let members = g.iter().map(|&i| (i, &problems[i])).collect::<Vec<_>>();
+                encode_crit_request(&members, &settings)
+            })
+            .collect();
+        let mut results = (0..problems.len()).map(|_| None).collect::<Vec<_>>();
+        for (g, response) in groups.iter().zip(self.exchange_all(requests, cancel)?) {
+            let searched = match response {
+                Some(response) => decode_crit_response(&response)?,
+                None => g.iter().map(|_| Err(MvDistError::Cancelled)).collect(),
+            };
+            if searched.len() != g.len() {
+                return Err(invalid("Wrong number of critical values"));
+            }
+            for (&i, result) in g.iter().zip(searched) {
+                results[i] = Some(result);
+            }
+        }
+        Ok(results.into_iter().map(|r| r.expect("every problem is searched")).collect())
+    }
+
+    /// Send each of `requests` to the next free worker until `cancel` is cancelled, returning the
+    /// responses in order, with `None` for the requests not sent.
+    fn exchange_all(&mut self,
+                    requests: Vec<Vec<u8>>,
+                    cancel: &CancellationToken)
+                    -> io::Result<Vec<Option<Vec<u8>>>> {
+        let n = requests.len();
+        let requests = Arc::new(requests);
+        let next = Arc::new(AtomicUsize::new(0));
+        let threads = self.workers
+            .drain(..)
+            .map(|mut worker| {
+                let (requests, next, cancel) = (requests.clone(), next.clone(), cancel.clone());
+                thread::spawn(move || {
+                    let mut responses = Vec::new();
+                    let status = loop {
+                        if cancel.is_cancelled() {
+                            break Ok(());
+                        }
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        if i >= requests.len() {
+                            break Ok(());
+                        }
+                        match worker.exchange(&requests[i]) {
+                            Ok(response) => responses.push((i, response)),
+                            Err(e) => break Err(e),
+                        }
+                    };
+                    (worker, status.map(|()| responses))
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut results = vec![None; n];
+        let mut status = Ok(());
+        for thread in threads {
+            let (worker, responses) = thread.join().expect("pool thread panicked");
+            self.workers.push(worker);
+            match responses {
+                Ok(responses) => {
+                    for (i, response) in responses {
+                        results[i] = Some(response);
+                    }
+                }
+                Err(e) => status = Err(e),
+            }
+        }
+        status?;
+        Ok(results)
+    }
+}
+
+impl Drop for ProcessPool {
+    fn drop(&mut self) {
+        for worker in &mut self.workers {
+            // closing the input tells the worker to exit
+            worker.stdin = None;
+        }
+        for worker in &mut self.workers {
+            let _ = worker.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    fn problem() -> MvProblem {
+        let mut problem = MvProblem::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]),
+                                         Array::from_vec(vec![::std::f64::NEG_INFINITY, -1.0]),
+                                         Array::from_vec(vec![0.0, 1.0]),
+                                         vec![BoundType::Above, BoundType::Both]);
+        problem.kernel = Kernel::StudentT { nu: 4.5 };
+        problem.config.seed = Some(7);
+        problem.config.reorder = Reorder::Custom(vec![1, 0]);
+        problem.config.retry.total_eval_budget = Some(1_000_000);
+        problem
+    }
+
+    #[test]
+    fn problems_round_trip() {
+        let problem = problem();
+        let message = encode_problem(&problem);
+        assert_eq!(decode_problem(&message).unwrap(), problem);
+        assert!(decode_problem(&message[..message.len() - 1]).is_err());
+        let mut longer = message.clone();
+        longer.push(0);
+        assert!(decode_problem(&longer).is_err());
+        for response in &[problem.mvdist(),
+                          Err(MvDistError::InvalidInput("bad".to_string())),
+                          Err(MvDistError::DimensionTooLarge { n: 2000, max: 1000 }),
+                          Err(MvDistError::MemoryLimitExceeded {
+                              required: 1 << 40,
+                              limit: 1 << 30,
+                          })] {
+            assert_eq!(decode_response(&encode_response(response)).unwrap(), *response);
+        }
+    }
+
+    #[test]
+    fn serves_requests_until_the_input_ends() {
+        let mut invalid = problem();
+        invalid.lb = Array::zeros((3,));
+        let mut input = Vec::new();
+        for p in &[problem(), invalid.clone()] {
+            write_frame(&mut input, &mvdist_request(p)).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(&input[..], &mut output, &CancellationToken::new()).unwrap();
+        let mut output = &output[..];
+        for p in &[problem(), invalid] {
+            let response = read_frame(&mut output).unwrap().unwrap();
+            assert_eq!(decode_response(&response).unwrap(), p.mvdist());
+        }
+        assert!(read_frame(&mut output).unwrap().is_none());
+    }
+
+    #[test]
+    fn serves_critical_value_groups() {
+        let crit = |rho: f64, kernel: Kernel| {
+            CritProblem {
+                cov: ::corr::exchangeable(3, rho),
+                kernel: kernel,
+                types: vec![BoundType::Both; 3],
+                constraints: Array::eye(3),
+                alpha: 0.05,
+            }
+        };
+        let problems = vec![crit(0.2, Kernel::Normal),
+                            crit(0.5, Kernel::StudentT { nu: 5.0 }),
+                            crit(0.3, Kernel::Normal)];
+        let finder = RootFinder::default();
+        let cancel = CancellationToken::new();
+        let expected = crit::mvcrit_batch(&problems, 100_000, 1e-4, 0.0, 3, &finder, &cancel);
+        let settings = CritSettings {
+            maxpts: 100_000,
+            abseps: 1e-4,
+            releps: 0.0,
+            seed: 3,
+            finder: finder,
+        };
+        // the first group, as `mvcrit_batch` forms it
+        let group = vec![(0, &problems[0]), (2, &problems[2])];
+        let mut input = Vec::new();
+        write_frame(&mut input, &encode_crit_request(&group, &settings)).unwrap();
+        let mut output = Vec::new();
+        serve(&input[..], &mut output, &cancel).unwrap();
+        let response = read_frame(&mut &output[..]).unwrap().unwrap();
+        assert_eq!(decode_crit_response(&response).unwrap(),
+                   vec![expected[0].clone(), expected[2].clone()]);
+        let errors = vec![Err(MvDistError::InvalidInput("bad".to_string())), expected[1].clone()];
+        assert_eq!(decode_crit_response(&encode_crit_response(&errors)).unwrap(), errors);
+        assert!(serve(&[1, 0, 0, 0, 9][..], &mut Vec::new(), &cancel).is_err());
+    }
+
+    /// Input given one frame per read, which cancels `cancel` when the frame `cancel_at` is read.
+    struct Frames {
+        frames: Vec<Vec<u8>>,
+        cancel_at: usize,
+        cancel: CancellationToken,
+    }
+
+    impl Read for Frames {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.frames.is_empty() {
+                return Ok(0);
+            }
+            if self.cancel_at == 0 {
+                self.cancel.cancel();
+            }
+            self.cancel_at = self.cancel_at.wrapping_sub(1);
+            let frame = self.frames.remove(0);
+            buf[..frame.len()].copy_from_slice(&frame);
+            Ok(frame.len())
+        }
+    }
+
+    #[test]
+    fn cancelling_mid_batch_keeps_completed_results() {
+        let cancel = CancellationToken::new();
+        let frames = (0..3)
+            .map(|_| {
+                let mut frame = Vec::new();
+                write_frame(&mut frame, &mvdist_request(&problem())).unwrap();
+                frame
+            })
+            .collect();
+        let input = Frames {
+            frames: frames,
+            cancel_at: 2,
+            cancel: cancel.clone(),
+        };
+        let mut output = Vec::new();
+        serve(input, &mut output, &cancel).unwrap();
+        let mut output = &output[..];
+        let mut responses = Vec::new();
+        while let Some(response) = read_frame(&mut output).unwrap() {
+            responses.push(decode_response(&response).unwrap());
+        }
+        let expected = problem().mvdist();
+        assert_eq!(responses,
+                   vec![expected.clone(), expected, Err(MvDistError::Cancelled)]);
+    }
+}
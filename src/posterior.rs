@@ -0,0 +1,263 @@
+//! Posterior probabilities of rectangles for Bayesian models with a multivariate normal sampling
+//! distribution, as for "the probability that all effects are positive":
+//! `E[P(lower < X < upper | μ, Σ)]` over the posterior of the mean `μ` and covariance `Σ`, the
+//! average of the probability over posterior draws. The draws are given as a matrix of means and
+//! a covariance for each row, or sampled from a `NormalInverseWishart` posterior.
+//!
+//! The draws are evaluated as by `mvdist_many_covs`, with an independent seed for each, so the
+//! sample variance of the probabilities of the draws includes their integration errors, and the
+//! standard error of the average accounts for both the sampling of the posterior and the
+//! integration.
+
+use linalg::{cholesky, solve_lower};
+use native::{SeedStreams, SplitMix64, DEFAULT_SEED};
+use ndarray::prelude::*;
+use rand::Rng;
+use rand::distributions::{Gamma, IndependentSample, Normal};
+use {BoundType, CancellationToken, EvalConfig, Kernel, MVInform, MVResult, MvDistError,
+     ERROR_SCALE};
+
+/// The result of `probability`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PosteriorProbability {
+    /// The average over the draws of the probability of the rectangle.
+    pub value: f64,
+    /// The standard error of `value`, from the sample variance of the probabilities of the draws.
+    pub std_error: f64,
+    pub draws: usize,
+    /// The integrand evaluations of all draws.
+    pub nevals: u64,
+    /// `MVInform::PtLimitReached` if any draw did not meet its tolerance.
+    pub state: MVInform,
+}
+
+impl PosteriorProbability {
+    /// The probability as an `MVResult`, with `error` at `ERROR_SCALE` standard errors.
+    pub fn result(&self) -> MVResult {
+        MVResult {
+            value: self.value,
+            error: ERROR_SCALE * self.std_error,
+            nevals: self.nevals,
+            state: self.state,
+        }
+    }
+}
+
+/// The posterior expected probability of `lower < X < upper`, with infinite limits leaving a side
+/// unbounded, over the draws whose means are the rows of `means` and whose covariances are `covs`.
+/// Each draw is evaluated with the tolerance, `maxpts` and backend of `config`, and draw `i` with
+/// the seed of stream `i` of `native::SeedStreams` for the seed of `config` (or
+/// `native::DEFAULT_SEED`). An error in any draw is returned, since the average would be biased
+/// without it.
+pub fn probability(means: &Array2<f64>,
+                   covs: &[Array2<f64>],
+                   lower: &Array1<f64>,
+                   upper: &Array1<f64>,
+                   config: &EvalConfig)
+                   -> Result<PosteriorProbability, MvDistError> {
+    let (k, n) = (covs.len(), lower.len());
+    if means.rows() != k || means.cols() != n || upper.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected {} means of length {} for {} \
+                                                      covariances, got a {}x{} matrix",
+                                                     k,
+                                                     n,
+                                                     k,
+                                                     means.rows(),
+                                                     means.cols())));
+    }
+    if k < 2 {
+        return Err(MvDistError::InvalidInput(format!("At least 2 draws are needed, got {}", k)));
+    }
+    let types = (0..n).map(|i| BoundType::from_limits(lower[i], upper[i])).collect::<Vec<_>>();
+    let results = ::mvdist_many_draws(covs,
+                                      Some(means),
+                                      Kernel::Normal,
+                                      lower,
+                                      upper,
+                                      &types,
+                                      &Array::eye(n),
+                                      &Array::zeros((n,)),
+                                      config,
+                                      &CancellationToken::new())?
+        .into_iter()
+        .collect::<Result<Vec<MVResult>, MvDistError>>()?;
+    let value = results.iter().map(|r| r.value).sum::<f64>() / k as f64;
+    let variance = results.iter().map(|r| (r.value - value).powi(2)).sum::<f64>() /
+                   (k - 1) as f64;
+    Ok(PosteriorProbability {
+        value: value,
+        std_error: (variance / k as f64).sqrt(),
+        draws: k,
+        nevals: results.iter().map(|r| r.nevals).sum(),
+        state: if results.iter().all(|r| r.state == MVInform::Normal) {
+            MVInform::Normal
+        } else {
+            MVInform::PtLimitReached
+        },
+    })
+}
+
+/// The normal-inverse-Wishart distribution of `(μ, Σ)`: `Σ` inverse Wishart with scale matrix
+/// `scale` and `nu` degrees of freedom, and `μ` given `Σ` normal with mean `mean` and covariance
+/// `Σ / kappa`, the conjugate posterior of a multivariate normal sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalInverseWishart {
+    mean: Array1<f64>,
+    kappa: f64,
+    nu: f64,
+    scale: Array2<f64>,
+    /// The Cholesky factor of `scale`.
+    factor: Array2<f64>,
+}
+
+impl NormalInverseWishart {
+    /// The distribution with `kappa > 0`, `nu > n - 1` for `n` variables and `scale` positive
+    /// definite.
+    pub fn new(mean: Array1<f64>,
+               kappa: f64,
+               nu: f64,
+               scale: Array2<f64>)
+               -> Result<NormalInverseWishart, MvDistError> {
+        let n = mean.len();
+        if n == 0 || scale.rows() != n || scale.cols() != n || mean.iter().any(|m| !m.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Invalid mean of length {} and {}x{} \
+                                                          scale matrix",
+                                                         n,
+                                                         scale.rows(),
+                                                         scale.cols())));
+        }
+        if !(kappa > 0.0 && kappa.is_finite() && nu > (n - 1) as f64 && nu.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Invalid parameters kappa = {} and nu = \
+                                                          {} for {} variables",
+                                                         kappa,
+                                                         nu,
+                                                         n)));
+        }
+        let factor = match cholesky(&scale) {
+            Some(l) => l,
+            None => {
+                return Err(MvDistError::InvalidInput(format!("The scale matrix is not positive \
+                                                              definite")))
+            }
+        };
+        Ok(NormalInverseWishart {
+            mean: mean,
+            kappa: kappa,
+            nu: nu,
+            scale: scale,
+            factor: factor,
+        })
+    }
+
+    /// Draw `(μ, Σ)`, with `Σ = U (A Aᵀ)⁻¹ Uᵀ` for the factor `U` of the scale matrix and the
+    /// Bartlett factor `A` of a standard Wishart matrix.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (Array1<f64>, Array2<f64>) {
+        let n = self.mean.len();
+        let normal = Normal::new(0.0, 1.0);
+        let mut a = Array2::zeros((n, n));
+        for i in 0..n {
+            a[[i, i]] = Gamma::new((self.nu - i as f64) / 2.0, 2.0).ind_sample(rng).sqrt();
+            for j in 0..i {
+                a[[i, j]] = normal.ind_sample(rng);
+            }
+        }
+        // Σ = M Mᵀ with M = U A⁻ᵀ, so each row of M solves A x = the row of U
+        let mut m = Array2::zeros((n, n));
+        for i in 0..n {
+            let row = solve_lower(&a, &self.factor.row(i).to_vec());
+            for j in 0..n {
+                m[[i, j]] = row[j];
+            }
+        }
+        let z = Array::from_vec((0..n).map(|_| normal.ind_sample(rng)).collect());
+        let mean = &self.mean + &(m.dot(&z) / self.kappa.sqrt());
+        (mean, m.dot(&m.t()))
+    }
+
+    /// The posterior expected probability of `lower < X < upper` as by `probability`, over `draws`
+    /// draws sampled with `sample` from stream `draws` of `native::SeedStreams` for the seed of
+    /// `config`, which the evaluations of the draws do not use.
+    pub fn probability(&self,
+                       draws: usize,
+                       lower: &Array1<f64>,
+                       upper: &Array1<f64>,
+                       config: &EvalConfig)
+                       -> Result<PosteriorProbability, MvDistError> {
+        let n = self.mean.len();
+        let streams = SeedStreams::new(config.seed.unwrap_or(DEFAULT_SEED));
+        let mut rng = SplitMix64::new(streams.seed(draws as u64));
+        let mut means = Array2::zeros((draws, n));
+        let mut covs = Vec::with_capacity(draws);
+        for i in 0..draws {
+            let (mean, cov) = self.sample(&mut rng);
+            means.row_mut(i).assign(&mean);
+            covs.push(cov);
+        }
+        probability(&means, &covs, lower, upper, config)
+    }
+
+    pub fn mean(&self) -> &Array1<f64> {
+        &self.mean
+    }
+
+    pub fn kappa(&self) -> f64 {
+        self.kappa
+    }
+
+    pub fn nu(&self) -> f64 {
+        self.nu
+    }
+
+    pub fn scale(&self) -> &Array2<f64> {
+        &self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use univariate::norm_cdf;
+    use {mvdist_config, Backend};
+
+    #[test]
+    fn averages_over_draws() {
+        let inf = ::std::f64::INFINITY;
+        let means = arr2(&[[0.0, 0.0], [1.0, 1.0]]);
+        let covs = vec![Array::eye(2), Array::eye(2)];
+        let (lower, upper) = (Array::zeros((2,)), arr1(&[inf, inf]));
+        let p = probability(&means, &covs, &lower, &upper, &EvalConfig::default()).unwrap();
+        let (p0, p1) = (0.25, norm_cdf(1.0).powi(2));
+        assert!((p.value - 0.5 * (p0 + p1)).abs() < 1e-10);
+        assert!((p.std_error - 0.5 * (p1 - p0)).abs() < 1e-10);
+        assert_eq!((p.draws, p.state), (2, MVInform::Normal));
+        assert!(probability(&means, &covs[..1], &lower, &upper, &EvalConfig::default()).is_err());
+    }
+
+    #[test]
+    fn normal_inverse_wishart_predictive() {
+        let inf = ::std::f64::INFINITY;
+        let (mean, kappa, nu) = (arr1(&[0.3, 0.1]), 4.0, 8.0);
+        let scale = arr2(&[[6.0, 1.5], [1.5, 3.0]]);
+        let niw = NormalInverseWishart::new(mean.clone(), kappa, nu, scale.clone()).unwrap();
+        let (lower, upper) = (Array::zeros((2,)), arr1(&[inf, inf]));
+        let config = EvalConfig { backend: Some(Backend::Native), ..EvalConfig::default() };
+        let p = niw.probability(2000, &lower, &upper, &config).unwrap();
+        // averaged over the posterior, X is t with nu - 1 degrees of freedom, location `mean` and
+        // scale matrix `scale (kappa + 1) / (kappa (nu - 1))`
+        let df = nu - 1.0;
+        let exact = mvdist_config(&(scale * ((kappa + 1.0) / (kappa * df))),
+                                  Kernel::StudentT { nu: df },
+                                  &(&lower - &mean),
+                                  &upper,
+                                  &[BoundType::Below; 2],
+                                  &Array::eye(2),
+                                  &Array::zeros((2,)),
+                                  &EvalConfig::default())
+            .unwrap()
+            .value;
+        assert!((p.value - exact).abs() < 4.0 * p.std_error && p.std_error < 0.01);
+        assert_eq!(p.result().error, ERROR_SCALE * p.std_error);
+        assert!(NormalInverseWishart::new(mean, kappa, 0.5, Array::eye(2)).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Power and sample-size calculations for the max-t procedures in `multcomp`, for designs with
+//! normally distributed groups sharing a common variance that is estimated from the data.
+//!
+//! The statistic of contrast `c_i` is `c_i · mean / (s sqrt(c_i D c_i))`, where `D` is diagonal
+//! with the inverse group sizes and `s` is the pooled standard deviation, with `N - g` degrees of
+//! freedom for `N` observations in `g` groups. Under the alternative these statistics are jointly
+//! noncentral multivariate t, which `mvdist` evaluates through its `delta` argument.
+
+use multcomp::{critical_value, Alternative};
+use ndarray::prelude::*;
+use {BoundType, Kernel, MVResult, MvDistError};
+
+/// The true group means and standard deviation, and the test to be performed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Design {
+    /// The contrasts tested, one row per hypothesis and one column per group (see `contrasts`).
+    pub contrasts: Array2<f64>,
+    /// The group means under the alternative.
+    pub means: Vec<f64>,
+    /// The common standard deviation of the observations.
+    pub sd: f64,
+    pub alternative: Alternative,
+    /// The familywise error rate.
+    pub alpha: f64,
+}
+
+/// The probability of rejecting at least one hypothesis with the single-step max-t test of
+/// `design` when group `j` has `n[j]` observations. `value` is the power; `error` is the
+/// integration error of the final `mvdist` evaluation.
+pub fn power(design: &Design,
+             n: &[usize],
+             maxpts: u64,
+             abseps: f64)
+             -> Result<MVResult, MvDistError> {
+    let g = design.means.len();
+    let c = &design.contrasts;
+    if c.cols() != g || n.len() != g {
+        return Err(MvDistError::InvalidInput(format!("{} contrast columns and {} group sizes for \
+                                                      {} groups",
+                                                     c.cols(),
+                                                     n.len(),
+                                                     g)));
+    }
+    let total = n.iter().sum::<usize>();
+    if n.iter().any(|&nj| nj == 0) || total <= g {
+        return Err(MvDistError::InvalidInput("Too few observations to estimate the variance"
+            .to_string()));
+    }
+    if !(design.sd > 0.0) || !(design.alpha > 0.0 && design.alpha < 1.0) {
+        return Err(MvDistError::InvalidInput(format!("Invalid sd {} or alpha {}",
+                                                     design.sd,
+                                                     design.alpha)));
+    }
+    let kernel = Kernel::StudentT { nu: (total - g) as f64 };
+    let k = c.rows();
+    // covariance of the contrast estimates in units of the variance
+    let mut cov = Array2::zeros((k, k));
+    for a in 0..k {
+        for b in 0..k {
+            cov[[a, b]] = (0..g).map(|j| c[[a, j]] * c[[b, j]] / n[j] as f64).sum::<f64>();
+        }
+    }
+    let se = (0..k).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if se.iter().any(|&s| !(s > 0.0)) {
+        return Err(MvDistError::InvalidInput("Contrasts must not be zero".to_string()));
+    }
+    let mut corr = Array2::zeros((k, k));
+    for a in 0..k {
+        for b in 0..k {
+            corr[[a, b]] = cov[[a, b]] / (se[a] * se[b]);
+        }
+    }
+    let delta = (0..k)
+        .map(|i| (0..g).map(|j| c[[i, j]] * design.means[j]).sum::<f64>() / (design.sd * se[i]))
+        .collect::<Vec<f64>>();
+    let level = 1.0 - design.alpha;
+    let crit = critical_value(&corr, kernel, design.alternative, level, maxpts, abseps)?;
+    let (lower, upper, bound) = match design.alternative {
+        Alternative::TwoSided => (-crit, crit, BoundType::Both),
+        Alternative::Greater => (crit, crit, BoundType::Above),
+        Alternative::Less => (-crit, -crit, BoundType::Below),
+    };
+    let accept = ::mvdist_kernel(&corr,
+                                 kernel,
+                                 &Array::from_elem((k,), lower),
+                                 &Array::from_elem((k,), upper),
+                                 &vec![bound; k],
+                                 &Array::eye(k),
+                                 &Array::from_vec(delta),
+                                 maxpts,
+                                 abseps,
+                                 0.0)?;
+    Ok(MVResult { value: (1.0 - accept.value).max(0.0).min(1.0), ..accept })
+}
+
+/// The smallest common group size, at most `max_n`, for which `power` reaches `target`, found by
+/// doubling and then bisection. Returns the size with its power, or
+/// `MvDistError::InvalidInput` if `max_n` observations per group are not enough.
+pub fn sample_size(design: &Design,
+                   target: f64,
+                   max_n: usize,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<(usize, MVResult), MvDistError> {
+    let g = design.means.len();
+    let at = |n: usize| power(design, &vec![n; g], maxpts, abseps);
+    // with one group a second observation is needed for the variance estimate
+    let mut lo = 1;
+    let mut hi = 2;
+    let mut best = at(hi)?;
+    while best.value < target {
+        if hi >= max_n {
+            return Err(MvDistError::InvalidInput(format!("Power {:.4} at n = {} is below the \
+                                                          target {}",
+                                                         best.value,
+                                                         hi,
+                                                         target)));
+        }
+        lo = hi;
+        hi = (2 * hi).min(max_n);
+        best = at(hi)?;
+    }
+    // power(lo) < target <= power(hi), where lo = 1 is below any feasible size
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        let p = at(mid)?;
+        if p.value >= target {
+            hi = mid;
+            best = p;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok((hi, best))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contrasts::dunnett;
+
+    fn design(effect: f64) -> Design {
+        Design {
+            contrasts: dunnett(2),
+            means: vec![0.0, effect, effect],
+            sd: 1.0,
+            alternative: Alternative::TwoSided,
+            alpha: 0.05,
+        }
+    }
+
+    #[test]
+    fn power_under_null_is_alpha() {
+        let p = power(&design(0.0), &[10, 10, 10], 100_000, 1e-6).unwrap();
+        assert!((p.value - 0.05).abs() < 1e-4);
+        assert!(power(&design(1.0), &[10, 10], 1000, 1e-3).is_err());
+    }
+
+    #[test]
+    fn sample_size_reaches_target() {
+        let d = design(1.0);
+        let (n, p) = sample_size(&d, 0.8, 1000, 100_000, 1e-6).unwrap();
+        assert!(p.value >= 0.8);
+        assert!(power(&d, &[n - 1; 3], 100_000, 1e-6).unwrap().value < 0.8);
+        assert!(power(&d, &[2 * n; 3], 100_000, 1e-6).unwrap().value > p.value);
+        assert!(sample_size(&d, 0.8, 4, 100_000, 1e-6).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Preprocessing of the constraint system of a problem, for constraint matrices generated by a
+//! modeling layer, which often repeat constraints or include ones that cannot bind. Such rows do
+//! not change the probability, but they widen the integrands and degrade the accuracy of the
+//! result, and rows that conflict leave an empty region whose probability the integrators only
+//! approximate. `preprocess` finds
+//!
+//! - constraints whose coefficients are all zero, which either always or never hold;
+//! - constraints that are scalar multiples of an earlier one, which are merged into it with the
+//!   tightest of their limits;
+//! - constraints implied by the limits of the single-variable constraints, by interval arithmetic
+//!   on the box they define;
+//!
+//! and reports the constraints that conflict when it finds the region empty, whose probability
+//! is then exactly 0. Unlike the simplification of `cone`, which merges constraints that are
+//! perfectly correlated under the covariance, this only uses the constraint system, so its
+//! findings hold for any covariance.
+//!
+//! Limits of the t distribution scale with its mixing variable, so for `nu > 0` only the
+//! constraints without noncentrality are analyzed; the others are kept as they are.
+
+use ndarray::prelude::*;
+use native::Reorder;
+use {BoundType, EvalConfig, MVInform, MVResult, MvDistError, MvProblem};
+
+/// The relative difference below which two rows of coefficients count as multiples.
+const TOL: f64 = 1e-12;
+
+/// Why a constraint was removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Removal {
+    /// Its coefficients are all zero and its limits hold.
+    Constant,
+    /// Its coefficients are `factor` times those of constraint `of`, whose limits now include
+    /// its own.
+    Multiple { of: usize, factor: f64 },
+    /// It holds everywhere in the box of the single-variable constraints.
+    Implied,
+}
+
+/// A constraint removed by `preprocess`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Removed {
+    /// The index of the constraint in the original problem.
+    pub constraint: usize,
+    pub reason: Removal,
+}
+
+/// A problem with its redundant constraints removed.
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    /// The problem with the remaining constraints, or `None` if the region is empty or no
+    /// constraints remain. A problem without redundant constraints is kept as it is.
+    pub problem: Option<MvProblem>,
+    /// The remaining constraints, by their index in the original problem.
+    pub kept: Vec<usize>,
+    /// The removed constraints, in increasing order of index.
+    pub removed: Vec<Removed>,
+    /// Constraints that no point satisfies together, or empty if none were found.
+    pub conflict: Vec<usize>,
+}
+
+impl Preprocessed {
+    /// The probability of the original problem: exactly 0 for an empty region, exactly 1 when no
+    /// constraints remain, and otherwise the result of `MvProblem::mvdist` for `problem`.
+    pub fn mvdist(&self) -> Result<MVResult, MvDistError> {
+        match self.problem {
+            Some(ref problem) => problem.mvdist(),
+            None => {
+                Ok(MVResult {
+                    value: if self.conflict.is_empty() { 1.0 } else { 0.0 },
+                    error: 0.0,
+                    nevals: 0,
+                    state: MVInform::Normal,
+                })
+            }
+        }
+    }
+}
+
+/// The range of `Σ_j coef_j x_j` over the box `lower <= x <= upper`.
+fn range(coef: &[f64], lower: &[f64], upper: &[f64]) -> (f64, f64) {
+    let (mut lo, mut hi) = (0.0, 0.0);
+    for j in 0..coef.len() {
+        let c = coef[j];
+        if c > 0.0 {
+            lo += c * lower[j];
+            hi += c * upper[j];
+        } else if c < 0.0 {
+            lo += c * upper[j];
+            hi += c * lower[j];
+        }
+    }
+    (lo, hi)
+}
+
+/// Remove the redundant constraints of `problem` as described in the module documentation. Each
+/// analyzed constraint is taken as limits on `(C X)_i`, with the noncentrality subtracted from
+/// them; a constraint merged with its multiples is rewritten with the merged limits, and the
+/// others are kept unchanged. The settings of the remaining problem are those of `problem`,
+/// except that a custom constraint ordering is replaced by `Reorder::Auto` when constraints are
+/// removed.
+pub fn preprocess(problem: &MvProblem) -> Result<Preprocessed, MvDistError> {
+    problem.validate()?;
+    let (m, n) = (problem.constraints.rows(), problem.constraints.cols());
+    let c = &problem.constraints;
+    let normal = problem.kernel.nu()? == 0.0;
+    let analyzed = (0..m)
+        .map(|i| normal || problem.delta[i] == 0.0)
+        .collect::<Vec<bool>>();
+    let mut limits = (0..m)
+        .map(|i| {
+            let (lo, hi) = problem.types[i].limits(problem.lb[i], problem.ub[i]);
+            (lo - problem.delta[i], hi - problem.delta[i])
+        })
+        .collect::<Vec<(f64, f64)>>();
+    let empty = |conflict: Vec<usize>| {
+        Ok(Preprocessed {
+            problem: None,
+            kept: Vec::new(),
+            removed: Vec::new(),
+            conflict: conflict,
+        })
+    };
+    if let Some(i) = (0..m).find(|&i| analyzed[i] && !(limits[i].0 <= limits[i].1)) {
+        return empty(vec![i]);
+    }
+    // a constraint removed as a multiple of an earlier one, or merged with later multiples
+    let mut removed = vec![None; m];
+    let mut merged = vec![false; m];
+    let mut members: Vec<Vec<usize>> = (0..m).map(|i| vec![i]).collect();
+    for i in 0..m {
+        if !analyzed[i] {
+            continue;
+        }
+        if (0..n).all(|j| c[[i, j]] == 0.0) {
+            if !(limits[i].0 <= 0.0 && 0.0 <= limits[i].1) {
+                return empty(vec![i]);
+            }
+            removed[i] = Some(Removal::Constant);
+            continue;
+        }
+        let dot = |a: usize, b: usize| (0..n).map(|j| c[[a, j]] * c[[b, j]]).sum::<f64>();
+        let size = (0..n).map(|j| c[[i, j]].abs()).fold(0.0, f64::max);
+        let multiple = (0..i)
+            .filter(|&r| analyzed[r] && removed[r].is_none())
+            .map(|r| (r, dot(i, r) / dot(r, r)))
+            .find(|&(r, k)| (0..n).all(|j| (c[[i, j]] - k * c[[r, j]]).abs() <= TOL * size));
+        if let Some((r, k)) = multiple {
+            let (lo, hi) = (limits[i].0 / k, limits[i].1 / k);
+            let (lo, hi) = if k < 0.0 { (hi, lo) } else { (lo, hi) };
+            limits[r] = (limits[r].0.max(lo), limits[r].1.min(hi));
+            members[r].push(i);
+            removed[i] = Some(Removal::Multiple { of: r, factor: k });
+            merged[r] = true;
+            if !(limits[r].0 <= limits[r].1) {
+                return empty(members[r].clone());
+            }
+        }
+    }
+    // the box of the single-variable constraints, and the constraints defining each side
+    let (mut lower, mut upper) = (vec![::std::f64::NEG_INFINITY; n], vec![::std::f64::INFINITY; n]);
+    let mut sides = vec![Vec::new(); n];
+    for i in (0..m).filter(|&i| analyzed[i] && removed[i].is_none()) {
+        let nonzero = (0..n).filter(|&j| c[[i, j]] != 0.0).collect::<Vec<usize>>();
+        if nonzero.len() == 1 {
+            let (j, a) = (nonzero[0], c[[i, nonzero[0]]]);
+            let (lo, hi) = (limits[i].0 / a, limits[i].1 / a);
+            let (lo, hi) = if a < 0.0 { (hi, lo) } else { (lo, hi) };
+            lower[j] = lower[j].max(lo);
+            upper[j] = upper[j].min(hi);
+            sides[j].push(i);
+            if !(lower[j] <= upper[j]) {
+                return empty(sides[j].clone());
+            }
+        }
+    }
+    for i in 0..m {
+        if !analyzed[i] || removed[i].is_some() {
+            continue;
+        }
+        let coef = (0..n).map(|j| c[[i, j]]).collect::<Vec<f64>>();
+        let used = (0..n).filter(|&j| coef[j] != 0.0).collect::<Vec<usize>>();
+        if used.len() < 2 {
+            continue;
+        }
+        let (lo, hi) = range(&coef, &lower, &upper);
+        if limits[i].0 <= lo && hi <= limits[i].1 {
+            removed[i] = Some(Removal::Implied);
+        } else if hi < limits[i].0 || lo > limits[i].1 {
+            // with the single-variable constraints merged into those defining the box
+            let mut conflict = vec![i];
+            for &j in &used {
+                for &side in &sides[j] {
+                    conflict.extend(members[side].iter().cloned());
+                }
+            }
+            conflict.sort();
+            conflict.dedup();
+            return empty(conflict);
+        }
+    }
+    let removed = (0..m)
+        .filter_map(|i| {
+            removed[i].map(|reason| {
+                Removed {
+                    constraint: i,
+                    reason: reason,
+                }
+            })
+        })
+        .collect::<Vec<Removed>>();
+    let kept = (0..m)
+        .filter(|&i| removed.iter().all(|r| r.constraint != i))
+        .collect::<Vec<usize>>();
+    if removed.is_empty() {
+        return Ok(Preprocessed {
+            problem: Some(problem.clone()),
+            kept: kept,
+            removed: removed,
+            conflict: Vec::new(),
+        });
+    }
+    if kept.is_empty() {
+        return Ok(Preprocessed {
+            problem: None,
+            kept: kept,
+            removed: removed,
+            conflict: Vec::new(),
+        });
+    }
+    let mut constraints = Array2::zeros((kept.len(), n));
+    let (mut lb, mut ub) = (Array1::zeros((kept.len(),)), Array1::zeros((kept.len(),)));
+    let mut types = Vec::with_capacity(kept.len());
+    for (a, &i) in kept.iter().enumerate() {
+        for j in 0..n {
+            constraints[[a, j]] = c[[i, j]];
+        }
+        if merged[i] {
+            let (lo, hi) = (limits[i].0 + problem.delta[i], limits[i].1 + problem.delta[i]);
+            let finite = |x: f64| if x.is_finite() { x } else { 0.0 };
+            lb[a] = finite(lo);
+            ub[a] = finite(hi);
+            types.push(BoundType::from_limits(lo, hi));
+        } else {
+            lb[a] = problem.lb[i];
+            ub[a] = problem.ub[i];
+            types.push(problem.types[i]);
+        }
+    }
+    let delta = Array::from_vec(kept.iter().map(|&i| problem.delta[i]).collect());
+    Ok(Preprocessed {
+        problem: Some(MvProblem {
+            lb: lb,
+            ub: ub,
+            types: types,
+            constraints: constraints,
+            delta: delta,
+            config: EvalConfig {
+                reorder: match problem.config.reorder {
+                    Reorder::Custom(_) => Reorder::Auto,
+                    ref r => r.clone(),
+                },
+                ..problem.config.clone()
+            },
+            ..problem.clone()
+        }),
+        kept: kept,
+        removed: removed,
+        conflict: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::ar1;
+    use ndarray::prelude::*;
+    use Kernel;
+
+    #[test]
+    fn removes_redundant_rows() {
+        // X_0, X_1 and X_2 in boxes, 2 X_0 <= 1, X_1 + X_2 within ±10 and a zero row
+        let c = arr2(&[[1.0, 0.0, 0.0],
+                       [0.0, 1.0, 0.0],
+                       [0.0, 0.0, 1.0],
+                       [2.0, 0.0, 0.0],
+                       [0.0, 1.0, 1.0],
+                       [0.0, 0.0, 0.0]]);
+        let mut problem = MvProblem::new(ar1(3, 0.5),
+                                         Array::from_vec(vec![0.0, -1.0, -1.0, 0.0, -10.0, -1.0]),
+                                         Array::from_vec(vec![0.8, 1.0, 1.0, 1.0, 10.0, 1.0]),
+                                         vec![BoundType::Above,
+                                              BoundType::Both,
+                                              BoundType::Both,
+                                              BoundType::Above,
+                                              BoundType::Both,
+                                              BoundType::Both]);
+        problem.constraints = c;
+        problem.delta = Array::zeros((6,));
+        let p = preprocess(&problem).unwrap();
+        assert_eq!(p.kept, vec![0, 1, 2]);
+        assert_eq!(p.removed,
+                   vec![Removed {
+                            constraint: 3,
+                            reason: Removal::Multiple { of: 0, factor: 2.0 },
+                        },
+                        Removed {
+                            constraint: 4,
+                            reason: Removal::Implied,
+                        },
+                        Removed {
+                            constraint: 5,
+                            reason: Removal::Constant,
+                        }]);
+        let reduced = p.problem.clone().unwrap();
+        assert_eq!(reduced.ub[0], 0.5);
+        assert_eq!(reduced.constraints, Array::eye(3));
+        let mut direct = MvProblem::new(ar1(3, 0.5),
+                                        Array::from_vec(vec![0.0, -1.0, -1.0]),
+                                        Array::from_vec(vec![0.5, 1.0, 1.0]),
+                                        vec![BoundType::Above, BoundType::Both, BoundType::Both]);
+        direct.config = problem.config.clone();
+        let expected = direct.mvdist().unwrap().value;
+        assert!((p.mvdist().unwrap().value - expected).abs() < 1e-6);
+        // the noncentrality of the normal moves the limits
+        problem.delta[3] = 0.4;
+        let reduced = preprocess(&problem).unwrap().problem.unwrap();
+        assert!((reduced.ub[0] - 0.3).abs() < 1e-15);
+        // a t constraint with noncentrality is kept
+        problem.kernel = Kernel::StudentT { nu: 4.0 };
+        assert_eq!(preprocess(&problem).unwrap().kept, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn detects_conflicts() {
+        let inf = ::std::f64::INFINITY;
+        // X_0 <= -1 and -X_0 <= -2
+        let mut problem = MvProblem::new(Array::eye(2),
+                                         Array::from_vec(vec![-inf, -inf]),
+                                         Array::from_vec(vec![-1.0, -2.0]),
+                                         vec![BoundType::Above; 2]);
+        problem.constraints = arr2(&[[1.0, 0.0], [-1.0, 0.0]]);
+        let p = preprocess(&problem).unwrap();
+        assert_eq!(p.conflict, vec![0, 1]);
+        assert_eq!(p.mvdist().unwrap().value, 0.0);
+        // X_0 and X_1 in [0, 1] but X_0 + X_1 >= 3
+        let mut problem = MvProblem::new(Array::eye(2),
+                                         Array::from_vec(vec![0.0, 0.0, 3.0]),
+                                         Array::from_vec(vec![1.0, 1.0, inf]),
+                                         vec![BoundType::Both, BoundType::Both, BoundType::Below]);
+        problem.constraints = arr2(&[[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        problem.delta = Array::zeros((3,));
+        let p = preprocess(&problem).unwrap();
+        assert!(p.problem.is_none() && p.conflict == vec![0, 1, 2]);
+        // a constraint that can bind is kept, and so is the problem
+        problem.lb[2] = 1.5;
+        let p = preprocess(&problem).unwrap();
+        assert!(p.removed.is_empty() && p.conflict.is_empty() && p.kept == vec![0, 1, 2]);
+    }
+}
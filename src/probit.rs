@@ -0,0 +1,367 @@
+//! Cell probabilities of multivariate probit models of correlated binary and ordinal variables.
+//! Variable `i` takes category `c` when its standard normal latent variable `Z_i` falls between
+//! the thresholds `tau_{i,c-1} < Z_i <= tau_{i,c}`, where `tau_{i,-1} = -∞` and the last category
+//! is above the last threshold, so a binary variable has one threshold and is 1 when `Z_i` exceeds
+//! it. The probability of a cell of the contingency table of the variables is then the rectangle
+//! probability of the latent variables, with correlation matrix `corr`, over the corresponding
+//! intervals: the tetrachoric and polychoric correlations are those of `corr` for two variables.
+//!
+//! `cell_probability` evaluates one cell and `table` every cell, in parallel with the `rayon`
+//! feature. Both use `mvdist_kernel`, which solves the common case of two variables exactly.
+//!
+//! `polychoric` estimates the correlation of two ordinal variables from their contingency table,
+//! or the tetrachoric correlation of two binary variables from a 2x2 table, by the usual two-step
+//! method: the thresholds are the normal quantiles of the cumulative marginal proportions, and the
+//! correlation then maximizes the likelihood of the cell counts, by Fisher scoring on the
+//! derivatives of the bivariate normal cell probabilities of `exact::bvn`.
+
+use exact::bvn;
+use ndarray::prelude::*;
+use std::f64::consts::PI;
+use univariate::norm_inv;
+use {BoundType, Kernel, MVResult, MvDistError};
+
+/// A cell of the contingency table with its probability.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cell {
+    /// The category of each variable.
+    pub categories: Vec<usize>,
+    pub probability: MVResult,
+}
+
+fn check(corr: &Array2<f64>, thresholds: &[Vec<f64>]) -> Result<(), MvDistError> {
+    let n = thresholds.len();
+    if n == 0 || corr.rows() != n || corr.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected a {}x{} correlation for {} \
+                                                      variables, got {}x{}",
+                                                     n,
+                                                     n,
+                                                     n,
+                                                     corr.rows(),
+                                                     corr.cols())));
+    }
+    if (0..n).any(|i| !((corr[[i, i]] - 1.0).abs() <= 1e-12)) {
+        return Err(MvDistError::InvalidInput("Latent variables must have unit variance"
+            .to_string()));
+    }
+    for (i, tau) in thresholds.iter().enumerate() {
+        let increasing = tau.windows(2).all(|w| w[1] > w[0]);
+        if tau.is_empty() || !increasing || tau.iter().any(|t| !t.is_finite()) {
+            return Err(MvDistError::InvalidInput(format!("Thresholds {:?} of variable {} are not \
+                                                          finite and increasing",
+                                                         tau,
+                                                         i)));
+        }
+    }
+    Ok(())
+}
+
+/// The limits of category `c` with thresholds `tau`.
+fn limits(tau: &[f64], c: usize) -> (f64, f64) {
+    let inf = ::std::f64::INFINITY;
+    (if c == 0 { -inf } else { tau[c - 1] }, if c == tau.len() { inf } else { tau[c] })
+}
+
+/// The probability of `categories`, without checking `corr` and `thresholds`.
+fn cell(corr: &Array2<f64>,
+        thresholds: &[Vec<f64>],
+        categories: &[usize],
+        maxpts: u64,
+        abseps: f64)
+        -> Result<MVResult, MvDistError> {
+    let n = thresholds.len();
+    if categories.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected {} categories, got {}",
+                                                     n,
+                                                     categories.len())));
+    }
+    let mut lb = Array::zeros((n,));
+    let mut ub = Array::zeros((n,));
+    for (i, (tau, &c)) in thresholds.iter().zip(categories).enumerate() {
+        if c > tau.len() {
+            return Err(MvDistError::InvalidInput(format!("Variable {} has no category {}", i, c)));
+        }
+        let (lo, hi) = limits(tau, c);
+        lb[i] = lo;
+        ub[i] = hi;
+    }
+    let types = (0..n).map(|i| BoundType::from_limits(lb[i], ub[i])).collect::<Vec<BoundType>>();
+    ::mvdist_kernel(corr,
+                    Kernel::Normal,
+                    &lb,
+                    &ub,
+                    &types,
+                    &Array::eye(n),
+                    &Array::zeros((n,)),
+                    maxpts,
+                    abseps,
+                    0.0)
+}
+
+/// The probability that variable `i` takes category `categories[i]` for every `i`, where
+/// `thresholds[i]` are the finite, increasing thresholds of variable `i` and `corr` the latent
+/// correlation matrix, evaluated with `maxpts` and `abseps`.
+pub fn cell_probability(corr: &Array2<f64>,
+                        thresholds: &[Vec<f64>],
+                        categories: &[usize],
+                        maxpts: u64,
+                        abseps: f64)
+                        -> Result<MVResult, MvDistError> {
+    check(corr, thresholds)?;
+    cell(corr, thresholds, categories, maxpts, abseps)
+}
+
+/// The probabilities of every cell of the contingency table, in row-major order (the categories
+/// of the last variable varying fastest), each evaluated as by `cell_probability`. Their sum is
+/// one to within the sum of their errors.
+pub fn table(corr: &Array2<f64>,
+             thresholds: &[Vec<f64>],
+             maxpts: u64,
+             abseps: f64)
+             -> Result<Vec<Cell>, MvDistError> {
+    check(corr, thresholds)?;
+    let mut cells = vec![vec![]];
+    for tau in thresholds {
+        cells = cells.into_iter()
+            .flat_map(|c: Vec<usize>| {
+                (0..(tau.len() + 1)).map(move |k| {
+                    let mut c = c.clone();
+                    c.push(k);
+                    c
+                })
+            })
+            .collect();
+    }
+    let probabilities = evaluate_cells(&cells, |c| cell(corr, thresholds, c, maxpts, abseps));
+    cells.into_iter()
+        .zip(probabilities)
+        .map(|(categories, p)| {
+            Ok(Cell {
+                categories: categories,
+                probability: p?,
+            })
+        })
+        .collect()
+}
+
+/// A polychoric correlation estimated by `polychoric`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Polychoric {
+    pub rho: f64,
+    /// The standard error of `rho` from the Fisher information, given the thresholds.
+    pub std_error: f64,
+    /// The thresholds of the row variable.
+    pub row_thresholds: Vec<f64>,
+    /// The thresholds of the column variable.
+    pub col_thresholds: Vec<f64>,
+    /// The log-likelihood of the counts at `rho`, without the multinomial coefficient.
+    pub log_likelihood: f64,
+    /// The number of scoring steps.
+    pub iterations: usize,
+}
+
+/// The greatest `|rho|` considered: the likelihood of a table without discordant counts increases
+/// up to `|rho| = 1`.
+const MAX_RHO: f64 = 1.0 - 1e-9;
+
+/// The thresholds of the categories with the marginal counts `margin`.
+fn marginal_thresholds(margin: &[f64]) -> Vec<f64> {
+    let total = margin.iter().sum::<f64>();
+    let mut cumulative = 0.0;
+    margin[..margin.len() - 1]
+        .iter()
+        .map(|&m| {
+            cumulative += m;
+            norm_inv(cumulative / total)
+        })
+        .collect()
+}
+
+/// The standard bivariate normal density at `(x, y)`, zero if either is infinite.
+fn bivariate_density(x: f64, y: f64, rho: f64) -> f64 {
+    if x.is_infinite() || y.is_infinite() {
+        return 0.0;
+    }
+    let s = 1.0 - rho * rho;
+    (-(x * x - 2.0 * rho * x * y + y * y) / (2.0 * s)).exp() / (2.0 * PI * s.sqrt())
+}
+
+/// The polychoric correlation of the contingency table `counts` of two ordinal variables, with
+/// the categories of the row variable in ascending order down the rows and those of the column
+/// variable across the columns. The counts may be weights, but every row and column must have a
+/// positive total; empty categories should be merged with a neighbour or left out. The thresholds
+/// are estimated from the margins, and the correlation by Fisher scoring from 0, stopping when a
+/// step changes it by less than `tolerance` or after `max_iterations` steps, with bisection on
+/// the sign of the score whenever a step would leave the interval where the root is known to be.
+pub fn polychoric(counts: &Array2<f64>,
+                  tolerance: f64,
+                  max_iterations: usize)
+                  -> Result<Polychoric, MvDistError> {
+    let (r, c) = (counts.rows(), counts.cols());
+    if r < 2 || c < 2 {
+        return Err(MvDistError::InvalidInput(format!("A {}x{} table has fewer than two \
+                                                      categories",
+                                                     r,
+                                                     c)));
+    }
+    if counts.iter().any(|&n| !(n >= 0.0) || n.is_infinite()) {
+        return Err(MvDistError::InvalidInput("Counts must be finite and non-negative"
+            .to_string()));
+    }
+    let rows = (0..r).map(|i| counts.row(i).iter().sum::<f64>()).collect::<Vec<f64>>();
+    let cols = (0..c).map(|j| counts.column(j).iter().sum::<f64>()).collect::<Vec<f64>>();
+    if rows.iter().chain(&cols).any(|&m| !(m > 0.0)) {
+        return Err(MvDistError::InvalidInput("Every category must have a positive count"
+            .to_string()));
+    }
+    let total = rows.iter().sum::<f64>();
+    let row_thresholds = marginal_thresholds(&rows);
+    let col_thresholds = marginal_thresholds(&cols);
+    // the log-likelihood, score and Fisher information at rho
+    let at = |rho: f64| {
+        let (mut ll, mut score, mut information) = (0.0, 0.0, 0.0);
+        for i in 0..r {
+            let (a0, a1) = limits(&row_thresholds, i);
+            for j in 0..c {
+                let (b0, b1) = limits(&col_thresholds, j);
+                let p = bvn([a0, b0], [a1, b1], rho).max(::std::f64::MIN_POSITIVE);
+                let dp = bivariate_density(a1, b1, rho) - bivariate_density(a0, b1, rho) -
+                         bivariate_density(a1, b0, rho) +
+                         bivariate_density(a0, b0, rho);
+                let n = counts[[i, j]];
+                if n > 0.0 {
+                    ll += n * p.ln();
+                }
+                score += n * dp / p;
+                information += total * dp * dp / p;
+            }
+        }
+        (ll, score, information)
+    };
+    let (mut lo, mut hi) = (-MAX_RHO, MAX_RHO);
+    let mut rho = 0.0;
+    let mut iterations = 0;
+    let (mut ll, mut score, mut information) = at(rho);
+    while iterations < max_iterations {
+        iterations += 1;
+        if score > 0.0 {
+            lo = rho;
+        } else {
+            hi = rho;
+        }
+        let mut next = rho + score / information;
+        if !(next > lo && next < hi) {
+            next = 0.5 * (lo + hi);
+        }
+        let step = next - rho;
+        rho = next;
+        let update = at(rho);
+        ll = update.0;
+        score = update.1;
+        information = update.2;
+        if step.abs() < tolerance {
+            break;
+        }
+    }
+    Ok(Polychoric {
+        rho: rho,
+        std_error: 1.0 / information.sqrt(),
+        row_thresholds: row_thresholds,
+        col_thresholds: col_thresholds,
+        log_likelihood: ll,
+        iterations: iterations,
+    })
+}
+
+#[cfg(feature = "rayon")]
+fn evaluate_cells<F>(cells: &[Vec<usize>], eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(&[usize]) -> Result<MVResult, MvDistError> + Sync
+{
+    use rayon::prelude::*;
+    cells.par_iter().map(|c| eval(&c[..])).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn evaluate_cells<F>(cells: &[Vec<usize>], eval: F) -> Vec<Result<MVResult, MvDistError>>
+    where F: Fn(&[usize]) -> Result<MVResult, MvDistError>
+{
+    cells.iter().map(|c| eval(&c[..])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::exchangeable;
+    use ndarray::prelude::*;
+    use std::f64::consts::PI;
+    use univariate::norm_cdf;
+
+    #[test]
+    fn tetrachoric_cells() {
+        // two binary variables split at their medians: P(0, 0) = 1/4 + asin(rho) / 2π
+        let corr = arr2(&[[1.0, 0.4], [0.4, 1.0]]);
+        let thresholds = vec![vec![0.0], vec![0.0]];
+        let cells = table(&corr, &thresholds, 10_000, 1e-10).unwrap();
+        let concordant = 0.25 + 0.4f64.asin() / (2.0 * PI);
+        let expected = [concordant, 0.5 - concordant, 0.5 - concordant, concordant];
+        for (c, &p) in cells.iter().zip(&expected) {
+            assert!((c.probability.value - p).abs() < 1e-10);
+        }
+        assert_eq!(cells[1].categories, vec![0, 1]);
+        // independent ordinal variables: products of the marginals
+        let thresholds = vec![vec![-1.0, 0.5], vec![0.3]];
+        let p = cell_probability(&Array::eye(2), &thresholds, &[1, 1], 1000, 1e-10).unwrap();
+        let marginal = (norm_cdf(0.5) - norm_cdf(-1.0)) * (1.0 - norm_cdf(0.3));
+        assert!((p.value - marginal).abs() < 1e-12);
+        assert!(cell_probability(&Array::eye(2), &thresholds, &[1, 2], 1000, 1e-10).is_err());
+        let unordered = vec![vec![0.5, -1.0], vec![0.3]];
+        assert!(table(&Array::eye(2), &unordered, 1000, 1e-10).is_err());
+    }
+
+    #[test]
+    fn ordinal_table() {
+        let corr = exchangeable(3, 0.3);
+        let thresholds = vec![vec![-0.5, 0.8], vec![0.0], vec![-1.0, 0.0, 1.2]];
+        let cells = table(&corr, &thresholds, 100_000, 1e-6).unwrap();
+        assert_eq!(cells.len(), 3 * 2 * 4);
+        assert_eq!(cells[5].categories, vec![0, 1, 1]);
+        let total = cells.iter().map(|c| c.probability.value).sum::<f64>();
+        let error = cells.iter().map(|c| c.probability.error).sum::<f64>();
+        assert!((total - 1.0).abs() <= error + 1e-10);
+    }
+
+    #[test]
+    fn polychoric_correlations() {
+        // tables of the expected counts of known correlations and thresholds are fitted exactly
+        for &(rho, ref row, ref col) in &[(0.5, vec![0.0], vec![0.3]),
+                                          (-0.3, vec![-0.8, 0.4], vec![-1.0, 0.1, 0.9])] {
+            let corr = arr2(&[[1.0, rho], [rho, 1.0]]);
+            let thresholds = vec![row.clone(), col.clone()];
+            let cells = table(&corr, &thresholds, 1000, 1e-12).unwrap();
+            let (r, c) = (row.len() + 1, col.len() + 1);
+            let counts = Array::from_shape_vec((r, c),
+                                               cells.iter()
+                                                   .map(|c| 500.0 * c.probability.value)
+                                                   .collect())
+                .unwrap();
+            let fit = polychoric(&counts, 1e-12, 100).unwrap();
+            assert!((fit.rho - rho).abs() < 1e-8, "{:?}", fit);
+            let fitted = fit.row_thresholds.iter().chain(&fit.col_thresholds);
+            for (a, b) in fitted.zip(row.iter().chain(col)) {
+                assert!((a - b).abs() < 1e-10);
+            }
+            assert!(fit.std_error > 0.0 && fit.std_error < 0.1);
+            assert!(fit.iterations < 100);
+        }
+        // independent margins
+        let counts = arr2(&[[10.0, 30.0], [20.0, 60.0]]);
+        assert!(polychoric(&counts, 1e-12, 100).unwrap().rho.abs() < 1e-8);
+        // a table without discordant counts is fitted at the boundary
+        let counts = arr2(&[[10.0, 0.0], [0.0, 20.0]]);
+        assert!(polychoric(&counts, 1e-12, 200).unwrap().rho > 0.999);
+        let empty = arr2(&[[10.0, 0.0], [0.0, 0.0]]);
+        assert!(polychoric(&empty, 1e-12, 100).is_err());
+    }
+}
@@ -0,0 +1,638 @@
+//! A self-contained description of a single `mvdist` problem, and (with the `json` feature) a file
+//! format for sharing problem instances.
+//!
+//! # JSON format
+//!
+//! A problem file is a single JSON object. Matrices are written as arrays of rows.
+//!
+//! ```json
+//! {
+//!   "covariance": [[1.0, 0.5], [0.5, 1.0]],
+//!   "kernel": {"StudentT": {"nu": 4.5}},
+//!   "lower": [0.0, null],
+//!   "upper": [1.0, 2.0],
+//!   "types": ["Both", "Above"],
+//!   "constraints": [[1.0, 0.0], [0.0, 1.0]],
+//!   "delta": [0.0, 0.0],
+//!   "maxpts": 100000,
+//!   "abseps": 1e-5,
+//!   "releps": 0.0,
+//!   "seed": null
+//! }
+//! ```
+//!
+//! - `covariance`: `n x n` covariance matrix. Required.
+//! - `kernel`: the distribution, `"Normal"` or `{"StudentT": {"nu": ...}}` as for `Kernel`.
+//!   Defaults to `"Normal"`. Files may give the integer `nu` of earlier versions instead, where
+//!   `0` (or any value `<= 0`) selects the normal distribution.
+//! - `lower`, `upper`: one entry per constraint. Bounds not used by the corresponding `types` entry
+//!   may be `null`, and infinite bounds are written `"inf"` or `"-inf"`. Required.
+//! - `types`: one of `"Unbounded"`, `"Above"`, `"Below"`, `"Both"` per constraint. Required.
+//! - `constraints`: `m x n` constraint matrix. Defaults to the `n x n` identity.
+//! - `delta`: noncentrality per constraint. Defaults to zeros.
+//! - `maxpts`, `abseps`, `releps`: integration budget and tolerances. Default to `100000`, `1e-5`
+//!   and `0`. The tolerances must be non-negative and not both zero, as for `Tolerance::both`.
+//! - `seed`: seed for backends with a seedable generator; the Fortran backend uses its own
+//!   internal generator and ignores it. Defaults to `null`.
+//! - `backend`: `"Fortran"`, `"Native"` or `"SparseGrid"` to force a backend, as for
+//!   `EvalConfig::backend`.
+//!   Defaults to `null`, choosing one automatically.
+//! - `reorder`: the constraint ordering of the native backend, `"Auto"`, `"None"` or
+//!   `{"Custom": [...]}` with a permutation of the constraints. Defaults to `"Auto"`.
+//! - `retry`: an object with any of the fields `max_doublings`, `total_eval_budget` and `backoff`
+//!   of `RetryPolicy`, the others taking their defaults. Defaults to no retries.
+
+use crit::{mvcrit_native, RootFinder};
+use ndarray::prelude::*;
+use std::hash::{Hash, Hasher};
+use {mvcrit_many, MvCritResult};
+use partition::marginal;
+use native::{Reorder, DEFAULT_SEED};
+use {mvdist_config, Backend, BoundType, EvalConfig, Kernel, MVResult, MvDistError, Tolerance};
+
+/// A complete `mvdist` problem: distribution, integration region, and integration settings.
+/// Problems compare equal, and hash identically, when every input affecting the result has the same
+/// bit pattern, except that unused bounds are ignored and `-0.0` is identified with `0.0`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ProblemFile", into = "ProblemFile"))]
+pub struct MvProblem {
+    pub cov: Array2<f64>,
+    pub kernel: Kernel,
+    pub lb: Array1<f64>,
+    pub ub: Array1<f64>,
+    pub types: Vec<BoundType>,
+    pub constraints: Array2<f64>,
+    pub delta: Array1<f64>,
+    pub config: EvalConfig,
+}
+
+impl MvProblem {
+    /// A multivariate normal rectangle problem `P(lb < X < ub)` with identity constraints, zero
+    /// noncentrality, and the default integration settings.
+    pub fn new(cov: Array2<f64>,
+               lb: Array1<f64>,
+               ub: Array1<f64>,
+               types: Vec<BoundType>)
+               -> MvProblem {
+        let n = cov.rows();
+        MvProblem {
+            cov: cov,
+            kernel: Kernel::Normal,
+            lb: lb,
+            ub: ub,
+            types: types,
+            constraints: Array::eye(n),
+            delta: Array::zeros((n,)),
+            config: EvalConfig::default(),
+        }
+    }
+
+    /// Check that the covariance is `n x n` for the `n` columns of the constraints, and that there
+    /// are bounds, bound types and noncentralities for each of their rows.
+    pub fn validate(&self) -> Result<(), MvDistError> {
+        let (m, n) = (self.constraints.rows(), self.constraints.cols());
+        if self.cov.rows() != n || self.cov.cols() != n {
+            return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected {}x{}",
+                                                         self.cov.rows(),
+                                                         self.cov.cols(),
+                                                         n,
+                                                         n)));
+        }
+        let lengths = [self.lb.len(), self.ub.len(), self.types.len(), self.delta.len()];
+        if lengths.iter().any(|&l| l != m) {
+            return Err(MvDistError::InvalidInput(format!("Expected bounds, types and \
+                                                          noncentralities for {} constraints, \
+                                                          got {:?}",
+                                                         m,
+                                                         lengths)));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the problem with `mvdist_config`.
+    pub fn mvdist(&self) -> Result<MVResult, MvDistError> {
+        mvdist_config(&self.cov,
+                      self.kernel,
+                      &self.lb,
+                      &self.ub,
+                      &self.types,
+                      &self.constraints,
+                      &self.delta,
+                      &self.config)
+    }
+
+    /// Compute the equicoordinate critical value for this problem's distribution and constraints
+    /// with `mvcrit`, retrying as set by the problem's retry policy. The problem's `delta`,
+    /// relative tolerance, seed, backend and ordering are not used. `mvcrit` takes integer degrees
+    /// of freedom, so for other t kernels the value is found by `crit::mvcrit_native` with the
+    /// default `RootFinder`, the problem's seed (or `native::DEFAULT_SEED`) and no relative
+    /// tolerance.
+    pub fn mvcrit(&self, alpha: f64) -> Result<MvCritResult, MvDistError> {
+        let nu = self.kernel.nu()?;
+        if nu != nu.trunc() || nu > ::std::i32::MAX as f64 {
+            let finder = RootFinder { retry: self.config.retry, ..RootFinder::default() };
+            return mvcrit_native(&self.cov,
+                                 self.kernel,
+                                 &self.types,
+                                 &self.constraints,
+                                 &Array::zeros((self.constraints.rows(),)),
+                                 alpha,
+                                 self.config.maxpts,
+                                 self.config.tolerance.abseps(),
+                                 0.0,
+                                 self.config.seed.unwrap_or(DEFAULT_SEED),
+                                 &finder);
+        }
+        mvcrit_many(&self.cov,
+                    nu as i32,
+                    &self.lb,
+                    &self.ub,
+                    &self.types,
+                    &self.constraints,
+                    &[alpha],
+                    self.config.maxpts,
+                    self.config.tolerance.abseps(),
+                    &self.config.retry)
+            .map(|results| results[0])
+    }
+
+    /// The problem restricted to the variables `indices`, in that order: the covariance is the
+    /// corresponding sub-matrix, the kernel is unchanged, and the constraints that
+    /// involve any other variable are dropped with their bounds and noncentrality. For identity
+    /// constraints this is the marginal rectangle problem. A custom constraint ordering is replaced
+    /// by `Reorder::Auto`.
+    pub fn marginal(&self, indices: &[usize]) -> Result<MvProblem, MvDistError> {
+        let n = self.cov.rows();
+        let sub = marginal(&self.cov, &Array::zeros((n,)), Kernel::Normal, indices)?;
+        let mut keep = vec![false; n];
+        for &i in indices {
+            keep[i] = true;
+        }
+        let rows = (0..self.constraints.rows())
+            .filter(|&r| (0..n).all(|j| keep[j] || self.constraints[[r, j]] == 0.0))
+            .collect::<Vec<usize>>();
+        if rows.is_empty() {
+            return Err(MvDistError::InvalidInput("No constraints involve only the selected \
+                                                  variables"
+                .to_string()));
+        }
+        let mut constraints = Array2::zeros((rows.len(), indices.len()));
+        for (a, &r) in rows.iter().enumerate() {
+            for (b, &j) in indices.iter().enumerate() {
+                constraints[[a, b]] = self.constraints[[r, j]];
+            }
+        }
+        let pick = |v: &Array1<f64>| Array::from_vec(rows.iter().map(|&r| v[r]).collect());
+        Ok(MvProblem {
+            cov: sub.cov,
+            lb: pick(&self.lb),
+            ub: pick(&self.ub),
+            types: rows.iter().map(|&r| self.types[r]).collect(),
+            constraints: constraints,
+            delta: pick(&self.delta),
+            config: EvalConfig {
+                reorder: match self.config.reorder {
+                    Reorder::Custom(_) => Reorder::Auto,
+                    ref r => r.clone(),
+                },
+                ..self.config.clone()
+            },
+            ..self.clone()
+        })
+    }
+
+    /// Parse a problem from the JSON format described in the module documentation.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<MvProblem, MvDistError> {
+        ::serde_json::from_str(json)
+            .map_err(|e| MvDistError::InvalidInput(format!("Invalid problem file: {}", e)))
+    }
+
+    /// Write the problem in the JSON format described in the module documentation.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).expect("problem serialization cannot fail")
+    }
+
+    /// A hash of every input affecting the result, as compared by `==`, that is the same in every
+    /// build of this version of the crate for platforms of the same word size, unlike the hashes
+    /// of `DefaultHasher`.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv(0xcbf29ce484222325);
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The bit patterns of every input of an `MvProblem` that affects its result. Bounds are keyed by
+/// the limits they imply, and their numbers are kept so that problems whose fields have
+/// inconsistent lengths compare unequal.
+#[derive(Hash, PartialEq, Eq)]
+struct ProblemKey {
+    n: usize,
+    m: usize,
+    bounds: (usize, usize),
+    nu: u64,
+    maxpts: u64,
+    seed: Option<u64>,
+    backend: Option<Backend>,
+    reorder: Reorder,
+    retry: (u32, Option<u64>, u64),
+    values: Vec<u64>,
+}
+
+fn bits(x: f64) -> u64 {
+    (x + 0.0).to_bits()
+}
+
+impl MvProblem {
+    fn key(&self) -> ProblemKey {
+        let mut values = Vec::new();
+        values.extend(self.cov.iter().map(|&x| bits(x)));
+        values.extend(self.constraints.iter().map(|&x| bits(x)));
+        for ((&t, &lb), &ub) in self.types.iter().zip(self.lb.iter()).zip(self.ub.iter()) {
+            let (lo, hi) = t.limits(lb, ub);
+            values.push(bits(lo));
+            values.push(bits(hi));
+        }
+        values.extend(self.delta.iter().map(|&x| bits(x)));
+        values.push(bits(self.config.tolerance.abseps()));
+        values.push(bits(self.config.tolerance.releps()));
+        let retry = self.config.retry;
+        // degrees of freedom in the convention of `Kernel::nu`, with 0 for the normal distribution
+        let nu = match self.kernel {
+            Kernel::Normal => 0.0,
+            Kernel::StudentT { nu } => if nu.is_finite() { nu } else { 0.0 },
+        };
+        ProblemKey {
+            n: self.cov.rows(),
+            m: self.types.len(),
+            bounds: (self.lb.len(), self.ub.len()),
+            nu: bits(nu),
+            maxpts: self.config.maxpts,
+            seed: self.config.seed,
+            backend: self.config.backend,
+            reorder: self.config.reorder.clone(),
+            retry: (retry.max_doublings, retry.total_eval_budget, bits(retry.backoff)),
+            values: values,
+        }
+    }
+}
+
+impl PartialEq for MvProblem {
+    fn eq(&self, other: &MvProblem) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for MvProblem {}
+
+impl Hash for MvProblem {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// FNV-1a, used instead of `DefaultHasher` because its output must be stable across builds.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_maxpts() -> u64 {
+    100_000
+}
+
+#[cfg(feature = "serde")]
+fn default_abseps() -> f64 {
+    1e-5
+}
+
+/// On-disk representation of `MvProblem`.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct ProblemFile {
+    covariance: Vec<Vec<f64>>,
+    #[serde(default)]
+    kernel: Option<Kernel>,
+    /// The degrees of freedom of files written before `kernel`, with `<= 0` for the normal
+    /// distribution.
+    #[serde(default, skip_serializing)]
+    nu: Option<i32>,
+    lower: Vec<Option<FileBound>>,
+    upper: Vec<Option<FileBound>>,
+    types: Vec<BoundType>,
+    #[serde(default)]
+    constraints: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    delta: Option<Vec<f64>>,
+    #[serde(default = "default_maxpts")]
+    maxpts: u64,
+    #[serde(default = "default_abseps")]
+    abseps: f64,
+    #[serde(default)]
+    releps: f64,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    backend: Option<Backend>,
+    #[serde(default)]
+    reorder: Option<Reorder>,
+    #[serde(default)]
+    retry: ::RetryPolicy,
+}
+
+/// A bound in a problem file: a number, or `"inf"` or `"-inf"`, which JSON has no numbers for.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FileBound {
+    Finite(f64),
+    Infinite(String),
+}
+
+#[cfg(feature = "serde")]
+impl FileBound {
+    fn new(x: f64) -> FileBound {
+        if x == ::std::f64::INFINITY {
+            FileBound::Infinite("inf".to_string())
+        } else if x == ::std::f64::NEG_INFINITY {
+            FileBound::Infinite("-inf".to_string())
+        } else {
+            FileBound::Finite(x)
+        }
+    }
+
+    fn value(&self) -> Result<f64, String> {
+        match *self {
+            FileBound::Finite(x) => Ok(x),
+            FileBound::Infinite(ref s) if s == "inf" => Ok(::std::f64::INFINITY),
+            FileBound::Infinite(ref s) if s == "-inf" => Ok(::std::f64::NEG_INFINITY),
+            FileBound::Infinite(ref s) => Err(format!("Invalid bound `{}`", s)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn to_rows(ar: &Array2<f64>) -> Vec<Vec<f64>> {
+    ar.outer_iter().map(|row| row.iter().cloned().collect()).collect()
+}
+
+#[cfg(feature = "serde")]
+fn from_rows(rows: Vec<Vec<f64>>, name: &str) -> Result<Array2<f64>, String> {
+    let r = rows.len();
+    let c = rows.first().map_or(0, |row| row.len());
+    if rows.iter().any(|row| row.len() != c) {
+        return Err(format!("Rows of `{}` have different lengths", name));
+    }
+    let flat = rows.into_iter().flat_map(|row| row.into_iter()).collect();
+    Array::from_shape_vec((r, c), flat).map_err(|e| format!("Invalid `{}`: {}", name, e))
+}
+
+#[cfg(feature = "serde")]
+impl From<MvProblem> for ProblemFile {
+    fn from(p: MvProblem) -> ProblemFile {
+        // only the bounds the bound type ignores are left out
+        let (mut lower, mut upper) = (Vec::new(), Vec::new());
+        for (i, &t) in p.types.iter().enumerate() {
+            let (lo, hi) = t.limits(0.0, 0.0);
+            lower.push(if lo.is_finite() { p.lb.get(i).map(|&x| FileBound::new(x)) } else { None });
+            upper.push(if hi.is_finite() { p.ub.get(i).map(|&x| FileBound::new(x)) } else { None });
+        }
+        ProblemFile {
+            covariance: to_rows(&p.cov),
+            kernel: Some(match p.kernel {
+                Kernel::StudentT { nu } if nu == ::std::f64::INFINITY => Kernel::Normal,
+                kernel => kernel,
+            }),
+            nu: None,
+            lower: lower,
+            upper: upper,
+            types: p.types,
+            constraints: Some(to_rows(&p.constraints)),
+            delta: Some(p.delta.iter().cloned().collect()),
+            maxpts: p.config.maxpts,
+            abseps: p.config.tolerance.abseps(),
+            releps: p.config.tolerance.releps(),
+            seed: p.config.seed,
+            backend: p.config.backend,
+            reorder: Some(p.config.reorder),
+            retry: p.config.retry,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::std::convert::TryFrom<ProblemFile> for MvProblem {
+    type Error = String;
+
+    fn try_from(f: ProblemFile) -> Result<MvProblem, String> {
+        let cov = from_rows(f.covariance, "covariance")?;
+        let n = cov.rows();
+        if cov.cols() != n {
+            return Err(format!("`covariance` must be square, but is {}x{}", n, cov.cols()));
+        }
+        let constraints = match f.constraints {
+            Some(rows) => from_rows(rows, "constraints")?,
+            None => Array::eye(n),
+        };
+        if constraints.cols() != n {
+            return Err(format!("`constraints` must have {} columns, but has {}",
+                               n,
+                               constraints.cols()));
+        }
+        let m = constraints.rows();
+        let delta = f.delta.unwrap_or_else(|| vec![0.0; m]);
+        for &(name, len) in &[("lower", f.lower.len()),
+                              ("upper", f.upper.len()),
+                              ("types", f.types.len()),
+                              ("delta", delta.len())] {
+            if len != m {
+                return Err(format!("`{}` must have {} entries, but has {}", name, m, len));
+            }
+        }
+        for (i, &t) in f.types.iter().enumerate() {
+            let (lo, hi) = t.limits(0.0, 0.0);
+            let missing_lower = lo.is_finite() && f.lower[i].is_none();
+            let missing_upper = hi.is_finite() && f.upper[i].is_none();
+            if missing_lower || missing_upper {
+                return Err(format!("Constraint {} is missing a bound required by {:?}", i, t));
+            }
+        }
+        let values = |bounds: Vec<Option<FileBound>>| {
+            bounds.iter()
+                .map(|b| b.as_ref().map_or(Ok(0.0), FileBound::value))
+                .collect::<Result<Vec<f64>, String>>()
+                .map(Array1::from_vec)
+        };
+        let kernel = match (f.kernel, f.nu) {
+            (Some(_), Some(_)) => return Err(format!("Only one of `kernel` and `nu` may be given")),
+            (Some(kernel), None) => kernel,
+            (None, nu) => Kernel::from_nu(nu.unwrap_or(0) as f64),
+        };
+        Ok(MvProblem {
+            cov: cov,
+            kernel: kernel,
+            lb: values(f.lower)?,
+            ub: values(f.upper)?,
+            types: f.types,
+            constraints: constraints,
+            delta: Array1::from_vec(delta),
+            config: EvalConfig {
+                maxpts: f.maxpts,
+                tolerance: Tolerance::both(f.abseps, f.releps).map_err(|e| e.to_string())?,
+                seed: f.seed,
+                backend: f.backend,
+                reorder: f.reorder.unwrap_or(Reorder::Auto),
+                retry: f.retry,
+            },
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use BoundType;
+
+    #[test]
+    fn json_roundtrip() {
+        let mut problem = MvProblem::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]),
+                                         Array1::from_vec(vec![0.0, ::std::f64::NEG_INFINITY]),
+                                         Array1::from_vec(vec![1.0, 2.0]),
+                                         vec![BoundType::Both, BoundType::Above]);
+        problem.config.seed = Some(42);
+        problem.config.reorder = Reorder::Custom(vec![1, 0]);
+        let parsed = MvProblem::from_json(&problem.to_json()).unwrap();
+        assert_eq!(parsed.cov, problem.cov);
+        assert_eq!(parsed.ub, problem.ub);
+        assert_eq!(parsed.lb[0], 0.0);
+        assert_eq!(parsed.constraints, problem.constraints);
+        assert_eq!(parsed.config, problem.config);
+        assert_eq!(parsed.kernel, Kernel::Normal);
+    }
+
+    #[test]
+    fn json_kernel_and_integer_nu() {
+        let mut problem = MvProblem::new(Array::eye(1),
+                                         Array1::from_vec(vec![0.0]),
+                                         Array1::from_vec(vec![1.0]),
+                                         vec![BoundType::Above]);
+        problem.kernel = Kernel::StudentT { nu: 4.5 };
+        assert_eq!(MvProblem::from_json(&problem.to_json()).unwrap(), problem);
+        let file = |nu: &str| {
+            MvProblem::from_json(&format!(r#"{{"covariance": [[1.0]], "lower": [null],
+                                               "upper": [1.0], "types": ["Above"], {}}}"#,
+                                          nu))
+        };
+        assert_eq!(file(r#""nu": 4"#).unwrap().kernel, Kernel::StudentT { nu: 4.0 });
+        assert_eq!(file(r#""nu": -1"#).unwrap().kernel, Kernel::Normal);
+        assert!(file(r#""nu": 4, "kernel": "Normal""#).is_err());
+    }
+
+    #[test]
+    fn json_roundtrip_with_infinite_limits() {
+        let inf = ::std::f64::INFINITY;
+        let problem = MvProblem::new(Array::eye(4),
+                                     Array1::from_vec(vec![0.0, -inf, -inf, 3.0]),
+                                     Array1::from_vec(vec![inf, 2.0, inf, 1.0]),
+                                     vec![BoundType::Both,
+                                          BoundType::Both,
+                                          BoundType::Above,
+                                          BoundType::Unbounded]);
+        let json = problem.to_json();
+        assert!(json.contains("\"inf\"") && json.contains("\"-inf\""));
+        let parsed = MvProblem::from_json(&json).unwrap();
+        assert_eq!(parsed, problem);
+        assert_eq!((parsed.ub[0], parsed.lb[1], parsed.ub[2]), (inf, -inf, inf));
+        assert!(MvProblem::from_json(r#"{"covariance": [[1.0]], "lower": [null],
+                                         "upper": ["big"], "types": ["Above"]}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn json_defaults_and_validation() {
+        let parsed = MvProblem::from_json(r#"{"covariance": [[1.0]], "lower": [null],
+                                              "upper": [0.0], "types": ["Above"]}"#)
+            .unwrap();
+        assert_eq!(parsed.config, EvalConfig::default());
+        assert_eq!(parsed.constraints, Array::eye(1));
+        assert!(MvProblem::from_json(r#"{"covariance": [[1.0]], "lower": [null],
+                                         "upper": [null], "types": ["Above"]}"#)
+            .is_err());
+        assert!(MvProblem::from_json(r#"{"covariance": [[1.0]], "lower": [null],
+                                         "upper": [0.0], "types": ["Above"], "abseps": 0.0}"#)
+            .is_err());
+        assert!(parsed.validate().is_ok());
+        assert!(MvProblem { cov: Array::eye(2), ..parsed.clone() }.validate().is_err());
+        assert!(MvProblem { delta: Array::zeros((2,)), ..parsed }.validate().is_err());
+    }
+}
+
+#[cfg(test)]
+mod key_tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use std::collections::HashSet;
+    use BoundType;
+
+    #[test]
+    fn equality_ignores_unused_bounds_and_signed_zero() {
+        let a = MvProblem::new(arr2(&[[1.0, 0.5], [0.5, 1.0]]),
+                               Array1::from_vec(vec![0.0, 0.0]),
+                               Array1::from_vec(vec![1.0, 0.0]),
+                               vec![BoundType::Above; 2]);
+        let mut b = a.clone();
+        b.lb[0] = 3.0;
+        b.ub[1] = -0.0;
+        assert_eq!(a, b);
+        let mut set = HashSet::new();
+        set.insert(a.clone());
+        assert!(set.contains(&b));
+        b.config.tolerance = Tolerance::absolute(1e-6).unwrap();
+        assert!(a != b);
+        b.config = EvalConfig::default();
+        assert_eq!(a, b);
+        b.config.backend = Some(Backend::Native);
+        assert!(a != b);
+        b.config = EvalConfig::default();
+        b.kernel = Kernel::StudentT { nu: ::std::f64::INFINITY };
+        assert_eq!(a, b);
+        b.kernel = Kernel::StudentT { nu: 3.5 };
+        assert!(a != b);
+        // inconsistent lengths compare unequal rather than panicking
+        let short = MvProblem { ub: Array1::from_vec(vec![1.0]), ..a.clone() };
+        assert!(a != short && short == short.clone());
+        set.insert(short);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn marginal_drops_other_constraints() {
+        let mut p = MvProblem::new(arr2(&[[1.0, 0.5, 0.1], [0.5, 2.0, 0.2], [0.1, 0.2, 3.0]]),
+                                   Array1::from_vec(vec![-1.0, -2.0, -3.0, 0.0]),
+                                   Array1::from_vec(vec![1.0, 2.0, 3.0, 1.0]),
+                                   vec![BoundType::Both; 4]);
+        p.constraints = arr2(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0, 0.0]]);
+        p.delta = Array1::from_vec(vec![0.0, 0.1, 0.2, 0.3]);
+        let m = p.marginal(&[1, 0]).unwrap();
+        assert_eq!(m.cov, arr2(&[[2.0, 0.5], [0.5, 1.0]]));
+        assert_eq!(m.constraints, arr2(&[[0.0, 1.0], [1.0, 0.0], [1.0, 1.0]]));
+        assert_eq!(m.lb, Array1::from_vec(vec![-1.0, -2.0, 0.0]));
+        assert_eq!(m.delta, Array1::from_vec(vec![0.0, 0.1, 0.3]));
+        assert_eq!(m.config, p.config);
+        assert!(p.marginal(&[3]).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Globally adaptive Gauss-Kronrod (7, 15) quadrature on finite intervals.
+
+const XGK: [f64; 8] = [0.991455371120812639206854697526329,
+                       0.949107912342758524526189684047851,
+                       0.864864423359769072789712788640926,
+                       0.741531185599394439863864773280788,
+                       0.586087235467691130294144845693013,
+                       0.405845151377397166906606412076961,
+                       0.207784955007898467600689403773245,
+                       0.000000000000000000000000000000000];
+
+const WGK: [f64; 8] = [0.022935322010529224963732008058970,
+                       0.063092092629978553290700663189204,
+                       0.104790010322250183839876322541518,
+                       0.140653259715525918745189590510238,
+                       0.169004726639267902826583426598550,
+                       0.190350578064785409913256402421014,
+                       0.204432940075298892414161999234649,
+                       0.209482141084727828012999174891714];
+
+// Gauss weights for the nodes XGK[1], XGK[3], XGK[5], XGK[7].
+const WG: [f64; 4] = [0.129484966168869693270611432679082,
+                      0.279705391489276667901467771423780,
+                      0.381830050505118944950369775488975,
+                      0.417959183673469387755102040816327];
+
+const MAX_INTERVALS: usize = 500;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Quadrature {
+    pub value: f64,
+    pub error: f64,
+    pub nevals: usize,
+}
+
+fn gk15<F: FnMut(f64) -> f64>(f: &mut F, a: f64, b: f64) -> (f64, f64) {
+    let center = 0.5 * (a + b);
+    let half = 0.5 * (b - a);
+    let fc = f(center);
+    let mut kronrod = fc * WGK[7];
+    let mut gauss = fc * WG[3];
+    for i in 0..7 {
+        let dx = half * XGK[i];
+        let fsum = f(center - dx) + f(center + dx);
+        kronrod += WGK[i] * fsum;
+        if i % 2 == 1 {
+            gauss += WG[i / 2] * fsum;
+        }
+    }
+    (kronrod * half, ((kronrod - gauss) * half).abs())
+}
+
+/// Integrate `f` over `[a, b]`, bisecting the interval with the largest error estimate until the
+/// total estimated error is below `max(abseps, releps * |value|)`.
+pub fn integrate<F: FnMut(f64) -> f64>(mut f: F,
+                                       a: f64,
+                                       b: f64,
+                                       abseps: f64,
+                                       releps: f64)
+                                       -> Quadrature {
+    let (v, e) = gk15(&mut f, a, b);
+    let mut intervals = vec![(a, b, v, e)];
+    let mut nevals = 15;
+    loop {
+        let value = intervals.iter().map(|i| i.2).sum::<f64>();
+        let error = intervals.iter().map(|i| i.3).sum::<f64>();
+        if error <= abseps.max(releps * value.abs()) || intervals.len() >= MAX_INTERVALS {
+            return Quadrature {
+                value: value,
+                error: error,
+                nevals: nevals,
+            };
+        }
+        // a NaN error estimate counts as the largest, so its interval is bisected first
+        let worst = {
+            let key = |i: usize| {
+                let e: f64 = intervals[i].3;
+                if e.is_nan() { ::std::f64::INFINITY } else { e }
+            };
+            (0..intervals.len()).max_by(|&i, &j| key(i).partial_cmp(&key(j)).unwrap()).unwrap()
+        };
+        let (lo, hi, _, _) = intervals.swap_remove(worst);
+        let mid = 0.5 * (lo + hi);
+        let (v1, e1) = gk15(&mut f, lo, mid);
+        let (v2, e2) = gk15(&mut f, mid, hi);
+        nevals += 30;
+        intervals.push((lo, mid, v1, e1));
+        intervals.push((mid, hi, v2, e2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrates_smooth_functions() {
+        let q = integrate(|x| x.exp(), 0.0, 1.0, 1e-12, 0.0);
+        assert!((q.value - (1f64.exp() - 1.0)).abs() < 1e-12);
+        let q = integrate(|x| x.sqrt(), 0.0, 1.0, 1e-10, 0.0);
+        assert!((q.value - 2.0 / 3.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn nan_integrand_does_not_panic() {
+        let q = integrate(|x| if x > 0.5 { ::std::f64::NAN } else { x }, 0.0, 1.0, 1e-10, 0.0);
+        assert!(q.value.is_nan());
+    }
+}
@@ -0,0 +1,224 @@
+//! Direct access to the backends with every parameter passed through unchanged, enabled by the
+//! `raw` feature, for replicating results that depend on exact settings of the underlying
+//! routines. Nothing here tries the exact solutions, routes between backends, retries, splits
+//! large budgets or checks its arguments: the caller gets precisely the computation they asked
+//! for, including its failures.
+//!
+//! `fortran_mvdist` and `fortran_mvcrit` are the `MVDIST` and `MVCRIT` routines of the Genz code
+//! with their original arguments, in column-major order and with the `INFIN` codes of
+//! `BoundType`. Their work arrays have a fixed size, so they are `unsafe`: arguments the Fortran
+//! code does not expect can overrun them. The parameters fixed inside that code (such as the number
+//! of randomizations of each lattice rule) cannot be changed from here.
+//!
+//! `native` runs the native integrator with `native::Options` exactly as given, including
+//! `QmcConfig` settings that `QmcConfig::validate` would reject.
+
+#[cfg(feature = "fortran")]
+use fortran;
+#[cfg(feature = "fortran")]
+use mvdist_sys::{mvcrit as sys_mvcrit, mvdist as sys_mvdist};
+use native::{self, Estimate, Options};
+use ndarray::prelude::*;
+use {BoundType, MvDistError};
+
+/// The outputs of a Fortran routine: its `ERROR`, `VALUE`, `NEVALS` and `INFORM` arguments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RawResult {
+    pub error: f64,
+    pub value: f64,
+    pub nevals: i32,
+    /// 0 for success, 1 if `maxpts` was reached, and otherwise an error code of the routine.
+    pub inform: i32,
+}
+
+/// One call of `MVDIST` with the given arguments, under the lock shared with the Fortran backend.
+/// `covrnc` is the `n × n` covariance and `constr` the `m × n` constraint matrix, both in
+/// column-major order; `nu <= 0` is the normal distribution.
+///
+/// # Safety
+///
+/// `n` and `m` must be between 1 and `fortran::MAX_DIMENSION`, and the slices must have the
+/// lengths those dimensions imply: `n²` for `covrnc`, `m n` for `constr` and `m` for the others.
+#[cfg(feature = "fortran")]
+pub unsafe fn fortran_mvdist(n: i32,
+                             covrnc: &[f64],
+                             nu: i32,
+                             m: i32,
+                             lower: &[f64],
+                             constr: &[f64],
+                             upper: &[f64],
+                             infin: &[i32],
+                             delta: &[f64],
+                             maxpts: i32,
+                             abseps: f64,
+                             releps: f64)
+                             -> RawResult {
+    let guard = fortran::lock();
+    let (error, value, nevals, inform) = sys_mvdist(n,
+                                                    covrnc,
+                                                    nu,
+                                                    m,
+                                                    lower,
+                                                    constr,
+                                                    upper,
+                                                    infin,
+                                                    delta,
+                                                    maxpts,
+                                                    abseps,
+                                                    releps);
+    drop(guard);
+    RawResult {
+        error: error,
+        value: value,
+        nevals: nevals,
+        inform: inform,
+    }
+}
+
+/// One call of `MVCRIT`, the critical value for the confidence level `1 - alpha`, with the same
+/// arguments and lock as `fortran_mvdist`.
+///
+/// # Safety
+///
+/// As for `fortran_mvdist`.
+#[cfg(feature = "fortran")]
+pub unsafe fn fortran_mvcrit(n: i32,
+                             covrnc: &[f64],
+                             nu: i32,
+                             m: i32,
+                             lower: &[f64],
+                             constr: &[f64],
+                             upper: &[f64],
+                             infin: &[i32],
+                             alpha: f64,
+                             maxpts: i32,
+                             abseps: f64)
+                             -> RawResult {
+    let guard = fortran::lock();
+    let (error, value, nevals, inform) = sys_mvcrit(n,
+                                                    covrnc,
+                                                    nu,
+                                                    m,
+                                                    lower,
+                                                    constr,
+                                                    upper,
+                                                    infin,
+                                                    alpha,
+                                                    maxpts,
+                                                    abseps);
+    drop(guard);
+    RawResult {
+        error: error,
+        value: value,
+        nevals: nevals,
+        inform: inform,
+    }
+}
+
+/// The native integration of the problem with `options` as given. Only the problem itself is
+/// checked, as it must be to build the integrand; a `QmcConfig` with fewer than 2 randomizations
+/// gives a NaN error, and one whose rules do not grow never ends unless the tolerance is met.
+pub fn native(cov: &Array2<f64>,
+              nu: f64,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &[BoundType],
+              constraints: &Array2<f64>,
+              delta: &Array1<f64>,
+              maxpts: usize,
+              abseps: f64,
+              releps: f64,
+              options: &Options)
+              -> Result<Estimate, MvDistError> {
+    let f = native::integrand_with(cov,
+                                   nu,
+                                   lb,
+                                   ub,
+                                   types,
+                                   constraints,
+                                   delta,
+                                   &options.reorder,
+                                   options.precision)?;
+    Ok(native::integrate_estimate(&f, maxpts, abseps, releps, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native::QmcConfig;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn native_forwards_the_options() {
+        let cov = arr2(&[[1.0, 0.5], [0.5, 1.0]]);
+        let (lb, ub) = (Array::zeros((2,)), Array::zeros((2,)));
+        let types = [BoundType::Below; 2];
+        let options = Options::default();
+        let raw = native(&cov,
+                         0.0,
+                         &lb,
+                         &ub,
+                         &types,
+                         &Array::eye(2),
+                         &Array::zeros((2,)),
+                         10_000,
+                         1e-6,
+                         0.0,
+                         &options)
+            .unwrap();
+        let checked = native::mvdist_estimate(&cov,
+                                              0.0,
+                                              &lb,
+                                              &ub,
+                                              &types,
+                                              &Array::eye(2),
+                                              &Array::zeros((2,)),
+                                              10_000,
+                                              1e-6,
+                                              0.0,
+                                              &options)
+            .unwrap();
+        assert_eq!(raw, checked);
+        // settings the checked entry point refuses
+        let options = Options {
+            qmc: QmcConfig {
+                randomizations: 1,
+                ..QmcConfig::default()
+            },
+            ..Options::default()
+        };
+        let raw = native(&cov,
+                         0.0,
+                         &lb,
+                         &ub,
+                         &types,
+                         &Array::eye(2),
+                         &Array::zeros((2,)),
+                         1000,
+                         1e-6,
+                         0.0,
+                         &options)
+            .unwrap();
+        assert!(raw.rules.iter().all(|r| r.estimates.len() == 1));
+        assert!((raw.result.value - 1.0 / 3.0).abs() < 1e-2);
+    }
+
+    #[cfg(feature = "fortran")]
+    #[test]
+    fn fortran_arguments_pass_through() {
+        let cov = [1.0, 0.5, 0.5, 1.0];
+        let eye = [1.0, 0.0, 0.0, 1.0];
+        let zeros = [0.0; 2];
+        let r = unsafe {
+            fortran_mvdist(2, &cov, 0, 2, &zeros, &eye, &zeros, &[1, 1], &zeros, 10_000, 1e-6, 0.0)
+        };
+        assert_eq!(r.inform, 0);
+        assert!((r.value - 1.0 / 3.0).abs() < 1e-5);
+        // an invalid dimension is reported by the routine itself
+        let r = unsafe {
+            fortran_mvdist(0, &cov, 0, 2, &zeros, &eye, &zeros, &[1, 1], &zeros, 10_000, 1e-6, 0.0)
+        };
+        assert_eq!(r.inform, 2);
+    }
+}
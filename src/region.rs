@@ -0,0 +1,297 @@
+//! Probabilities of regions built from hyper-rectangles by unions, intersections and differences,
+//! for `X = mean + Y` with `Y` multivariate normal or t as in `order`. A region is expanded by
+//! inclusion–exclusion into a signed sum of rectangles, and each rectangle is evaluated with
+//! `mvdist_kernel`.
+//!
+//! The expansion of a union of `k` regions has up to `2^k - 1` terms. Empty intersections are
+//! dropped and repeated rectangles merged as the expansion is built, and terms whose probability
+//! is bounded below `abseps / 2` in total by the univariate marginals are not evaluated.
+
+use ndarray::prelude::*;
+use univariate::{norm_cdf, t_cdf};
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+/// The box `lower < x < upper`. Infinite limits leave a side unbounded.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rectangle {
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+}
+
+impl Rectangle {
+    pub fn new(lower: Vec<f64>, upper: Vec<f64>) -> Result<Rectangle, String> {
+        if lower.len() != upper.len() {
+            return Err(format!("Rectangle has {} lower and {} upper limits",
+                               lower.len(),
+                               upper.len()));
+        }
+        if lower.iter().chain(upper.iter()).any(|x| x.is_nan()) {
+            return Err("Rectangle limits must not be NaN".to_string());
+        }
+        Ok(Rectangle {
+            lower: lower,
+            upper: upper,
+        })
+    }
+
+    /// The whole of `n`-dimensional space.
+    pub fn everywhere(n: usize) -> Rectangle {
+        Rectangle {
+            lower: vec![::std::f64::NEG_INFINITY; n],
+            upper: vec![::std::f64::INFINITY; n],
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.lower.len()
+    }
+
+    pub fn lower(&self) -> &[f64] {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &[f64] {
+        &self.upper
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lower.iter().zip(self.upper.iter()).any(|(l, u)| l >= u)
+    }
+
+    fn intersect(&self, other: &Rectangle) -> Rectangle {
+        Rectangle {
+            lower: self.lower.iter().zip(other.lower.iter()).map(|(a, b)| a.max(*b)).collect(),
+            upper: self.upper.iter().zip(other.upper.iter()).map(|(a, b)| a.min(*b)).collect(),
+        }
+    }
+}
+
+/// A set of points built from rectangles.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Region {
+    Rectangle(Rectangle),
+    /// The points in any of the regions, or no points if there are none.
+    Union(Vec<Region>),
+    /// The points in all of the regions, or all points if there are none.
+    Intersection(Vec<Region>),
+    /// The points in the first region but not the second.
+    Difference(Box<Region>, Box<Region>),
+}
+
+impl From<Rectangle> for Region {
+    fn from(r: Rectangle) -> Region {
+        Region::Rectangle(r)
+    }
+}
+
+/// A signed sum of rectangle indicators.
+type Terms = Vec<(f64, Rectangle)>;
+
+fn add(terms: &mut Terms, c: f64, r: Rectangle) {
+    if c == 0.0 || r.is_empty() {
+        return;
+    }
+    match terms.iter().position(|t| t.1 == r) {
+        Some(i) => terms[i].0 += c,
+        None => terms.push((c, r)),
+    }
+}
+
+fn product(a: &Terms, b: &Terms) -> Terms {
+    let mut terms = Vec::new();
+    for &(ca, ref ra) in a {
+        for &(cb, ref rb) in b {
+            add(&mut terms, ca * cb, ra.intersect(rb));
+        }
+    }
+    terms
+}
+
+/// `a + s b`
+fn combine(mut a: Terms, s: f64, b: Terms) -> Terms {
+    for (c, r) in b {
+        add(&mut a, s * c, r);
+    }
+    a
+}
+
+impl Region {
+    pub fn union(self, other: Region) -> Region {
+        Region::Union(vec![self, other])
+    }
+
+    pub fn intersection(self, other: Region) -> Region {
+        Region::Intersection(vec![self, other])
+    }
+
+    pub fn difference(self, other: Region) -> Region {
+        Region::Difference(Box::new(self), Box::new(other))
+    }
+
+    fn has_dim(&self, n: usize) -> bool {
+        match *self {
+            Region::Rectangle(ref r) => r.dim() == n,
+            Region::Union(ref rs) |
+            Region::Intersection(ref rs) => rs.iter().all(|r| r.has_dim(n)),
+            Region::Difference(ref a, ref b) => a.has_dim(n) && b.has_dim(n),
+        }
+    }
+
+    /// The indicator of the region as a signed sum of rectangle indicators, without zero terms.
+    fn expand(&self, n: usize) -> Terms {
+        let mut terms = match *self {
+            Region::Rectangle(ref r) => {
+                let mut terms = Vec::new();
+                add(&mut terms, 1.0, r.clone());
+                terms
+            }
+            // 1[A ∪ B] = 1[A] + 1[B] - 1[A ∩ B]
+            Region::Union(ref rs) => {
+                rs.iter().fold(Vec::new(), |acc, r| {
+                    let e = r.expand(n);
+                    let both = product(&acc, &e);
+                    combine(combine(acc, 1.0, e), -1.0, both)
+                })
+            }
+            Region::Intersection(ref rs) => {
+                rs.iter().fold(vec![(1.0, Rectangle::everywhere(n))],
+                               |acc, r| product(&acc, &r.expand(n)))
+            }
+            Region::Difference(ref a, ref b) => {
+                let a = a.expand(n);
+                let both = product(&a, &b.expand(n));
+                combine(a, -1.0, both)
+            }
+        };
+        terms.retain(|t| t.0 != 0.0);
+        terms
+    }
+}
+
+/// `P(X ∈ region)`. The terms of the inclusion–exclusion expansion are evaluated with half of
+/// `abseps` shared between them, and the other half bounds the total probability of the terms
+/// that are skipped. `error` includes both, weighted by the coefficients of the terms, and
+/// `nevals` is the total over all evaluations.
+pub fn probability(mean: &Array1<f64>,
+                   cov: &Array2<f64>,
+                   kernel: Kernel,
+                   region: &Region,
+                   maxpts: u64,
+                   abseps: f64)
+                   -> Result<MVResult, MvDistError> {
+    let n = mean.len();
+    if cov.rows() != n || cov.cols() != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{}, expected {}x{}",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     n,
+                                                     n)));
+    }
+    if !region.has_dim(n) {
+        return Err(MvDistError::InvalidInput(format!("Region does not have dimension {}", n)));
+    }
+    let marginal = |z: f64| match kernel {
+        Kernel::Normal => norm_cdf(z),
+        Kernel::StudentT { nu } => t_cdf(nu, z),
+    };
+    let sd = (0..n).map(|i| cov[[i, i]].sqrt()).collect::<Vec<f64>>();
+    if sd.iter().any(|&s| !(s > 0.0)) {
+        return Err(MvDistError::InvalidInput("Variances must be positive".to_string()));
+    }
+    // an upper bound on the probability of each term from its least likely coordinate
+    let mut terms = region.expand(n)
+        .into_iter()
+        .map(|(c, r)| {
+            let bound = (0..n)
+                .map(|i| {
+                    marginal((r.upper[i] - mean[i]) / sd[i]) -
+                    marginal((r.lower[i] - mean[i]) / sd[i])
+                })
+                .fold(1.0f64, f64::min);
+            (c.abs() * bound, c, r)
+        })
+        .collect::<Vec<_>>();
+    terms.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut skipped = 0.0;
+    let mut first = 0;
+    while first < terms.len() && skipped + terms[first].0 <= abseps / 2.0 {
+        skipped += terms[first].0;
+        first += 1;
+    }
+    let weight = terms[first..].iter().map(|t| t.1.abs()).sum::<f64>();
+    let mut total = MVResult {
+        value: 0.0,
+        error: skipped,
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    for &(_, c, ref r) in &terms[first..] {
+        let types = r.lower
+            .iter()
+            .zip(r.upper.iter())
+            .map(|(&l, &u)| BoundType::from_limits(l, u))
+            .collect::<Vec<BoundType>>();
+        let result = ::mvdist_kernel(cov,
+                                     kernel,
+                                     &(&Array::from_vec(r.lower.clone()) - mean),
+                                     &(&Array::from_vec(r.upper.clone()) - mean),
+                                     &types,
+                                     &Array::eye(n),
+                                     &Array::zeros((n,)),
+                                     maxpts,
+                                     abseps / (2.0 * weight),
+                                     0.0)?;
+        total.value += c * result.value;
+        total.error += c.abs() * result.error;
+        total.nevals += result.nevals;
+        if result.state == MVInform::PtLimitReached {
+            total.state = MVInform::PtLimitReached;
+        }
+    }
+    total.value = total.value.max(0.0).min(1.0);
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    fn square(lo: f64, hi: f64) -> Region {
+        Region::from(Rectangle::new(vec![lo; 2], vec![hi; 2]).unwrap())
+    }
+
+    #[test]
+    fn inclusion_exclusion() {
+        let (mean, cov) = (Array::zeros((2,)), Array::eye(2));
+        let p = |region: &Region| {
+            probability(&mean, &cov, Kernel::Normal, region, 100_000, 1e-8).unwrap().value
+        };
+        let (a, b) = (square(-1.0, 1.0), square(0.0, 2.0));
+        let pa = (norm_cdf(1.0) - norm_cdf(-1.0)).powi(2);
+        let pb = (norm_cdf(2.0) - 0.5).powi(2);
+        let pab = (norm_cdf(1.0) - 0.5).powi(2);
+        assert!((p(&a.clone().intersection(b.clone())) - pab).abs() < 1e-8);
+        assert!((p(&a.clone().union(b.clone())) - (pa + pb - pab)).abs() < 1e-8);
+        assert!((p(&a.clone().difference(b.clone())) - (pa - pab)).abs() < 1e-8);
+        // a far-away square is skipped without changing the result
+        let far = a.clone().union(square(10.0, 11.0));
+        assert!((p(&far) - pa).abs() < 1e-8);
+        assert_eq!(p(&Region::Union(vec![])), 0.0);
+        assert!(probability(&Array::zeros((3,)), &Array::eye(3), Kernel::Normal, &a, 1000, 1e-3)
+            .is_err());
+    }
+
+    #[test]
+    fn expansion_is_pruned() {
+        let a = square(-1.0, 1.0);
+        assert_eq!(a.clone().union(a.clone()).expand(2).len(), 1);
+        assert_eq!(a.clone().difference(a.clone()).expand(2).len(), 0);
+        // disjoint squares have no intersection term
+        let terms = a.union(square(2.0, 3.0)).expand(2);
+        assert_eq!(terms.len(), 2);
+        assert!(terms.iter().all(|t| t.0 == 1.0));
+    }
+}
@@ -0,0 +1,332 @@
+//! Evaluation results together with the settings they were computed under and how long they took,
+//! for tuning tolerances and budgets after the fact, and human-readable formatting of results.
+
+use conditioning::{conditioning, Conditioning};
+use exact;
+use instrument;
+use native::{self, Reorder};
+use std::fmt;
+use std::time::{Duration, Instant};
+use warning::Warning;
+use {backend, Backend, BoundType, Kernel, MVInform, MVResult, MvDistError, MvProblem};
+
+/// Which of the two error criteria determined the tolerance `max(abseps, releps * |value|)`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Criterion {
+    Absolute,
+    Relative,
+}
+
+/// How the result of a problem was obtained, for auditing it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostics {
+    /// The distribution integrated. Normal problems, including those whose degrees of freedom
+    /// select the normal distribution, are integrated without the radial variable of the t
+    /// distribution by both backends.
+    pub kernel: Kernel,
+    /// The backend that integrated the problem, or `None` if it was solved on the `fast_path`.
+    pub backend: Option<Backend>,
+    /// Whether the constraints were integrated in an order other than the given one, by
+    /// `Reorder::Auto` (as in the Fortran backend) or a custom order. Constraints with infinite
+    /// limits on both sides are left out of the comparison, since they are dropped.
+    pub reordered: bool,
+    /// The number of constraints with `BoundType::Both` and equal limits, which make the
+    /// probability zero.
+    pub degenerate_bounds: usize,
+    /// Whether the problem was solved by one of the special-purpose methods of `exact` rather than
+    /// by integration. `reordered` is `false` in that case.
+    pub fast_path: bool,
+}
+
+/// An `MVResult` with the requested tolerances, the wall-clock time of the evaluation, and the
+/// conditioning of the covariance, other diagnostics and warnings when they were determined.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+    pub result: MVResult,
+    pub abseps: f64,
+    pub releps: f64,
+    pub elapsed: Duration,
+    pub conditioning: Option<Conditioning>,
+    pub diagnostics: Option<Diagnostics>,
+    /// The inputs handled without an error but not as given, empty if none were found or they
+    /// were not determined.
+    pub warnings: Vec<Warning>,
+}
+
+impl Report {
+    /// Time the evaluation `f`, which was requested with tolerances `abseps` and `releps`.
+    pub fn timed<F>(abseps: f64, releps: f64, f: F) -> Result<Report, MvDistError>
+        where F: FnOnce() -> Result<MVResult, MvDistError>
+    {
+        let start = Instant::now();
+        let result = f()?;
+        Ok(Report {
+            result: result,
+            abseps: abseps,
+            releps: releps,
+            elapsed: start.elapsed(),
+            conditioning: None,
+            diagnostics: None,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// The tolerance the error was required to meet, `max(abseps, releps * |value|)`.
+    pub fn tolerance(&self) -> f64 {
+        self.abseps.max(self.releps * self.result.value.abs())
+    }
+
+    /// The criterion that determined `tolerance`. Ties count as `Absolute`.
+    pub fn binding(&self) -> Criterion {
+        if self.abseps >= self.releps * self.result.value.abs() {
+            Criterion::Absolute
+        } else {
+            Criterion::Relative
+        }
+    }
+
+    /// The criterion the reported error meets, preferring `Absolute` when it meets both, or `None`
+    /// if it meets neither. Unlike `binding`, this shows which requirement actually stopped the
+    /// evaluation.
+    pub fn tolerance_met(&self) -> Option<Criterion> {
+        if self.result.error <= self.abseps {
+            Some(Criterion::Absolute)
+        } else if self.result.error <= self.releps * self.result.value.abs() {
+            Some(Criterion::Relative)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the reported error meets the requested tolerance.
+    pub fn converged(&self) -> bool {
+        self.result.state == MVInform::Normal && self.result.error <= self.tolerance()
+    }
+}
+
+impl MvProblem {
+    /// Evaluate the problem with `mvdist`, reporting the time taken, the tolerances used, the
+    /// conditioning of the covariance, by which the result is flagged, its `diagnostics` and its
+    /// `warnings`, which with the `tracing` feature are also logged.
+    pub fn mvdist_report(&self) -> Result<Report, MvDistError> {
+        let conditioning = conditioning(&self.cov)?;
+        let diagnostics = self.diagnostics()?;
+        let warnings = self.warnings()?;
+        instrument::warnings(&warnings);
+        let tolerance = self.config.tolerance;
+        let mut report = Report::timed(tolerance.abseps(), tolerance.releps(), || self.mvdist())?;
+        report.result = conditioning.flag(report.result);
+        report.conditioning = Some(conditioning);
+        report.diagnostics = Some(diagnostics);
+        report.warnings = warnings;
+        Ok(report)
+    }
+
+    /// How `mvdist` evaluates the problem with its settings. This takes the setup of an
+    /// integration, `O(m²n)` for `m` constraints on `n` variables, but no integrand evaluations.
+    pub fn diagnostics(&self) -> Result<Diagnostics, MvDistError> {
+        let kernel = self.kernel;
+        let nu = kernel.nu()?;
+        let degenerate = (0..self.types.len())
+            .filter(|&i| match self.types[i] {
+                BoundType::Both => self.lb.get(i).map_or(false, |lb| Some(lb) == self.ub.get(i)),
+                _ => false,
+            })
+            .count();
+        let tolerance = self.config.tolerance;
+        let fast_path = self.config.backend.is_none() &&
+                        exact::try_solve(&self.cov,
+                                         nu,
+                                         &self.lb,
+                                         &self.ub,
+                                         &self.types,
+                                         &self.constraints,
+                                         &self.delta,
+                                         tolerance.abseps(),
+                                         tolerance.releps())
+            .is_some();
+        let reordered = !fast_path && self.config.reorder != Reorder::None && {
+            let f = native::integrand(&self.cov,
+                                      nu,
+                                      &self.lb,
+                                      &self.ub,
+                                      &self.types,
+                                      &self.constraints,
+                                      &self.delta,
+                                      &self.config.reorder)?;
+            f.order().windows(2).any(|w| w[0] > w[1])
+        };
+        let (n, m) = (self.constraints.cols(), self.constraints.rows());
+        let chosen = match self.config.backend {
+            _ if fast_path => None,
+            Some(chosen) => Some(chosen),
+            None if self.config.reorder == Reorder::Auto => Some(backend(n, m, kernel)),
+            None => Some(Backend::Native),
+        };
+        Ok(Diagnostics {
+            kernel: kernel,
+            backend: chosen,
+            reordered: reordered,
+            degenerate_bounds: degenerate,
+            fast_path: fast_path,
+        })
+    }
+}
+
+/// `n` with `,` between groups of thousands.
+fn grouped(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A compact one-line report, e.g. `P = 0.012345 ± 3.1e-6 (99% CI), 48,000 evaluations,
+/// converged`.
+impl<F: fmt::Display + fmt::LowerExp> fmt::Display for MVResult<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "P = {:.6} ± {:.1e} (99% CI), {} evaluations, {}",
+               self.value,
+               self.error,
+               grouped(self.nevals),
+               match self.state {
+                   MVInform::Normal => "converged",
+                   MVInform::PtLimitReached => "point limit reached",
+                   MVInform::IllConditioned => "ill-conditioned",
+               })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        write!(f,
+               "{} in {:.3}s (tolerance {:.1e}, {}",
+               self.result,
+               secs,
+               self.tolerance(),
+               match self.binding() {
+                   Criterion::Absolute => "absolute",
+                   Criterion::Relative => "relative",
+               })?;
+        if let Some(c) = self.conditioning {
+            write!(f, ", condition number {:.1e}", c.condition_number)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A one-line summary of a batch of results: how many converged, the largest error, and the total
+/// number of evaluations.
+pub fn summary(results: &[MVResult]) -> String {
+    let converged = results.iter().filter(|r| r.state == MVInform::Normal).count();
+    let max_error = results.iter().map(|r| r.error).fold(0.0, f64::max);
+    let nevals = results.iter().map(|r| r.nevals).sum::<u64>();
+    format!("{} results, {} converged, max error {:.1e}, {} evaluations",
+            results.len(),
+            converged,
+            max_error,
+            grouped(nevals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use std::time::Duration;
+    use BoundType;
+
+    #[test]
+    fn binding_criterion() {
+        let mut report = Report {
+            result: MVResult {
+                value: 0.5,
+                error: 1e-4,
+                nevals: 1000,
+                state: MVInform::Normal,
+            },
+            abseps: 1e-5,
+            releps: 1e-3,
+            elapsed: Duration::from_millis(1),
+            conditioning: None,
+            diagnostics: None,
+            warnings: Vec::new(),
+        };
+        assert_eq!(report.binding(), Criterion::Relative);
+        assert_eq!(report.tolerance(), 5e-4);
+        assert_eq!(report.tolerance_met(), Some(Criterion::Relative));
+        assert!(report.converged());
+        report.releps = 0.0;
+        assert_eq!(report.binding(), Criterion::Absolute);
+        assert_eq!(report.tolerance_met(), None);
+        assert!(!report.converged());
+        report.abseps = 1e-3;
+        assert_eq!(report.tolerance_met(), Some(Criterion::Absolute));
+    }
+
+    #[test]
+    fn display_formats() {
+        let res = MVResult {
+            value: 0.0123454,
+            error: 3.1e-6,
+            nevals: 48_000,
+            state: MVInform::Normal,
+        };
+        assert_eq!(res.to_string(),
+                   "P = 0.012345 ± 3.1e-6 (99% CI), 48,000 evaluations, converged");
+        assert_eq!(grouped(1_234_567), "1,234,567");
+        assert_eq!(grouped(999), "999");
+        assert_eq!(summary(&[res, res]),
+                   "2 results, 2 converged, max error 3.1e-6, 96,000 evaluations");
+    }
+
+    #[test]
+    fn flags_ill_conditioned_problems() {
+        let lb = Array::from_elem((2,), -1.0);
+        let ub = Array::from_elem((2,), 1.0);
+        let types = vec![BoundType::Both; 2];
+        let well = MvProblem::new(Array::eye(2), lb.clone(), ub.clone(), types.clone())
+            .mvdist_report()
+            .unwrap();
+        assert_eq!(well.result.state, MVInform::Normal);
+        assert!(well.to_string().ends_with("absolute, condition number 1.0e0)"));
+        let cov = arr2(&[[1.0, 1.0 - 1e-13], [1.0 - 1e-13, 1.0]]);
+        let near = MvProblem::new(cov, lb, ub, types).mvdist_report().unwrap();
+        assert_eq!(near.result.state, MVInform::IllConditioned);
+        assert!(!near.converged() && !near.conditioning.unwrap().is_well_conditioned());
+    }
+
+    #[test]
+    fn diagnoses_the_evaluation() {
+        let lb = Array1::from_vec(vec![-1.0, 0.5, -2.0]);
+        let ub = Array1::from_vec(vec![1.0, 0.5, 2.0]);
+        let types = vec![BoundType::Both; 3];
+        let independent = MvProblem::new(Array::eye(3), lb.clone(), ub.clone(), types.clone());
+        let d = independent.diagnostics().unwrap();
+        assert_eq!((d.fast_path, d.reordered, d.degenerate_bounds), (true, false, 1));
+        assert_eq!((d.kernel, d.backend), (Kernel::Normal, None));
+        // the narrow second interval is integrated first
+        let cov = arr2(&[[1.0, 0.3, 0.1], [0.3, 1.0, 0.2], [0.1, 0.2, 1.0]]);
+        let mut problem = MvProblem::new(cov, lb, Array1::from_vec(vec![1.0, 0.6, 2.0]), types);
+        let d = problem.diagnostics().unwrap();
+        assert_eq!((d.fast_path, d.reordered, d.degenerate_bounds), (false, true, 0));
+        assert_eq!(d.backend, Some(::backend(3, 3, Kernel::Normal)));
+        problem.kernel = Kernel::StudentT { nu: 4.0 };
+        assert_eq!(problem.diagnostics().unwrap().kernel, Kernel::StudentT { nu: 4.0 });
+        problem.config.reorder = Reorder::None;
+        let d = problem.diagnostics().unwrap();
+        assert_eq!((d.reordered, d.backend), (false, Some(Backend::Native)));
+        let report = problem.mvdist_report().unwrap();
+        assert_eq!(report.diagnostics, problem.diagnostics().ok());
+        assert!(report.warnings.is_empty());
+    }
+}
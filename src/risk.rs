@@ -0,0 +1,251 @@
+//! Tail expectations of `X = mean + Y`, where `Y` is multivariate normal or t with scatter matrix
+//! `cov` and distribution `kernel`, as used for expected shortfall under normal and t copulas, and
+//! joint tail probabilities of losses with arbitrary marginals joined by such a copula.
+//!
+//! `truncated_mean` uses the boundary form of the first truncated moment: for the normal kernel
+//! `E[Y 1(a < Y < b)] = cov (H(a) - H(b))`, where `H_k(x)` is the density of `Y_k` at `x` times
+//! the probability that the other variables lie in the rectangle given `Y_k = x`. For the t kernel
+//! with `nu > 1` the same holds with the density replaced by
+//! `(nu + x² / cov[k, k]) / (nu - 1)` times the t density, and the conditional probability taken
+//! under a t with `nu - 1` degrees of freedom and scatter scaled by the same factor.
+
+use ndarray::prelude::*;
+use partition::conditional;
+use univariate::{norm_cdf, norm_inv, norm_pdf, t_cdf, t_inv, t_pdf};
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+fn check(mean: &Array1<f64>, cov: &Array2<f64>, len: usize) -> Result<(), MvDistError> {
+    let n = mean.len();
+    if n == 0 {
+        return Err(MvDistError::InvalidInput("No variables given".to_string()));
+    }
+    if cov.rows() != n || cov.cols() != n || len != n {
+        return Err(MvDistError::InvalidInput(format!("Covariance is {}x{} and the other inputs \
+                                                      have length {}, expected {} variables",
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     len,
+                                                     n)));
+    }
+    Ok(())
+}
+
+/// The degrees of freedom of a t kernel, for which the first moment must exist, or `None` for a
+/// normal one.
+fn first_moment_df(kernel: Kernel) -> Result<Option<f64>, MvDistError> {
+    match kernel {
+        Kernel::StudentT { nu } if nu.is_finite() => {
+            if nu > 1.0 {
+                Ok(Some(nu))
+            } else {
+                Err(MvDistError::InvalidInput(format!("The mean does not exist for {} degrees of \
+                                                       freedom",
+                                                      nu)))
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The expected shortfall `E[wᵀX | wᵀX <= q]` of the portfolio `w`. `wᵀX` is a location-scale
+/// transform of a univariate normal or t variable, so this has a closed form.
+pub fn expected_shortfall(mean: &Array1<f64>,
+                          cov: &Array2<f64>,
+                          kernel: Kernel,
+                          w: &Array1<f64>,
+                          q: f64)
+                          -> Result<f64, MvDistError> {
+    check(mean, cov, w.len())?;
+    let nu = first_moment_df(kernel)?;
+    let location = w.dot(mean);
+    let scale = w.dot(&cov.dot(w)).sqrt();
+    if !(scale > 0.0) {
+        return Err(MvDistError::InvalidInput("The portfolio has zero variance".to_string()));
+    }
+    let z = (q - location) / scale;
+    // E[T | T <= z] for the standardized variable
+    let tail = match nu {
+        None => -norm_pdf(z) / norm_cdf(z),
+        Some(nu) => -(nu + z * z) / (nu - 1.0) * t_pdf(nu, z) / t_cdf(nu, z),
+    };
+    if !tail.is_finite() {
+        return Err(MvDistError::InvalidInput(format!("P(wᵀX <= {}) is zero", q)));
+    }
+    Ok(location + scale * tail)
+}
+
+/// `E[X | lower < X < upper]`, with infinite limits leaving a side unbounded, and the probability
+/// of the rectangle. This takes one `mvdist` evaluation for the rectangle and one in `n - 1`
+/// dimensions for each finite limit, each with the given `maxpts` and `abseps`, so the error of
+/// the mean is of order `abseps` divided by the probability of the rectangle.
+pub fn truncated_mean(mean: &Array1<f64>,
+                      cov: &Array2<f64>,
+                      kernel: Kernel,
+                      lower: &Array1<f64>,
+                      upper: &Array1<f64>,
+                      maxpts: u64,
+                      abseps: f64)
+                      -> Result<(Array1<f64>, MVResult), MvDistError> {
+    let n = mean.len();
+    check(mean, cov, lower.len())?;
+    check(mean, cov, upper.len())?;
+    let nu = first_moment_df(kernel)?;
+    let (a, b) = (lower - mean, upper - mean);
+    let types = (0..n).map(|i| BoundType::from_limits(a[i], b[i])).collect::<Vec<BoundType>>();
+    let zero = Array::zeros((n,));
+    let p = ::mvdist_kernel(cov,
+                            kernel,
+                            &a,
+                            &b,
+                            &types,
+                            &Array::eye(n),
+                            &zero,
+                            maxpts,
+                            abseps,
+                            0.0)?;
+    if !(p.value > 0.0) {
+        return Err(MvDistError::InvalidInput("The rectangle has zero probability".to_string()));
+    }
+    let boundary = |k: usize, x: f64| -> Result<f64, MvDistError> {
+        let var = cov[[k, k]];
+        let density = match nu {
+            None => norm_pdf(x / var.sqrt()) / var.sqrt(),
+            Some(nu) => (nu + x * x / var) / (nu - 1.0) * t_pdf(nu, x / var.sqrt()) / var.sqrt(),
+        };
+        if n == 1 {
+            return Ok(density);
+        }
+        let mut rest = conditional(cov, &zero, Kernel::Normal, &[(k, x)])?;
+        if let Some(nu) = nu {
+            rest.kernel = Kernel::StudentT { nu: nu - 1.0 };
+            rest.cov = &rest.cov * ((nu + x * x / var) / (nu - 1.0));
+        }
+        let pick = |v: &Array1<f64>| Array::from_vec(rest.indices.iter().map(|&j| v[j]).collect());
+        let types = rest.indices.iter().map(|&j| types[j]).collect::<Vec<BoundType>>();
+        let inside = rest.probability(&pick(&a), &pick(&b), &types, maxpts, abseps)?;
+        Ok(density * inside.value)
+    };
+    let mut h = Array1::zeros((n,));
+    for k in 0..n {
+        if a[k].is_finite() {
+            h[k] += boundary(k, a[k])?;
+        }
+        if b[k].is_finite() {
+            h[k] -= boundary(k, b[k])?;
+        }
+    }
+    Ok((mean + &(&cov.dot(&h) * (1.0 / p.value)), p))
+}
+
+/// `P(L_i > thresholds[i] for all i)` for losses `L_i` with CDFs `marginal_cdf(i, x)`, joined by
+/// the normal or t copula with correlation matrix `corr` and distribution `kernel`. Each threshold
+/// is mapped to its copula quantile, `Φ⁻¹(F_i(x_i))` or `t_nu⁻¹(F_i(x_i))`, and the upper orthant
+/// probability of the copula variables is evaluated with `mvdist`.
+pub fn joint_exceedance<F>(corr: &Array2<f64>,
+                           kernel: Kernel,
+                           marginal_cdf: F,
+                           thresholds: &[f64],
+                           maxpts: u64,
+                           abseps: f64)
+                           -> Result<MVResult, MvDistError>
+    where F: Fn(usize, f64) -> f64
+{
+    let n = thresholds.len();
+    check(&Array::zeros((n,)), corr, n)?;
+    let mut z = Array1::zeros((n,));
+    for (i, &x) in thresholds.iter().enumerate() {
+        let u = marginal_cdf(i, x);
+        if !(u >= 0.0 && u <= 1.0) {
+            return Err(MvDistError::InvalidInput(format!("Marginal CDF {} is {} at {}", i, u, x)));
+        }
+        z[i] = match kernel {
+            Kernel::Normal => norm_inv(u),
+            Kernel::StudentT { nu } => t_inv(nu, u),
+        };
+    }
+    let inf = ::std::f64::INFINITY;
+    if z.iter().any(|&z| z == inf) {
+        return Ok(MVResult {
+            value: 0.0,
+            error: 0.0,
+            nevals: 0,
+            state: MVInform::Normal,
+        });
+    }
+    ::mvdist_kernel(corr,
+                    kernel,
+                    &z,
+                    &Array::from_elem((n,), inf),
+                    &z.iter().map(|&z| BoundType::from_limits(z, inf)).collect::<Vec<BoundType>>(),
+                    &Array::eye(n),
+                    &Array::zeros((n,)),
+                    maxpts,
+                    abseps,
+                    0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+
+    #[test]
+    fn shortfall_matches_truncated_mean() {
+        let mean = Array1::from_vec(vec![1.0]);
+        let cov = arr2(&[[4.0]]);
+        let upper = Array1::from_vec(vec![-0.5]);
+        let lower = Array1::from_vec(vec![::std::f64::NEG_INFINITY]);
+        for &kernel in &[Kernel::Normal, Kernel::StudentT { nu: 3.5 }] {
+            let es = expected_shortfall(&mean, &cov, kernel, &Array::from_elem((1,), 1.0), -0.5)
+                .unwrap();
+            let (m, _) = truncated_mean(&mean, &cov, kernel, &lower, &upper, 1_000_000, 1e-9)
+                .unwrap();
+            assert!((es - m[0]).abs() < 1e-6);
+            assert!(es < -0.5);
+        }
+        assert!(expected_shortfall(&mean,
+                                   &cov,
+                                   Kernel::StudentT { nu: 1.0 },
+                                   &Array::from_elem((1,), 1.0),
+                                   0.0)
+            .is_err());
+    }
+
+    #[test]
+    fn independent_and_correlated_normals() {
+        let inf = ::std::f64::INFINITY;
+        let zero = Array::zeros((2,));
+        let upper = Array1::from_vec(vec![0.0, 1.0]);
+        let lower = Array::from_elem((2,), -inf);
+        let eye = Array::eye(2);
+        let (m, p) = truncated_mean(&zero, &eye, Kernel::Normal, &lower, &upper, 100_000, 1e-10)
+            .unwrap();
+        assert!((p.value - 0.5 * norm_cdf(1.0)).abs() < 1e-12);
+        assert!((m[0] + norm_pdf(0.0) / 0.5).abs() < 1e-10);
+        assert!((m[1] + norm_pdf(1.0) / norm_cdf(1.0)).abs() < 1e-10);
+        // truncating only the first of two correlated variables
+        let cov = arr2(&[[1.0, 0.6], [0.6, 1.0]]);
+        let upper = Array1::from_vec(vec![0.0, inf]);
+        let (m, _) = truncated_mean(&zero, &cov, Kernel::Normal, &lower, &upper, 100_000, 1e-10)
+            .unwrap();
+        assert!((m[1] - 0.6 * m[0]).abs() < 1e-8);
+    }
+
+    #[test]
+    fn exceedance_through_the_copula() {
+        // exponential losses with rates 1 and 2
+        let cdf = |i: usize, x: f64| 1.0 - (-(i as f64 + 1.0) * x).exp();
+        let thresholds = [1.0, 0.25];
+        let p = joint_exceedance(&Array::eye(2), Kernel::Normal, &cdf, &thresholds, 100_000, 1e-10)
+            .unwrap();
+        assert!((p.value - (-1.5f64).exp()).abs() < 1e-10);
+        // comonotonic losses exceed together whenever the less likely exceedance occurs
+        let t = Kernel::StudentT { nu: 4.0 };
+        let same = arr2(&[[1.0, 1.0], [1.0, 1.0]]);
+        let p = joint_exceedance(&same, t, &cdf, &thresholds, 100_000, 1e-8).unwrap();
+        assert!((p.value - (-1.0f64).exp()).abs() < 1e-6);
+        let never = joint_exceedance(&same, t, |_, _| 1.0, &thresholds, 1000, 1e-3).unwrap();
+        assert_eq!(never.value, 0.0);
+        assert!(joint_exceedance(&same, t, |_, _| 2.0, &thresholds, 1000, 1e-3).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! One-sided boundary crossing probabilities of discretely monitored Gaussian sequences, as for
+//! the stopping boundaries of group-sequential designs. For `X = mean + Y` observed at looks
+//! `1, ..., n`, with `Y` multivariate normal or t with scatter matrix `cov`, the probability of
+//! never crossing the upper boundary `b` is the rectangle probability `P(X_k <= b_k for all k)`,
+//! and the probability of first crossing at look `k` is the difference of those of the first
+//! `k - 1` and `k` looks.
+//!
+//! `brownian_motion` and `score_statistics` give the covariances of Brownian motion observed at
+//! given times and of the standardized statistics `B(t_k) / sqrt(t_k)` of a group-sequential
+//! trial at given information times. Stationary sequences have a Toeplitz covariance, as given by
+//! `corr::toeplitz` from the autocovariances.
+
+use ndarray::prelude::*;
+use {BoundType, Kernel, MVInform, MVResult, MvDistError};
+
+fn check_times(times: &[f64]) -> Result<(), MvDistError> {
+    if times.is_empty() {
+        return Err(MvDistError::InvalidInput("No times given".to_string()));
+    }
+    let increasing = times.windows(2).all(|t| t[1] > t[0]);
+    if !(times[0] > 0.0) || !increasing || !times[times.len() - 1].is_finite() {
+        let msg = "Times must be positive, finite and strictly increasing";
+        return Err(MvDistError::InvalidInput(msg.to_string()));
+    }
+    Ok(())
+}
+
+/// The covariance `min(t_i, t_j)` of standard Brownian motion at the positive, increasing `times`.
+pub fn brownian_motion(times: &[f64]) -> Result<Array2<f64>, MvDistError> {
+    check_times(times)?;
+    let n = times.len();
+    let mut cov = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            cov[[i, j]] = times[i.min(j)];
+        }
+    }
+    Ok(cov)
+}
+
+/// The correlation `sqrt(t_i / t_j)` for `i <= j` of the standardized statistics
+/// `B(t_k) / sqrt(t_k)` at the positive, increasing information times `information`, the
+/// canonical joint distribution of group-sequential test statistics.
+pub fn score_statistics(information: &[f64]) -> Result<Array2<f64>, MvDistError> {
+    check_times(information)?;
+    let n = information.len();
+    let mut corr = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            let (a, b) = (information[i.min(j)], information[i.max(j)]);
+            corr[[i, j]] = (a / b).sqrt();
+        }
+    }
+    Ok(corr)
+}
+
+fn check(mean: &Array1<f64>, cov: &Array2<f64>, upper: &[f64]) -> Result<(), MvDistError> {
+    let n = mean.len();
+    if n == 0 || cov.rows() != n || cov.cols() != n || upper.len() != n {
+        return Err(MvDistError::InvalidInput(format!("Expected {} looks, got a {}x{} \
+                                                      covariance and {} bounds",
+                                                     n,
+                                                     cov.rows(),
+                                                     cov.cols(),
+                                                     upper.len())));
+    }
+    if upper.iter().any(|b| b.is_nan()) {
+        return Err(MvDistError::InvalidInput("Boundaries must not be NaN".to_string()));
+    }
+    Ok(())
+}
+
+/// `P(X_k <= upper[k] for all k)` over the first `looks` looks.
+fn stays_below(mean: &Array1<f64>,
+               cov: &Array2<f64>,
+               kernel: Kernel,
+               upper: &[f64],
+               looks: usize,
+               maxpts: u64,
+               abseps: f64)
+               -> Result<MVResult, MvDistError> {
+    let mut leading = Array2::zeros((looks, looks));
+    for i in 0..looks {
+        for j in 0..looks {
+            leading[[i, j]] = cov[[i, j]];
+        }
+    }
+    let ub = Array::from_vec((0..looks).map(|k| upper[k] - mean[k]).collect());
+    let types = ub.iter()
+        .map(|&b| BoundType::from_limits(::std::f64::NEG_INFINITY, b))
+        .collect::<Vec<BoundType>>();
+    ::mvdist_kernel(&leading,
+                    kernel,
+                    &Array::zeros((looks,)),
+                    &ub,
+                    &types,
+                    &Array::eye(looks),
+                    &Array::zeros((looks,)),
+                    maxpts,
+                    abseps,
+                    0.0)
+}
+
+/// The probability that `X` never crosses the upper boundary `upper`, `P(X_k <= upper[k] for all
+/// k)`. An infinite bound leaves a look unmonitored.
+pub fn non_crossing(mean: &Array1<f64>,
+                    cov: &Array2<f64>,
+                    kernel: Kernel,
+                    upper: &[f64],
+                    maxpts: u64,
+                    abseps: f64)
+                    -> Result<MVResult, MvDistError> {
+    check(mean, cov, upper)?;
+    stays_below(mean, cov, kernel, upper, mean.len(), maxpts, abseps)
+}
+
+/// The probability that `X` first crosses the upper boundary `upper` at each look, whose sum is
+/// one minus `non_crossing`: the rejection probabilities of each analysis of a group-sequential
+/// design under `mean`. Each of the `n` non-crossing probabilities of the first looks is evaluated
+/// with `abseps / 2`, so the error of each entry, the sum of those of the two evaluations it is the
+/// difference of, is at most `abseps`.
+pub fn first_passage(mean: &Array1<f64>,
+                     cov: &Array2<f64>,
+                     kernel: Kernel,
+                     upper: &[f64],
+                     maxpts: u64,
+                     abseps: f64)
+                     -> Result<Vec<MVResult>, MvDistError> {
+    check(mean, cov, upper)?;
+    let mut previous = MVResult {
+        value: 1.0,
+        error: 0.0,
+        nevals: 0,
+        state: MVInform::Normal,
+    };
+    let mut crossings = Vec::with_capacity(mean.len());
+    for looks in 1..(mean.len() + 1) {
+        let below = stays_below(mean, cov, kernel, upper, looks, maxpts, abseps / 2.0)?;
+        crossings.push(MVResult {
+            value: (previous.value - below.value).max(0.0),
+            error: previous.error + below.error,
+            nevals: below.nevals,
+            state: if below.state == MVInform::Normal {
+                previous.state
+            } else {
+                below.state
+            },
+        });
+        previous = below;
+    }
+    Ok(crossings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use std::f64::consts::PI;
+    use univariate::norm_cdf;
+
+    #[test]
+    fn two_looks() {
+        // statistics at half and full information cross zero at the first look with probability
+        // 1/2 and first at the second with 1/2 - (1/4 + asin(sqrt(1/2)) / 2π) = 1/8
+        let corr = score_statistics(&[0.5, 1.0]).unwrap();
+        assert!((corr[[0, 1]] - 0.5f64.sqrt()).abs() < 1e-15);
+        let zero = Array::zeros((2,));
+        let p = first_passage(&zero, &corr, Kernel::Normal, &[0.0, 0.0], 10_000, 1e-8).unwrap();
+        assert!((p[0].value - 0.5).abs() < 1e-12 && (p[1].value - 0.125).abs() < 1e-8);
+        let q = non_crossing(&zero, &corr, Kernel::Normal, &[0.0, 0.0], 10_000, 1e-8).unwrap();
+        assert!((q.value - (0.25 + (0.5f64.sqrt()).asin() / (2.0 * PI))).abs() < 1e-8);
+        // an unmonitored look
+        let inf = ::std::f64::INFINITY;
+        let q = non_crossing(&zero, &corr, Kernel::Normal, &[inf, 1.0], 10_000, 1e-8).unwrap();
+        assert!((q.value - norm_cdf(1.0)).abs() < 1e-12);
+        assert!(score_statistics(&[1.0, 0.5]).is_err());
+        assert!(non_crossing(&zero, &corr, Kernel::Normal, &[0.0], 10_000, 1e-8).is_err());
+    }
+
+    #[test]
+    fn brownian_boundary() {
+        // a drifting Brownian motion at five looks, against a flat boundary
+        let times = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let cov = brownian_motion(&times).unwrap();
+        assert_eq!(cov[[1, 3]], 2.0);
+        let mean = Array::from_vec(times.iter().map(|t| 0.3 * t).collect());
+        let upper = [2.0; 5];
+        let p = first_passage(&mean, &cov, Kernel::Normal, &upper, 200_000, 1e-5).unwrap();
+        let q = non_crossing(&mean, &cov, Kernel::Normal, &upper, 200_000, 1e-5).unwrap();
+        let total = p.iter().map(|r| r.value).sum::<f64>();
+        assert!((total + q.value - 1.0).abs() < 2e-5);
+        assert!((p[0].value - norm_cdf(-1.7)).abs() < 1e-12);
+        assert!(p.iter().all(|r| r.value > 0.0 && r.error <= 1e-5));
+    }
+}
@@ -0,0 +1,137 @@
+//! A lower-level interface taking plain slices instead of `ndarray` types, for callers that don't
+//! otherwise use `ndarray`. Matrices are row-major: `cov` is `n x n` and `constraints` is `m x n`,
+//! where `m = types.len()` is the number of constraints. `lb`, `ub` and `delta` have length `m`.
+//!
+//! These functions check the lengths of their arguments, then call the `ndarray` functions at the
+//! crate root.
+
+use ndarray::prelude::*;
+use {BoundType, MVResult, MvCritResult, MvDistError};
+
+fn check_len(name: &str, len: usize, expected: usize) -> Result<(), String> {
+    if len == expected {
+        Ok(())
+    } else {
+        Err(format!("`{}` has length {}, expected {}", name, len, expected))
+    }
+}
+
+fn matrix(name: &str, data: &[f64], rows: usize, cols: usize) -> Result<Array2<f64>, String> {
+    check_len(name, data.len(), rows * cols)?;
+    Array::from_shape_vec((rows, cols), data.to_vec()).map_err(|e| format!("{}", e))
+}
+
+/// Converted inputs common to `mvdist` and `mvcrit`.
+struct Inputs {
+    cov: Array2<f64>,
+    lb: Array1<f64>,
+    ub: Array1<f64>,
+    types: Vec<BoundType>,
+    constraints: Array2<f64>,
+}
+
+fn inputs(n: usize,
+          cov: &[f64],
+          lb: &[f64],
+          ub: &[f64],
+          types: &[BoundType],
+          constraints: &[f64])
+          -> Result<Inputs, String> {
+    let m = types.len();
+    check_len("lb", lb.len(), m)?;
+    check_len("ub", ub.len(), m)?;
+    Ok(Inputs {
+        cov: matrix("cov", cov, n, n)?,
+        lb: Array1::from_vec(lb.to_vec()),
+        ub: Array1::from_vec(ub.to_vec()),
+        types: types.to_vec(),
+        constraints: matrix("constraints", constraints, m, n)?,
+    })
+}
+
+/// Slice counterpart of `mvdist`.
+pub fn mvdist(n: usize,
+              cov: &[f64],
+              nu: i32,
+              lb: &[f64],
+              ub: &[f64],
+              types: &[BoundType],
+              constraints: &[f64],
+              delta: &[f64],
+              maxpts: u64,
+              abseps: f64,
+              releps: f64)
+              -> Result<MVResult, MvDistError> {
+    let i = inputs(n, cov, lb, ub, types, constraints)?;
+    check_len("delta", delta.len(), types.len())?;
+    ::mvdist(&i.cov,
+             nu,
+             &i.lb,
+             &i.ub,
+             &i.types,
+             &i.constraints,
+             &Array1::from_vec(delta.to_vec()),
+             maxpts,
+             abseps,
+             releps)
+}
+
+/// Slice counterpart of `mvcrit`.
+pub fn mvcrit(n: usize,
+              cov: &[f64],
+              nu: i32,
+              lb: &[f64],
+              ub: &[f64],
+              types: &[BoundType],
+              constraints: &[f64],
+              alpha: f64,
+              maxpts: u64,
+              abseps: f64)
+              -> Result<MvCritResult, MvDistError> {
+    let i = inputs(n, cov, lb, ub, types, constraints)?;
+    ::mvcrit(&i.cov,
+             nu,
+             &i.lb,
+             &i.ub,
+             &i.types,
+             &i.constraints,
+             alpha,
+             maxpts,
+             abseps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_mvdist_works() {
+        let cov = [1.0, 0.0, 0.0, 1.0];
+        let con = [1.0, 0.0, 0.0, 1.0];
+        let result = mvdist(2,
+                            &cov,
+                            0,
+                            &[0.0; 2],
+                            &[0.0; 2],
+                            &[BoundType::Above; 2],
+                            &con,
+                            &[0.0; 2],
+                            10_000,
+                            1e-5,
+                            0.0)
+            .unwrap();
+        assert!((result.value - 0.25).abs() < 1e-5);
+        assert!(mvdist(2,
+                       &cov[..3],
+                       0,
+                       &[0.0; 2],
+                       &[0.0; 2],
+                       &[BoundType::Above; 2],
+                       &con,
+                       &[0.0; 2],
+                       10_000,
+                       1e-5,
+                       0.0)
+            .is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Sobol sequences with optional hash-based Owen scrambling, for the native backend.
+//!
+//! Primitive polynomials are enumerated in order of degree. The initial direction numbers of the
+//! first 16 dimensions are those of Joe and Kuo (2008); later dimensions use pseudo-random odd
+//! initial direction numbers, which still give a valid (if less carefully optimized) digital
+//! sequence.
+
+use native::SplitMix64;
+
+const BITS: usize = 32;
+
+/// Initial direction numbers `m_1, ..., m_s` for dimensions 2 to 16, from Joe and Kuo's
+/// `new-joe-kuo-6.21201` table.
+const JOE_KUO: [&'static [u32]; 15] = [&[1],
+                                       &[1, 3],
+                                       &[1, 3, 1],
+                                       &[1, 1, 1],
+                                       &[1, 1, 3, 3],
+                                       &[1, 3, 5, 13],
+                                       &[1, 1, 5, 5, 17],
+                                       &[1, 1, 5, 5, 5],
+                                       &[1, 1, 7, 11, 19],
+                                       &[1, 1, 5, 1, 1],
+                                       &[1, 1, 1, 3, 11],
+                                       &[1, 3, 5, 5, 31],
+                                       &[1, 3, 3, 9, 7, 49],
+                                       &[1, 1, 1, 15, 21, 21],
+                                       &[1, 3, 1, 13, 27, 49]];
+
+/// Product of polynomials over GF(2) modulo `p` of degree `deg`.
+fn mulmod(a: u64, b: u64, p: u64, deg: u32) -> u64 {
+    let mut result = 0;
+    let mut a = a;
+    let mut b = b;
+    while b != 0 {
+        if b & 1 == 1 {
+            result ^= a;
+        }
+        b >>= 1;
+        a <<= 1;
+        if a >> deg & 1 == 1 {
+            a ^= p;
+        }
+    }
+    result
+}
+
+/// `x^e` modulo `p`.
+fn powmod(e: u64, p: u64, deg: u32) -> u64 {
+    // `x` reduced modulo `p`
+    let (mut result, mut base, mut e) = (1, mulmod(1, 2, p, deg), e);
+    while e != 0 {
+        if e & 1 == 1 {
+            result = mulmod(result, base, p, deg);
+        }
+        base = mulmod(base, base, p, deg);
+        e >>= 1;
+    }
+    result
+}
+
+/// Whether `p` (of degree `deg`) is primitive, i.e. `x` has order `2^deg - 1` modulo `p`.
+fn is_primitive(p: u64, deg: u32) -> bool {
+    let order = (1u64 << deg) - 1;
+    if powmod(order, p, deg) != 1 {
+        return false;
+    }
+    let mut rest = order;
+    let mut q = 2;
+    while q * q <= rest {
+        if rest % q == 0 {
+            if powmod(order / q, p, deg) == 1 {
+                return false;
+            }
+            while rest % q == 0 {
+                rest /= q;
+            }
+        }
+        q += 1;
+    }
+    rest == 1 || powmod(order / rest, p, deg) != 1
+}
+
+/// The first `n` primitive polynomials as `(degree, a)`, where the bits of `a` are the
+/// coefficients of `x^(degree - 1), ..., x`.
+fn primitive_polynomials(n: usize) -> Vec<(u32, u64)> {
+    let mut polys = Vec::with_capacity(n);
+    let mut deg = 1;
+    while polys.len() < n {
+        for a in 0..(1u64 << (deg - 1)) {
+            if polys.len() == n {
+                break;
+            }
+            if is_primitive(1 << deg | a << 1 | 1, deg) {
+                polys.push((deg, a));
+            }
+        }
+        deg += 1;
+    }
+    polys
+}
+
+/// Direction numbers of a `d`-dimensional Sobol sequence.
+#[derive(Clone, Debug)]
+pub struct Sobol {
+    /// `v[j][k]` is the `k`-th direction number of dimension `j`, as a 32-bit binary fraction.
+    v: Vec<[u32; BITS]>,
+}
+
+impl Sobol {
+    pub fn new(d: usize) -> Sobol {
+        let mut v = Vec::with_capacity(d);
+        if d == 0 {
+            return Sobol { v: v };
+        }
+        let mut first = [0; BITS];
+        for k in 0..BITS {
+            first[k] = 1 << (BITS - 1 - k);
+        }
+        v.push(first);
+        let mut rng = SplitMix64::new(0x736f_626f_6c);
+        for (j, (s, a)) in primitive_polynomials(d - 1).into_iter().enumerate() {
+            let s = s as usize;
+            let m = if j < JOE_KUO.len() {
+                JOE_KUO[j].to_vec()
+            } else {
+                (0..s).map(|k| (rng.next_u64() as u32 % (1 << (k + 1))) | 1).collect()
+            };
+            let mut dir = [0u32; BITS];
+            for k in 0..s.min(BITS) {
+                dir[k] = m[k] << (BITS - 1 - k);
+            }
+            for k in s..BITS {
+                dir[k] = dir[k - s] ^ (dir[k - s] >> s);
+                for i in 1..s {
+                    if a >> (s - 1 - i) & 1 == 1 {
+                        dir[k] ^= dir[k - i];
+                    }
+                }
+            }
+            v.push(dir);
+        }
+        Sobol { v: v }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.v.len()
+    }
+
+    /// Advance `x` from point `i - 1` to point `i` of the sequence in Gray-code order, which
+    /// visits the same set of points as the natural order for each power-of-two prefix.
+    pub fn next(&self, i: u32, x: &mut [u32]) {
+        let k = i.trailing_zeros() as usize;
+        for (xj, vj) in x.iter_mut().zip(self.v.iter()) {
+            *xj ^= vj[k];
+        }
+    }
+}
+
+/// Burley's hash-based approximation of a nested uniform (Owen) scramble of the binary fraction
+/// `x`.
+pub fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut y = x.reverse_bits().wrapping_add(seed);
+    y ^= y.wrapping_mul(0x6c50_b47c);
+    y ^= y.wrapping_mul(0xb82f_1e52);
+    y ^= y.wrapping_mul(0xc7af_e638);
+    y ^= y.wrapping_mul(0x8d22_f6e6);
+    y.reverse_bits()
+}
+
+/// The binary fraction `x` as a point of `(0, 1)`.
+pub fn to_unit(x: u32) -> f64 {
+    (x as f64 + 0.5) / (1u64 << BITS) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polynomials_and_direction_numbers() {
+        let polys = primitive_polynomials(15);
+        assert_eq!(&polys[..8],
+                   &[(1, 0), (2, 1), (3, 1), (3, 2), (4, 1), (4, 4), (5, 2), (5, 4)]);
+        assert_eq!(polys[14], (6, 16));
+        for (m, &(s, _)) in JOE_KUO.iter().zip(polys.iter()) {
+            assert_eq!(m.len(), s as usize);
+            assert!(m.iter().enumerate().all(|(k, &mk)| mk % 2 == 1 && mk < 1 << (k + 1)));
+        }
+    }
+
+    #[test]
+    fn prefixes_are_stratified() {
+        // each power-of-two prefix of a one-dimensional projection has one point per interval
+        let sobol = Sobol::new(40);
+        let mut x = vec![0; sobol.dim()];
+        let mut points = vec![x.clone()];
+        for i in 1..64 {
+            sobol.next(i, &mut x);
+            points.push(x.clone());
+        }
+        for j in 0..sobol.dim() {
+            let mut cells = points.iter().map(|p| p[j] >> 26).collect::<Vec<u32>>();
+            cells.sort();
+            assert_eq!(cells, (0..64).collect::<Vec<u32>>());
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Deterministic integration of the transformed integrand of `native` on dimension-adaptive
+//! sparse grids (Smolyak's construction, refined as by Gerstner and Griebel), for smooth problems
+//! of moderate dimension where a result must not carry a Monte Carlo error statement. The points
+//! are fixed: the same problem always gives the same result, with no seed involved.
+//!
+//! The one-dimensional rules are the nested Fejér rules of the second kind, with `2^(l + 1) - 1`
+//! interior points at level `l`. The integral is the sum of the tensor products of the differences
+//! of consecutive rules over a downward-closed set of multi-indices, grown one index at a time in
+//! the direction of the largest difference. `error` is the sum of the absolute differences that
+//! have been computed but not yet refined, the usual error estimate of the method. It is
+//! deterministic but not a rigorous bound: it is reliable when the integrand is smooth, and may be
+//! optimistic where the limits of several constraints on one variable cross, which makes the
+//! integrand kinked.
+
+use native::{self, Integrand, Reorder};
+use ndarray::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use {BoundType, MVInform, MVResult, MvDistError};
+
+/// The most integration variables the sparse grids are used for. Beyond this the number of points
+/// needed grows too quickly for the method to compete with the lattice rules.
+pub const MAX_DIMENSION: usize = 8;
+
+/// The highest level of the one-dimensional rules, with `2^(MAX_LEVEL + 1) - 1` points.
+pub const MAX_LEVEL: usize = 10;
+
+/// The Fejér rule of one level on `[0, 1]`.
+struct Rule {
+    nodes: Vec<f64>,
+    /// The weights less those of the rule one level lower at the same nodes, which are the nodes
+    /// of even index.
+    differences: Vec<f64>,
+}
+
+impl Rule {
+    fn new(level: usize) -> Rule {
+        let weights = |level: usize| {
+            let n = (1usize << (level + 1)) - 1;
+            (1..n + 1)
+                .map(|k| {
+                    let theta = k as f64 * PI / (n + 1) as f64;
+                    let sum = (1..(n + 1) / 2 + 1)
+                        .map(|j| {
+                            let odd = (2 * j - 1) as f64;
+                            (odd * theta).sin() / odd
+                        })
+                        .sum::<f64>();
+                    2.0 * theta.sin() / (n + 1) as f64 * sum
+                })
+                .collect::<Vec<f64>>()
+        };
+        let n = (1usize << (level + 1)) - 1;
+        let nodes = (1..n + 1)
+            .map(|k| 0.5 * (1.0 - (k as f64 * PI / (n + 1) as f64).cos()))
+            .collect();
+        let mut differences = weights(level);
+        if level > 0 {
+            for (k, w) in weights(level - 1).into_iter().enumerate() {
+                // node `k + 1` of the lower rule is node `2 (k + 1)` of this one
+                differences[2 * k + 1] -= w;
+            }
+        }
+        Rule {
+            nodes: nodes,
+            differences: differences,
+        }
+    }
+}
+
+/// The rules and function values of a dimension-adaptive integration.
+struct SparseGrid {
+    rules: Vec<Rule>,
+    /// The function values, keyed by the index of each coordinate's node in the rule of
+    /// `MAX_LEVEL`, so that the points shared by the nested rules are evaluated once.
+    values: HashMap<Vec<u32>, f64>,
+}
+
+impl SparseGrid {
+    /// Build the rules up to the highest level of `index`.
+    fn extend(&mut self, index: &[usize]) {
+        let top = index.iter().cloned().max().unwrap_or(0);
+        while self.rules.len() <= top {
+            let level = self.rules.len();
+            self.rules.push(Rule::new(level));
+        }
+    }
+
+    /// The number of points of `index` not yet evaluated.
+    fn cost(&mut self, index: &[usize]) -> usize {
+        self.extend(index);
+        let (rules, values) = (&self.rules, &self.values);
+        let mut new = 0;
+        each_point(rules, index, |key, _, _| if !values.contains_key(key) {
+            new += 1;
+        });
+        new
+    }
+
+    /// The tensor product of the differences of the rules of `index` applied to `f`.
+    fn difference<F>(&mut self, index: &[usize], f: &mut F) -> f64
+        where F: FnMut(&[f64]) -> f64
+    {
+        self.extend(index);
+        let (rules, values) = (&self.rules, &mut self.values);
+        let mut sum = 0.0;
+        each_point(rules, index, |key, x, weight| {
+            let value = *values.entry(key.to_vec()).or_insert_with(|| f(x));
+            sum += weight * value;
+        });
+        sum
+    }
+}
+
+/// Call `visit` with the key, point and weight of each point of the tensor product of the
+/// differences of the rules of `index`.
+fn each_point<V>(rules: &[Rule], index: &[usize], mut visit: V)
+    where V: FnMut(&[u32], &[f64], f64)
+{
+    let d = index.len();
+    let (mut at, mut key, mut x) = (vec![0; d], vec![0; d], vec![0.0; d]);
+    loop {
+        let mut weight = 1.0;
+        for j in 0..d {
+            let rule = &rules[index[j]];
+            key[j] = ((at[j] + 1) << (MAX_LEVEL - index[j])) as u32;
+            x[j] = rule.nodes[at[j]];
+            weight *= rule.differences[at[j]];
+        }
+        visit(&key, &x, weight);
+        // advance the mixed-radix counter
+        let mut j = 0;
+        while j < d {
+            at[j] += 1;
+            if at[j] < rules[index[j]].nodes.len() {
+                break;
+            }
+            at[j] = 0;
+            j += 1;
+        }
+        if j == d {
+            return;
+        }
+    }
+}
+
+/// Integrate `f` over `[0, 1]^ndim`, for `ndim >= 1`, until the error estimate is within
+/// `max(abseps, releps * value)` or refining further would exceed `maxpts` evaluations.
+fn adapt<F>(ndim: usize, mut f: F, maxpts: usize, abseps: f64, releps: f64) -> MVResult
+    where F: FnMut(&[f64]) -> f64
+{
+    let mut grid = SparseGrid {
+        rules: Vec::new(),
+        values: HashMap::new(),
+    };
+    let origin = vec![0; ndim];
+    let first = grid.difference(&origin, &mut f);
+    let mut active = vec![(origin.clone(), first)];
+    let mut old = HashSet::new();
+    let mut seen = HashSet::new();
+    seen.insert(origin);
+    // the differences of indices that cannot be refined past `MAX_LEVEL`
+    let (mut total, mut residual) = (0.0, 0.0);
+    loop {
+        let value = total + active.iter().map(|&(_, d)| d).sum::<f64>();
+        let error = residual + active.iter().map(|&(_, d): &(_, f64)| d.abs()).sum::<f64>();
+        let nevals = grid.values.len();
+        let result = |state| {
+            MVResult {
+                value: value,
+                error: error,
+                nevals: nevals as u64,
+                state: state,
+            }
+        };
+        if error <= abseps.max(releps * value.abs()) {
+            return result(MVInform::Normal);
+        }
+        if active.is_empty() {
+            return result(MVInform::PtLimitReached);
+        }
+        // a NaN difference counts as the largest, so its index is refined first
+        let largest = {
+            let key = |i: usize| {
+                let d: f64 = active[i].1.abs();
+                if d.is_nan() { ::std::f64::INFINITY } else { d }
+            };
+            (0..active.len()).max_by(|&a, &b| key(a).partial_cmp(&key(b)).unwrap()).unwrap()
+        };
+        let (index, d) = active[largest].clone();
+        let mut next = Vec::new();
+        let mut blocked = false;
+        for j in 0..ndim {
+            let mut forward = index.clone();
+            forward[j] += 1;
+            if forward[j] > MAX_LEVEL {
+                blocked = true;
+                continue;
+            }
+            // the index is used once every backward neighbour has been refined
+            let admissible = (0..ndim).all(|i| {
+                i == j || forward[i] == 0 ||
+                {
+                    let mut backward = forward.clone();
+                    backward[i] -= 1;
+                    old.contains(&backward)
+                }
+            });
+            if admissible && !seen.contains(&forward) {
+                next.push(forward);
+            }
+        }
+        let cost = next.iter().map(|k| grid.cost(k)).sum::<usize>();
+        if nevals + cost > maxpts {
+            return result(MVInform::PtLimitReached);
+        }
+        active.swap_remove(largest);
+        old.insert(index);
+        total += d;
+        if blocked {
+            residual += d.abs();
+        }
+        for k in next {
+            let d = grid.difference(&k, &mut f);
+            seen.insert(k.clone());
+            active.push((k, d));
+        }
+    }
+}
+
+/// Integrate `f` on sparse grids, with at most `maxpts` evaluations. It is an error for `f` to
+/// have more than `MAX_DIMENSION` integration variables.
+pub fn integrate(f: &Integrand,
+                 maxpts: usize,
+                 abseps: f64,
+                 releps: f64)
+                 -> Result<MVResult, MvDistError> {
+    let ndim = f.ndim();
+    if ndim > MAX_DIMENSION {
+        return Err(MvDistError::InvalidInput(format!("Sparse grids integrate at most {} \
+                                                      variables, the problem has {}",
+                                                     MAX_DIMENSION,
+                                                     ndim)));
+    }
+    let mut y = vec![0.0; f.rank()];
+    if ndim == 0 {
+        return Ok(MVResult {
+            value: f.eval(&[], &mut y),
+            error: 0.0,
+            nevals: 1,
+            state: MVInform::Normal,
+        });
+    }
+    Ok(adapt(ndim, |w| f.eval(w, &mut y), maxpts, abseps, releps))
+}
+
+/// The probability of the problem given as to `native::mvdist`, integrated on sparse grids with
+/// the constraints ordered by `reorder`.
+pub fn mvdist(cov: &Array2<f64>,
+              nu: f64,
+              lb: &Array1<f64>,
+              ub: &Array1<f64>,
+              types: &[BoundType],
+              constraints: &Array2<f64>,
+              delta: &Array1<f64>,
+              maxpts: u64,
+              abseps: f64,
+              releps: f64,
+              reorder: &Reorder)
+              -> Result<MVResult, MvDistError> {
+    let f = native::integrand(cov, nu, lb, ub, types, constraints, delta, reorder)?;
+    integrate(&f,
+              maxpts.min(::std::usize::MAX as u64) as usize,
+              abseps,
+              releps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exact::bvt;
+
+    #[test]
+    fn rules_are_nested() {
+        for level in 0..4 {
+            let rule = Rule::new(level);
+            // the differences of a rule exact for constants integrate them to zero, except level 0
+            let sum = rule.differences.iter().sum::<f64>();
+            assert!((sum - if level == 0 { 1.0 } else { 0.0 }).abs() < 1e-14);
+            let moment = rule.nodes.iter().zip(&rule.differences).map(|(x, w)| x * x * w);
+            let expected = if level == 0 { 0.25 } else if level == 1 { 1.0 / 12.0 } else { 0.0 };
+            assert!((moment.sum::<f64>() - expected).abs() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn orthant_probabilities() {
+        let cov = arr2(&[[1.0, 0.5, 0.3], [0.5, 1.0, 0.4], [0.3, 0.4, 1.0]]);
+        let types = vec![BoundType::Above; 3];
+        let zeros = Array::zeros((3,));
+        let r = mvdist(&cov,
+                       0.0,
+                       &zeros,
+                       &zeros,
+                       &types,
+                       &Array::eye(3),
+                       &zeros,
+                       1_000_000,
+                       1e-8,
+                       0.0,
+                       &Reorder::Auto)
+            .unwrap();
+        let exact = 0.125 + (0.5f64.asin() + 0.3f64.asin() + 0.4f64.asin()) / (4.0 * PI);
+        assert_eq!(r.state, MVInform::Normal);
+        assert!((r.value - exact).abs() < 1e-7 && r.error <= 1e-8);
+        let again = mvdist(&cov,
+                           0.0,
+                           &zeros,
+                           &zeros,
+                           &types,
+                           &Array::eye(3),
+                           &zeros,
+                           1_000_000,
+                           1e-8,
+                           0.0,
+                           &Reorder::Auto)
+            .unwrap();
+        assert_eq!(again, r);
+        // a bivariate t, with the scale as a second integration variable
+        let (lower, upper) = ([-1.0, -0.5], [1.5, 2.0]);
+        let r = mvdist(&arr2(&[[1.0, 0.6], [0.6, 1.0]]),
+                       5.0,
+                       &arr1(&lower),
+                       &arr1(&upper),
+                       &[BoundType::Both, BoundType::Both],
+                       &Array::eye(2),
+                       &Array::zeros((2,)),
+                       1_000_000,
+                       1e-7,
+                       0.0,
+                       &Reorder::Auto)
+            .unwrap();
+        assert!((r.value - bvt(lower, upper, 0.6, 5).unwrap()).abs() < 2e-6);
+        let few = mvdist(&cov,
+                         0.0,
+                         &zeros,
+                         &zeros,
+                         &types,
+                         &Array::eye(3),
+                         &zeros,
+                         20,
+                         1e-8,
+                         0.0,
+                         &Reorder::Auto)
+            .unwrap();
+        assert!(few.state == MVInform::PtLimitReached && few.nevals <= 20);
+        let n = MAX_DIMENSION + 2;
+        let zeros = Array::zeros((n,));
+        assert!(mvdist(&::corr::exchangeable(n, 0.5),
+                       0.0,
+                       &zeros,
+                       &zeros,
+                       &vec![BoundType::Above; n],
+                       &Array::eye(n),
+                       &zeros,
+                       1000,
+                       1e-4,
+                       0.0,
+                       &Reorder::Auto)
+            .is_err());
+    }
+
+    #[test]
+    fn nan_integrand_does_not_panic() {
+        let r = adapt(2, |x| if x[0] > 0.5 { ::std::f64::NAN } else { x[1] }, 1000, 1e-8, 0.0);
+        assert!(r.value.is_nan() && r.state == MVInform::PtLimitReached);
+    }
+}
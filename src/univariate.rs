@@ -0,0 +1,412 @@
+//! Univariate distribution functions needed by the Rust-side code paths: the standard normal CDF
+//! and quantile, and the Student-t CDF for real-valued degrees of freedom.
+
+use std::f64::consts::PI;
+
+const SQRT_2: f64 = 1.414213562373095048801688724209;
+
+/// Standard normal density.
+pub fn norm_pdf(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * PI).sqrt()
+}
+
+/// Standard normal CDF, accurate to about 1e-15. This is Genz's `MVPHI`, based on
+/// J.L. Schonfelder, Math Comp 32 (1978), pp 1232-1240.
+pub fn norm_cdf(z: f64) -> f64 {
+    const A: [f64; 25] = [6.10143081923200417926465815756e-1,
+                          -4.34841272712577471828182820888e-1,
+                          1.76351193643605501125840298123e-1,
+                          -6.0710795609249414860051215825e-2,
+                          1.7712068995694114486147141191e-2,
+                          -4.321119385567293818599864968e-3,
+                          8.54216676887098678819832055e-4,
+                          -1.27155090609162742628893940e-4,
+                          1.1248167243671189468847072e-5,
+                          3.13063885421820972630152e-7,
+                          -2.70988068537762022009086e-7,
+                          3.0737622701407688440959e-8,
+                          2.515620384817622937314e-9,
+                          -1.028929921320319127590e-9,
+                          2.9944052119949939363e-11,
+                          2.6051789687266936290e-11,
+                          -2.634839924171969386e-12,
+                          -6.43404509890636443e-13,
+                          1.12457401801663447e-13,
+                          1.7281533389986098e-14,
+                          -4.264101694942375e-15,
+                          -5.45371977880191e-16,
+                          1.58697607761671e-16,
+                          2.0899837844334e-17,
+                          -5.900526869409e-18];
+    let xa = z.abs() / SQRT_2;
+    let p = if xa > 100.0 {
+        0.0
+    } else {
+        let t = (8.0 * xa - 30.0) / (4.0 * xa + 15.0);
+        let mut bm = 0.0;
+        let mut b = 0.0;
+        let mut bp = 0.0;
+        for &a in A.iter().rev() {
+            bp = b;
+            b = bm;
+            bm = t * b - bp + a;
+        }
+        (-xa * xa).exp() * (bm - bp) / 4.0
+    };
+    if z > 0.0 { 1.0 - p } else { p }
+}
+
+/// Standard normal quantile function (Wichura's AS241, accurate to about 1e-16).
+pub fn norm_inv(p: f64) -> f64 {
+    if p <= 0.0 {
+        return ::std::f64::NEG_INFINITY;
+    } else if p >= 1.0 {
+        return ::std::f64::INFINITY;
+    }
+    let q = p - 0.5;
+    if q.abs() <= 0.425 {
+        let r = 0.180625 - q * q;
+        return q *
+               (((((((r * 2509.0809287301226727 + 33430.575583588128105) * r +
+                     67265.770927008700853) * r + 45921.953931549871457) *
+                   r + 13731.693765509461125) * r + 1971.5909503065514427) *
+                 r + 133.14166789178437745) * r + 3.387132872796366608) /
+               (((((((r * 5226.495278852545925 + 28729.085735721942674) * r +
+                     39307.89580009271061) * r + 21213.794301586595867) *
+                   r + 5394.1960214247511077) * r + 687.1870074920579083) *
+                 r + 42.313330701600911252) * r + 1.0);
+    }
+    let r = if q < 0.0 { p } else { 1.0 - p };
+    let r = (-r.ln()).sqrt();
+    let val = if r <= 5.0 {
+        let r = r - 1.6;
+        (((((((r * 7.7454501427834140764e-4 + 0.0227238449892691845833) * r +
+              0.24178072517745061177) * r + 1.27045825245236838258) * r +
+            3.64784832476320460504) * r + 5.7694972214606914055) * r +
+          4.6303378461565452959) * r + 1.42343711074968357734) /
+        (((((((r * 1.05075007164441684324e-9 + 5.475938084995344946e-4) * r +
+              0.0151986665636164571966) * r + 0.14810397642748007459) * r +
+            0.68976733498510000455) * r + 1.6763848301838038494) * r +
+          2.05319162663775882187) * r + 1.0)
+    } else {
+        let r = r - 5.0;
+        (((((((r * 2.01033439929228813265e-7 + 2.71155556874348757815e-5) * r +
+              0.0012426609473880784386) * r + 0.026532189526576123093) * r +
+            0.29656057182850489123) * r + 1.7848265399172913358) * r +
+          5.4637849111641143699) * r + 6.6579046435011037772) /
+        (((((((r * 2.04426310338993978564e-15 + 1.4215117583164458887e-7) * r +
+              1.8463183175100546818e-5) * r + 7.868691311456132591e-4) * r +
+            0.0148753612908506148525) * r + 0.13692988092273580531) * r +
+          0.59983220655588793769) * r + 1.0)
+    };
+    if q < 0.0 { -val } else { val }
+}
+
+/// Natural logarithm of the gamma function (Lanczos approximation, g = 7).
+pub fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 9] = [0.99999999999980993,
+                         676.5203681218851,
+                         -1259.1392167224028,
+                         771.32342877765313,
+                         -176.61502916214059,
+                         12.507343278686905,
+                         -0.13857109526572012,
+                         9.9843695780195716e-6,
+                         1.5056327351493116e-7];
+    if x < 0.5 {
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut a = G[0];
+    let t = x + 7.5;
+    for (i, &g) in G.iter().enumerate().skip(1) {
+        a += g / (x + i as f64);
+    }
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+/// The digamma function `d/dx ln Γ(x)` for `x > 0`, by the recurrence `ψ(x) = ψ(x + 1) - 1 / x`
+/// up to `x >= 10` and the asymptotic series there.
+pub fn digamma(x: f64) -> f64 {
+    let (mut x, mut shift) = (x, 0.0);
+    while x < 10.0 {
+        shift -= 1.0 / x;
+        x += 1.0;
+    }
+    let f = 1.0 / (x * x);
+    let series = f *
+                 (1.0 / 12.0 -
+                  f * (1.0 / 120.0 - f * (1.0 / 252.0 - f * (1.0 / 240.0 - f / 132.0))));
+    shift + x.ln() - 0.5 / x - series
+}
+
+/// Continued fraction for the regularized incomplete beta function (modified Lentz).
+fn beta_cf(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..10_000 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < 1e-16 {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`.
+pub fn inc_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    } else if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * beta_cf(a, b, x) / a
+    } else {
+        1.0 - ln_front.exp() * beta_cf(b, a, 1.0 - x) / b
+    }
+}
+
+/// Student-t CDF with `nu > 0` (not necessarily integer) degrees of freedom. Infinite `nu` gives
+/// the normal CDF.
+pub fn t_cdf(nu: f64, t: f64) -> f64 {
+    if nu.is_infinite() {
+        return norm_cdf(t);
+    }
+    if t.is_infinite() {
+        return if t > 0.0 { 1.0 } else { 0.0 };
+    }
+    let x = nu / (nu + t * t);
+    let tail = 0.5 * inc_beta(nu / 2.0, 0.5, x);
+    if t > 0.0 { 1.0 - tail } else { tail }
+}
+
+/// Student-t quantile function with `nu > 0` degrees of freedom, by bisection on `t_cdf`. Infinite
+/// `nu` gives the normal quantile function.
+pub fn t_inv(nu: f64, p: f64) -> f64 {
+    if nu.is_infinite() {
+        return norm_inv(p);
+    }
+    if p <= 0.0 {
+        return ::std::f64::NEG_INFINITY;
+    } else if p >= 1.0 {
+        return ::std::f64::INFINITY;
+    } else if p > 0.5 {
+        return -t_inv(nu, 1.0 - p);
+    }
+    let (mut lo, mut hi) = (-1.0, 0.0);
+    while t_cdf(nu, lo) > p {
+        hi = lo;
+        lo *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if mid == lo || mid == hi {
+            break;
+        }
+        if t_cdf(nu, mid) > p {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+/// Student-t density with `nu > 0` degrees of freedom.
+pub fn t_pdf(nu: f64, t: f64) -> f64 {
+    if nu.is_infinite() {
+        return norm_pdf(t);
+    }
+    (ln_gamma((nu + 1.0) / 2.0) - ln_gamma(nu / 2.0) - 0.5 * (nu * PI).ln() -
+     (nu + 1.0) / 2.0 * (1.0 + t * t / nu).ln())
+        .exp()
+}
+
+/// Regularized lower incomplete gamma function `P(a, x)`.
+pub fn inc_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    } else if x.is_infinite() {
+        return 1.0;
+    }
+    let ln_front = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // series representation
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut ap = a;
+        for _ in 0..10_000 {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-16 {
+                break;
+            }
+        }
+        sum * ln_front.exp()
+    } else {
+        // continued fraction for the upper tail (modified Lentz)
+        const TINY: f64 = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..10_000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let del = d * c;
+            h *= del;
+            if (del - 1.0).abs() < 1e-16 {
+                break;
+            }
+        }
+        1.0 - ln_front.exp() * h
+    }
+}
+
+/// Inverse of `inc_gamma` in `x`: the `p` quantile of the Gamma(a, 1) distribution.
+pub fn inc_gamma_inv(a: f64, p: f64) -> f64 {
+    if p <= 0.0 {
+        return 0.0;
+    } else if p >= 1.0 {
+        return ::std::f64::INFINITY;
+    }
+    // Wilson-Hilferty starting point, refined by Halley's method
+    let z = norm_inv(p);
+    let t = 1.0 / (9.0 * a);
+    let mut x = a * (1.0 - t + z * t.sqrt()).powi(3);
+    if !(x > 0.0) {
+        x = (p * (a * ln_gamma(a).exp())).powf(1.0 / a);
+    }
+    let lga = ln_gamma(a);
+    for _ in 0..100 {
+        let err = inc_gamma(a, x) - p;
+        let pdf = ((a - 1.0) * x.ln() - x - lga).exp();
+        if pdf == 0.0 {
+            break;
+        }
+        let step = err / pdf;
+        let step = step / (1.0 - 0.5 * (step * ((a - 1.0) / x - 1.0)).min(1.0));
+        let next = if x - step <= 0.0 { 0.5 * x } else { x - step };
+        if (next - x).abs() <= 1e-15 * x {
+            x = next;
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+/// Quantile function of `S = sqrt(W / nu)` where `W` is chi-square with `nu` degrees of freedom.
+pub fn scaled_chi_inv(nu: f64, p: f64) -> f64 {
+    (2.0 * inc_gamma_inv(nu / 2.0, p) / nu).sqrt()
+}
+
+/// Density of `S = sqrt(W / nu)` where `W` is chi-square with `nu` degrees of freedom. This is the
+/// mixing variable of the multivariate t distribution.
+pub fn scaled_chi_pdf(nu: f64, s: f64) -> f64 {
+    if s <= 0.0 {
+        return 0.0;
+    }
+    ((nu / 2.0) * nu.ln() + (nu - 1.0) * s.ln() - nu * s * s / 2.0 -
+     (nu / 2.0 - 1.0) * 2f64.ln() - ln_gamma(nu / 2.0))
+        .exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn norm_cdf_values() {
+        assert!((norm_cdf(0.0) - 0.5).abs() < 1e-15);
+        assert!((norm_cdf(1.959963984540054) - 0.975).abs() < 1e-14);
+        assert!((norm_cdf(-5.0) - 2.866515718791939e-7).abs() < 1e-20);
+    }
+
+    #[test]
+    fn norm_inv_roundtrips() {
+        for &p in &[1e-10, 0.01, 0.3, 0.5, 0.8, 0.999] {
+            assert!((norm_cdf(norm_inv(p)) - p).abs() < 1e-14 * p.max(1e-3));
+        }
+    }
+
+    #[test]
+    fn chi_square_quantiles() {
+        assert!((2.0 * inc_gamma_inv(0.5, 0.95) - 3.841458820694124).abs() < 1e-12);
+        assert!((2.0 * inc_gamma_inv(5.0, 0.5) - 9.341817765591966).abs() < 1e-12);
+        for &p in &[1e-8, 0.1, 0.5, 0.9, 1.0 - 1e-8] {
+            assert!((inc_gamma(3.5, inc_gamma_inv(3.5, p)) - p).abs() < 1e-13);
+        }
+    }
+
+    #[test]
+    fn digamma_values() {
+        // ψ(1) = -γ and ψ(1/2) = -γ - 2 ln 2
+        let euler = 0.5772156649015329;
+        assert!((digamma(1.0) + euler).abs() < 1e-13);
+        assert!((digamma(0.5) + euler + 2.0 * 2f64.ln()).abs() < 1e-13);
+        assert!((digamma(30.5) - digamma(29.5) - 1.0 / 29.5).abs() < 1e-14);
+    }
+
+    #[test]
+    fn t_cdf_values() {
+        // Cauchy
+        assert!((t_cdf(1.0, 1.0) - 0.75).abs() < 1e-14);
+        // nu = 2 has a closed form
+        let t: f64 = 1.5;
+        let exact = 0.5 + t / (2.0 * (2.0 + t * t).sqrt());
+        assert!((t_cdf(2.0, t) - exact).abs() < 1e-14);
+        assert!((t_cdf(2.0, -t) - (1.0 - exact)).abs() < 1e-14);
+    }
+
+    #[test]
+    fn t_inv_roundtrips() {
+        // Cauchy
+        assert!((t_inv(1.0, 0.75) - 1.0).abs() < 1e-14);
+        for &p in &[1e-10, 0.01, 0.3, 0.5, 0.8, 0.999] {
+            assert!((t_cdf(3.5, t_inv(3.5, p)) - p).abs() < 1e-14 * p.max(1e-3));
+        }
+    }
+}
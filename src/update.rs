@@ -0,0 +1,216 @@
+//! Rank-one updates of the covariance of a problem without refactorizing it, for leave-one-out
+//! analyses and online filtering, where the covariance changes by `± v vᵀ` between evaluations.
+//! Building the integrand takes `O(m²n + m³)` operations for `m` constraints on `n` variables;
+//! an update of the factor it holds takes `O(mn + m²)`.
+//!
+//! `Factorization` keeps the Cholesky factor of the induced covariance `C Σ Cᵀ` in the constraint
+//! order chosen for the covariance it was built from. The order is not chosen again after an
+//! update, so after many updates that change the covariance substantially, building a new
+//! `Factorization` may need fewer integrand evaluations for the same tolerance.
+
+use linalg::{cholesky, cholesky_downdate, cholesky_update};
+use native::{self, integrate, Integrand, DEFAULT_SEED};
+use ndarray::prelude::*;
+use {EvalConfig, MVResult, MvDistError, MvProblem};
+
+/// The factor of the induced covariance of a problem, updated in place as its covariance changes.
+#[derive(Clone, Debug)]
+pub struct Factorization {
+    cov: Array2<f64>,
+    /// The rows of the constraint matrix of the constraints `order`, in that order.
+    constraints: Array2<f64>,
+    /// The lower triangular factor of `constraints Σ constraintsᵀ`.
+    factor: Array2<f64>,
+    order: Vec<usize>,
+    /// The limits and noncentralities of all constraints, indexed as in the problem.
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    delta: Vec<f64>,
+    nu: f64,
+    config: EvalConfig,
+}
+
+impl Factorization {
+    /// Factor the induced covariance of `problem`, with the constraints ordered as by
+    /// `problem.config.reorder`. Updates need the induced covariance of the constraints with a
+    /// finite limit to be positive definite, so a singular one is an error.
+    pub fn new(problem: &MvProblem) -> Result<Factorization, MvDistError> {
+        let nu = problem.kernel.nu()?;
+        let f = native::integrand(&problem.cov,
+                                  nu,
+                                  &problem.lb,
+                                  &problem.ub,
+                                  &problem.types,
+                                  &problem.constraints,
+                                  &problem.delta,
+                                  &problem.config.reorder)?;
+        let order = f.order().to_vec();
+        let n = problem.cov.rows();
+        let mut constraints = Array2::zeros((order.len(), n));
+        for (a, &i) in order.iter().enumerate() {
+            constraints.row_mut(a).assign(&problem.constraints.row(i));
+        }
+        let singular = || {
+            MvDistError::InvalidInput(format!("Rank-one updates need a positive definite induced \
+                                               covariance"))
+        };
+        if f.rank() < order.len() {
+            return Err(singular());
+        }
+        let factor = cholesky(&constraints.dot(&problem.cov).dot(&constraints.t()))
+            .ok_or_else(singular)?;
+        let (lower, upper): (Vec<f64>, Vec<f64>) = (0..problem.lb.len())
+            .map(|i| problem.types[i].limits(problem.lb[i], problem.ub[i]))
+            .unzip();
+        Ok(Factorization {
+            cov: problem.cov.clone(),
+            constraints: constraints,
+            factor: factor,
+            order: order,
+            lower: lower,
+            upper: upper,
+            delta: problem.delta.to_vec(),
+            nu: nu,
+            config: problem.config.clone(),
+        })
+    }
+
+    /// Replace the covariance `Σ` by `Σ + v vᵀ`.
+    pub fn update(&mut self, v: &Array1<f64>) -> Result<(), MvDistError> {
+        let mut w = self.image(v)?;
+        cholesky_update(&mut self.factor, &mut w);
+        self.add_outer(v, 1.0);
+        Ok(())
+    }
+
+    /// Replace the covariance `Σ` by `Σ - v vᵀ`. Fails, leaving the factorization unchanged, if
+    /// the induced covariance would not be positive definite.
+    pub fn downdate(&mut self, v: &Array1<f64>) -> Result<(), MvDistError> {
+        let mut w = self.image(v)?;
+        let mut factor = self.factor.clone();
+        if !cholesky_downdate(&mut factor, &mut w) {
+            return Err(MvDistError::InvalidInput(format!("The downdated induced covariance is \
+                                                          not positive definite")));
+        }
+        self.factor = factor;
+        self.add_outer(v, -1.0);
+        Ok(())
+    }
+
+    /// `C v` for the constraints used, checking the length of `v`.
+    fn image(&self, v: &Array1<f64>) -> Result<Vec<f64>, MvDistError> {
+        if v.len() != self.cov.rows() {
+            return Err(MvDistError::InvalidInput(format!("Expected an update of length {}, got \
+                                                          {}",
+                                                         self.cov.rows(),
+                                                         v.len())));
+        }
+        Ok(self.constraints.dot(v).to_vec())
+    }
+
+    fn add_outer(&mut self, v: &Array1<f64>, sign: f64) {
+        let n = v.len();
+        for i in 0..n {
+            for j in 0..n {
+                self.cov[[i, j]] += sign * v[i] * v[j];
+            }
+        }
+    }
+
+    /// The integrand of the problem with the current covariance.
+    pub fn integrand(&self) -> Integrand {
+        let k = self.order.len();
+        let rows = (0..k)
+            .map(|i| (0..(i + 1)).map(|j| self.factor[[i, j]]).collect::<Vec<f64>>())
+            .collect::<Vec<_>>();
+        let variances = rows.iter()
+            .map(|row| row.iter().map(|x| x * x).sum::<f64>())
+            .collect::<Vec<_>>();
+        Integrand::from_factor(&rows,
+                               &variances,
+                               k,
+                               self.order.clone(),
+                               &self.lower,
+                               &self.upper,
+                               &self.delta,
+                               self.nu)
+    }
+
+    /// The probability of the problem with the current covariance, integrated natively with the
+    /// tolerance, `maxpts` and seed (or `native::DEFAULT_SEED`) of its configuration.
+    pub fn mvdist(&self) -> MVResult {
+        let (maxpts, abseps, releps) = native::budget(&self.config);
+        integrate(&self.integrand(),
+                  maxpts,
+                  abseps,
+                  releps,
+                  self.config.seed.unwrap_or(DEFAULT_SEED))
+    }
+
+    /// The current covariance.
+    pub fn cov(&self) -> &Array2<f64> {
+        &self.cov
+    }
+
+    /// The current factor of the induced covariance of the constraints `order`, in that order.
+    pub fn factor(&self) -> &Array2<f64> {
+        &self.factor
+    }
+
+    /// The constraints with a finite limit, in the order of `factor`.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BoundType;
+
+    fn problem(cov: Array2<f64>) -> MvProblem {
+        let inf = ::std::f64::INFINITY;
+        MvProblem {
+            constraints: arr2(&[[1.0, 0.0, 0.0], [1.0, -1.0, 0.0], [0.0, 1.0, 1.0]]),
+            ..MvProblem::new(cov,
+                             arr1(&[-1.0, -0.5, -inf]),
+                             arr1(&[1.5, inf, 0.8]),
+                             vec![BoundType::Both, BoundType::Below, BoundType::Above])
+        }
+    }
+
+    #[test]
+    fn downdate_inverts_update() {
+        let cov = arr2(&[[2.0, 0.4, 0.1], [0.4, 1.0, 0.3], [0.1, 0.3, 1.5]]);
+        let mut f = Factorization::new(&problem(cov.clone())).unwrap();
+        let original = f.factor().clone();
+        let v = arr1(&[0.5, -0.3, 0.8]);
+        f.update(&v).unwrap();
+        let ordered = f.constraints.clone();
+        let expected = cholesky(&ordered.dot(f.cov()).dot(&ordered.t())).unwrap();
+        assert!(f.factor().iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        f.downdate(&v).unwrap();
+        assert!(f.factor().iter().zip(original.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(f.cov().iter().zip(cov.iter()).all(|(a, b)| (a - b).abs() < 1e-15));
+        // removing more than the variance of the first variable leaves it negative
+        assert!(f.downdate(&arr1(&[2.0, 0.0, 0.0])).is_err());
+        assert!(f.factor().iter().zip(original.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(f.update(&arr1(&[1.0, 0.0])).is_err());
+    }
+
+    #[test]
+    fn matches_fresh_factorization() {
+        let cov = arr2(&[[1.0, 0.5, 0.2], [0.5, 1.0, 0.4], [0.2, 0.4, 1.0]]);
+        let mut f = Factorization::new(&problem(cov.clone())).unwrap();
+        let (u, d) = (arr1(&[0.3, 0.6, -0.2]), arr1(&[0.1, 0.2, 0.3]));
+        f.update(&u).unwrap();
+        f.downdate(&d).unwrap();
+        let updated = f.mvdist();
+        let fresh = problem(f.cov().clone()).mvdist().unwrap();
+        assert!((updated.value - fresh.value).abs() < updated.error + fresh.error);
+        let unchanged = Factorization::new(&problem(cov.clone())).unwrap().mvdist();
+        assert!((updated.value - unchanged.value).abs() > 5e-3);
+        let singular = MvProblem { constraints: arr2(&[[1.0, 0.0, 0.0]; 3]), ..problem(cov) };
+        assert!(Factorization::new(&singular).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Cross-checking the backends on one problem, for confidence when moving code from one backend
+//! to another. The problem is evaluated by every method that applies to it: the exact solutions of
+//! `exact::try_solve`, the Fortran backend when it is built and accepts the problem, and the native
+//! backend, which accepts every problem. Each pair of results is then compared in units of their
+//! combined standard error, taking each `error` as `ERROR_SCALE` standard errors.
+
+use exact;
+use native::Reorder;
+use {Backend, EvalConfig, MVResult, MvDistError, MvProblem, ERROR_SCALE};
+
+/// A way of evaluating a problem.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Method {
+    Exact,
+    Fortran,
+    Native,
+}
+
+/// The result of one method.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Evaluation {
+    pub method: Method,
+    pub result: Result<MVResult, MvDistError>,
+}
+
+/// The comparison of the successful results of two methods.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Comparison {
+    pub first: Method,
+    pub second: Method,
+    /// `first` minus `second`.
+    pub difference: f64,
+    /// `|difference|` divided by the combined standard error of the two results. Infinite when
+    /// both errors are zero and the values differ, and 0 when they are equal.
+    pub sigmas: f64,
+}
+
+/// The results of `verify`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Verification {
+    /// One evaluation per method that applies, in the order of `Method`.
+    pub evaluations: Vec<Evaluation>,
+    /// One comparison per pair of successful evaluations.
+    pub comparisons: Vec<Comparison>,
+}
+
+impl Verification {
+    /// The largest discrepancy in sigmas, or 0 with fewer than two successful evaluations.
+    pub fn max_sigmas(&self) -> f64 {
+        self.comparisons.iter().map(|c| c.sigmas).fold(0.0, f64::max)
+    }
+
+    /// Whether every pair of results agrees within `sigmas` standard errors and no method failed.
+    pub fn agrees(&self, sigmas: f64) -> bool {
+        self.evaluations.iter().all(|e| e.result.is_ok()) && self.max_sigmas() <= sigmas
+    }
+}
+
+/// Evaluate `problem` by every method that applies to it, with the budget, tolerance, seed and
+/// retry policy of `config` in place of the problem's own, and compare the results. The Fortran
+/// backend always orders the constraints with `Reorder::Auto`; the native backend uses
+/// `config.reorder`. `config.backend` is ignored.
+pub fn verify(problem: &MvProblem, config: &EvalConfig) -> Verification {
+    let kernel = problem.kernel;
+    let (n, m) = (problem.constraints.cols(), problem.constraints.rows());
+    let run = |backend: Backend| {
+        let config = EvalConfig {
+            backend: Some(backend),
+            reorder: if backend == Backend::Fortran {
+                Reorder::Auto
+            } else {
+                config.reorder.clone()
+            },
+            ..config.clone()
+        };
+        ::mvdist_config(&problem.cov,
+                        kernel,
+                        &problem.lb,
+                        &problem.ub,
+                        &problem.types,
+                        &problem.constraints,
+                        &problem.delta,
+                        &config)
+    };
+    let mut evaluations = Vec::new();
+    let exact = kernel.nu().ok().and_then(|nu| {
+        exact::try_solve(&problem.cov,
+                         nu,
+                         &problem.lb,
+                         &problem.ub,
+                         &problem.types,
+                         &problem.constraints,
+                         &problem.delta,
+                         config.tolerance.abseps(),
+                         config.tolerance.releps())
+    });
+    if let Some(result) = exact {
+        evaluations.push(Evaluation {
+            method: Method::Exact,
+            result: Ok(result),
+        });
+    }
+    if ::backend(n, m, kernel) == Backend::Fortran {
+        evaluations.push(Evaluation {
+            method: Method::Fortran,
+            result: run(Backend::Fortran),
+        });
+    }
+    evaluations.push(Evaluation {
+        method: Method::Native,
+        result: run(Backend::Native),
+    });
+
+    let mut comparisons = Vec::new();
+    for (i, a) in evaluations.iter().enumerate() {
+        for b in &evaluations[(i + 1)..] {
+            if let (&Ok(ref x), &Ok(ref y)) = (&a.result, &b.result) {
+                let difference = x.value - y.value;
+                let se = (x.error * x.error + y.error * y.error).sqrt() / ERROR_SCALE;
+                comparisons.push(Comparison {
+                    first: a.method,
+                    second: b.method,
+                    difference: difference,
+                    sigmas: if difference == 0.0 {
+                        0.0
+                    } else {
+                        difference.abs() / se
+                    },
+                });
+            }
+        }
+    }
+    Verification {
+        evaluations: evaluations,
+        comparisons: comparisons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corr::ar1;
+    use ndarray::prelude::*;
+    use {BoundType, MvProblem};
+
+    #[test]
+    fn methods_agree() {
+        // an equicorrelated problem, solved exactly and by each backend built
+        let mut cov = Array::from_elem((3, 3), 0.5);
+        for i in 0..3 {
+            cov[[i, i]] = 1.0;
+        }
+        let problem = MvProblem::new(cov,
+                                     Array::from_elem((3,), -1.0),
+                                     Array::from_elem((3,), 2.0),
+                                     vec![BoundType::Both; 3]);
+        let v = verify(&problem, &EvalConfig::default());
+        let methods = v.evaluations.iter().map(|e| e.method).collect::<Vec<Method>>();
+        if cfg!(feature = "fortran") {
+            assert_eq!(methods, vec![Method::Exact, Method::Fortran, Method::Native]);
+            assert_eq!(v.comparisons.len(), 3);
+        } else {
+            assert_eq!(methods, vec![Method::Exact, Method::Native]);
+            assert_eq!(v.comparisons.len(), 1);
+        }
+        assert!(v.agrees(5.0));
+        // no exact solution for a general correlation of four variables
+        let problem = MvProblem::new(ar1(4, 0.5),
+                                     Array::from_elem((4,), -1.0),
+                                     Array::from_elem((4,), 2.0),
+                                     vec![BoundType::Both; 4]);
+        let config = EvalConfig {
+            reorder: Reorder::None,
+            ..EvalConfig::default()
+        };
+        let v = verify(&problem, &config);
+        assert!(v.evaluations.iter().all(|e| e.method != Method::Exact));
+        assert_eq!(v.comparisons.len(), v.evaluations.len() - 1);
+        assert!(v.agrees(5.0));
+    }
+}
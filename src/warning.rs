@@ -0,0 +1,214 @@
+//! Warnings about inputs that the integrators accept but handle in ways that change the problem
+//! without saying so, for diagnosing disagreements with other implementations such as R's
+//! `mvtnorm`, which may reject the same inputs or treat them differently. Both backends
+//!
+//! - evaluate the normal distribution function at the standardized limits, where limits more than
+//!   `EXTREME_LIMIT` standard deviations from the mean are lost to rounding;
+//! - treat the value of a constraint whose variance is tiny relative to the others as known, and a
+//!   constraint whose conditional variance given those integrated before it is tiny as a linear
+//!   combination of them;
+//! - treat negative conditional variances within rounding of zero as zero, which for a covariance
+//!   with a correlation slightly outside `[-1, 1]` is the same as moving it to the boundary.
+//!
+//! `warnings` detects these before evaluating, and `MvProblem::mvdist_report` attaches them to its
+//! `Report`. With the `tracing` feature, each is also logged as a `warn` event.
+
+use degenerate::ZERO_VARIANCE;
+use native;
+use std::fmt;
+use {Kernel, MvDistError, MvProblem};
+
+/// The distance from the mean, in standard deviations, beyond which a finite limit of the normal
+/// distribution is reported. The normal tail there is below `1e-18`, under the rounding error of a
+/// probability near 1, so an upper limit this far above the mean acts as an infinite one, and a
+/// region beyond a lower limit this far above it has a probability computed without relative
+/// accuracy.
+pub const EXTREME_LIMIT: f64 = 9.0;
+
+/// An input handled without an error but not as given.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Warning {
+    /// A finite limit of the constraint is `sds` standard deviations from the mean of its value,
+    /// beyond `EXTREME_LIMIT`. Only reported for the normal distribution, whose tails are light
+    /// enough for this to matter.
+    ExtremeLimit { constraint: usize, sds: f64 },
+    /// The value of the constraint has variance `variance`, at most `ZERO_VARIANCE` times the
+    /// largest variance of the constraint values, so it is taken as known and its limits
+    /// compared to `delta` instead of integrated.
+    TinyVariance { constraint: usize, variance: f64 },
+    /// Of the `constraints` with a finite limit, only `rank` have a conditional variance given
+    /// those before them above `1e-12` times the largest variance; the others, including those of
+    /// `TinyVariance`, are integrated as linear combinations of them.
+    Dependent { rank: usize, constraints: usize },
+    /// The covariance of variables `row` and `col` gives them the correlation `correlation`,
+    /// outside `[-1, 1]`.
+    CorrelationOutOfRange {
+        row: usize,
+        col: usize,
+        correlation: f64,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::ExtremeLimit { constraint, sds } => {
+                write!(f,
+                       "constraint {} has a limit {:.1} standard deviations from its mean",
+                       constraint,
+                       sds)
+            }
+            Warning::TinyVariance { constraint, variance } => {
+                write!(f,
+                       "constraint {} has variance {:.1e} and is treated as known",
+                       constraint,
+                       variance)
+            }
+            Warning::Dependent { rank, constraints } => {
+                write!(f,
+                       "only {} of {} constraints are linearly independent",
+                       rank,
+                       constraints)
+            }
+            Warning::CorrelationOutOfRange { row, col, correlation } => {
+                write!(f,
+                       "variables {} and {} have correlation {}",
+                       row,
+                       col,
+                       correlation)
+            }
+        }
+    }
+}
+
+/// The warnings for `problem`, ordered by kind as in `Warning` and then by index. This takes the
+/// setup of an integration, `O(m²n)` for `m` constraints on `n` variables, and `O(mn²)` for the
+/// variances of the constraint values.
+pub fn warnings(problem: &MvProblem) -> Result<Vec<Warning>, MvDistError> {
+    let kernel = problem.kernel;
+    let f = native::integrand(&problem.cov,
+                              kernel.nu()?,
+                              &problem.lb,
+                              &problem.ub,
+                              &problem.types,
+                              &problem.constraints,
+                              &problem.delta,
+                              &problem.config.reorder)?;
+    let (cov, c) = (&problem.cov, &problem.constraints);
+    let (n, m) = (c.cols(), c.rows());
+    let cs = c.dot(cov);
+    let variances = (0..m)
+        .map(|i| (0..n).map(|j| cs[[i, j]] * c[[i, j]]).sum::<f64>())
+        .collect::<Vec<f64>>();
+    let scale = variances.iter().cloned().fold(0.0, f64::max);
+    let mut extreme = Vec::new();
+    let mut tiny = Vec::new();
+    for i in 0..m {
+        let (lower, upper) = problem.types[i].limits(problem.lb[i], problem.ub[i]);
+        if lower.is_infinite() && upper.is_infinite() {
+            continue;
+        }
+        if variances[i] <= ZERO_VARIANCE * scale {
+            tiny.push(Warning::TinyVariance {
+                constraint: i,
+                variance: variances[i],
+            });
+        } else if kernel == Kernel::Normal {
+            let sd = variances[i].sqrt();
+            let sds = [lower, upper]
+                .iter()
+                .filter(|x| x.is_finite())
+                .map(|x| (x - problem.delta[i]) / sd)
+                .fold(0.0, |a: f64, z: f64| if z.abs() > a.abs() { z } else { a });
+            if sds.abs() > EXTREME_LIMIT {
+                extreme.push(Warning::ExtremeLimit {
+                    constraint: i,
+                    sds: sds,
+                });
+            }
+        }
+    }
+    let mut warnings = extreme;
+    warnings.extend(tiny);
+    if f.rank() < f.order().len() {
+        warnings.push(Warning::Dependent {
+            rank: f.rank(),
+            constraints: f.order().len(),
+        });
+    }
+    for i in 0..n {
+        for j in 0..i {
+            let correlation = cov[[i, j]] / (cov[[i, i]] * cov[[j, j]]).sqrt();
+            if correlation.abs() > 1.0 {
+                warnings.push(Warning::CorrelationOutOfRange {
+                    row: i,
+                    col: j,
+                    correlation: correlation,
+                });
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+impl MvProblem {
+    /// The inputs of the problem that `mvdist` handles without an error but not as given.
+    pub fn warnings(&self) -> Result<Vec<Warning>, MvDistError> {
+        warnings(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::prelude::*;
+    use BoundType;
+
+    #[test]
+    fn detects_silent_coercions() {
+        let inf = ::std::f64::INFINITY;
+        let problem = MvProblem::new(Array::eye(3),
+                                     arr1(&[-1.0, -inf, -2.0]),
+                                     arr1(&[1.0, 12.0, 2.0]),
+                                     vec![BoundType::Both, BoundType::Above, BoundType::Both]);
+        assert_eq!(problem.warnings().unwrap(),
+                   vec![Warning::ExtremeLimit {
+                            constraint: 1,
+                            sds: 12.0,
+                        }]);
+        // scaled by its standard deviation, the limit is ordinary, and the t tails are heavy
+        let mut cov = Array::eye(3);
+        cov[[1, 1]] = 4.0;
+        let scaled = MvProblem { cov: cov, ..problem.clone() };
+        assert!(scaled.warnings().unwrap().is_empty());
+        let t = MvProblem { kernel: Kernel::StudentT { nu: 3.0 }, ..problem.clone() };
+        assert!(t.warnings().unwrap().is_empty());
+        // the third constraint repeats the first, and the second has no variance
+        let dependent = MvProblem {
+            constraints: arr2(&[[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [2.0, 0.0, 0.0]]),
+            ..problem.clone()
+        };
+        let w = dependent.warnings().unwrap();
+        assert_eq!(w,
+                   vec![Warning::TinyVariance {
+                            constraint: 1,
+                            variance: 0.0,
+                        },
+                        Warning::Dependent {
+                            rank: 1,
+                            constraints: 3,
+                        }]);
+        assert_eq!(w[1].to_string(), "only 1 of 3 constraints are linearly independent");
+        let cov = arr2(&[[1.0, 1.0 + 1e-9, 0.0], [1.0 + 1e-9, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        let nudged = MvProblem { cov: cov, ..problem };
+        let w = nudged.warnings().unwrap();
+        assert_eq!(w.len(), 3);
+        match w[2] {
+            Warning::CorrelationOutOfRange { row: 1, col: 0, correlation } => {
+                assert!(correlation > 1.0)
+            }
+            w => panic!("unexpected warning {:?}", w),
+        }
+    }
+}
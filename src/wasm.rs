@@ -0,0 +1,62 @@
+//! Small `wasm-bindgen` API for browser use, enabled by the `wasm` feature. Build it without the
+//! Fortran backend:
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --no-default-features --features wasm
+//! ```
+//!
+//! Matrices are passed as flat row-major arrays. Infinite bounds (`-Infinity`/`Infinity` in
+//! JavaScript) leave the corresponding side unbounded.
+
+use ndarray::prelude::*;
+use wasm_bindgen::prelude::*;
+use {mvdist, BoundType, MVInform};
+use std::i32;
+
+/// Result of an evaluation, exposed to JavaScript as an object with read-only fields.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct WasmResult {
+    pub value: f64,
+    pub error: f64,
+    pub nevals: i32,
+    pub converged: bool,
+}
+
+/// `P(lower < X < upper)` for `X` multivariate normal (`nu <= 0`) or multivariate t with `nu`
+/// degrees of freedom, with the `n x n` covariance `cov` where `n = lower.length`.
+#[wasm_bindgen]
+pub fn rectangle_probability(cov: &[f64],
+                             nu: i32,
+                             lower: &[f64],
+                             upper: &[f64],
+                             maxpts: i32,
+                             abseps: f64,
+                             releps: f64)
+                             -> Result<WasmResult, JsValue> {
+    let n = lower.len();
+    if upper.len() != n || cov.len() != n * n {
+        return Err(JsValue::from_str("Inconsistent problem dimensions"));
+    }
+    let types = (0..n).map(|i| BoundType::from_limits(lower[i], upper[i])).collect::<Vec<_>>();
+    let cov = Array::from_shape_vec((n, n), cov.to_vec()).unwrap();
+    mvdist(&cov,
+           nu,
+           &Array1::from_vec(lower.to_vec()),
+           &Array1::from_vec(upper.to_vec()),
+           &types,
+           &Array::eye(n),
+           &Array::zeros((n,)),
+           maxpts.max(0) as u64,
+           abseps,
+           releps)
+        .map(|res| {
+            WasmResult {
+                value: res.value,
+                error: res.error,
+                nevals: res.nevals.min(i32::MAX as u64) as i32,
+                converged: res.state == MVInform::Normal,
+            }
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
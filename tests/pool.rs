@@ -0,0 +1,70 @@
+//! `ProcessPool` with `mvdist worker` processes. Cargo only builds the command-line interface with
+//! the `cli` feature, and only gives integration tests its path.
+#![cfg(all(feature = "pool", feature = "cli"))]
+
+extern crate mvdist;
+extern crate ndarray;
+
+use mvdist::crit::{mvcrit_batch, CritProblem};
+use mvdist::pool::ProcessPool;
+use mvdist::{Backend, BoundType, CancellationToken, Kernel, MvDistError, MvProblem, RootFinder};
+use ndarray::prelude::*;
+
+fn pool(workers: usize) -> ProcessPool {
+    ProcessPool::new(workers, env!("CARGO_BIN_EXE_mvdist"), &["worker"]).unwrap()
+}
+
+fn problem(rho: f64) -> MvProblem {
+    let mut problem = MvProblem::new(arr2(&[[1.0, rho], [rho, 1.0]]),
+                                     Array::from_vec(vec![::std::f64::NEG_INFINITY, -1.0]),
+                                     Array::from_vec(vec![0.0, 1.0]),
+                                     vec![BoundType::Above, BoundType::Both]);
+    problem.config.seed = Some(7);
+    problem.config.backend = Some(Backend::Native);
+    problem
+}
+
+#[test]
+fn workers_evaluate_problems_in_order() {
+    let mut pool = pool(3);
+    assert_eq!(pool.workers(), 3);
+    let mut problems = (0..10).map(|i| problem(0.08 * i as f64)).collect::<Vec<_>>();
+    problems[4].lb = Array::zeros((3,));
+    let expected = problems.iter().map(MvProblem::mvdist).collect::<Vec<_>>();
+    assert!(expected[4].is_err());
+    let cancel = CancellationToken::new();
+    assert_eq!(pool.evaluate_all(&problems, &cancel).unwrap(), expected);
+    // the workers stay up for further batches
+    assert_eq!(pool.evaluate_all(&problems[..2], &cancel).unwrap(), &expected[..2]);
+    assert!(pool.evaluate_all(&[], &cancel).unwrap().is_empty());
+    cancel.cancel();
+    let results = pool.evaluate_all(&problems, &cancel).unwrap();
+    assert!(results.iter().all(|r| *r == Err(MvDistError::Cancelled)));
+}
+
+#[test]
+fn workers_search_critical_values() {
+    let crit = |rho: f64, kernel: Kernel| {
+        CritProblem {
+            cov: arr2(&[[1.0, rho], [rho, 1.0]]),
+            kernel: kernel,
+            types: vec![BoundType::Both; 2],
+            constraints: Array::eye(2),
+            alpha: 0.05,
+        }
+    };
+    let problems = vec![crit(0.1, Kernel::Normal),
+                        crit(0.4, Kernel::StudentT { nu: 6.0 }),
+                        crit(0.2, Kernel::Normal),
+                        crit(0.5, Kernel::StudentT { nu: 6.0 })];
+    let (finder, cancel) = (RootFinder::default(), CancellationToken::new());
+    let mut pool = pool(2);
+    assert_eq!(pool.mvcrit_batch(&problems, 100_000, 1e-4, 0.0, 11, &finder, &cancel).unwrap(),
+               mvcrit_batch(&problems, 100_000, 1e-4, 0.0, 11, &finder, &cancel));
+}
+
+#[test]
+fn a_missing_worker_program_is_an_error() {
+    assert!(ProcessPool::new(1, "/nonexistent/mvdist-worker", &["worker"]).is_err());
+    assert!(ProcessPool::new(0, env!("CARGO_BIN_EXE_mvdist"), &["worker"]).is_err());
+}